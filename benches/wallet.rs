@@ -0,0 +1,70 @@
+//! Benchmarks need the regtest servers, see the README for the environment variables.
+
+use bewallet::test_util::{TestElectrumServer, TestElectrumWallet};
+use bewallet::{Amount, CreateTransactionOpt, Destination, ElectrumWallet};
+use criterion::{criterion_group, criterion_main, Criterion};
+use elements::bitcoin::hashes::hex::ToHex;
+use std::env;
+use tempdir::TempDir;
+
+const MNEMONIC: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+fn bench_wallet(c: &mut Criterion) {
+    let electrs_exec = env::var("ELECTRS_LIQUID_EXEC")
+        .expect("env ELECTRS_LIQUID_EXEC pointing to electrs executable is required");
+    let node_exec = env::var("ELEMENTSD_EXEC")
+        .expect("env ELEMENTSD_EXEC pointing to elementsd executable is required");
+    let mut server = TestElectrumServer::new(false, electrs_exec, node_exec);
+    let mut wallet = TestElectrumWallet::new(&server.electrs.electrum_url, MNEMONIC.to_string());
+    wallet.fund_btc(&mut server);
+    for _ in 0..10 {
+        wallet.fund_asset(&mut server);
+    }
+    let policy_asset = wallet.policy_asset();
+    let address = server.node_getnewaddress(None);
+    let electrum_wallet = wallet.electrum_wallet();
+
+    c.bench_function("utxos", |b| b.iter(|| electrum_wallet.utxos().unwrap()));
+    c.bench_function("balance", |b| b.iter(|| electrum_wallet.balance().unwrap()));
+
+    let mut opt = CreateTransactionOpt::default();
+    opt.addressees.push(
+        Destination::new(
+            &address.to_string(),
+            Amount::from_sat(1_000),
+            &policy_asset.to_hex(),
+        )
+        .unwrap(),
+    );
+    // includes a sync without changes
+    c.bench_function("create_tx", |b| {
+        b.iter(|| electrum_wallet.create_tx(&mut opt.clone()).unwrap())
+    });
+
+    // a sync on an empty store downloads and unblinds every output of the wallet
+    let mut group = c.benchmark_group("unblind");
+    group.sample_size(10);
+    group.bench_function("sync_empty_store", |b| {
+        b.iter(|| {
+            let db_root = TempDir::new("bench").unwrap();
+            let wallet = ElectrumWallet::new_regtest(
+                &policy_asset.to_hex(),
+                &server.electrs.electrum_url,
+                false,
+                false,
+                false,
+                &format!("{}", db_root.path().display()),
+                MNEMONIC,
+            )
+            .unwrap();
+            wallet.sync().unwrap();
+        })
+    });
+    group.finish();
+
+    server.stop();
+}
+
+criterion_group!(benches, bench_wallet);
+criterion_main!(benches);
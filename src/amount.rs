@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Value of an asset in satoshi, the smallest unit of the asset.
+///
+/// Arithmetic is checked, returning None instead of overflowing. Serialized as the bare number
+/// of satoshi.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub const fn from_sat(satoshi: u64) -> Self {
+        Amount(satoshi)
+    }
+
+    pub const fn as_sat(self) -> u64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Sum of `amounts`, None on overflow
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |sum, amount| sum.checked_add(amount))
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(satoshi: u64) -> Self {
+        Amount(satoshi)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat", self.0)
+    }
+}
+
+/// Fee rate in satoshi per 1000 virtual bytes (sat/kvB), serialized as the bare number.
+///
+/// Fee estimation works in satoshi per virtual byte, use `as_sat_per_vbyte` to get it.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    pub const ZERO: FeeRate = FeeRate(0);
+    pub const MAX: FeeRate = FeeRate(u64::MAX);
+
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> Self {
+        FeeRate(sat_per_kvb)
+    }
+
+    /// Rounding down to the satoshi per 1000 vbytes, None if `sat_per_vbyte` is negative or
+    /// not finite
+    pub fn from_sat_per_vbyte(sat_per_vbyte: f64) -> Option<Self> {
+        let sat_per_kvb = sat_per_vbyte * 1000.0;
+        if sat_per_kvb.is_finite() && sat_per_kvb >= 0.0 && sat_per_kvb < u64::MAX as f64 {
+            Some(FeeRate(sat_per_kvb as u64))
+        } else {
+            None
+        }
+    }
+
+    pub const fn as_sat_per_kvb(self) -> u64 {
+        self.0
+    }
+
+    pub fn as_sat_per_vbyte(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+
+    /// Fee paid by `vbytes` at this rate, rounded down, None on overflow
+    pub fn fee(self, vbytes: u64) -> Option<Amount> {
+        self.0.checked_mul(vbytes).map(|fee| Amount(fee / 1000))
+    }
+
+    /// Fee rate of `fee` paid by `vbytes`, rounded down, None on overflow or if `vbytes` is 0
+    pub fn from_fee(fee: Amount, vbytes: u64) -> Option<Self> {
+        fee.0
+            .checked_mul(1000)
+            .and_then(|fee| fee.checked_div(vbytes))
+            .map(FeeRate)
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat/kvB", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_checked() {
+        let max = Amount::from_sat(u64::MAX);
+        assert_eq!(max.checked_add(Amount::from_sat(1)), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::from_sat(1)), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(
+            Amount::from_sat(3).checked_sub(Amount::from_sat(1)),
+            Some(Amount::from_sat(2))
+        );
+        assert_eq!(
+            Amount::checked_sum(vec![Amount::from_sat(1), Amount::from_sat(2)]),
+            Some(Amount::from_sat(3))
+        );
+        assert_eq!(Amount::checked_sum(vec![max, Amount::from_sat(1)]), None);
+        assert_eq!(serde_json::to_string(&Amount::from_sat(10)).unwrap(), "10");
+    }
+
+    #[test]
+    fn test_fee_rate_units() {
+        let fee_rate = FeeRate::from_sat_per_kvb(100);
+        assert_eq!(fee_rate.as_sat_per_vbyte(), 0.1);
+        assert_eq!(FeeRate::from_sat_per_vbyte(0.1), Some(fee_rate));
+        assert_eq!(FeeRate::from_sat_per_vbyte(-1.0), None);
+        assert_eq!(FeeRate::from_sat_per_vbyte(f64::NAN), None);
+        assert_eq!(fee_rate.fee(1_500), Some(Amount::from_sat(150)));
+        assert_eq!(FeeRate::MAX.fee(2), None);
+        assert_eq!(
+            FeeRate::from_fee(Amount::from_sat(150), 1_500),
+            Some(fee_rate)
+        );
+        assert_eq!(FeeRate::from_fee(Amount::from_sat(150), 0), None);
+        assert_eq!(FeeRate::from_fee(Amount::from_sat(u64::MAX), 1), None);
+        assert_eq!(serde_json::to_string(&fee_rate).unwrap(), "100");
+    }
+}
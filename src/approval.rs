@@ -0,0 +1,63 @@
+use elements::issuance::AssetId;
+use elements::Transaction;
+use std::collections::HashMap;
+
+/// Operation for which the wallet is about to sign
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignKind {
+    /// A transaction created by the wallet, see `ElectrumWallet::sign_tx`
+    Transaction,
+    /// The wallet inputs of a payjoin, as receiver or sender
+    Payjoin,
+    /// The maker input of a LiquiDEX proposal
+    LiquidexMake,
+    /// The maker input of a transaction filling a partial LiquiDEX proposal
+    LiquidexFill,
+    /// The taker inputs of a transaction taking a LiquiDEX proposal
+    LiquidexTake,
+    /// The maker input of a LiquiDEX proposal locked by a multisig, signed by a cosigner `Signer`
+    LiquidexCosign,
+}
+
+/// What the wallet is about to sign, given to the `SignApprover`
+#[derive(Debug)]
+pub struct SignRequest<'a> {
+    pub kind: SignKind,
+    /// The transaction, blinded and not yet signed by the wallet
+    pub transaction: &'a Transaction,
+    /// Indexes of the inputs signed by the wallet
+    pub inputs: &'a [usize],
+    /// Value spent by the signed inputs, by asset
+    pub spent: HashMap<AssetId, u64>,
+    /// Sum of the fee outputs
+    pub fee: u64,
+}
+
+/// Decision of a `SignApprover`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignApproval {
+    Approve,
+    /// Signing fails with `Error::SigningRejected` and the reason
+    Reject(String),
+    /// Signing fails with `Error::SigningDelayed`, the caller can sign again later, for instance
+    /// once the user confirmed the operation on another device
+    Delay,
+}
+
+/// Hook invoked before the wallet signs with its keys, allowing to add a confirmation layer
+/// such as a second factor.
+///
+/// `approve` may also block until the user decides, meanwhile other operations on the wallet may
+/// wait. It is implemented by closures taking the `SignRequest`.
+pub trait SignApprover: Send + Sync {
+    fn approve(&self, request: &SignRequest) -> SignApproval;
+}
+
+impl<F> SignApprover for F
+where
+    F: Fn(&SignRequest) -> SignApproval + Send + Sync,
+{
+    fn approve(&self, request: &SignRequest) -> SignApproval {
+        self(request)
+    }
+}
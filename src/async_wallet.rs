@@ -0,0 +1,102 @@
+use crate::amount::Amount;
+use crate::cancel::CancelToken;
+use crate::error::Error;
+use crate::model::{GetTransactionsOpt, Payment, TransactionDetails};
+use crate::ElectrumWallet;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Async wrapper of `ElectrumWallet` for tokio applications.
+///
+/// The network calls and the store locks are blocking, every call runs in the tokio blocking
+/// thread pool so that the runtime threads are never blocked.
+#[derive(Clone)]
+pub struct AsyncElectrumWallet {
+    inner: Arc<ElectrumWallet>,
+}
+
+impl AsyncElectrumWallet {
+    pub fn new(wallet: ElectrumWallet) -> Self {
+        AsyncElectrumWallet {
+            inner: Arc::new(wallet),
+        }
+    }
+
+    /// The wrapped wallet. Its calls take the store locks and may block, call them from
+    /// `run_blocking` in async code.
+    pub fn wallet(&self) -> &ElectrumWallet {
+        &self.inner
+    }
+
+    /// Run `f` with the wrapped wallet in the tokio blocking thread pool, for the calls without
+    /// an async variant
+    pub async fn run_blocking<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ElectrumWallet) -> Result<T, Error> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || f(&inner))
+            .await
+            .map_err(|e| Error::Generic(format!("blocking task failed: {}", e)))?
+    }
+
+    pub async fn sync_async(&self) -> Result<(), Error> {
+        self.run_blocking(|w| w.sync()).await
+    }
+
+    pub async fn sync_cancellable_async(&self, cancel: CancelToken) -> Result<(), Error> {
+        self.run_blocking(move |w| w.sync_cancellable(&cancel))
+            .await
+    }
+
+    pub async fn broadcast_async(&self, transaction: elements::Transaction) -> Result<(), Error> {
+        self.run_blocking(move |w| w.broadcast_tx(&transaction))
+            .await
+    }
+
+    pub async fn balance_async(&self) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        self.run_blocking(|w| w.balance()).await
+    }
+
+    pub async fn transactions_async(
+        &self,
+        opt: GetTransactionsOpt,
+    ) -> Result<Vec<TransactionDetails>, Error> {
+        self.run_blocking(move |w| w.transactions(&opt)).await
+    }
+
+    pub async fn wait_for_payment_async(
+        &self,
+        address: elements::Address,
+        min_amount: Amount,
+        asset: elements::issuance::AssetId,
+        timeout: Duration,
+        cancel: CancelToken,
+    ) -> Result<Option<Vec<Payment>>, Error> {
+        self.run_blocking(move |w| {
+            w.wait_for_payment(&address, min_amount, &asset, timeout, &cancel)
+        })
+        .await
+    }
+
+    #[cfg(feature = "registry")]
+    pub async fn refresh_asset_metadata_async(
+        &self,
+        registry_url: String,
+        assets: Vec<elements::issuance::AssetId>,
+    ) -> Result<usize, Error> {
+        self.run_blocking(move |w| w.refresh_asset_metadata(&registry_url, &assets))
+            .await
+    }
+
+    pub async fn update_fee_estimates_async(&self) -> Result<(), Error> {
+        self.run_blocking(|w| {
+            w.update_fee_estimates();
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::Error;
+use crate::model::FeeEstimate;
+
+use electrum_client::{Client, ElectrumApi, GetMerkleRes, Param};
+use elements::{BlockHash, BlockHeader, Script, Txid};
+
+/// Agent for the http backends, failing requests taking more than `timeout` seconds and
+/// identifying with `user_agent` if given
+#[cfg(any(
+    feature = "esplora",
+    feature = "elementsd",
+    feature = "payjoin",
+    feature = "registry",
+    feature = "remote-signer"
+))]
+pub(crate) fn http_agent(timeout: Option<u8>, user_agent: Option<&str>) -> ureq::Agent {
+    http_agent_builder(timeout, user_agent).build()
+}
+
+/// Builder of `http_agent`, to customize it further
+#[cfg(any(
+    feature = "esplora",
+    feature = "elementsd",
+    feature = "payjoin",
+    feature = "registry",
+    feature = "remote-signer"
+))]
+pub(crate) fn http_agent_builder(
+    timeout: Option<u8>,
+    user_agent: Option<&str>,
+) -> ureq::AgentBuilder {
+    let mut builder = ureq::AgentBuilder::new();
+    if let Some(user_agent) = user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    match timeout {
+        Some(timeout) => builder.timeout(std::time::Duration::from_secs(timeout as u64)),
+        None => builder,
+    }
+}
+
+/// Version of the electrum protocol negotiated by `electrum_client_id`
+const ELECTRUM_PROTOCOL_VERSION: &str = "1.4";
+
+/// Identify as `client_id` to the electrum server with `server.version`, which must be the
+/// first request of the connection
+pub(crate) fn electrum_client_id<E: ElectrumApi>(client: &E, client_id: &str) -> Result<(), Error> {
+    client.raw_call(
+        "server.version",
+        vec![
+            Param::String(client_id.to_string()),
+            Param::String(ELECTRUM_PROTOCOL_VERSION.to_string()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// A transaction in the history of a script
+#[derive(Debug, Clone)]
+pub struct History {
+    pub txid: Txid,
+    /// 0 or less if the transaction is unconfirmed
+    pub height: i32,
+}
+
+/// Source of blockchain data used by the wallet to sync, verify and broadcast transactions.
+pub trait BlockchainBackend {
+    /// Height and header of the blockchain tip
+    fn tip(&self) -> Result<(u32, BlockHeader), Error>;
+
+    /// Transactions involving each of `scripts`, in the same order
+    fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error>;
+
+    /// Transactions with the given txids, in the same order
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error>;
+
+    /// Block headers at the given heights, in the same order
+    fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error>;
+
+    /// Merkle proof, in electrum format, of the inclusion of `txid` in the block at `height`
+    fn merkle_proof(&self, txid: &Txid, height: u32) -> Result<GetMerkleRes, Error>;
+
+    fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error>;
+
+    /// Minimum relay fee followed by the fee estimates for confirmation in 1 to 24 blocks, in
+    /// satoshi/kbyte
+    fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error>;
+
+    /// Hash and BIP158 basic filter of the block at `height`
+    fn block_filter(&self, _height: u32) -> Result<(BlockHash, Vec<u8>), Error> {
+        Err(Error::Generic(
+            "block filters not supported by the backend".into(),
+        ))
+    }
+
+    /// Block at `height`
+    fn block(&self, _height: u32) -> Result<elements::Block, Error> {
+        Err(Error::Generic("blocks not supported by the backend".into()))
+    }
+}
+
+/// Implement `BlockchainBackend` for an electrum client type
+macro_rules! impl_electrum_backend {
+    ($client:ty) => {
+        impl BlockchainBackend for $client {
+            fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+                let header = self.block_headers_subscribe_raw()?;
+                let block_header: BlockHeader = elements::encode::deserialize(&header.header)?;
+                Ok((header.height as u32, block_header))
+            }
+
+            fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+                let scripts_bitcoin: Vec<elements::bitcoin::Script> = scripts
+                    .iter()
+                    .map(|e| elements::bitcoin::Script::from(e.clone().into_bytes()))
+                    .collect();
+                let scripts_bitcoin: Vec<&elements::bitcoin::Script> =
+                    scripts_bitcoin.iter().collect();
+                Ok(self
+                    .batch_script_get_history(scripts_bitcoin)?
+                    .into_iter()
+                    .map(|history| {
+                        history
+                            .into_iter()
+                            .map(|el| History {
+                                txid: Txid::from_hash(el.tx_hash.as_hash()),
+                                height: el.height,
+                            })
+                            .collect()
+                    })
+                    .collect())
+            }
+
+            fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+                let txs_bitcoin: Vec<elements::bitcoin::Txid> = txids
+                    .iter()
+                    .map(|t| elements::bitcoin::Txid::from_hash(t.as_hash()))
+                    .collect();
+                let txs_bitcoin: Vec<&elements::bitcoin::Txid> = txs_bitcoin.iter().collect();
+                let mut txs = vec![];
+                for vec in self.batch_transaction_get_raw(txs_bitcoin)? {
+                    txs.push(elements::encode::deserialize(&vec)?);
+                }
+                Ok(txs)
+            }
+
+            fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+                let mut headers = vec![];
+                for vec in self.batch_block_header_raw(heights.to_vec())? {
+                    headers.push(elements::encode::deserialize(&vec)?);
+                }
+                Ok(headers)
+            }
+
+            fn merkle_proof(&self, txid: &Txid, height: u32) -> Result<GetMerkleRes, Error> {
+                Ok(self.transaction_get_merkle(
+                    &elements::bitcoin::Txid::from_hash(txid.as_hash()),
+                    height as usize,
+                )?)
+            }
+
+            fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error> {
+                self.transaction_broadcast_raw(&elements::encode::serialize(tx))?;
+                Ok(())
+            }
+
+            fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+                let relay_fee = (self.relay_fee()? * 100_000_000.0) as u64;
+                let blocks: Vec<usize> = (1..25).collect();
+                // max is covering a rounding errors in production electrs which sometimes cause a fee
+                // estimates lower than relay fee
+                let mut estimates: Vec<FeeEstimate> = self
+                    .batch_estimate_fee(blocks)?
+                    .iter()
+                    .map(|e| FeeEstimate(relay_fee.max((*e * 100_000_000.0) as u64)))
+                    .collect();
+                estimates.insert(0, FeeEstimate(relay_fee));
+                Ok(estimates)
+            }
+        }
+    };
+}
+
+impl_electrum_backend!(Client);
+
+#[cfg(feature = "pinning")]
+impl_electrum_backend!(electrum_client::raw_client::RawClient<crate::pinning::PinnedStream>);
@@ -0,0 +1,173 @@
+use crate::error::Error;
+use crate::store::RawStore;
+
+use aes_gcm_siv::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
+use aes_gcm_siv::Aes256GcmSiv;
+use elements::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use elements::bitcoin::hashes::{sha256, Hash, HashEngine};
+use elements::bitcoin::util::bip32::{ExtendedPubKey, Fingerprint};
+use elements::secp256k1_zkp::SecretKey;
+use elements::slip77::MasterBlindingKey;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Version byte prepended to the encrypted backup
+const BACKUP_VERSION: u8 = 0;
+
+/// PBKDF2 iterations deriving the encryption key from the password
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Everything needed to restore a watch-only wallet and its settings except the seed: the
+/// account xpub, the SLIP-77 master blinding key and the wallet store (invoices with their
+/// memos, LiquiDEX assets and swaps).
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WalletBackup {
+    xpub: String,
+    master_blinding: String,
+    master_fingerprint: String,
+    #[serde(default)]
+    separate_view_key: bool,
+    store: RawStore,
+}
+
+impl WalletBackup {
+    pub fn new(
+        xpub: &ExtendedPubKey,
+        master_blinding: &MasterBlindingKey,
+        master_fingerprint: &Fingerprint,
+        separate_view_key: bool,
+        store: RawStore,
+    ) -> Self {
+        WalletBackup {
+            xpub: xpub.to_string(),
+            master_blinding: hex::encode(&master_blinding.0[..]),
+            master_fingerprint: hex::encode(&master_fingerprint[..]),
+            separate_view_key,
+            store,
+        }
+    }
+
+    pub fn xpub(&self) -> Result<ExtendedPubKey, Error> {
+        Ok(ExtendedPubKey::from_str(&self.xpub)?)
+    }
+
+    pub fn master_blinding(&self) -> Result<MasterBlindingKey, Error> {
+        let bytes = hex::decode(&self.master_blinding)?;
+        Ok(MasterBlindingKey(SecretKey::from_slice(&bytes)?))
+    }
+
+    pub fn master_fingerprint(&self) -> Result<Fingerprint, Error> {
+        let bytes = hex::decode(&self.master_fingerprint)?;
+        if bytes.len() != 4 {
+            return Err(Error::Generic("invalid backup fingerprint".into()));
+        }
+        Ok(Fingerprint::from(&bytes[..]))
+    }
+
+    /// The master blinding key is not derived from the seed of the signing keys
+    pub fn separate_view_key(&self) -> bool {
+        self.separate_view_key
+    }
+
+    pub fn into_store(self) -> RawStore {
+        self.store
+    }
+
+    /// Serialize and encrypt the backup with a key derived from `password`
+    pub fn encrypt(&self, password: &str) -> Result<Vec<u8>, Error> {
+        let mut salt = [0u8; SALT_LEN];
+        thread_rng().fill(&mut salt);
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        thread_rng().fill(&mut nonce_bytes);
+
+        let key_bytes = derive_key(password, &salt, PBKDF2_ROUNDS);
+        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&key_bytes));
+        let mut plaintext = serde_cbor::to_vec(self)?;
+        cipher.encrypt_in_place(GenericArray::from_slice(&nonce_bytes), b"", &mut plaintext)?;
+
+        let mut bytes = vec![BACKUP_VERSION];
+        bytes.extend(&salt);
+        bytes.extend(&nonce_bytes);
+        bytes.extend(plaintext);
+        Ok(bytes)
+    }
+
+    pub fn decrypt(bytes: &[u8], password: &str) -> Result<Self, Error> {
+        if bytes.len() < 1 + SALT_LEN + NONCE_LEN {
+            return Err(Error::Generic("backup is too short".into()));
+        }
+        if bytes[0] != BACKUP_VERSION {
+            return Err(Error::Generic(format!(
+                "unknown backup version {}",
+                bytes[0]
+            )));
+        }
+        let (salt, rest) = bytes[1..].split_at(SALT_LEN);
+        let (nonce_bytes, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let key_bytes = derive_key(password, salt, PBKDF2_ROUNDS);
+        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&key_bytes));
+        let mut plaintext = ciphertext.to_vec();
+        cipher
+            .decrypt_in_place(GenericArray::from_slice(nonce_bytes), b"", &mut plaintext)
+            .map_err(|_| Error::Generic("cannot decrypt backup, wrong password?".into()))?;
+        Ok(serde_cbor::from_slice(&plaintext)?)
+    }
+}
+
+/// PBKDF2-HMAC-SHA256 with a single 32 bytes output block
+fn derive_key(password: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(password.as_bytes());
+    engine.input(salt);
+    engine.input(&1u32.to_be_bytes());
+    let mut u = Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+    let mut key = u;
+    for _ in 1..rounds {
+        let mut engine = HmacEngine::<sha256::Hash>::new(password.as_bytes());
+        engine.input(&u);
+        u = Hmac::<sha256::Hash>::from_engine(engine).into_inner();
+        for (k, b) in key.iter_mut().zip(u.iter()) {
+            *k ^= b;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_key() {
+        // RFC 7914 PBKDF2-HMAC-SHA256 test vector, first 32 bytes
+        assert_eq!(
+            hex::encode(derive_key("passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+
+    #[test]
+    fn test_backup_roundtrip() {
+        let xpub = ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4YfG9CySHqKHFbaLcD7hSDyqRUtCmMKNim5fkiJtTnFeqKsRHMHSK5ddFrhqRr3Ghv1JtuWkBzikuBqKu1xCpjQ9YxoPGgqU").unwrap();
+        let master_blinding = MasterBlindingKey::new(&[1u8; 64]);
+        let fingerprint = Fingerprint::from(&[1u8, 2, 3, 4][..]);
+        let backup = WalletBackup::new(
+            &xpub,
+            &master_blinding,
+            &fingerprint,
+            false,
+            RawStore::default(),
+        );
+
+        let bytes = backup.encrypt("password").unwrap();
+        assert!(WalletBackup::decrypt(&bytes, "wrong").is_err());
+        let restored = WalletBackup::decrypt(&bytes, "password").unwrap();
+        assert_eq!(restored.xpub().unwrap(), xpub);
+        assert_eq!(restored.master_blinding().unwrap().0, master_blinding.0);
+        assert_eq!(restored.master_fingerprint().unwrap(), fingerprint);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::error::Error;
+use crate::utils::derive_blinder;
+
+use elements::bitcoin::hashes::{sha256, sha256d, Hash, HashEngine};
+use elements::bitcoin::secp256k1::SecretKey;
+use elements::secp256k1_zkp::Tweak;
+use elements::slip77::MasterBlindingKey;
+use elements::Script;
+
+/// Holder of the SLIP-77 master blinding key, deriving the blinding keys and the blinding
+/// factors of the wallet outputs.
+///
+/// It is implemented by `MasterBlindingKey`, hardware devices holding the master blinding key,
+/// such as Jade, could implement it returning the derived values without exposing the key.
+pub trait Blinder: Send + Sync {
+    /// Blinding private key of the outputs locked by `script_pubkey`
+    fn blinding_key(&self, script_pubkey: &Script) -> Result<SecretKey, Error>;
+
+    /// Blinding factor of output `vout` of a transaction spending the outpoints hashed in
+    /// `hash_prevouts`, derived as Ledger and Jade do
+    fn blinding_factor(
+        &self,
+        hash_prevouts: &sha256d::Hash,
+        vout: u32,
+        is_asset_blinder: bool,
+    ) -> Result<Tweak, Error>;
+
+    /// `sha256(tag || master blinding key || data)`, used to derive the keys encrypting the
+    /// values of LiquiDEX proposals
+    fn tagged_secret(&self, tag: &[u8], data: &[u8]) -> Result<[u8; 32], Error>;
+
+    /// Sender ephemeral key of output `vout` of the transaction identified by `tx_hash`, which
+    /// commits to the inputs and to the fields that blinding does not change, the wallet could
+    /// unblind again the outputs it sent knowing the receiver address
+    fn ephemeral_key(&self, tx_hash: &sha256d::Hash, vout: u32) -> Result<SecretKey, Error> {
+        const TAG: &[u8; 13] = b"ephemeral_key";
+        let mut data = tx_hash.into_inner().to_vec();
+        data.extend(&vout.to_le_bytes());
+        Ok(SecretKey::from_slice(&self.tagged_secret(TAG, &data)?)?)
+    }
+
+    /// Identifier of the master blinding key, it keeps apart the data of wallets with the same
+    /// spend key and a different view key
+    fn id(&self) -> Result<sha256::Hash, Error>;
+
+    /// The master blinding key, if it could be exported in the wallet backups
+    fn master_blinding_key(&self) -> Option<MasterBlindingKey> {
+        None
+    }
+}
+
+impl Blinder for MasterBlindingKey {
+    fn blinding_key(&self, script_pubkey: &Script) -> Result<SecretKey, Error> {
+        Ok(self.derive_blinding_key(script_pubkey))
+    }
+
+    fn blinding_factor(
+        &self,
+        hash_prevouts: &sha256d::Hash,
+        vout: u32,
+        is_asset_blinder: bool,
+    ) -> Result<Tweak, Error> {
+        Ok(derive_blinder(self, hash_prevouts, vout, is_asset_blinder)?)
+    }
+
+    fn tagged_secret(&self, tag: &[u8], data: &[u8]) -> Result<[u8; 32], Error> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag);
+        engine.input(&self.0[..]);
+        engine.input(data);
+        Ok(sha256::Hash::from_engine(engine).into_inner())
+    }
+
+    fn id(&self) -> Result<sha256::Hash, Error> {
+        Ok(sha256::Hash::hash(&self.0[..]))
+    }
+
+    fn master_blinding_key(&self) -> Option<MasterBlindingKey> {
+        Some(self.clone())
+    }
+}
@@ -0,0 +1,129 @@
+use crate::cancel::CancelToken;
+use crate::ElectrumWallet;
+
+use elements::Txid;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::warn;
+
+/// Seconds waited after the first failed broadcast, doubled at every further failure
+const BACKOFF_SECS: u64 = 10;
+
+/// Maximum seconds waited between two broadcasts of the same transaction
+const MAX_BACKOFF_SECS: u64 = 3_600;
+
+/// Failed broadcasts after which a queued transaction is dropped
+pub const MAX_BROADCAST_ATTEMPTS: u32 = 10;
+
+/// Transaction waiting in the persisted broadcast queue
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedTx {
+    pub transaction: elements::Transaction,
+    /// Failed broadcasts so far
+    pub attempts: u32,
+    /// Unix time in seconds of the next broadcast
+    pub next_attempt: u64,
+    pub last_error: Option<String>,
+}
+
+impl QueuedTx {
+    pub fn new(transaction: elements::Transaction, now: u64) -> Self {
+        QueuedTx {
+            transaction,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+        }
+    }
+
+    /// Record a failed broadcast at `now`, returns false if the transaction should be dropped
+    pub fn failed(&mut self, error: String, now: u64) -> bool {
+        self.attempts += 1;
+        self.next_attempt = now + backoff_secs(self.attempts);
+        self.last_error = Some(error);
+        self.attempts < MAX_BROADCAST_ATTEMPTS
+    }
+}
+
+/// Seconds to wait before broadcasting again after `attempts` failures
+pub fn backoff_secs(attempts: u32) -> u64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    (BACKOFF_SECS << exp).min(MAX_BACKOFF_SECS)
+}
+
+/// Outcome of broadcasting a queued transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastEvent {
+    /// Broadcast succeeded or the transaction is already known by the wallet, it left the queue
+    Broadcasted(Txid),
+    /// Broadcast failed, it will be retried at unix time `next_attempt`
+    Retrying {
+        txid: Txid,
+        attempts: u32,
+        next_attempt: u64,
+        error: String,
+    },
+    /// Broadcast failed `MAX_BROADCAST_ATTEMPTS` times, it left the queue
+    Failed { txid: Txid, error: String },
+}
+
+/// Spawn a thread processing the broadcast queue of `wallet` every `interval` until `cancel`
+/// is cancelled or `events` is dropped.
+///
+/// Transactions queued by a previous process are broadcast as soon as the worker starts.
+pub fn spawn_broadcast_worker(
+    wallet: Arc<ElectrumWallet>,
+    interval: Duration,
+    events: Sender<BroadcastEvent>,
+    cancel: CancelToken,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !cancel.is_cancelled() {
+            match wallet.process_broadcast_queue() {
+                Ok(processed) => {
+                    for event in processed {
+                        if events.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => warn!("cannot process the broadcast queue: {}", e),
+            }
+            if cancel.sleep(interval).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff_secs(1), 10);
+        assert_eq!(backoff_secs(2), 20);
+        assert_eq!(backoff_secs(4), 80);
+        assert_eq!(backoff_secs(9), MAX_BACKOFF_SECS);
+        assert_eq!(backoff_secs(u32::MAX), MAX_BACKOFF_SECS);
+
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        let mut queued = QueuedTx::new(tx, 100);
+        assert!(queued.failed("err".into(), 100));
+        assert_eq!(queued.next_attempt, 110);
+        for _ in 1..MAX_BROADCAST_ATTEMPTS - 1 {
+            assert!(queued.failed("err".into(), 100));
+        }
+        assert!(!queued.failed("err".into(), 100));
+        assert_eq!(queued.attempts, MAX_BROADCAST_ATTEMPTS);
+    }
+}
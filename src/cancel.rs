@@ -0,0 +1,87 @@
+use crate::error::Error;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between the checks of a token in `CancelToken::sleep`
+const SLEEP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Token to abort a long running network operation from another thread.
+/// Clones share the same state, cancelling one cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Returns `Error::Cancelled` if the token has been cancelled
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sleep for `duration`, returning `Error::Cancelled` soon after the token is cancelled
+    pub fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        let end = Instant::now() + duration;
+        loop {
+            self.check()?;
+            let now = Instant::now();
+            if now >= end {
+                return Ok(());
+            }
+            thread::sleep(SLEEP_CHECK_INTERVAL.min(end - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancelToken;
+    use crate::error::Error;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_cancel_shared() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(matches!(token.check(), Err(Error::Cancelled)));
+    }
+
+    #[test]
+    fn test_cancel_sleep() {
+        let token = CancelToken::new();
+        assert!(token.sleep(Duration::from_millis(10)).is_ok());
+
+        let clone = token.clone();
+        let cancel = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            clone.cancel();
+        });
+        let start = Instant::now();
+        assert!(matches!(
+            token.sleep(Duration::from_secs(60)),
+            Err(Error::Cancelled)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        cancel.join().unwrap();
+    }
+}
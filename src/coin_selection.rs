@@ -0,0 +1,262 @@
+use crate::amount::{Amount, FeeRate};
+use crate::error::Error;
+use crate::model::UnblindedTXO;
+use crate::scripts::AddressType;
+use crate::transaction::{add_input, estimated_fee, DUST_VALUE};
+
+use elements::issuance::AssetId;
+use elements::OutPoint;
+use std::collections::HashMap;
+
+/// Selector of the inputs and of the change outputs of the transactions created by the wallet,
+/// replacing the default selection spending the biggest utxos first.
+pub trait CoinSelector: Send + Sync {
+    /// Select among `candidates` the utxos paying `targets`, the value of the transaction
+    /// outputs by asset, and the fee of the transaction estimated by `fee_model`.
+    ///
+    /// Inputs must cover the targets and the changes exactly for every asset but the policy
+    /// asset, whose remainder is the fee, which must not be lower than the estimate nor higher
+    /// than the estimate plus `DUST_VALUE`. Changes of the policy asset must not be dust.
+    fn select(
+        &self,
+        candidates: &[UnblindedTXO],
+        targets: &HashMap<AssetId, u64>,
+        fee_model: &FeeModel,
+    ) -> Result<CoinSelection, Error>;
+}
+
+/// Inputs and change outputs chosen by a `CoinSelector`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoinSelection {
+    pub inputs: Vec<OutPoint>,
+    /// Value of the change output of every asset, added on the next internal addresses
+    pub changes: HashMap<AssetId, u64>,
+}
+
+/// Fee of the transaction being created, which has the outputs paying the addressees only
+pub struct FeeModel {
+    tx: elements::Transaction,
+    fee_rate: FeeRate,
+    policy_asset: AssetId,
+    address_type: AddressType,
+}
+
+impl FeeModel {
+    pub(crate) fn new(
+        tx: &elements::Transaction,
+        fee_rate: FeeRate,
+        policy_asset: AssetId,
+        address_type: AddressType,
+    ) -> Self {
+        FeeModel {
+            tx: tx.clone(),
+            fee_rate,
+            policy_asset,
+            address_type,
+        }
+    }
+
+    pub fn fee_rate(&self) -> FeeRate {
+        self.fee_rate
+    }
+
+    /// Asset paying the fee
+    pub fn policy_asset(&self) -> AssetId {
+        self.policy_asset
+    }
+
+    /// Estimated fee of the transaction once it spends `inputs` wallet utxos and has `changes`
+    /// change outputs
+    pub fn fee(&self, inputs: usize, changes: usize) -> Amount {
+        let mut tx = self.tx.clone();
+        for _ in 0..inputs {
+            add_input(&mut tx, OutPoint::default());
+        }
+        let fee_rate = self.fee_rate.as_sat_per_vbyte();
+        Amount::from_sat(estimated_fee(
+            &tx,
+            fee_rate,
+            changes as u8,
+            self.address_type,
+        ))
+    }
+}
+
+/// Check `selection` spends distinct `candidates` balancing `targets` and the changes, paying the
+/// estimated fee up to `DUST_VALUE` more, returning the fee it pays
+pub(crate) fn check_selection(
+    selection: &CoinSelection,
+    candidates: &[UnblindedTXO],
+    targets: &HashMap<AssetId, u64>,
+    fee_model: &FeeModel,
+) -> Result<u64, Error> {
+    let mut balances: HashMap<AssetId, i128> = HashMap::new();
+    for (i, outpoint) in selection.inputs.iter().enumerate() {
+        if selection.inputs[..i].contains(outpoint) {
+            return Err(Error::Generic(format!(
+                "coin selection spends {} twice",
+                outpoint
+            )));
+        }
+        let utxo = candidates
+            .iter()
+            .find(|u| u.txo.outpoint == *outpoint)
+            .ok_or_else(|| Error::Generic(format!("coin selection spends unknown {}", outpoint)))?;
+        *balances.entry(utxo.unblinded.asset).or_insert(0) += utxo.unblinded.value as i128;
+    }
+    for (asset, satoshi) in targets.iter().chain(selection.changes.iter()) {
+        *balances.entry(*asset).or_insert(0) -= *satoshi as i128;
+    }
+
+    let policy_asset = fee_model.policy_asset();
+    let fee = balances.remove(&policy_asset).unwrap_or(0);
+    if let Some((asset, _)) = balances.iter().find(|(_, balance)| **balance != 0) {
+        return Err(Error::Generic(format!(
+            "coin selection does not balance asset {}",
+            asset
+        )));
+    }
+    match selection.changes.get(&policy_asset) {
+        Some(satoshi) if *satoshi > 0 && *satoshi < DUST_VALUE => {
+            return Err(Error::Generic(format!(
+                "coin selection change of {} is dust",
+                satoshi
+            )));
+        }
+        _ => (),
+    }
+    let changes = selection.changes.values().filter(|s| **s > 0).count();
+    let estimated_fee = fee_model.fee(selection.inputs.len(), changes).as_sat();
+    if fee < estimated_fee as i128 {
+        return Err(Error::Generic(format!(
+            "coin selection pays a fee of {}, below the estimated {}",
+            fee, estimated_fee
+        )));
+    }
+    if fee > estimated_fee as i128 + DUST_VALUE as i128 {
+        return Err(Error::Generic(format!(
+            "coin selection pays a fee of {}, above the estimated {}",
+            fee, estimated_fee
+        )));
+    }
+    Ok(fee as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::TXO;
+    use elements::bitcoin::hashes::Hash;
+    use elements::{Script, TxOutSecrets};
+
+    fn utxo(vout: u32, asset: AssetId, value: u64) -> UnblindedTXO {
+        let outpoint = OutPoint {
+            txid: elements::Txid::from_inner([1u8; 32]),
+            vout,
+        };
+        UnblindedTXO {
+            txo: TXO::new(outpoint, Script::new(), None),
+            unblinded: TxOutSecrets::new(
+                asset,
+                elements::confidential::AssetBlindingFactor::zero(),
+                value,
+                elements::confidential::ValueBlindingFactor::zero(),
+            ),
+            reissuance_token_of: None,
+            commitments_mismatch: false,
+            confirmations: 1,
+            age_days: None,
+            explicit: false,
+        }
+    }
+
+    #[test]
+    fn test_check_selection() {
+        let policy_asset = AssetId::from_slice(&[2u8; 32]).unwrap();
+        let asset = AssetId::from_slice(&[3u8; 32]).unwrap();
+        let fee_model = FeeModel::new(
+            &elements::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![],
+            },
+            FeeRate::from_sat_per_kvb(100),
+            policy_asset,
+            AddressType::default(),
+        );
+        assert!(fee_model.fee(2, 0) > fee_model.fee(1, 0));
+        assert!(fee_model.fee(1, 1) > fee_model.fee(1, 0));
+
+        let candidates = vec![
+            utxo(0, policy_asset, 100_000),
+            utxo(1, asset, 1_000),
+            utxo(2, asset, 2_000),
+        ];
+        let targets: HashMap<AssetId, u64> = vec![(policy_asset, 10_000), (asset, 1_500)]
+            .into_iter()
+            .collect();
+        let fee = fee_model.fee(3, 2).as_sat();
+        let selection = CoinSelection {
+            inputs: candidates.iter().map(|u| u.txo.outpoint).collect(),
+            changes: vec![(policy_asset, 90_000 - fee), (asset, 1_500)]
+                .into_iter()
+                .collect(),
+        };
+        assert_eq!(
+            check_selection(&selection, &candidates, &targets, &fee_model).unwrap(),
+            fee
+        );
+
+        let mut unbalanced = selection.clone();
+        unbalanced.changes.insert(asset, 1_000);
+        assert!(check_selection(&unbalanced, &candidates, &targets, &fee_model).is_err());
+        let mut low_fee = selection.clone();
+        low_fee.changes.insert(policy_asset, 90_000);
+        assert!(check_selection(&low_fee, &candidates, &targets, &fee_model).is_err());
+        let mut high_fee = selection.clone();
+        high_fee
+            .changes
+            .insert(policy_asset, 90_000 - fee - DUST_VALUE - 1);
+        assert!(check_selection(&high_fee, &candidates, &targets, &fee_model).is_err());
+        high_fee
+            .changes
+            .insert(policy_asset, 90_000 - fee - DUST_VALUE);
+        assert!(check_selection(&high_fee, &candidates, &targets, &fee_model).is_ok());
+
+        // a dust change is added to the fee instead
+        let fee_no_change = fee_model.fee(3, 1).as_sat();
+        let dust_change = CoinSelection {
+            inputs: selection.inputs.clone(),
+            changes: vec![(policy_asset, DUST_VALUE - 1), (asset, 1_500)]
+                .into_iter()
+                .collect(),
+        };
+        let targets_dust: HashMap<AssetId, u64> = vec![
+            (policy_asset, 100_000 - fee - DUST_VALUE + 1),
+            (asset, 1_500),
+        ]
+        .into_iter()
+        .collect();
+        assert!(check_selection(&dust_change, &candidates, &targets_dust, &fee_model).is_err());
+        let mut no_change = dust_change.clone();
+        no_change.changes.remove(&policy_asset);
+        let targets_no_change: HashMap<AssetId, u64> = vec![
+            (policy_asset, 100_000 - fee_no_change - 100),
+            (asset, 1_500),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            check_selection(&no_change, &candidates, &targets_no_change, &fee_model).unwrap(),
+            fee_no_change + 100
+        );
+
+        let mut twice = selection.clone();
+        twice.inputs.push(candidates[0].txo.outpoint);
+        assert!(check_selection(&twice, &candidates, &targets, &fee_model).is_err());
+        let mut unknown = selection;
+        unknown.inputs[0].vout = 3;
+        assert!(check_selection(&unknown, &candidates, &targets, &fee_model).is_err());
+    }
+}
@@ -0,0 +1,407 @@
+use crate::error::Error;
+
+use elements::confidential::{Asset, Value};
+use elements::pset::PartiallySignedTransaction;
+use elements::secp256k1_zkp::{self, All, Secp256k1};
+use serde::{Deserialize, Serialize};
+
+/// Inputs and outputs indexes contributed by a party of a collaborative transaction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CoinjoinParty {
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+}
+
+/// Collaborative transaction built combining the PSETs of several parties.
+///
+/// The confidential outputs of a party have as blinder index its first input, so that every
+/// party blinds its own outputs knowing only the secrets of its own inputs. All parties but the
+/// last one blind with `blind_own_outputs(.., false)`, the last one balances the blinding
+/// factors with `blind_own_outputs(.., true)`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CoinjoinPset {
+    #[serde(with = "pset_hex")]
+    pset: PartiallySignedTransaction,
+    parties: Vec<CoinjoinParty>,
+}
+
+/// Combine the PSETs of the parties appending their inputs and outputs, in order.
+///
+/// Outputs already having a blinder index keep pointing to the same input of their party. The
+/// global fields of the PSETs are merged, their transaction version and locktime must match.
+pub fn combine_psets(psets: Vec<PartiallySignedTransaction>) -> Result<CoinjoinPset, Error> {
+    let mut psets = psets.into_iter();
+    let mut combined = psets
+        .next()
+        .ok_or_else(|| Error::Generic("coinjoin: no psets to combine".into()))?;
+    let mut parties = vec![];
+    let first = set_blinder_indexes(&mut combined, 0)?;
+    parties.push(first);
+
+    for mut pset in psets {
+        let input_offset = combined.inputs.len();
+        let output_offset = combined.outputs.len();
+        let party = set_blinder_indexes(&mut pset, input_offset)?;
+        merge_global(&mut combined, &mut pset)?;
+        for input in pset.inputs {
+            combined.add_input(input);
+        }
+        for output in pset.outputs {
+            combined.add_output(output);
+        }
+        parties.push(CoinjoinParty {
+            inputs: party.inputs.iter().map(|i| i + input_offset).collect(),
+            outputs: party.outputs.iter().map(|i| i + output_offset).collect(),
+        });
+    }
+
+    Ok(CoinjoinPset {
+        pset: combined,
+        parties,
+    })
+}
+
+/// Move the global fields of `pset` to `combined`, keeping the ones of `combined` on conflicts
+fn merge_global(
+    combined: &mut PartiallySignedTransaction,
+    pset: &mut PartiallySignedTransaction,
+) -> Result<(), Error> {
+    let (global, other) = (&mut combined.global, &mut pset.global);
+    if global.tx_data.version != other.tx_data.version
+        || global.tx_data.fallback_locktime != other.tx_data.fallback_locktime
+    {
+        return Err(Error::Generic(
+            "coinjoin: psets with different transaction version or locktime".into(),
+        ));
+    }
+    for (key, value) in std::mem::take(&mut other.xpub) {
+        global.xpub.entry(key).or_insert(value);
+    }
+    for scalar in std::mem::take(&mut other.scalars) {
+        if !global.scalars.contains(&scalar) {
+            global.scalars.push(scalar);
+        }
+    }
+    for (key, value) in std::mem::take(&mut other.proprietary) {
+        global.proprietary.entry(key).or_insert(value);
+    }
+    for (key, value) in std::mem::take(&mut other.unknown) {
+        global.unknown.entry(key).or_insert(value);
+    }
+    Ok(())
+}
+
+/// Point the outputs blinder indexes of `pset`, which will be appended after `input_offset`
+/// inputs, to the inputs of the same party
+fn set_blinder_indexes(
+    pset: &mut PartiallySignedTransaction,
+    input_offset: usize,
+) -> Result<CoinjoinParty, Error> {
+    if pset.inputs.is_empty() {
+        return Err(Error::Generic("coinjoin: party without inputs".into()));
+    }
+    let n_inputs = pset.inputs.len();
+    for output in pset.outputs.iter_mut() {
+        let index = match output.blinder_index {
+            Some(index) if index as usize >= n_inputs => {
+                return Err(Error::Generic("coinjoin: invalid blinder index".into()))
+            }
+            Some(index) => index as usize,
+            None => 0,
+        };
+        if output.blinding_key.is_some() {
+            output.blinder_index = Some((index + input_offset) as u32);
+        }
+    }
+    Ok(CoinjoinParty {
+        inputs: (0..n_inputs).collect(),
+        outputs: (0..pset.outputs.len()).collect(),
+    })
+}
+
+impl CoinjoinPset {
+    pub fn pset(&self) -> &PartiallySignedTransaction {
+        &self.pset
+    }
+
+    pub fn into_pset(self) -> PartiallySignedTransaction {
+        self.pset
+    }
+
+    /// Parties in the order their PSETs were combined
+    pub fn parties(&self) -> &[CoinjoinParty] {
+        &self.parties
+    }
+
+    /// Blind the confidential outputs of `party`, `secrets` are the secrets of its inputs in
+    /// order. The `last` party also balances the blinding factors of the transaction.
+    pub fn blind_own_outputs(
+        &mut self,
+        secp: &Secp256k1<All>,
+        party: usize,
+        secrets: &[elements::TxOutSecrets],
+        last: bool,
+    ) -> Result<(), Error> {
+        let party = self
+            .parties
+            .get(party)
+            .ok_or_else(|| Error::Generic("coinjoin: unknown party".into()))?;
+        if secrets.len() != party.inputs.len() {
+            return Err(Error::Generic(
+                "coinjoin: secrets do not match the party inputs".into(),
+            ));
+        }
+        let mut inp_txout_sec = vec![None; self.pset.inputs.len()];
+        for (&i, secret) in party.inputs.iter().zip(secrets.iter()) {
+            inp_txout_sec[i] = Some(secret);
+        }
+
+        let mut rng = rand::thread_rng();
+        if last {
+            self.pset.blind_last(&mut rng, secp, &inp_txout_sec[..])?;
+        } else {
+            self.pset
+                .blind_non_last(&mut rng, secp, &inp_txout_sec[..])?;
+        }
+        Ok(())
+    }
+
+    pub fn extract_tx(&self) -> Result<elements::Transaction, Error> {
+        Ok(self.pset.extract_tx()?)
+    }
+}
+
+/// Check the value commitments of `tx` balance, `spent` are the previous outputs in the order of
+/// the inputs. Explicit values, such as the fee, are committed with a zero blinding factor.
+pub fn verify_blinding(
+    secp: &Secp256k1<All>,
+    tx: &elements::Transaction,
+    spent: &[elements::TxOut],
+) -> Result<(), Error> {
+    if spent.len() != tx.input.len() {
+        return Err(Error::Generic(
+            "coinjoin: previous outputs do not match the inputs".into(),
+        ));
+    }
+    if tx.input.iter().any(|i| i.has_issuance || i.is_pegin) {
+        return Err(Error::Generic(
+            "coinjoin: issuances and pegins are not supported".into(),
+        ));
+    }
+
+    let inputs = spent
+        .iter()
+        .map(|o| value_commitment(secp, o))
+        .collect::<Result<Vec<_>, _>>()?;
+    let outputs = tx
+        .output
+        .iter()
+        .map(|o| value_commitment(secp, o))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !secp256k1_zkp::verify_commitments_sum_to_equal(secp, &inputs, &outputs) {
+        return Err(Error::Generic("coinjoin: blinding does not balance".into()));
+    }
+    Ok(())
+}
+
+fn value_commitment(
+    secp: &Secp256k1<All>,
+    txout: &elements::TxOut,
+) -> Result<secp256k1_zkp::PedersenCommitment, Error> {
+    match (txout.asset, txout.value) {
+        (_, Value::Confidential(commitment)) => Ok(commitment),
+        (Asset::Explicit(asset), Value::Explicit(value)) => {
+            let asset_tag = secp256k1_zkp::Tag::from(asset.into_inner().into_inner());
+            let generator = secp256k1_zkp::Generator::new_unblinded(secp, asset_tag);
+            Ok(secp256k1_zkp::PedersenCommitment::new_unblinded(
+                secp, value, generator,
+            ))
+        }
+        _ => Err(Error::Generic("coinjoin: unexpected output".into())),
+    }
+}
+
+/// Serialize the PSET as the hex of its consensus encoding
+mod pset_hex {
+    use elements::pset::PartiallySignedTransaction;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        pset: &PartiallySignedTransaction,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(elements::encode::serialize(pset)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PartiallySignedTransaction, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&s).map_err(D::Error::custom)?;
+        elements::encode::deserialize(&bytes).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::add_input;
+    use elements::bitcoin::hashes::Hash;
+    use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+
+    fn explicit_txout(asset: elements::issuance::AssetId, value: u64) -> elements::TxOut {
+        elements::TxOut {
+            asset: Asset::Explicit(asset),
+            value: Value::Explicit(value),
+            ..Default::default()
+        }
+    }
+
+    /// PSET of a party spending `spent` with an output blinded to the key `n` for each of
+    /// `values`, and an explicit fee output of `fee` if not zero
+    fn party_pset(
+        secp: &Secp256k1<All>,
+        n: u8,
+        spent: &elements::TxOut,
+        values: &[u64],
+        fee: u64,
+    ) -> PartiallySignedTransaction {
+        let asset = match spent.asset {
+            Asset::Explicit(asset) => asset,
+            _ => unreachable!(),
+        };
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: values
+                .iter()
+                .map(|value| elements::TxOut {
+                    script_pubkey: elements::Script::from(vec![0x51]),
+                    ..explicit_txout(asset, *value)
+                })
+                .collect(),
+        };
+        if fee > 0 {
+            tx.output.push(explicit_txout(asset, fee));
+        }
+        add_input(
+            &mut tx,
+            elements::OutPoint::new(elements::Txid::from_slice(&[n; 32]).unwrap(), 0),
+        );
+        let mut pset = PartiallySignedTransaction::from_tx(tx);
+        pset.inputs[0].witness_utxo = Some(spent.clone());
+        let key = secp256k1_zkp::SecretKey::from_slice(&[n; 32]).unwrap();
+        for output in pset.outputs.iter_mut().take(values.len()) {
+            output.blinding_key = Some(elements::bitcoin::PublicKey {
+                compressed: true,
+                key: secp256k1_zkp::PublicKey::from_secret_key(secp, &key),
+            });
+            output.blinder_index = Some(0);
+        }
+        pset
+    }
+
+    fn explicit_secrets(asset: elements::issuance::AssetId, value: u64) -> elements::TxOutSecrets {
+        elements::TxOutSecrets::new(
+            asset,
+            AssetBlindingFactor::zero(),
+            value,
+            ValueBlindingFactor::zero(),
+        )
+    }
+
+    #[test]
+    fn test_combine_psets() {
+        let secp = Secp256k1::new();
+        let asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        let spent1 = explicit_txout(asset, 1_000);
+        let spent2 = explicit_txout(asset, 2_000);
+        let mut pset1 = party_pset(&secp, 1, &spent1, &[1_000], 0);
+        let mut pset2 = party_pset(&secp, 2, &spent2, &[600, 1_300], 100);
+        let scalar = secp256k1_zkp::Tweak::from_slice(&[3u8; 32]).unwrap();
+        pset2.global.scalars.push(scalar);
+
+        assert!(combine_psets(vec![]).is_err());
+        let combined = combine_psets(vec![pset1.clone(), pset2.clone()]).unwrap();
+        assert_eq!(
+            combined.parties(),
+            &[
+                CoinjoinParty {
+                    inputs: vec![0],
+                    outputs: vec![0],
+                },
+                CoinjoinParty {
+                    inputs: vec![1],
+                    outputs: vec![1, 2, 3],
+                },
+            ]
+        );
+        let outputs = &combined.pset().outputs;
+        assert_eq!(outputs[0].blinder_index, Some(0));
+        assert_eq!(outputs[1].blinder_index, Some(1));
+        assert_eq!(outputs[2].blinder_index, Some(1));
+        assert_eq!(outputs[3].blinder_index, None);
+        // the global fields of every party are kept
+        assert_eq!(combined.pset().global.scalars, vec![scalar]);
+
+        let serialized = serde_json::to_string(&combined).unwrap();
+        let deserialized: CoinjoinPset = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.parties(), combined.parties());
+        assert_eq!(deserialized.pset(), combined.pset());
+
+        pset2.global.tx_data.fallback_locktime = Some(100);
+        assert!(combine_psets(vec![pset1.clone(), pset2]).is_err());
+        pset1.outputs[0].blinder_index = Some(1);
+        assert!(combine_psets(vec![pset1]).is_err());
+    }
+
+    #[test]
+    fn test_blind_own_outputs() {
+        let secp = Secp256k1::new();
+        let asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        let spent1 = explicit_txout(asset, 1_000);
+        let spent2 = explicit_txout(asset, 2_000);
+        let pset1 = party_pset(&secp, 1, &spent1, &[1_000], 0);
+        let pset2 = party_pset(&secp, 2, &spent2, &[600, 1_300], 100);
+        let mut combined = combine_psets(vec![pset1, pset2]).unwrap();
+
+        let secrets1 = [explicit_secrets(asset, 1_000)];
+        let secrets2 = [explicit_secrets(asset, 2_000)];
+        assert!(combined
+            .blind_own_outputs(&secp, 2, &secrets1, false)
+            .is_err());
+        assert!(combined.blind_own_outputs(&secp, 0, &[], false).is_err());
+        combined
+            .blind_own_outputs(&secp, 0, &secrets1, false)
+            .unwrap();
+        combined
+            .blind_own_outputs(&secp, 1, &secrets2, true)
+            .unwrap();
+
+        let tx = combined.extract_tx().unwrap();
+        assert!(tx.output[..3].iter().all(|o| o.value.is_confidential()));
+        assert!(tx.output[3].is_fee());
+        verify_blinding(&secp, &tx, &[spent1, spent2]).unwrap();
+    }
+
+    #[test]
+    fn test_verify_blinding() {
+        let secp = Secp256k1::new();
+        let asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![explicit_txout(asset, 900), explicit_txout(asset, 100)],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let spent = vec![explicit_txout(asset, 1_000)];
+        assert!(verify_blinding(&secp, &tx, &spent).is_ok());
+
+        let spent = vec![explicit_txout(asset, 1_001)];
+        assert!(verify_blinding(&secp, &tx, &spent).is_err());
+        assert!(verify_blinding(&secp, &tx, &[]).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::backend::{http_agent, BlockchainBackend, History};
+use crate::error::Error;
+use crate::model::FeeEstimate;
+
+use electrum_client::GetMerkleRes;
+use elements::bitcoin::hashes::hex::ToHex;
+use elements::bitcoin::hashes::{sha256d, Hash, HashEngine};
+use elements::{BlockHash, BlockHeader, Script, Txid};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Client for the JSON-RPC interface of a local elementsd node.
+///
+/// Script histories are found with `scantxoutset`, so only transactions having outputs still
+/// unspent are returned and unconfirmed transactions are not seen. The node must run with
+/// `-txindex` to fetch transactions with `getrawtransaction` and with `-blockfilterindex` to
+/// sync with block filters, which also sees spent outputs.
+pub struct ElementsRpcClient {
+    url: String,
+    auth: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ScanResult {
+    unspents: Vec<ScanUnspent>,
+}
+
+#[derive(Deserialize)]
+struct ScanUnspent {
+    txid: String,
+    #[serde(rename = "scriptPubKey")]
+    script_pubkey: String,
+    height: i32,
+}
+
+#[derive(Deserialize)]
+struct NetworkInfo {
+    relayfee: f64,
+}
+
+#[derive(Deserialize)]
+struct SmartFee {
+    feerate: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct BlockFilter {
+    filter: String,
+}
+
+#[derive(Deserialize)]
+struct Block {
+    tx: Vec<String>,
+}
+
+impl ElementsRpcClient {
+    pub fn new(
+        url: &str,
+        user: &str,
+        password: &str,
+        timeout: Option<u8>,
+        user_agent: Option<&str>,
+    ) -> Self {
+        Self::with_agent(url, user, password, http_agent(timeout, user_agent))
+    }
+
+    /// Client making the requests with `agent`
+    pub(crate) fn with_agent(url: &str, user: &str, password: &str, agent: ureq::Agent) -> Self {
+        ElementsRpcClient {
+            url: url.to_string(),
+            auth: format!("Basic {}", base64::encode(format!("{}:{}", user, password))),
+            agent,
+        }
+    }
+
+    fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, Error> {
+        let body = json!({"jsonrpc": "1.0", "id": "bewallet", "method": method, "params": params});
+        let response = match self
+            .agent
+            .post(&self.url)
+            .set("Authorization", &self.auth)
+            .send_string(&body.to_string())
+        {
+            Ok(response) => response,
+            // node returns rpc errors with an http error status, the reason is in the body
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(e.into()),
+        };
+        let response: RpcResponse<T> = serde_json::from_str(&response.into_string()?)?;
+        if let Some(error) = response.error {
+            return Err(Error::Generic(format!("{}: {}", method, error.message)));
+        }
+        response
+            .result
+            .ok_or_else(|| Error::Generic(format!("{}: empty result", method)))
+    }
+
+    fn block_hash(&self, height: u32) -> Result<String, Error> {
+        self.call("getblockhash", json!([height]))
+    }
+
+    fn header(&self, hash: &str) -> Result<BlockHeader, Error> {
+        let header_hex: String = self.call("getblockheader", json!([hash, false]))?;
+        Ok(elements::encode::deserialize(&hex::decode(header_hex)?)?)
+    }
+}
+
+/// Merkle branch of the transaction at `pos` in electrum format, nodes are in reversed byte order
+fn merkle_branch(mut level: Vec<[u8; 32]>, mut pos: usize) -> Vec<[u8; 32]> {
+    let mut branch = vec![];
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            let last = level[level.len() - 1];
+            level.push(last);
+        }
+        let mut sibling = level[pos ^ 1];
+        sibling.reverse();
+        branch.push(sibling);
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut engine = sha256d::Hash::engine();
+                engine.input(&pair[0]);
+                engine.input(&pair[1]);
+                sha256d::Hash::from_engine(engine).into_inner()
+            })
+            .collect();
+        pos /= 2;
+    }
+    branch
+}
+
+impl BlockchainBackend for ElementsRpcClient {
+    fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+        let height: u32 = self.call("getblockcount", json!([]))?;
+        let header = self.header(&self.block_hash(height)?)?;
+        Ok((height, header))
+    }
+
+    fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+        let descriptors: Vec<Value> = scripts
+            .iter()
+            .map(|s| json!({ "desc": format!("raw({})", s.to_hex()) }))
+            .collect();
+        let scan: ScanResult = self.call("scantxoutset", json!(["start", descriptors]))?;
+        let mut by_script: HashMap<String, Vec<History>> = HashMap::new();
+        for unspent in scan.unspents {
+            let history = by_script.entry(unspent.script_pubkey).or_default();
+            let txid = Txid::from_str(&unspent.txid)?;
+            // many outputs of the same transaction could pay the script
+            if history.iter().all(|h| h.txid != txid) {
+                history.push(History {
+                    txid,
+                    height: unspent.height,
+                });
+            }
+        }
+        Ok(scripts
+            .iter()
+            .map(|s| by_script.remove(&s.to_hex()).unwrap_or_default())
+            .collect())
+    }
+
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+        let mut txs = vec![];
+        for txid in txids {
+            let tx_hex: String = self.call("getrawtransaction", json!([txid.to_string()]))?;
+            txs.push(elements::encode::deserialize(&hex::decode(tx_hex)?)?);
+        }
+        Ok(txs)
+    }
+
+    fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+        let mut headers = vec![];
+        for height in heights {
+            headers.push(self.header(&self.block_hash(*height)?)?);
+        }
+        Ok(headers)
+    }
+
+    fn merkle_proof(&self, txid: &Txid, height: u32) -> Result<GetMerkleRes, Error> {
+        let block: Block = self.call("getblock", json!([self.block_hash(height)?, 1]))?;
+        let mut txids = vec![];
+        for tx in block.tx.iter() {
+            txids.push(Txid::from_str(tx)?.into_inner());
+        }
+        let pos = txids
+            .iter()
+            .position(|t| *t == txid.into_inner())
+            .ok_or_else(|| Error::Generic(format!("{} not in block {}", txid, height)))?;
+        Ok(GetMerkleRes {
+            block_height: height as usize,
+            merkle: merkle_branch(txids, pos),
+            pos,
+        })
+    }
+
+    fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error> {
+        let tx_hex = hex::encode(elements::encode::serialize(tx));
+        let _txid: String = self.call("sendrawtransaction", json!([tx_hex]))?;
+        Ok(())
+    }
+
+    fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+        // rates returned by the node are in BTC/kbyte
+        let info: NetworkInfo = self.call("getnetworkinfo", json!([]))?;
+        let relay_fee = (info.relayfee * 100_000_000.0) as u64;
+        let mut estimates = vec![FeeEstimate(relay_fee)];
+        for target in 1..25 {
+            let fee: SmartFee = self.call("estimatesmartfee", json!([target]))?;
+            let fee = fee.feerate.map(|f| (f * 100_000_000.0) as u64).unwrap_or(0);
+            estimates.push(FeeEstimate(relay_fee.max(fee)));
+        }
+        Ok(estimates)
+    }
+
+    fn block_filter(&self, height: u32) -> Result<(BlockHash, Vec<u8>), Error> {
+        let hash = self.block_hash(height)?;
+        let filter: BlockFilter = self.call("getblockfilter", json!([hash, "basic"]))?;
+        Ok((BlockHash::from_str(&hash)?, hex::decode(filter.filter)?))
+    }
+
+    fn block(&self, height: u32) -> Result<elements::Block, Error> {
+        let block_hex: String = self.call("getblock", json!([self.block_hash(height)?, 0]))?;
+        Ok(elements::encode::deserialize(&hex::decode(block_hex)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::compute_merkle_root;
+    use elements::TxMerkleNode;
+
+    #[test]
+    fn test_merkle_branch() {
+        let txids: Vec<[u8; 32]> = (0u8..5).map(|i| [i; 32]).collect();
+        let root = {
+            let mut level = txids.clone();
+            while level.len() > 1 {
+                if level.len() % 2 == 1 {
+                    level.push(level[level.len() - 1]);
+                }
+                level = level
+                    .chunks(2)
+                    .map(|p| sha256d::Hash::hash(&[p[0], p[1]].concat()).into_inner())
+                    .collect();
+            }
+            TxMerkleNode::from_slice(&level[0]).unwrap()
+        };
+        for pos in 0..txids.len() {
+            let merkle = GetMerkleRes {
+                block_height: 1,
+                merkle: merkle_branch(txids.clone(), pos),
+                pos,
+            };
+            let txid = Txid::from_slice(&txids[pos]).unwrap();
+            assert_eq!(compute_merkle_root(&txid, merkle).unwrap(), root);
+        }
+    }
+}
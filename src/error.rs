@@ -15,10 +15,69 @@ pub enum Error {
     UnknownCall,
     InvalidMnemonic(bip39::Error),
     InsufficientFunds,
+    /// Coin selection could not cover `missing` satoshi of `asset`, assuming `estimated_fee`
+    InsufficientAssetFunds {
+        asset: elements::issuance::AssetId,
+        missing: u64,
+        estimated_fee: u64,
+    },
     InvalidAmount,
+    /// A sum of amounts exceeds the maximum `u64`
+    AmountOverflow,
+    /// The inputs of a transaction do not cover its outputs and fee of the asset
+    Unbalanced(elements::issuance::AssetId),
+    /// The output spent by a transaction input is not in the wallet cache or cannot be unblinded
+    MissingPreviousOutput(elements::OutPoint),
+    /// Not the hex of a 32 bytes asset id, or an asset not existing on the network
+    InvalidAsset(String),
     EmptyAddressees,
     AssetEmpty,
     InvalidHeaders,
+    NetworkMismatch,
+    InvalidStore,
+    /// The store directory is in use by another wallet, see `Config::ignore_store_lock`
+    StoreLocked(std::path::PathBuf),
+    /// Requests to the server are not sent after too many failures, see `RetryPolicy`
+    CircuitOpen(String),
+    /// An HTTP request did not get a response, for instance the connection failed or timed out
+    HttpTransport(String),
+    /// Sha256 of a TLS server certificate not in `Config::tls_pins`
+    CertificatePinMismatch(elements::bitcoin::hashes::sha256::Hash),
+    InvalidSignature,
+    /// Sighash byte of an undefined type or with flags not allowed on the network
+    InvalidSigHashType(u8),
+    /// Creating the proofs of a transaction failed `attempts` times, the last time at output
+    /// `output` if known
+    BlindingFailed {
+        output: Option<usize>,
+        attempts: u8,
+        reason: String,
+    },
+    /// The outpoint is not an unspent output of the wallet
+    UtxoNotFound(elements::OutPoint),
+    /// The rangeproof of the output cannot be rewound with the wallet blinding keys
+    CannotUnblind(elements::OutPoint),
+    /// The LiquiDEX rate gives a received amount that is zero or above the maximum money
+    RateOutOfRange(f64),
+    Cancelled,
+    /// The `SignApprover` rejected signing, for the given reason
+    SigningRejected(String),
+    /// The `SignApprover` delayed signing, which can be retried later
+    SigningDelayed,
+    UnknownAsset(elements::issuance::AssetId),
+    FeeAssetUnsupported(elements::issuance::AssetId),
+    InvalidLockTime(u32),
+    FeeRateTooLow(u64),
+    /// Fee rate in satoshi per 1000 vbytes outside of the `Config` bounds `min..=max`
+    FeeRateOutOfBounds {
+        fee_rate: u64,
+        min: u64,
+        max: u64,
+    },
+    TransactionTooLarge(usize),
+    DustOutput(usize),
+    NonStandardScript(usize),
+    ReissuanceToken(elements::issuance::AssetId),
     SendAll,
     AddrParse(String),
     Bitcoin(elements::bitcoin::util::Error),
@@ -51,10 +110,92 @@ impl Display for Error {
                 write!(f, "invalid mnemonic: {}", mnemonic_err)
             }
             Error::InsufficientFunds => write!(f, "insufficient funds"),
+            Error::InsufficientAssetFunds {
+                asset,
+                missing,
+                estimated_fee,
+            } => write!(
+                f,
+                "insufficient funds: missing {} of asset {}, estimated fee {}",
+                missing, asset, estimated_fee
+            ),
             Error::SendAll => write!(f, "sendall error"),
             Error::InvalidAddress => write!(f, "invalid address"),
             Error::InvalidAmount => write!(f, "invalid amount"),
+            Error::AmountOverflow => write!(f, "amount overflow"),
+            Error::Unbalanced(ref asset) => {
+                write!(f, "inputs do not cover outputs and fee of asset {}", asset)
+            }
+            Error::MissingPreviousOutput(ref outpoint) => {
+                write!(f, "missing previous output {}", outpoint)
+            }
+            Error::InvalidAsset(asset) => write!(f, "invalid asset {}", asset),
             Error::InvalidHeaders => write!(f, "invalid headers"),
+            Error::NetworkMismatch => write!(f, "store was created for a different network"),
+            Error::InvalidStore => write!(f, "store scripts do not match the wallet keys"),
+            Error::StoreLocked(ref path) => {
+                write!(f, "store {} is in use by another wallet", path.display())
+            }
+            Error::CertificatePinMismatch(ref hash) => {
+                write!(f, "TLS certificate {} is not pinned", hash)
+            }
+            Error::CircuitOpen(ref server) => {
+                write!(f, "too many failed requests to {}, retry later", server)
+            }
+            Error::HttpTransport(ref err) => write!(f, "http: {}", err),
+            Error::InvalidSignature => write!(f, "invalid signature"),
+            Error::InvalidSigHashType(n) => write!(f, "invalid sighash type {:#04x}", n),
+            Error::BlindingFailed {
+                output,
+                attempts,
+                reason,
+            } => match output {
+                Some(vout) => write!(
+                    f,
+                    "blinding output {} failed after {} attempts: {}",
+                    vout, attempts, reason
+                ),
+                None => write!(f, "blinding failed after {} attempts: {}", attempts, reason),
+            },
+            Error::UtxoNotFound(ref outpoint) => write!(f, "utxo {} not found", outpoint),
+            Error::CannotUnblind(ref outpoint) => write!(f, "cannot unblind {}", outpoint),
+            Error::RateOutOfRange(rate) => write!(f, "rate {} is out of range", rate),
+            Error::Cancelled => write!(f, "operation cancelled"),
+            Error::SigningRejected(ref reason) => write!(f, "signing rejected: {}", reason),
+            Error::SigningDelayed => write!(f, "signing delayed, retry later"),
+            Error::UnknownAsset(ref asset) => write!(f, "unknown asset {}", asset),
+            Error::FeeAssetUnsupported(ref asset) => {
+                write!(f, "fees cannot be paid in asset {}", asset)
+            }
+            Error::InvalidLockTime(lock_time) => write!(f, "invalid locktime {}", lock_time),
+            Error::ReissuanceToken(ref token) => {
+                write!(
+                    f,
+                    "{} is a reissuance token, spending it must be allowed",
+                    token
+                )
+            }
+            Error::FeeRateTooLow(fee_rate) => {
+                write!(
+                    f,
+                    "fee rate {} sat/kvB is below the minimum relay fee",
+                    fee_rate
+                )
+            }
+            Error::FeeRateOutOfBounds { fee_rate, min, max } => {
+                write!(
+                    f,
+                    "fee rate {} sat/kvB is outside of the allowed range {}..={}",
+                    fee_rate, min, max
+                )
+            }
+            Error::TransactionTooLarge(vbytes) => {
+                write!(f, "transaction of {} vbytes is too large", vbytes)
+            }
+            Error::DustOutput(vout) => write!(f, "output {} is dust", vout),
+            Error::NonStandardScript(vout) => {
+                write!(f, "output {} has a non standard script", vout)
+            }
             Error::EmptyAddressees => write!(f, "addressees cannot be empty"),
             Error::AssetEmpty => write!(f, "asset_tag cannot be empty in liquid"),
             Error::UnknownCall => write!(f, "unknown call"),
@@ -115,6 +256,25 @@ impl_error!(serde_cbor::error::Error);
 impl_error!(elements::bitcoin::hashes::hex::Error);
 impl_error!(std::string::FromUtf8Error);
 impl_error!(elements::bitcoin::util::key::Error);
+impl_error!(elements::bitcoin::util::bip158::Error);
+#[cfg(feature = "sled")]
+impl_error!(sled::Error);
+#[cfg(any(
+    feature = "esplora",
+    feature = "elementsd",
+    feature = "webhooks",
+    feature = "payjoin",
+    feature = "registry",
+    feature = "remote-signer"
+))]
+impl From<ureq::Error> for Error {
+    fn from(err: ureq::Error) -> Self {
+        match err {
+            ureq::Error::Transport(transport) => Error::HttpTransport(transport.to_string()),
+            err => Error::Generic(err.to_string()),
+        }
+    }
+}
 
 impl From<std::array::TryFromSliceError> for Error {
     fn from(err: std::array::TryFromSliceError) -> Self {
@@ -0,0 +1,252 @@
+use crate::backend::{http_agent, BlockchainBackend, History};
+use crate::error::Error;
+use crate::model::FeeEstimate;
+
+use electrum_client::GetMerkleRes;
+use elements::bitcoin::hashes::{sha256, Hash};
+use elements::{BlockHeader, Script, Txid};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Esplora returns 25 confirmed transactions per page in the scripthash history
+const CHAIN_TXS_PER_PAGE: usize = 25;
+
+/// Liquid minimum relay fee, in satoshi/kbyte, esplora does not return it
+const MIN_RELAY_FEE: u64 = 100;
+
+/// Client for the esplora HTTP API, such as https://blockstream.info/liquid/api
+pub struct EsploraClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct EsploraTx {
+    txid: String,
+    status: EsploraTxStatus,
+}
+
+#[derive(Deserialize)]
+struct EsploraTxStatus {
+    block_height: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct EsploraMerkleProof {
+    block_height: usize,
+    merkle: Vec<String>,
+    pos: usize,
+}
+
+impl EsploraMerkleProof {
+    fn into_merkle_res(self) -> Result<GetMerkleRes, Error> {
+        let mut merkle = vec![];
+        for hash in self.merkle {
+            let bytes = hex::decode(hash)?;
+            if bytes.len() != 32 {
+                return Err(Error::Generic("invalid merkle proof node".into()));
+            }
+            let mut node = [0u8; 32];
+            node.copy_from_slice(&bytes);
+            merkle.push(node);
+        }
+        Ok(GetMerkleRes {
+            block_height: self.block_height,
+            merkle,
+            pos: self.pos,
+        })
+    }
+}
+
+/// Electrum style script hash, sha256 of the script in reverse byte order
+fn script_hash(script: &Script) -> String {
+    let mut script_hash = sha256::Hash::hash(script.as_bytes()).into_inner();
+    script_hash.reverse();
+    hex::encode(script_hash)
+}
+
+/// Fee estimates by confirmation target in satoshi/kbyte, as the other backends, from the esplora
+/// ones in satoshi/vbyte, never below the minimum relay fee
+fn fee_estimates(estimates: &HashMap<String, f64>) -> Vec<FeeEstimate> {
+    let mut result = vec![FeeEstimate(MIN_RELAY_FEE)];
+    for target in 1..25 {
+        let estimate = estimates
+            .get(&target.to_string())
+            .map(|e| (*e * 1000.0) as u64)
+            .unwrap_or(MIN_RELAY_FEE);
+        result.push(FeeEstimate(MIN_RELAY_FEE.max(estimate)));
+    }
+    result
+}
+
+impl EsploraClient {
+    pub fn new(url: &str, timeout: Option<u8>, user_agent: Option<&str>) -> Self {
+        Self::with_agent(url, http_agent(timeout, user_agent))
+    }
+
+    /// Client making the requests with `agent`
+    pub(crate) fn with_agent(url: &str, agent: ureq::Agent) -> Self {
+        EsploraClient {
+            url: url.trim_end_matches('/').to_string(),
+            agent,
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<String, Error> {
+        let url = format!("{}{}", self.url, path);
+        Ok(self.agent.get(&url).call()?.into_string()?)
+    }
+
+    fn get_header(&self, hash: &str) -> Result<BlockHeader, Error> {
+        let header_hex = self.get(&format!("/block/{}/header", hash))?;
+        Ok(elements::encode::deserialize(&hex::decode(
+            header_hex.trim(),
+        )?)?)
+    }
+
+    fn script_history(&self, script: &Script) -> Result<Vec<History>, Error> {
+        let script_hash = script_hash(script);
+
+        let mut history = vec![];
+        let mut path = format!("/scripthash/{}/txs", script_hash);
+        loop {
+            let txs: Vec<EsploraTx> = serde_json::from_str(&self.get(&path)?)?;
+            let mut confirmed = 0;
+            for tx in txs.iter() {
+                let height = tx.status.block_height.unwrap_or(0);
+                if height > 0 {
+                    confirmed += 1;
+                }
+                history.push(History {
+                    txid: Txid::from_str(&tx.txid)?,
+                    height,
+                });
+            }
+            match txs.last() {
+                Some(last) if confirmed == CHAIN_TXS_PER_PAGE => {
+                    path = format!("/scripthash/{}/txs/chain/{}", script_hash, last.txid);
+                }
+                _ => break,
+            }
+        }
+        Ok(history)
+    }
+}
+
+impl BlockchainBackend for EsploraClient {
+    fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+        let hash = self.get("/blocks/tip/hash")?;
+        let header = self.get_header(hash.trim())?;
+        Ok((header.height, header))
+    }
+
+    fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+        scripts.iter().map(|s| self.script_history(s)).collect()
+    }
+
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+        let mut txs = vec![];
+        for txid in txids {
+            let tx_hex = self.get(&format!("/tx/{}/hex", txid))?;
+            txs.push(elements::encode::deserialize(&hex::decode(tx_hex.trim())?)?);
+        }
+        Ok(txs)
+    }
+
+    fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+        let mut headers = vec![];
+        for height in heights {
+            let hash = self.get(&format!("/block-height/{}", height))?;
+            headers.push(self.get_header(hash.trim())?);
+        }
+        Ok(headers)
+    }
+
+    fn merkle_proof(&self, txid: &Txid, _height: u32) -> Result<GetMerkleRes, Error> {
+        let proof: EsploraMerkleProof =
+            serde_json::from_str(&self.get(&format!("/tx/{}/merkle-proof", txid))?)?;
+        proof.into_merkle_res()
+    }
+
+    fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error> {
+        let url = format!("{}/tx", self.url);
+        self.agent
+            .post(&url)
+            .send_string(&hex::encode(elements::encode::serialize(tx)))?;
+        Ok(())
+    }
+
+    fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+        let estimates: HashMap<String, f64> = serde_json::from_str(&self.get("/fee-estimates")?)?;
+        Ok(fee_estimates(&estimates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_esplora_client_url() {
+        let client = EsploraClient::new("https://blockstream.info/liquid/api/", None, None);
+        assert_eq!(client.url, "https://blockstream.info/liquid/api");
+    }
+
+    #[test]
+    fn test_script_hash() {
+        assert_eq!(
+            script_hash(&Script::new()),
+            "55b852781b9995a44c939b64e441ae2724b96f99c8f4fb9a141cfc9842c4b0e3"
+        );
+    }
+
+    #[test]
+    fn test_esplora_tx() {
+        let txs: Vec<EsploraTx> = serde_json::from_str(
+            r#"[
+                {"txid": "0000000000000000000000000000000000000000000000000000000000000001",
+                 "status": {"confirmed": false}},
+                {"txid": "0000000000000000000000000000000000000000000000000000000000000002",
+                 "status": {"confirmed": true, "block_height": 1000}}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(txs[0].status.block_height, None);
+        assert_eq!(txs[1].status.block_height, Some(1000));
+    }
+
+    #[test]
+    fn test_fee_estimates() {
+        let estimates: HashMap<String, f64> =
+            serde_json::from_str(r#"{"1": 0.25, "2": 0.1, "6": 0.05}"#).unwrap();
+        let estimates = fee_estimates(&estimates);
+        assert_eq!(estimates.len(), 25);
+        assert_eq!(estimates[0].0, MIN_RELAY_FEE);
+        assert_eq!(estimates[1].0, 250);
+        assert_eq!(estimates[2].0, MIN_RELAY_FEE);
+        assert_eq!(estimates[3].0, MIN_RELAY_FEE);
+        assert_eq!(estimates[24].0, MIN_RELAY_FEE);
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        let node = "11".repeat(32);
+        let proof: EsploraMerkleProof = serde_json::from_str(&format!(
+            r#"{{"block_height": 1000, "merkle": ["{}"], "pos": 3}}"#,
+            node
+        ))
+        .unwrap();
+        let res = proof.into_merkle_res().unwrap();
+        assert_eq!(res.block_height, 1000);
+        assert_eq!(res.merkle, vec![[0x11u8; 32]]);
+        assert_eq!(res.pos, 3);
+
+        let proof = EsploraMerkleProof {
+            block_height: 1000,
+            merkle: vec!["11".to_string()],
+            pos: 0,
+        };
+        assert!(proof.into_merkle_res().is_err());
+    }
+}
@@ -12,17 +12,21 @@ use elements::script::Instruction;
 use elements::TxMerkleNode;
 use elements::{opcodes, script};
 use elements::{BlockHash, Script, Txid};
-use log::info;
+use tracing::info;
 
+use std::collections::HashMap;
 use std::io::Write;
 
 /// liquid v1 block header verifier, not suitable for dynafed
 /// checks the challenge is exactly equal to the one present in block 1
 /// checks the solution script against the challenge, verifying signatures
+/// headers at a checkpoint height are checked against the checkpoint hash only
 pub struct Verifier {
     secp: Secp256k1<VerifyOnly>,
     challenge: Script,
     genesis: BlockHash,
+    checkpoints: HashMap<u32, BlockHash>,
+    use_checkpoints: bool,
     is_regtest: bool,
 }
 
@@ -32,8 +36,22 @@ const LIQUID_GENESIS_HASH: &'static str =
 const ELEMENTS_REGTEST_GENESIS_HASH: &'static str =
     "209577bda6bf4b5804bd46f8621580dd6d4e8bfa2d190e1c50e932492baca07d";
 
+/// (height, block hash) of known liquid blocks, only the first blocks of the chain: they do not
+/// spare the signature verification of recent headers, recent checkpoints are given with
+/// `Config::spv_extra_checkpoints`
+const LIQUID_CHECKPOINTS: &[(u32, &str)] = &[
+    (0, LIQUID_GENESIS_HASH),
+    (
+        1,
+        "afafbbdfc52a45e51a3b634f391f952f6bdfd14ef74b34925954b4e20d0ad639",
+    ),
+];
+
 /// compute the merkle root from the merkle path of a tx in electrum format (note the hash.reverse())
-fn compute_merkle_root(txid: &Txid, merkle: GetMerkleRes) -> Result<TxMerkleNode, Error> {
+pub(crate) fn compute_merkle_root(
+    txid: &Txid,
+    merkle: GetMerkleRes,
+) -> Result<TxMerkleNode, Error> {
     let mut pos = merkle.pos;
     let mut current = txid.into_inner();
 
@@ -55,15 +73,26 @@ fn compute_merkle_root(txid: &Txid, merkle: GetMerkleRes) -> Result<TxMerkleNode
 }
 
 impl Verifier {
-    pub fn new(network: ElementsNetwork) -> Self {
-        let (is_regtest, genesis_hash) = match network {
-            ElementsNetwork::Liquid => (false, LIQUID_GENESIS_HASH),
-            ElementsNetwork::ElementsRegtest => (true, ELEMENTS_REGTEST_GENESIS_HASH),
+    /// if `use_checkpoints` is false, headers are always verified with the block signatures
+    pub fn new(network: ElementsNetwork, use_checkpoints: bool) -> Self {
+        let (is_regtest, genesis_hash, checkpoints) = match network {
+            ElementsNetwork::Liquid => (false, LIQUID_GENESIS_HASH, LIQUID_CHECKPOINTS),
+            ElementsNetwork::ElementsRegtest => (true, ELEMENTS_REGTEST_GENESIS_HASH, &[][..]),
+        };
+        let checkpoints = if use_checkpoints {
+            checkpoints
+                .iter()
+                .map(|(height, hash)| (*height, BlockHash::from_hex(hash).unwrap()))
+                .collect()
+        } else {
+            HashMap::new()
         };
         Verifier {
             secp: Secp256k1::verification_only(),
             challenge: Script::from(hex::decode(CHALLENGE).unwrap()),
             genesis: BlockHash::from_hex(genesis_hash).unwrap(),
+            checkpoints,
+            use_checkpoints,
             is_regtest,
         }
     }
@@ -88,14 +117,34 @@ impl Verifier {
         }
     }
 
+    /// Also check the headers at the heights of `checkpoints` against their hashes, replacing the
+    /// embedded checkpoints at the same heights, ignored if the verifier does not use checkpoints
+    pub fn with_checkpoints(mut self, checkpoints: &[(u32, BlockHash)]) -> Self {
+        if self.use_checkpoints {
+            self.checkpoints.extend(checkpoints.iter().cloned());
+        }
+        self
+    }
+
     /// verify the given liquid header
-    fn verify_header(&self, header: &elements::BlockHeader) -> Result<(), Error> {
+    pub fn verify_header(&self, header: &elements::BlockHeader) -> Result<(), Error> {
         let mut stack = vec![];
         let hash = header.block_hash();
         if hash == self.genesis || self.is_regtest {
             // TODO add regtest verification
             return Ok(());
         }
+        if let Some(checkpoint) = self.checkpoints.get(&header.height) {
+            return if *checkpoint == hash {
+                info!(
+                    "block {} matches checkpoint at height {}",
+                    hash, header.height
+                );
+                Ok(())
+            } else {
+                Err(Error::InvalidHeaders)
+            };
+        }
 
         match &header.ext {
             BlockExtData::Proof {
@@ -214,9 +263,37 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_liquid_checkpoints() {
+        let verifier = Verifier::new(ElementsNetwork::Liquid, true);
+
+        // liquid block 1
+        let block_header : elements::BlockHeader = deserialize(&hex::decode("000000200360208a889692372c8d68b084a62efdf60ea1a359a04c94b20d223658276614c8a804bd8a3f6bcfa6f6dc06e596b9b3cab6b57e357185b0e8d0ca3d9da327f25b32ac5b01000000fd01025b21026a2a106ec32c8a1e8052e5d02a7b0a150423dbd9b116fc48d46630ff6e6a05b92102791646a8b49c2740352b4495c118d876347bf47d0551c01c4332fdc2df526f1a2102888bda53a424466b0451627df22090143bbf7c060e9eacb1e38426f6b07f2ae12102aee8967150dee220f613de3b239320355a498808084a93eaf39a34dcd62024852102d46e9259d0a0bb2bcbc461a3e68f34adca27b8d08fbe985853992b4b104e27412102e9944e35e5750ab621e098145b8e6cf373c273b7c04747d1aa020be0af40ccd62102f9a9d4b10a6d6c56d8c955c547330c589bb45e774551d46d415e51cd9ad5116321033b421566c124dfde4db9defe4084b7aa4e7f36744758d92806b8f72c2e943309210353dcc6b4cf6ad28aceb7f7b2db92a4bf07ac42d357adf756f3eca790664314b621037f55980af0455e4fb55aad9b85a55068bb6dc4740ea87276dc693f4598db45fa210384001daa88dabd23db878dbb1ce5b4c2a5fa72c3113e3514bf602325d0c37b8e21039056d089f2fe72dbc0a14780b4635b0dc8a1b40b7a59106325dd1bc45cc70493210397ab8ea7b0bf85bc7fc56bb27bf85e75502e94e76a6781c409f3f2ec3d1122192103b00e3b5b77884bf3cae204c4b4eac003601da75f96982ffcb3dcb29c5ee419b92103c1f3c0874cfe34b8131af34699589aacec4093399739ae352e8a46f80a6f68375fae00").unwrap()).unwrap();
+        assert!(
+            verifier.verify_header(&block_header).is_ok(),
+            "header matching a checkpoint does not need the solution"
+        );
+
+        let mut wrong_header = block_header.clone();
+        wrong_header.time += 1;
+        assert!(verifier.verify_header(&wrong_header).is_err());
+
+        let verifier = Verifier::new(ElementsNetwork::Liquid, false);
+        assert!(verifier.verify_header(&block_header).is_err());
+
+        // checkpoints given by the caller replace the embedded ones
+        let verifier = Verifier::new(ElementsNetwork::Liquid, true)
+            .with_checkpoints(&[(1, wrong_header.block_hash())]);
+        assert!(verifier.verify_header(&block_header).is_err());
+        assert!(verifier.verify_header(&wrong_header).is_ok());
+        let verifier = Verifier::new(ElementsNetwork::Liquid, false)
+            .with_checkpoints(&[(1, block_header.block_hash())]);
+        assert!(verifier.verify_header(&block_header).is_err());
+    }
+
     #[test]
     fn test_liquid() {
-        let verifier = Verifier::new(ElementsNetwork::Liquid);
+        let verifier = Verifier::new(ElementsNetwork::Liquid, false);
 
         // liquid block genesis
         let mut genesis_header : elements::BlockHeader = deserialize(&hex::decode("010000000000000000000000000000000000000000000000000000000000000000000000d767f204777d8ebd0825f4f26c3d773c0d3f40268dc6afb3632a0fcbd49fde45dae5494d00000000fd01025b21026a2a106ec32c8a1e8052e5d02a7b0a150423dbd9b116fc48d46630ff6e6a05b92102791646a8b49c2740352b4495c118d876347bf47d0551c01c4332fdc2df526f1a2102888bda53a424466b0451627df22090143bbf7c060e9eacb1e38426f6b07f2ae12102aee8967150dee220f613de3b239320355a498808084a93eaf39a34dcd62024852102d46e9259d0a0bb2bcbc461a3e68f34adca27b8d08fbe985853992b4b104e27412102e9944e35e5750ab621e098145b8e6cf373c273b7c04747d1aa020be0af40ccd62102f9a9d4b10a6d6c56d8c955c547330c589bb45e774551d46d415e51cd9ad5116321033b421566c124dfde4db9defe4084b7aa4e7f36744758d92806b8f72c2e943309210353dcc6b4cf6ad28aceb7f7b2db92a4bf07ac42d357adf756f3eca790664314b621037f55980af0455e4fb55aad9b85a55068bb6dc4740ea87276dc693f4598db45fa210384001daa88dabd23db878dbb1ce5b4c2a5fa72c3113e3514bf602325d0c37b8e21039056d089f2fe72dbc0a14780b4635b0dc8a1b40b7a59106325dd1bc45cc70493210397ab8ea7b0bf85bc7fc56bb27bf85e75502e94e76a6781c409f3f2ec3d1122192103b00e3b5b77884bf3cae204c4b4eac003601da75f96982ffcb3dcb29c5ee419b92103c1f3c0874cfe34b8131af34699589aacec4093399739ae352e8a46f80a6f68375fae00").unwrap()).unwrap();
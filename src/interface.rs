@@ -1,47 +1,93 @@
+use crate::amount::{Amount, FeeRate};
+use crate::approval::{SignApproval, SignApprover, SignKind, SignRequest};
 use crate::model::{GetTransactionsOpt, SPVVerifyResult};
+use crate::redact::redact;
 use elements;
-use elements::bitcoin::hashes::hex::ToHex;
-use elements::bitcoin::hashes::{sha256, Hash};
+use elements::bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
 use elements::bitcoin::secp256k1::{self, All, Secp256k1};
 use elements::bitcoin::util::bip32::{
-    ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey,
+    ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint,
 };
-use elements::bitcoin::PublicKey;
+use elements::bitcoin::{PrivateKey, PublicKey};
 use elements::secp256k1_zkp;
 use elements::{BlockHash, Script, Txid};
 use hex;
-use log::{info, trace};
-
-use crate::model::{CreateTransactionOpt, TransactionDetails, UnblindedTXO, TXO};
-use crate::network::{Config, ElementsNetwork};
-use crate::scripts::{p2pkh_script, p2shwpkh_script, p2shwpkh_script_sig};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use tracing::{info, trace};
+
+use crate::model::{
+    ChangeOutput, Contact, Counterparty, CreateTransactionOpt, DecodedInput, DecodedOutput,
+    DecodedTx, Invoice, InvoiceStatus, Payment, PendingIncoming, PendingIncomingTx, SyncReport,
+    TransactionDetails, UnblindedTXO, UtxoSort, UtxosOpt, WalletStats, TXO,
+};
+use crate::network::{Backend, Config, ElementsNetwork};
+use crate::scripts::{
+    p2pkh_script, p2shwpkh_script, p2shwpkh_script_sig, script_kind, AddressType,
+};
 use bip39;
 
+use crate::backend::BlockchainBackend;
+use crate::backup::WalletBackup;
+use crate::blinder::Blinder;
+use crate::coin_selection::{check_selection, CoinSelector, FeeModel};
 use crate::error::{fn_err, Error};
-use crate::store::{Store, StoreMeta};
-use crate::utils::derive_blinder;
+use crate::manager::WalletManager;
+use crate::payjoin::{PayjoinInput, PayjoinProposal};
+use crate::persister::{FilePersister, Persister};
+use crate::privacy::privacy_warnings;
+#[cfg(feature = "registry")]
+use crate::registry::RegistryClient;
+use crate::registry::{embedded_metadata, AssetMetadata};
+use crate::sighash::{segwitv0_sighash, SigHashFlags};
+use crate::signer::Signer;
+use crate::spendable::{relative_lock_blocks, template_script_pubkey, SpendableScript};
+use crate::store::{legacy_xpub, Store, StoreMeta, StoreSnapshot};
 
 use crate::transaction::*;
 use elements::confidential::{Asset, Nonce, Value};
 use elements::slip77::MasterBlindingKey;
-use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::liquidex::{
     liquidex_blind, liquidex_changes, liquidex_estimated_changes, liquidex_fee, liquidex_needs,
-    LiquidexMakeOpt, LiquidexProposal,
+    liquidex_receive_value, ohlc, LiquidexAssetList, LiquidexMakeOpt, LiquidexPartial,
+    LiquidexProposal, LiquidexSwap, PriceCandle,
 };
 
+/// Locktime values below this are block heights, above are timestamps
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Attempts at creating the proofs of a transaction, each with fresh randomness, before failing
+/// with `Error::BlindingFailed`
+const BLINDING_ATTEMPTS: u8 = 3;
+
+/// Maximum inputs of a transaction created by `sweep_to`, keeping it well below the standard
+/// transaction size
+const SWEEP_MAX_INPUTS: usize = 100;
+
+/// Inputs reserved to pay the fee in a `sweep_to` transaction moving an asset other than the
+/// policy asset
+const SWEEP_FEE_INPUTS: usize = 5;
+
 pub struct WalletCtx {
     pub secp: Secp256k1<All>,
     pub config: Config,
     pub store: Store,
     pub xpub: ExtendedPubKey,
-    pub master_blinding: MasterBlindingKey,
+    pub blinder: Arc<dyn Blinder>,
     pub change_max_deriv: u32,
+    wallet_id: String,
+    master_fingerprint: Fingerprint,
+    /// The master blinding key is not derived from the seed of the signing keys
+    separate_view_key: bool,
+    /// Selector of the `create_tx` inputs, None for the biggest utxos first
+    coin_selector: Option<Arc<dyn CoinSelector>>,
+    /// Asked before signing with the wallet keys, None to sign without asking
+    sign_approver: Option<Arc<dyn SignApprover>>,
 }
 
 fn mnemonic2seed(mnemonic: &str) -> Result<Vec<u8>, Error> {
@@ -52,26 +98,29 @@ fn mnemonic2seed(mnemonic: &str) -> Result<Vec<u8>, Error> {
     Ok(seed.to_vec())
 }
 
-fn mnemonic2xprv(mnemonic: &str, config: Config) -> Result<ExtendedPrivKey, Error> {
+fn mnemonic2master_xprv(
+    mnemonic: &str,
+    network: ElementsNetwork,
+) -> Result<ExtendedPrivKey, Error> {
     let seed = mnemonic2seed(mnemonic)?;
-    let xprv = ExtendedPrivKey::new_master(
-        elements::bitcoin::network::constants::Network::Testnet,
-        &seed,
-    )?;
-
-    // BIP44: m / purpose' / coin_type' / account' / change / address_index
-    // coin_type = 1776 liquid bitcoin as defined in https://github.com/satoshilabs/slips/blob/master/slip-0044.md
-    // slip44 suggest 1 for every testnet, so we are using it also for regtest
-    let coin_type: u32 = match config.network() {
-        ElementsNetwork::Liquid => 1776,
-        ElementsNetwork::ElementsRegtest => 1,
-    };
-    // since we use P2WPKH-nested-in-P2SH it is 49 https://github.com/bitcoin/bips/blob/master/bip-0049.mediawiki
-    let path_string = format!("m/49'/{}'/0'", coin_type);
-    info!("Using derivation path {}/0|1/*", path_string);
-    let path = DerivationPath::from_str(&path_string)?;
+    Ok(ExtendedPrivKey::new_master(network.bip32_network(), &seed)?)
+}
+
+/// Master and account extended private keys derived from the BIP39 `seed`, the account at the
+/// path of `Config::derivation`
+fn seed2xprvs(seed: &[u8], config: &Config) -> Result<(ExtendedPrivKey, ExtendedPrivKey), Error> {
+    let master = ExtendedPrivKey::new_master(config.network().bip32_network(), seed)?;
+    let path = config.derivation().account_path;
+    info!("Using derivation path {}/0|1/*", path);
     let secp = Secp256k1::new();
-    Ok(xprv.derive_priv(&secp, &path)?)
+    let xprv = master.derive_priv(&secp, &path)?;
+    Ok((master, xprv))
+}
+
+/// Account extended private key derived from `mnemonic` at the account path of
+/// `Config::derivation`
+pub(crate) fn mnemonic2xprv(mnemonic: &str, config: Config) -> Result<ExtendedPrivKey, Error> {
+    Ok(seed2xprvs(&mnemonic2seed(mnemonic)?, &config)?.1)
 }
 
 // Copied from current elements master
@@ -114,7 +163,6 @@ pub fn make_rangeproof_message(
     message
 }
 
-#[allow(dead_code)]
 pub fn parse_rangeproof_message(
     message: &[u8],
 ) -> Result<(elements::issuance::AssetId, secp256k1_zkp::Tweak), Error> {
@@ -127,36 +175,361 @@ pub fn parse_rangeproof_message(
     Ok((asset, asset_blinder))
 }
 
+fn file_persister(
+    data_root: &str,
+    wallet_id: &str,
+    config: &Config,
+) -> Result<Box<dyn Persister>, Error> {
+    let manager = WalletManager::new(data_root);
+    manager.migrate_legacy_dir(config.network(), wallet_id, config.ignore_store_lock)?;
+    let path = manager.wallet_dir(config.network(), wallet_id);
+    info!("Store root path: {:?}", path);
+    if config.ignore_store_lock {
+        Ok(Box::new(FilePersister::new_unlocked(&path)?))
+    } else {
+        Ok(Box::new(FilePersister::new(&path)?))
+    }
+}
+
+/// Seconds since the unix epoch
+pub(crate) fn now() -> Result<u64, Error> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::Generic("system time before unix epoch".into()))?
+        .as_secs())
+}
+
+fn compute_wallet_id(
+    xpub: &ExtendedPubKey,
+    config: &Config,
+    separate_view_key: Option<&dyn Blinder>,
+) -> Result<String, Error> {
+    // The description must not change when fields are added to Config, otherwise existing
+    // wallets would get a different id and a new empty store, so it's the Debug format of
+    // Config at the time the wallet id was introduced.
+    let server = match &config.backend {
+        Backend::Electrum(url) => format!("electrum_url: {:?}", url),
+        #[cfg(feature = "esplora")]
+        Backend::Esplora(url) => format!("esplora_url: {:?}", url),
+        #[cfg(feature = "elementsd")]
+        Backend::ElementsRpc { url, .. } => format!("elementsd_url: {:?}", url),
+    };
+    let mut wallet_desc = format!(
+        "{}Config {{ network: {:?}, policy_asset: {:?}, {}, spv_enabled: {:?} }}",
+        legacy_xpub(xpub),
+        config.network(),
+        config.policy_asset_id(),
+        server,
+        config.spv_enabled
+    );
+    if let Some(view_key) = separate_view_key {
+        // keep apart the data of wallets with the same spend key and a different view key
+        wallet_desc.push_str(&format!(" view_key: {}", view_key.id()?));
+    }
+    let address_type = config.derivation().address_type;
+    if address_type != AddressType::default() {
+        // keep apart the data of wallets with the same keys and a different address type
+        wallet_desc.push_str(&format!(" address_type: {:?}", address_type));
+    }
+    Ok(hex::encode(sha256::Hash::hash(wallet_desc.as_bytes())))
+}
+
 impl WalletCtx {
     pub fn from_mnemonic(mnemonic: &str, data_root: &str, config: Config) -> Result<Self, Error> {
-        let xprv = mnemonic2xprv(mnemonic, config.clone())?;
+        // the seed derivation is slow, done once for the wallet id and the keys
+        let seed = mnemonic2seed(mnemonic)?;
+        let (_, xprv) = seed2xprvs(&seed, &config)?;
+        let xpub = ExtendedPubKey::from_private(&Secp256k1::new(), &xprv);
+
+        let persister = file_persister(
+            data_root,
+            &compute_wallet_id(&xpub, &config, None)?,
+            &config,
+        )?;
+        Self::from_seed_with_store(&seed, persister, config)
+    }
+
+    /// Create a wallet signing with the keys derived from `spend_mnemonic` and unblinding with the
+    /// SLIP-77 master blinding key derived from `view_mnemonic`.
+    pub fn from_mnemonics(
+        spend_mnemonic: &str,
+        view_mnemonic: &str,
+        data_root: &str,
+        config: Config,
+    ) -> Result<Self, Error> {
         let secp = Secp256k1::new();
+        let (master, xprv) = seed2xprvs(&mnemonic2seed(spend_mnemonic)?, &config)?;
         let xpub = ExtendedPubKey::from_private(&secp, &xprv);
+        let master_fingerprint = master.fingerprint(&secp);
+        Self::from_view_key(xpub, master_fingerprint, view_mnemonic, data_root, config)
+    }
 
-        let wallet_desc = format!("{}{:?}", xpub, config);
-        let wallet_id = hex::encode(sha256::Hash::hash(wallet_desc.as_bytes()));
+    /// Create a wallet which can see and unblind its transactions with the master blinding key
+    /// derived from `view_mnemonic` but cannot sign, the spend key stays elsewhere.
+    pub fn from_view_key(
+        xpub: ExtendedPubKey,
+        master_fingerprint: Fingerprint,
+        view_mnemonic: &str,
+        data_root: &str,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let master_blinding = MasterBlindingKey::new(&mnemonic2seed(view_mnemonic)?);
+        Self::from_blinder(
+            xpub,
+            master_fingerprint,
+            Arc::new(master_blinding),
+            data_root,
+            config,
+        )
+    }
 
-        let seed = mnemonic2seed(mnemonic)?;
-        let master_blinding = MasterBlindingKey::new(&seed);
+    /// Create a wallet which can see and unblind its transactions with `blinder`, for instance a
+    /// hardware device holding the master blinding key, but cannot sign.
+    pub fn from_blinder(
+        xpub: ExtendedPubKey,
+        master_fingerprint: Fingerprint,
+        blinder: Arc<dyn Blinder>,
+        data_root: &str,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let wallet_id = compute_wallet_id(&xpub, &config, Some(&*blinder))?;
+        let persister = file_persister(data_root, &wallet_id, &config)?;
+        Self::from_keys(xpub, blinder, master_fingerprint, true, persister, config)
+    }
 
-        let mut path: PathBuf = data_root.into();
-        if !path.exists() {
-            std::fs::create_dir_all(&path)?;
-        }
-        path.push(wallet_id);
-        info!("Store root path: {:?}", path);
-        let store = Arc::new(RwLock::new(StoreMeta::new(&path, xpub)?));
+    /// Create the wallet using `persister` to store its data.
+    pub fn from_mnemonic_with_store(
+        mnemonic: &str,
+        persister: Box<dyn Persister>,
+        config: Config,
+    ) -> Result<Self, Error> {
+        Self::from_seed_with_store(&mnemonic2seed(mnemonic)?, persister, config)
+    }
+
+    /// Create the wallet of the BIP39 `seed` using `persister` to store its data
+    fn from_seed_with_store(
+        seed: &[u8],
+        persister: Box<dyn Persister>,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let (master, xprv) = seed2xprvs(seed, &config)?;
+        let secp = Secp256k1::new();
+        let xpub = ExtendedPubKey::from_private(&secp, &xprv);
+        let master_fingerprint = master.fingerprint(&secp);
+
+        let master_blinding = MasterBlindingKey::new(seed);
+
+        Self::from_keys(
+            xpub,
+            Arc::new(master_blinding),
+            master_fingerprint,
+            false,
+            persister,
+            config,
+        )
+    }
+
+    /// Restore a watch-only wallet from a backup created with `export_backup`, the wallet
+    /// settings in the backup replace the ones in the store.
+    pub fn from_backup(
+        backup: &[u8],
+        password: &str,
+        data_root: &str,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let backup = WalletBackup::decrypt(backup, password)?;
+        let master_blinding = backup.master_blinding()?;
+        let view_key =
+            Some(&master_blinding as &dyn Blinder).filter(|_| backup.separate_view_key());
+        let wallet_id = compute_wallet_id(&backup.xpub()?, &config, view_key)?;
+        let persister = file_persister(data_root, &wallet_id, &config)?;
+        Self::from_backup_with_store(backup, persister, config)
+    }
+
+    fn from_backup_with_store(
+        backup: WalletBackup,
+        persister: Box<dyn Persister>,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let wallet = Self::from_keys(
+            backup.xpub()?,
+            Arc::new(backup.master_blinding()?),
+            backup.master_fingerprint()?,
+            backup.separate_view_key(),
+            persister,
+            config,
+        )?;
+        wallet.store.write()?.restore(backup.into_store())?;
+        Ok(wallet)
+    }
+
+    fn from_keys(
+        xpub: ExtendedPubKey,
+        blinder: Arc<dyn Blinder>,
+        master_fingerprint: Fingerprint,
+        separate_view_key: bool,
+        persister: Box<dyn Persister>,
+        config: Config,
+    ) -> Result<Self, Error> {
+        let secp = Secp256k1::new();
+        // backups and watch-only keys could have the testnet version bytes of older wallets
+        let xpub = ExtendedPubKey {
+            network: config.network().bip32_network(),
+            ..xpub
+        };
+        let view_key = Some(&*blinder).filter(|_| separate_view_key);
+        let wallet_id = compute_wallet_id(&xpub, &config, view_key)?;
+
+        let mut store = StoreMeta::new_with_address_type(
+            persister,
+            xpub,
+            config.network(),
+            config.derivation().address_type,
+        )?;
+        store.check_blinding_key(blinder.id()?)?;
+        let store = Arc::new(RwLock::new(store));
 
         Ok(WalletCtx {
             store,
             config, // TODO: from db
             secp,
             xpub,
-            master_blinding,
+            blinder,
             change_max_deriv: 0,
+            wallet_id,
+            master_fingerprint,
+            separate_view_key,
+            coin_selector: None,
+            sign_approver: None,
         })
     }
 
+    /// Select the inputs of the transactions created by `create_tx` with `coin_selector`,
+    /// None restores the default selection spending the biggest utxos first
+    pub fn set_coin_selector(&mut self, coin_selector: Option<Arc<dyn CoinSelector>>) {
+        self.coin_selector = coin_selector;
+    }
+
+    /// Ask `sign_approver` before signing with the wallet keys, None signs without asking
+    pub fn set_sign_approver(&mut self, sign_approver: Option<Arc<dyn SignApprover>>) {
+        self.sign_approver = sign_approver;
+    }
+
+    /// Ask the `SignApprover`, if any, whether the wallet can sign the `inputs` of `tx`.
+    ///
+    /// The store lock is taken to compute the values spent and released before asking, so that
+    /// an approver waiting for the user does not block the wallet, the caller must not hold it.
+    fn approve_signing(
+        &self,
+        kind: SignKind,
+        tx: &elements::Transaction,
+        inputs: &[usize],
+    ) -> Result<(), Error> {
+        if self.sign_approver.is_none() {
+            return Ok(());
+        }
+        let spent = self.signing_spent(&*self.store.read()?, tx, inputs)?;
+        self.approve_spending(kind, tx, inputs, spent)
+    }
+
+    /// Value spent by the wallet `inputs` of `tx`, by asset
+    fn signing_spent(
+        &self,
+        store_read: &StoreMeta,
+        tx: &elements::Transaction,
+        inputs: &[usize],
+    ) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        let mut spent = HashMap::new();
+        for &i in inputs {
+            let previous_output = tx.input[i].previous_output;
+            let unblinded = store_read
+                .cache
+                .unblinded
+                .get(&previous_output)
+                .ok_or(Error::MissingPreviousOutput(previous_output))?;
+            add_amount(&mut spent, unblinded.asset, unblinded.value)?;
+        }
+        Ok(spent)
+    }
+
+    /// Ask the `SignApprover`, if any, whether the `inputs` of `tx` spending `spent` can be
+    /// signed, it must be called without holding the store lock
+    fn approve_spending(
+        &self,
+        kind: SignKind,
+        tx: &elements::Transaction,
+        inputs: &[usize],
+        spent: HashMap<elements::issuance::AssetId, u64>,
+    ) -> Result<(), Error> {
+        let sign_approver = match &self.sign_approver {
+            Some(sign_approver) => sign_approver,
+            None => return Ok(()),
+        };
+        let mut fee = 0u64;
+        for output in tx.output.iter().filter(|o| o.is_fee()) {
+            fee = fee
+                .checked_add(output.minimum_value())
+                .ok_or(Error::AmountOverflow)?;
+        }
+        let request = SignRequest {
+            kind,
+            transaction: tx,
+            inputs,
+            spent,
+            fee,
+        };
+        match sign_approver.approve(&request) {
+            SignApproval::Approve => Ok(()),
+            SignApproval::Reject(reason) => {
+                info!("signing {:?} rejected", kind);
+                Err(Error::SigningRejected(reason))
+            }
+            SignApproval::Delay => Err(Error::SigningDelayed),
+        }
+    }
+
+    /// Identifier of the wallet, it is also the name of the store directory.
+    pub fn wallet_id(&self) -> &str {
+        &self.wallet_id
+    }
+
+    /// Encrypted backup of everything needed to restore the wallet except the seed.
+    pub fn export_backup(&self, password: &str) -> Result<Vec<u8>, Error> {
+        let master_blinding = self
+            .blinder
+            .master_blinding_key()
+            .ok_or_else(|| Error::Generic("the master blinding key cannot be exported".into()))?;
+        let store = self.store.read()?.store.clone();
+        let backup = WalletBackup::new(
+            &self.xpub,
+            &master_blinding,
+            &self.master_fingerprint,
+            self.separate_view_key,
+            store,
+        );
+        backup.encrypt(password)
+    }
+
+    /// Fingerprint of the master key the wallet keys are derived from.
+    pub fn master_fingerprint(&self) -> Fingerprint {
+        self.master_fingerprint
+    }
+
+    /// Account extended public key, with the version bytes of the wallet network.
+    pub fn xpub(&self) -> ExtendedPubKey {
+        self.xpub
+    }
+
+    /// Account extended private key derived from `mnemonic`, with the version bytes of the
+    /// wallet network.
+    pub fn account_xprv(&self, mnemonic: &str) -> Result<ExtendedPrivKey, Error> {
+        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
+        if ExtendedPubKey::from_private(&self.secp, &xprv) != self.xpub {
+            return Err(Error::Generic("mnemonic does not match the wallet".into()));
+        }
+        Ok(xprv)
+    }
+
     fn derive_address(
         &self,
         xpub: &ExtendedPubKey,
@@ -167,78 +540,231 @@ impl WalletCtx {
             .map(|x| ChildNumber::Normal { index: *x })
             .collect();
         let derived = xpub.derive_pub(&self.secp, &path)?;
-        let script = p2shwpkh_script(&derived.public_key);
-        let blinding_key = self.master_blinding.derive_blinding_key(&script);
+        let address_type = self.address_type();
+        let script = address_type.script_pubkey(&derived.public_key);
+        let blinding_key = self.blinder.blinding_key(&script)?;
         let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &blinding_key);
         let blinder = Some(public_key);
-        let addr = elements::Address::p2shwpkh(
+        let addr = address_type.address(
             &derived.public_key,
             blinder,
-            address_params(self.config.network()),
+            self.config.network().address_params(),
         );
 
         Ok(addr)
     }
 
+    /// Type of the addresses of the wallet keys, see `Config::derivation`
+    fn address_type(&self) -> AddressType {
+        self.config.derivation().address_type
+    }
+
     pub fn get_tip(&self) -> Result<(u32, BlockHash), Error> {
         Ok(self.store.read()?.cache.tip)
     }
 
-    pub fn list_tx(&self, opt: &GetTransactionsOpt) -> Result<Vec<TransactionDetails>, Error> {
+    /// Locktime of the created transactions, `lock_time` if it is final in the next block,
+    /// otherwise the tip height to discourage fee sniping
+    fn lock_time(&self, lock_time: Option<u32>) -> Result<u32, Error> {
+        let tip_height = self.get_tip()?.0;
+        match lock_time {
+            // a height locktime above the tip would not be final in the next block
+            Some(lock_time) if lock_time < LOCKTIME_THRESHOLD && lock_time > tip_height => {
+                Err(Error::InvalidLockTime(lock_time))
+            }
+            Some(lock_time) => Ok(lock_time),
+            None => Ok(tip_height),
+        }
+    }
+
+    /// Cached header at `height`, headers are cached at the heights of the wallet transactions
+    /// and of the blockchain tip.
+    pub fn get_header(&self, height: u32) -> Result<Option<elements::BlockHeader>, Error> {
+        Ok(self.store.read()?.cache.headers.get(&height).cloned())
+    }
+
+    /// Outputs of the wallet transaction `txid` received on external addresses, with the address
+    /// and the unblinded values.
+    pub fn received_outputs(
+        &self,
+        txid: &Txid,
+    ) -> Result<Vec<(u32, elements::Address, elements::TxOutSecrets)>, Error> {
         let store_read = self.store.read()?;
+        let tx = store_read
+            .cache
+            .all_txs
+            .get(txid)?
+            .ok_or_else(fn_err(&format!("received_outputs no tx {}", txid)))?;
+        let mut result = vec![];
+        for (vout, output) in tx.output.iter().enumerate() {
+            let path = store_read.cache.paths.get(&output.script_pubkey);
+            if !matches!(
+                path.map(|p| p.as_ref()),
+                Some([ChildNumber::Normal { index: 0 }, _])
+            ) {
+                continue;
+            }
+            let outpoint = elements::OutPoint::new(*txid, vout as u32);
+            if let Some(unblinded) = store_read.cache.unblinded.get(&outpoint) {
+                let blinding_key = self.blinder.blinding_key(&output.script_pubkey)?;
+                let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &blinding_key);
+                let address = elements::Address::from_script(
+                    &output.script_pubkey,
+                    Some(public_key),
+                    self.config.network().address_params(),
+                )
+                .ok_or_else(fn_err("received_outputs invalid script"))?;
+                result.push((vout as u32, address, unblinded.clone()));
+            }
+        }
+        Ok(result)
+    }
 
-        let mut txs = vec![];
-        let mut my_txids: Vec<(&Txid, &Option<u32>)> = store_read.cache.heights.iter().collect();
-        my_txids.sort_by(|a, b| {
-            let height_cmp =
-                b.1.unwrap_or(std::u32::MAX)
-                    .cmp(&a.1.unwrap_or(std::u32::MAX));
-            match height_cmp {
-                Ordering::Equal => b.0.cmp(a.0),
-                h @ _ => h,
+    /// Counts of the synced transactions and of their outputs by kind of script, outputs with
+    /// scripts the wallet does not know are listed in `SyncReport::unknown_scripts`
+    pub fn sync_report(&self) -> Result<SyncReport, Error> {
+        let store_read = self.store.read()?;
+        let mut report = SyncReport::default();
+        for (txid, height) in store_read.cache.heights.iter() {
+            let tx = match store_read.cache.all_txs.get(txid)? {
+                Some(tx) => tx,
+                None => continue,
+            };
+            report.txs += 1;
+            if height.is_none() {
+                report.unconfirmed_txs += 1;
             }
-        });
+            for output in tx.output.iter() {
+                *report
+                    .script_kinds
+                    .entry(script_kind(&output.script_pubkey))
+                    .or_default() += 1;
+            }
+        }
+        report.unknown_scripts = store_read.cache.unknown_scripts.iter().cloned().collect();
+        report.unknown_scripts.sort();
+        Ok(report)
+    }
 
-        let policy_asset = Some(elements::confidential::Asset::Explicit(
-            self.config.policy_asset(),
-        ));
-        for (tx_id, height) in my_txids.iter().skip(opt.first).take(opt.count) {
-            trace!("tx_id {}", tx_id);
+    /// Counts of the wallet transactions, utxos and issued addresses, with the store size, the
+    /// time of the last sync and the confirmed transactions verified by SPV
+    pub fn stats(&self) -> Result<WalletStats, Error> {
+        let store_read = self.store.read()?;
+        let mut stats = WalletStats {
+            txs: store_read.cache.heights.len(),
+            external_index: store_read.cache.indexes.external,
+            internal_index: store_read.cache.indexes.internal,
+            store_size: store_read.size()?,
+            last_sync: store_read.cache.last_sync,
+            ..Default::default()
+        };
+        for (txid, height) in store_read.cache.heights.iter() {
+            if height.is_none() {
+                stats.unconfirmed_txs += 1;
+            } else if let Some(SPVVerifyResult::Verified) = store_read.cache.txs_verif.get(txid) {
+                stats.spv_verified_txs += 1;
+            }
+        }
+        for outpoint in store_read.cache.utxos.iter() {
+            if let Some(unblinded) = store_read.cache.unblinded.get(outpoint) {
+                *stats.utxos.entry(unblinded.asset).or_default() += 1;
+            }
+        }
+        Ok(stats)
+    }
 
-            let tx = store_read
-                .cache
-                .all_txs
-                .get(*tx_id)
-                .ok_or_else(fn_err(&format!("list_tx no tx {}", tx_id)))?;
+    /// Unconfirmed transactions paying the wallet and not spending its outputs, with the fee
+    /// paid by the sender compared with the cached fee estimates, to decide whether to accept
+    /// the payments before they confirm.
+    pub fn pending_incoming(&self) -> Result<PendingIncoming, Error> {
+        let store_read = self.store.read()?;
+        let fee_estimates = store_read.fee_estimates();
+        let policy_asset = Asset::Explicit(self.config.policy_asset_id());
+        let mut pending = PendingIncoming::default();
+        for (txid, height) in store_read.cache.heights.iter() {
+            if height.is_some() {
+                continue;
+            }
+            let tx = match store_read.cache.all_txs.get(txid)? {
+                Some(tx) => tx,
+                None => continue,
+            };
+            if tx
+                .input
+                .iter()
+                .any(|i| store_read.cache.unblinded.contains_key(&i.previous_output))
+            {
+                continue;
+            }
+            let received: HashMap<_, _> = my_balance_changes(&tx, &store_read.cache.unblinded)
+                .into_iter()
+                .filter(|(_, v)| *v > 0)
+                .map(|(asset, v)| (asset, v as u64))
+                .collect();
+            if received.is_empty() {
+                continue;
+            }
 
-            let fee = fee(
-                &tx,
-                &store_read.cache.all_txs,
-                &store_read.cache.unblinded,
-                &policy_asset,
-            )?;
-            trace!("tx_id {} fee {}", tx_id, fee);
+            // the sender inputs are not known, the fee is the one of the explicit fee outputs
+            let mut fee = 0u64;
+            for output in tx.output.iter().filter(|o| o.is_fee()) {
+                if output.asset == policy_asset {
+                    fee = fee
+                        .checked_add(output.minimum_value())
+                        .ok_or(Error::AmountOverflow)?;
+                }
+            }
+            let fee_rate = FeeRate::from_fee(Amount::from_sat(fee), cached_vbytes(&tx) as u64)
+                .ok_or(Error::AmountOverflow)?;
+            // the first estimate is the minimum relay fee
+            let confirmation_target = fee_estimates
+                .iter()
+                .enumerate()
+                .skip(1)
+                .find(|(_, estimate)| fee_rate.as_sat_per_kvb() >= estimate.0)
+                .map(|(target, _)| target as u32);
+            for (asset, value) in received.iter() {
+                add_amount(&mut pending.totals, *asset, *value)?;
+            }
+            pending.txs.push(PendingIncomingTx {
+                txid: txid.to_string(),
+                received,
+                fee: fee.into(),
+                fee_rate,
+                confirmation_target,
+                replaceable: tx.input.iter().any(|i| i.sequence < 0xffff_fffe),
+            });
+        }
+        pending.txs.sort_by(|a, b| a.txid.cmp(&b.txid));
+        Ok(pending)
+    }
 
-            let balances = my_balance_changes(&tx, &store_read.cache.unblinded);
-            trace!("tx_id {} balances {:?}", tx_id, balances);
+    pub fn store_snapshot(&self) -> Result<StoreSnapshot, Error> {
+        Ok(self.store.read()?.snapshot())
+    }
 
-            let spv_verified = if self.config.spv_enabled {
-                store_read
-                    .cache
-                    .txs_verif
-                    .get(*tx_id)
-                    .unwrap_or(&SPVVerifyResult::InProgress)
-                    .clone()
-            } else {
-                SPVVerifyResult::Disabled
-            };
+    pub fn set_autoflush(&self, autoflush: bool) -> Result<(), Error> {
+        self.store.write()?.set_autoflush(autoflush)
+    }
 
-            trace!("tx_id {} spv_verified {:?}", tx_id, spv_verified);
+    pub fn flush(&self) -> Result<(), Error> {
+        self.store.read()?.flush()
+    }
 
-            let tx_details =
-                TransactionDetails::new(tx.clone(), balances, fee, **height, spv_verified);
+    pub fn list_tx(&self, opt: &GetTransactionsOpt) -> Result<Vec<TransactionDetails>, Error> {
+        let store_read = self.store.read()?;
 
-            txs.push(tx_details);
+        // newest first, unconfirmed txs have the max height
+        let mut txs = vec![];
+        for (_, tx_id) in store_read
+            .cache
+            .txs_by_height
+            .iter()
+            .rev()
+            .skip(opt.first)
+            .take(opt.count)
+        {
+            txs.push(self.tx_details(&store_read, tx_id)?);
         }
         info!(
             "list_tx {:?}",
@@ -248,110 +774,500 @@ impl WalletCtx {
         Ok(txs)
     }
 
+    /// Transactions confirmed at `height` or after and the unconfirmed ones, newest first
+    pub fn list_tx_since(&self, height: u32) -> Result<Vec<TransactionDetails>, Error> {
+        let store_read = self.store.read()?;
+
+        let mut txs = vec![];
+        for (_, tx_id) in store_read
+            .cache
+            .txs_by_height
+            .iter()
+            .rev()
+            .take_while(|(h, _)| *h >= height)
+        {
+            txs.push(self.tx_details(&store_read, tx_id)?);
+        }
+        info!("list_tx_since {} returned {} txs", height, txs.len());
+
+        Ok(txs)
+    }
+
+    fn tx_details(
+        &self,
+        store_read: &StoreMeta,
+        tx_id: &Txid,
+    ) -> Result<TransactionDetails, Error> {
+        trace!("tx_id {}", tx_id);
+
+        let tx = store_read
+            .cache
+            .all_txs
+            .get(tx_id)?
+            .ok_or_else(fn_err(&format!("list_tx no tx {}", tx_id)))?;
+        let height = store_read.cache.heights.get(tx_id).cloned().flatten();
+
+        let policy_asset = Some(elements::confidential::Asset::Explicit(
+            self.config.policy_asset_id(),
+        ));
+        let fee = fee(
+            &tx,
+            &store_read.cache.all_txs,
+            &store_read.cache.unblinded,
+            &policy_asset,
+        )?;
+        trace!("tx_id {} fee {}", tx_id, redact(fee));
+
+        let balances = my_balance_changes(&tx, &store_read.cache.unblinded);
+        trace!("tx_id {} balances {:?}", tx_id, redact(&balances));
+
+        let spv_verified = if self.config.spv_enabled {
+            store_read
+                .cache
+                .txs_verif
+                .get(tx_id)
+                .unwrap_or(&SPVVerifyResult::InProgress)
+                .clone()
+        } else {
+            SPVVerifyResult::Disabled
+        };
+
+        trace!("tx_id {} spv_verified {:?}", tx_id, spv_verified);
+
+        let mut details =
+            TransactionDetails::new((*tx).clone(), balances, fee.into(), height, spv_verified);
+        details.burns = store_read
+            .cache
+            .burns
+            .get(tx_id)
+            .cloned()
+            .unwrap_or_default();
+        details.tx_type = Some(tx_type(
+            &tx,
+            &details.balances,
+            fee,
+            self.config.policy_asset_id(),
+            &details.burns,
+        ));
+        if tx.input.iter().any(|input| {
+            store_read
+                .cache
+                .unblinded
+                .contains_key(&input.previous_output)
+        }) {
+            details.counterparties = self.counterparties(&tx, store_read, &HashMap::new());
+        }
+        Ok(details)
+    }
+
+    /// Outputs of `tx` not locked to the wallet scripts nor paying the fee, with the address in
+    /// `addresses` of their script, or their values as recorded when `tx` was created.
+    fn counterparties(
+        &self,
+        tx: &elements::Transaction,
+        store_read: &StoreMeta,
+        addresses: &HashMap<Script, String>,
+    ) -> Vec<Counterparty> {
+        let recorded = tx
+            .input
+            .first()
+            .and_then(|input| store_read.counterparties(&input.previous_output));
+        let params = self.config.network().address_params();
+        let mut result = vec![];
+        for (vout, output) in tx.output.iter().enumerate() {
+            let script = &output.script_pubkey;
+            if script.is_empty() || store_read.cache.paths.contains_key(script) {
+                continue;
+            }
+            let vout = vout as u32;
+            let recorded = recorded.and_then(|r| {
+                r.iter()
+                    .find(|c| c.vout == vout && c.script_pubkey == *script)
+            });
+            if let Some(counterparty) = recorded {
+                result.push(counterparty.clone());
+                continue;
+            }
+            let address = addresses.get(script).cloned().or_else(|| {
+                elements::Address::from_script(script, None, params).map(|a| a.to_string())
+            });
+            result.push(Counterparty {
+                vout,
+                script_pubkey: script.clone(),
+                address,
+                asset: match output.asset {
+                    Asset::Explicit(asset) => Some(asset),
+                    _ => None,
+                },
+                satoshi: match output.value {
+                    Value::Explicit(satoshi) => Some(Amount::from_sat(satoshi)),
+                    _ => None,
+                },
+            });
+        }
+        result
+    }
+
+    /// Decode the transaction `hex`, annotating the inputs spending wallet outputs and the
+    /// wallet outputs with their derivation paths and unblinded values, to check transactions
+    /// built by others before signing them.
+    pub fn decode_tx(&self, hex: &str) -> Result<DecodedTx, Error> {
+        let tx: elements::Transaction = elements::encode::deserialize(&hex::decode(hex)?)?;
+        let store_read = self.store.read()?;
+        let params = self.config.network().address_params();
+        let mut balances: HashMap<elements::issuance::AssetId, i64> = HashMap::new();
+
+        let mut inputs = vec![];
+        for input in tx.input.iter() {
+            let outpoint = input.previous_output;
+            let wallet_prevout = store_read
+                .cache
+                .all_txs
+                .get(&outpoint.txid)?
+                .and_then(|prev_tx| prev_tx.output.get(outpoint.vout as usize).cloned())
+                .and_then(|out| store_read.cache.paths.get(&out.script_pubkey));
+            let unblinded = store_read.cache.unblinded.get(&outpoint).cloned();
+            if let Some(unblinded) = &unblinded {
+                add_balance(&mut balances, unblinded.asset, unblinded.value, true)?;
+            }
+            inputs.push(DecodedInput {
+                outpoint,
+                sequence: input.sequence,
+                derivation_path: wallet_prevout.cloned(),
+                unblinded,
+            });
+        }
+
+        let mut outputs = vec![];
+        for output in tx.output.iter() {
+            let script = &output.script_pubkey;
+            let derivation_path = store_read.cache.paths.get(script).cloned();
+            let asset = match output.asset {
+                Asset::Explicit(asset) => Some(asset),
+                _ => None,
+            };
+            let satoshi = match output.value {
+                Value::Explicit(satoshi) => Some(satoshi),
+                _ => None,
+            };
+            let mut unblinded = None;
+            if derivation_path.is_some() {
+                match (asset, satoshi) {
+                    (Some(asset), Some(satoshi)) => {
+                        add_balance(&mut balances, asset, satoshi, false)?;
+                    }
+                    _ => {
+                        unblinded = self
+                            .blinder
+                            .blinding_key(script)
+                            .ok()
+                            .and_then(|key| output.unblind(&self.secp, key).ok());
+                        if let Some(unblinded) = &unblinded {
+                            add_balance(&mut balances, unblinded.asset, unblinded.value, false)?;
+                        }
+                    }
+                }
+            }
+            outputs.push(DecodedOutput {
+                script_pubkey: script.clone(),
+                script_kind: script_kind(script),
+                address: elements::Address::from_script(script, None, params)
+                    .map(|a| a.to_string()),
+                asset,
+                satoshi,
+                derivation_path,
+                unblinded,
+            });
+        }
+
+        let mut fee = 0u64;
+        for output in tx.output.iter().filter(|o| o.is_fee()) {
+            fee = fee
+                .checked_add(output.minimum_value())
+                .ok_or(Error::AmountOverflow)?;
+        }
+        info!("decode_tx {} fee {:?}", tx.txid(), redact(fee));
+
+        Ok(DecodedTx {
+            txid: tx.txid().to_string(),
+            version: tx.version,
+            lock_time: tx.lock_time,
+            weight: tx.get_weight(),
+            fee: fee.into(),
+            inputs,
+            outputs,
+            balances: balances.into_iter().filter(|(_, v)| *v != 0).collect(),
+        })
+    }
+
+    /// Satoshi of `asset` burned by the wallet txs, confirmed or not
+    pub fn total_burned(&self, asset: &elements::issuance::AssetId) -> Result<Amount, Error> {
+        let store_read = self.store.read()?;
+        let burns = store_read.cache.burns.values().flatten();
+        Amount::checked_sum(
+            burns
+                .filter(|burn| burn.asset == *asset)
+                .map(|burn| burn.satoshi),
+        )
+        .ok_or(Error::AmountOverflow)
+    }
+
     pub fn utxos(&self) -> Result<Vec<UnblindedTXO>, Error> {
         info!("start utxos");
 
         let store_read = self.store.read()?;
+        let policy_asset = self.config.policy_asset_id();
+        let tip_height = store_read.cache.tip.0;
+        let now = now()?;
         let mut txos = vec![];
-        let spent = store_read.spent()?;
-        for (tx_id, height) in store_read.cache.heights.iter() {
+        for outpoint in store_read.cache.utxos.iter() {
+            let unblinded = &store_read.cache.unblinded[outpoint];
+            if unblinded.value < DUST_VALUE && unblinded.asset == policy_asset {
+                continue;
+            }
             let tx = store_read
                 .cache
                 .all_txs
-                .get(tx_id)
-                .ok_or_else(fn_err(&format!("txos no tx {}", tx_id)))?;
-            let tx_txos: Vec<UnblindedTXO> = {
-                let policy_asset = self.config.policy_asset();
-                tx.output
-                    .clone()
-                    .into_iter()
-                    .enumerate()
-                    .map(|(vout, output)| {
-                        (
-                            elements::OutPoint {
-                                txid: tx.txid(),
-                                vout: vout as u32,
-                            },
-                            output,
-                        )
-                    })
-                    .filter(|(outpoint, _)| !spent.contains(&outpoint))
-                    .filter_map(|(outpoint, output)| {
-                        if let Some(unblinded) = store_read.cache.unblinded.get(&outpoint) {
-                            if unblinded.value < DUST_VALUE && unblinded.asset == policy_asset {
-                                return None;
-                            }
-                            let txo = TXO::new(outpoint, output.script_pubkey, height.clone());
-                            return Some(UnblindedTXO {
-                                txo: txo,
-                                unblinded: unblinded.clone(),
-                            });
-                        }
-                        None
-                    })
-                    .collect()
-            };
-            txos.extend(tx_txos);
+                .get(&outpoint.txid)?
+                .ok_or_else(fn_err(&format!("txos no tx {}", outpoint.txid)))?;
+            let height = store_read
+                .cache
+                .heights
+                .get(&outpoint.txid)
+                .cloned()
+                .flatten();
+            let script_pubkey = tx.output[outpoint.vout as usize].script_pubkey.clone();
+            if script_pubkey.is_v0_p2wsh() && !store_read.cache.paths.contains_key(&script_pubkey) {
+                // output of a template not registered since the wallet has been loaded
+                continue;
+            }
+            let confirmations = height.map_or(0, |h| (tip_height + 1).saturating_sub(h));
+            let age_days = height
+                .and_then(|h| store_read.cache.headers.get(&h))
+                .map(|header| (now.saturating_sub(header.time as u64) / 86_400) as u32);
+            txos.push(UnblindedTXO {
+                txo: TXO::new(*outpoint, script_pubkey, height),
+                unblinded: unblinded.clone(),
+                reissuance_token_of: store_read.reissuance_token_of(&unblinded.asset),
+                commitments_mismatch: store_read.cache.mismatched_commitments.contains(outpoint),
+                confirmations,
+                age_days,
+                explicit: store_read.cache.explicit_outputs.contains(outpoint),
+            });
         }
         txos.sort_by(|a, b| b.unblinded.value.cmp(&a.unblinded.value));
 
         Ok(txos)
     }
 
+    /// Utxos filtered and sorted as requested by `opt`
+    pub fn utxos_opt(&self, opt: &UtxosOpt) -> Result<Vec<UnblindedTXO>, Error> {
+        let mut utxos: Vec<UnblindedTXO> = self
+            .utxos()?
+            .into_iter()
+            .filter(|u| opt.asset.map_or(true, |a| a == u.unblinded.asset))
+            .filter(|u| u.confirmations >= opt.min_confirmations)
+            .filter(|u| opt.max_value.map_or(true, |v| u.unblinded.value <= v))
+            .collect();
+        match opt.sort {
+            UtxoSort::ValueDesc => (),
+            UtxoSort::ValueAsc => utxos.sort_by_key(|u| u.unblinded.value),
+            UtxoSort::OldestFirst => utxos.sort_by_key(|u| u.txo.height.unwrap_or(std::u32::MAX)),
+            UtxoSort::NewestFirst => {
+                utxos.sort_by_key(|u| std::cmp::Reverse(u.txo.height.unwrap_or(std::u32::MAX)))
+            }
+        }
+        Ok(utxos)
+    }
+
+    /// Balance of every asset, reissuance tokens are in `reissuance_tokens_balance`
     pub fn balance(&self) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
         info!("start balance");
         let mut result = HashMap::new();
-        result.entry(self.config.policy_asset()).or_insert(0);
+        result.entry(self.config.policy_asset_id()).or_insert(0);
         for u in self.utxos()?.iter() {
-            *result.entry(u.unblinded.asset).or_default() += u.unblinded.value;
+            if u.reissuance_token_of.is_none() && !u.commitments_mismatch {
+                *result.entry(u.unblinded.asset).or_default() += u.unblinded.value;
+            }
         }
         Ok(result)
     }
 
-    #[allow(clippy::cognitive_complexity)]
-    pub fn create_tx(&self, opt: &mut CreateTransactionOpt) -> Result<TransactionDetails, Error> {
-        info!("create_tx {:?}", opt);
-
-        // TODO put checks into CreateTransaction::validate, add check asset are valid asset hex
-        // eagerly check for address validity
-        let address_params = address_params(self.config.network());
-        for address in opt.addressees.iter().map(|a| a.address()) {
-            if address.params != address_params {
-                return Err(Error::InvalidAddress);
-            }
-        }
-
-        if opt.addressees.is_empty() {
-            return Err(Error::EmptyAddressees);
-        }
-
-        if opt.addressees.iter().any(|a| a.satoshi() == 0) {
-            return Err(Error::InvalidAmount);
+    /// Balance of every asset after the block at `height`, replaying the wallet transactions
+    /// confirmed up to it, reissuance tokens are excluded as in `balance`
+    pub fn balance_at(
+        &self,
+        height: u32,
+    ) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        info!("start balance_at {}", height);
+        let store_read = self.store.read()?;
+        let policy_asset = self.config.policy_asset_id();
+        let txs = store_read
+            .cache
+            .txs_by_height
+            .iter()
+            .take_while(|(h, _)| *h <= height && *h != std::u32::MAX)
+            .map(|(_, txid)| {
+                store_read
+                    .cache
+                    .all_txs
+                    .get(txid)?
+                    .ok_or_else(fn_err(&format!("balance_at no tx {}", txid)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let spent: HashSet<elements::OutPoint> = txs
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|i| i.previous_output))
+            .collect();
+
+        let mut result = HashMap::new();
+        result.entry(policy_asset).or_insert(0);
+        for tx in txs {
+            let txid = tx.txid();
+            for vout in 0..tx.output.len() as u32 {
+                let outpoint = elements::OutPoint::new(txid, vout);
+                if spent.contains(&outpoint)
+                    || store_read.cache.mismatched_commitments.contains(&outpoint)
+                {
+                    continue;
+                }
+                if let Some(unblinded) = store_read.cache.unblinded.get(&outpoint) {
+                    let dust = unblinded.value < DUST_VALUE && unblinded.asset == policy_asset;
+                    let token = store_read.reissuance_token_of(&unblinded.asset).is_some();
+                    if !dust && !token {
+                        *result.entry(unblinded.asset).or_default() += unblinded.value;
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Balance of the reissuance tokens, with the asset they reissue
+    pub fn reissuance_tokens_balance(
+        &self,
+    ) -> Result<HashMap<elements::issuance::AssetId, (elements::issuance::AssetId, u64)>, Error>
+    {
+        let mut result = HashMap::new();
+        for u in self.utxos()?.iter() {
+            if let (Some(asset), false) = (u.reissuance_token_of, u.commitments_mismatch) {
+                result.entry(u.unblinded.asset).or_insert((asset, 0)).1 += u.unblinded.value;
+            }
+        }
+        Ok(result)
+    }
+
+    /// An asset is known if it is the policy asset, it has been received by the wallet, it is
+    /// in the LiquiDEX assets, it has been issued by a transaction of the wallet or it is
+    /// registered in the asset registry, as cached or embedded
+    fn is_known_asset(&self, asset: &elements::issuance::AssetId) -> Result<bool, Error> {
+        if *asset == self.config.policy_asset_id() {
+            return Ok(true);
+        }
+        let store_read = self.store.read()?;
+        if store_read
+            .cache
+            .unblinded
+            .values()
+            .any(|u| u.asset == *asset)
+            || store_read.known_assets().contains(asset)
+        {
+            return Ok(true);
+        }
+        Ok(store_read.reissuance_token_of(asset).is_some()
+            || store_read.is_reissuable(asset)
+            || store_read.asset_metadata(asset).is_some()
+            || embedded_metadata(asset).is_some())
+    }
+
+    /// Look up in the registry the assets of `assets` unknown to the wallet, caching the
+    /// metadata of the registered ones so that they become known
+    #[cfg(feature = "registry")]
+    pub fn lookup_assets(
+        &self,
+        registry: &RegistryClient,
+        assets: &[elements::issuance::AssetId],
+    ) -> Result<(), Error> {
+        for asset in assets {
+            if self.is_known_asset(asset)? {
+                continue;
+            }
+            if let Some(cached) = registry.metadata(asset, None)? {
+                self.store.write()?.asset_metadata_insert(*asset, cached)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::cognitive_complexity)]
+    pub fn create_tx(&self, opt: &mut CreateTransactionOpt) -> Result<TransactionDetails, Error> {
+        info!("create_tx {:?}", redact(&opt));
+
+        if let Some(request_id) = &opt.request_id {
+            if let Some(details) = self.store.read()?.request(request_id) {
+                info!("create_tx returning the tx of request {}", request_id);
+                return Ok(details.clone());
+            }
+        }
+
+        let network = self.config.network();
+        let policy_asset = self.config.policy_asset_id();
+        if opt.addressees.iter().any(|a| a.contact().is_some()) {
+            let store_read = self.store.read()?;
+            for addressee in opt.addressees.iter_mut() {
+                if let Some(name) = addressee.contact() {
+                    let contact = store_read
+                        .contact(name)
+                        .ok_or_else(|| Error::Generic(format!("unknown contact {}", name)))?;
+                    addressee.resolve(contact, policy_asset)?;
+                }
+            }
+        }
+
+        opt.validate(network, policy_asset)?;
+
+        if !opt.spend_reissuance_tokens {
+            let store_read = self.store.read()?;
+            for address_amount in opt.addressees.iter() {
+                let asset = address_amount.asset_or(policy_asset);
+                if store_read.reissuance_token_of(&asset).is_some() {
+                    return Err(Error::ReissuanceToken(asset));
+                }
+            }
         }
 
-        for address_amount in opt.addressees.iter() {
-            if address_amount.satoshi() <= DUST_VALUE {
-                if address_amount.asset() == self.config.policy_asset() {
-                    // we apply dust rules for liquid bitcoin as elements do
-                    return Err(Error::InvalidAmount);
+        if opt.check_assets {
+            for address_amount in opt.addressees.iter() {
+                let asset = address_amount.asset_or(policy_asset);
+                if !self.is_known_asset(&asset)? {
+                    return Err(Error::UnknownAsset(asset));
                 }
             }
         }
 
-        // convert from satoshi/kbyte to satoshi/byte
-        let default_value = 100;
-        let fee_rate = (opt.fee_rate.unwrap_or(default_value) as f64) / 1000.0;
-        info!("target fee_rate {:?} satoshi/byte", fee_rate);
+        let lock_time = self.lock_time(opt.lock_time)?;
+
+        let fee_rate = self.config.fee_rate(opt.fee_rate)?;
+        info!("target fee_rate {}", fee_rate);
 
-        let utxos = match &opt.utxos {
+        let mut utxos = match &opt.utxos {
             None => self.utxos()?,
             Some(utxos) => utxos.clone(),
         };
+        if !opt.from_labels.is_empty() {
+            let store_read = self.store.read()?;
+            utxos.retain(|u| store_read.has_label(&u.txo.script_pubkey, &opt.from_labels));
+        }
+        if opt.fee_utxos.is_some() || opt.fee_account.is_some() {
+            let fee_utxos = self.fee_utxos(opt, &utxos)?;
+            utxos.retain(|u| u.unblinded.asset != policy_asset);
+            utxos.extend(fee_utxos);
+        }
         info!("utxos len:{}", utxos.len());
 
         let mut tx = elements::Transaction {
             version: 2,
-            lock_time: 0,
+            lock_time,
             input: vec![],
             output: vec![],
         };
@@ -362,38 +1278,307 @@ impl WalletCtx {
 
         // STEP 1) add the outputs requested for this transactions
         for out in opt.addressees.iter() {
-            add_output(&mut tx, &out.address(), out.satoshi(), out.asset().to_hex())
-                .map_err(|_| Error::InvalidAddress)?;
+            let asset = out.asset_or(policy_asset);
+            match out.address() {
+                Some(address) => add_output(&mut tx, &address, out.satoshi().as_sat(), asset)
+                    .map_err(|_| Error::InvalidAddress)?,
+                None => add_pegout_output(
+                    &mut tx,
+                    out.script_pubkey(network)?,
+                    out.satoshi().as_sat(),
+                    asset,
+                ),
+            }
         }
 
         // STEP 2) add utxos until tx outputs are covered (including fees) or fail
+        // STEP 3) adding change(s)
+        let store_read = self.store.read()?;
+        let mut changes = match &self.coin_selector {
+            Some(coin_selector) => self.add_selection(
+                &mut tx,
+                fee_rate,
+                &utxos,
+                coin_selector.as_ref(),
+                opt.spend_reissuance_tokens,
+                &store_read,
+            )?,
+            None => {
+                let fee_rate = fee_rate.as_sat_per_vbyte();
+                self.add_inputs(
+                    &mut tx,
+                    fee_rate,
+                    &utxos,
+                    opt.spend_reissuance_tokens,
+                    &store_read,
+                )?;
+                self.add_changes(&mut tx, fee_rate, &store_read)?
+            }
+        };
+
+        for input in tx.input.iter_mut() {
+            if let Some(sequence) = opt.sequences.get(&input.previous_output) {
+                input.sequence = *sequence;
+            }
+        }
+        if opt.lock_time.is_some() && tx.input.iter().all(|i| i.sequence == 0xffff_ffff) {
+            // final sequences disable the locktime
+            return Err(Error::InvalidLockTime(tx.lock_time));
+        }
+
+        // randomized by default, BIP69 has been rejected because lacks wallets adoption
+        order_tx(&mut tx, opt.ordering);
+        locate_changes(&tx, &mut changes);
+
+        let policy_asset = Some(elements::confidential::Asset::Explicit(
+            self.config.policy_asset_id(),
+        ));
+        let fee_val = fee(
+            &tx,
+            &store_read.cache.all_txs,
+            &store_read.cache.unblinded,
+            &policy_asset,
+        )?; // recompute exact fee_val from built tx
+        add_fee_output(&mut tx, fee_val, &policy_asset)?;
+
+        info!("created tx fee {:?}", redact(fee_val));
+
+        let mut satoshi = my_balance_changes(&tx, &store_read.cache.unblinded);
+
+        for (_, v) in satoshi.iter_mut() {
+            *v = v.abs();
+        }
+
+        let privacy_warnings = if opt.privacy_warnings {
+            let payments: Vec<(Script, u64)> = opt
+                .addressees
+                .iter()
+                .map(|a| Ok((a.script_pubkey(network)?, a.satoshi().as_sat())))
+                .collect::<Result<_, Error>>()?;
+            let mut used_scripts: HashSet<Script> = HashSet::new();
+            for txid in store_read.cache.heights.keys() {
+                if let Some(tx) = store_read.cache.all_txs.get(txid)? {
+                    used_scripts.extend(tx.output.iter().map(|o| o.script_pubkey.clone()));
+                }
+            }
+            privacy_warnings(&tx, &payments, &used_scripts)
+        } else {
+            vec![]
+        };
+
+        check_standardness(&tx, self.config.policy_asset_id(), self.address_type())?;
+
+        // the addresses and the values are lost once the outputs are blinded
+        let addresses: HashMap<Script, String> = opt
+            .addressees
+            .iter()
+            .map(|a| {
+                let address = match (a.address(), a.mainchain_address()) {
+                    (Some(address), _) => address.to_string(),
+                    (None, Some(address)) => address.to_string(),
+                    (None, None) => return Err(Error::InvalidAddress),
+                };
+                Ok((a.script_pubkey(network)?, address))
+            })
+            .collect::<Result<_, Error>>()?;
+        let counterparties = self.counterparties(&tx, &store_read, &addresses);
+        drop(store_read);
+        if let Some(input) = tx.input.first() {
+            self.store
+                .write()?
+                .counterparties_insert(input.previous_output, counterparties.clone())?;
+        }
+
+        let mut details = TransactionDetails::new(
+            tx,
+            satoshi,
+            fee_val.into(),
+            None,
+            SPVVerifyResult::NotVerified,
+        );
+        details.change_outputs = changes;
+        details.privacy_warnings = privacy_warnings;
+        details.counterparties = counterparties;
+        if let Some(account) = &opt.fee_account {
+            // labelled only once signed, the change address is used again by the next tx
+            // otherwise
+            let mut store_write = self.store.write()?;
+            for change in details.change_outputs.iter() {
+                if change.asset == self.config.policy_asset_id() {
+                    store_write.pending_label_insert(change.address.script_pubkey(), account);
+                }
+            }
+        }
+        if let Some(request_id) = &opt.request_id {
+            self.store
+                .write()?
+                .request_insert(request_id.clone(), details.clone())?;
+        }
+        Ok(details)
+    }
+
+    /// Policy asset utxos of `candidates` among `opt.fee_utxos`, which must be candidates, and
+    /// in `opt.fee_account`
+    fn fee_utxos(
+        &self,
+        opt: &CreateTransactionOpt,
+        candidates: &[UnblindedTXO],
+    ) -> Result<Vec<UnblindedTXO>, Error> {
+        let policy_asset = self.config.policy_asset_id();
+        if let Some(fee_utxos) = &opt.fee_utxos {
+            for outpoint in fee_utxos.iter() {
+                if !candidates
+                    .iter()
+                    .any(|u| u.txo.outpoint == *outpoint && u.unblinded.asset == policy_asset)
+                {
+                    return Err(Error::UtxoNotFound(*outpoint));
+                }
+            }
+        }
+        let store_read = self.store.read()?;
+        Ok(candidates
+            .iter()
+            .filter(|u| u.unblinded.asset == policy_asset)
+            .filter(|u| {
+                opt.fee_utxos
+                    .as_ref()
+                    .map_or(true, |f| f.contains(&u.txo.outpoint))
+            })
+            .filter(|u| {
+                opt.fee_account.as_ref().map_or(true, |account| {
+                    store_read.in_account(&u.txo.outpoint, &u.txo.script_pubkey, account)
+                })
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Add wallet inputs and changes to `tx`, an unsigned transaction with outputs only, pay the
+    /// fee and blind it.
+    ///
+    /// Outputs must have explicit assets and values. Outputs with a blinding public key in the
+    /// nonce are blinded, the others, such as covenant outputs, stay unconfidential. Signing the
+    /// returned transaction does not blind it again.
+    pub fn fund_tx(
+        &self,
+        mut tx: elements::Transaction,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<TransactionDetails, Error> {
+        info!("fund_tx {:?}", redact(&tx));
+        if !tx.input.is_empty() {
+            return Err(Error::Generic("fund_tx: transaction has inputs".into()));
+        }
+        if tx.output.is_empty() {
+            return Err(Error::EmptyAddressees);
+        }
+        for output in tx.output.iter() {
+            match (output.asset, output.value) {
+                (Asset::Explicit(_), Value::Explicit(value)) if value > 0 => (),
+                _ => return Err(Error::InvalidAmount),
+            }
+            if output.is_fee() {
+                return Err(Error::Generic(
+                    "fund_tx: fee output is added by the wallet".into(),
+                ));
+            }
+        }
+
+        let fee_rate = self.config.fee_rate(fee_rate)?.as_sat_per_vbyte();
+        let utxos = self.utxos()?;
         let store_read = self.store.read()?;
+        self.add_inputs(&mut tx, fee_rate, &utxos, false, &store_read)?;
+        let mut changes = self.add_changes(&mut tx, fee_rate, &store_read)?;
+        scramble(&mut tx);
+        locate_changes(&tx, &mut changes);
+
+        let policy_asset = Some(Asset::Explicit(self.config.policy_asset_id()));
+        let fee_val = fee(
+            &tx,
+            &store_read.cache.all_txs,
+            &store_read.cache.unblinded,
+            &policy_asset,
+        )?;
+        add_fee_output(&mut tx, fee_val, &policy_asset)?;
+        info!("funded tx fee {:?}", redact(fee_val));
+
+        let mut satoshi = my_balance_changes(&tx, &store_read.cache.unblinded);
+        for (_, v) in satoshi.iter_mut() {
+            *v = v.abs();
+        }
+        drop(store_read);
+        self.blind_tx(&mut tx)?;
+
+        check_standardness(&tx, self.config.policy_asset_id(), self.address_type())?;
+        let mut details = TransactionDetails::new(
+            tx,
+            satoshi,
+            fee_val.into(),
+            None,
+            SPVVerifyResult::NotVerified,
+        );
+        details.change_outputs = changes;
+        Ok(details)
+    }
+
+    /// Add inputs from `utxos` until the outputs of `tx` and the fee are covered, spending
+    /// the biggest utxos first. Reissuance tokens are selected only if `spend_reissuance_tokens`.
+    fn add_inputs(
+        &self,
+        tx: &mut elements::Transaction,
+        fee_rate: f64,
+        utxos: &[UnblindedTXO],
+        spend_reissuance_tokens: bool,
+        store_read: &StoreMeta,
+    ) -> Result<(), Error> {
         let mut used_utxo: HashSet<elements::OutPoint> = HashSet::new();
+        // every iteration adds a utxo not used yet or fails, so the loop ends after at most
+        // `utxos.len()` iterations even if the estimated fee grows with the inputs
         loop {
             let mut needs = needs(
-                &tx,
+                tx,
                 fee_rate,
-                self.config.policy_asset(),
+                self.config.policy_asset_id(),
                 &store_read.cache.all_txs,
                 &store_read.cache.unblinded,
-            );
-            info!("needs: {:?}", needs);
+                self.address_type(),
+            )?;
+            info!("needs: {:?}", redact(&needs));
             if needs.is_empty() {
                 // SUCCESS tx doesn't need other inputs
                 break;
             }
 
-            let (asset, _) = needs.pop().unwrap(); // safe to unwrap just checked it's not empty
+            let (asset, missing) = needs.pop().unwrap(); // safe to unwrap just checked it's not empty
 
             // taking only utxos of current asset considered, filters also utxos used in this loop
             let mut asset_utxos: Vec<&UnblindedTXO> = utxos
                 .iter()
                 .filter(|u| u.unblinded.asset == asset && !used_utxo.contains(&u.txo.outpoint))
+                .filter(|u| self.is_selectable(u, spend_reissuance_tokens, store_read))
                 .collect();
 
             // sort by biggest utxo, random maybe another option, but it should be deterministically random (purely random breaks send_all algorithm)
             asset_utxos.sort_by(|a, b| a.unblinded.value.cmp(&b.unblinded.value));
-            let utxo = asset_utxos.pop().ok_or(Error::InsufficientFunds)?;
+            let utxo = match asset_utxos.pop() {
+                Some(utxo) => utxo,
+                None => {
+                    let estimated_fee = estimated_fee(
+                        tx,
+                        fee_rate,
+                        estimated_changes(
+                            tx,
+                            &store_read.cache.all_txs,
+                            &store_read.cache.unblinded,
+                        )?,
+                        self.address_type(),
+                    );
+                    return Err(Error::InsufficientAssetFunds {
+                        asset,
+                        missing,
+                        estimated_fee,
+                    });
+                }
+            };
 
             // Don't spend same script together in liquid. This would allow an attacker
             // to cheaply send assets without value to the target, which will have to
@@ -401,100 +1586,613 @@ impl WalletCtx {
             // While blinded address are required and not public knowledge,
             // they are still available to whom transacted with us in the past
             used_utxo.insert(utxo.txo.outpoint.clone());
-            add_input(&mut tx, utxo.txo.outpoint.clone());
+            self.add_wallet_input(tx, utxo, store_read);
         }
+        Ok(())
+    }
 
-        // STEP 3) adding change(s)
+    /// Add the inputs and the changes chosen by `coin_selector` among `utxos` to `tx`, checking
+    /// they balance the outputs and pay at least the fee estimated at `fee_rate`
+    fn add_selection(
+        &self,
+        tx: &mut elements::Transaction,
+        fee_rate: FeeRate,
+        utxos: &[UnblindedTXO],
+        coin_selector: &dyn CoinSelector,
+        spend_reissuance_tokens: bool,
+        store_read: &StoreMeta,
+    ) -> Result<Vec<ChangeOutput>, Error> {
+        let candidates: Vec<UnblindedTXO> = utxos
+            .iter()
+            .filter(|u| self.is_selectable(u, spend_reissuance_tokens, store_read))
+            .cloned()
+            .collect();
+        let targets = explicit_outputs(tx)?;
+        let fee_model = FeeModel::new(
+            tx,
+            fee_rate,
+            self.config.policy_asset_id(),
+            self.address_type(),
+        );
+        let selection = coin_selector.select(&candidates, &targets, &fee_model)?;
+        let fee = check_selection(&selection, &candidates, &targets, &fee_model)?;
+        info!(
+            "coin selector spends {} utxos, fee {:?}",
+            selection.inputs.len(),
+            redact(fee)
+        );
+
+        for outpoint in selection.inputs.iter() {
+            // checked by check_selection
+            let utxo = candidates
+                .iter()
+                .find(|u| u.txo.outpoint == *outpoint)
+                .unwrap();
+            self.add_wallet_input(tx, utxo, store_read);
+        }
+        let changes: Vec<_> = selection.changes.iter().filter(|(_, s)| **s > 0).collect();
+        let mut change_outputs = vec![];
+        for (i, (asset, satoshi)) in changes.into_iter().enumerate() {
+            let change_index = store_read.cache.indexes.internal + i as u32 + 1;
+            change_outputs.push(self.add_change(tx, change_index, *satoshi, *asset)?);
+        }
+        Ok(change_outputs)
+    }
+
+    /// Whether `utxo` could be spent by the transactions created by the wallet
+    fn is_selectable(
+        &self,
+        utxo: &UnblindedTXO,
+        spend_reissuance_tokens: bool,
+        store_read: &StoreMeta,
+    ) -> bool {
+        is_mature(utxo, store_read)
+            && !self.needs_cosigners(utxo, store_read)
+            && (spend_reissuance_tokens || utxo.reissuance_token_of.is_none())
+            && !utxo.commitments_mismatch
+    }
+
+    fn add_wallet_input(
+        &self,
+        tx: &mut elements::Transaction,
+        utxo: &UnblindedTXO,
+        store_read: &StoreMeta,
+    ) {
+        add_input(tx, utxo.txo.outpoint.clone());
+        if let Some(template) = store_read.script_template(&utxo.txo.script_pubkey) {
+            let last = tx.input.len() - 1;
+            tx.input[last].sequence = template.sequence();
+        }
+    }
+
+    /// Add the change outputs of `tx` on the next internal addresses
+    fn add_changes(
+        &self,
+        tx: &mut elements::Transaction,
+        fee_rate: f64,
+        store_read: &StoreMeta,
+    ) -> Result<Vec<ChangeOutput>, Error> {
         let estimated_fee = estimated_fee(
-            &tx,
+            tx,
             fee_rate,
-            estimated_changes(&tx, &store_read.cache.all_txs, &store_read.cache.unblinded),
+            estimated_changes(tx, &store_read.cache.all_txs, &store_read.cache.unblinded)?,
+            self.address_type(),
         );
         let changes = changes(
-            &tx,
+            tx,
             estimated_fee,
-            self.config.policy_asset(),
+            self.config.policy_asset_id(),
             &store_read.cache.all_txs,
             &store_read.cache.unblinded,
-        );
-        for (i, (asset, satoshi)) in changes.iter().enumerate() {
+        )?;
+        let mut change_outputs = vec![];
+        for (i, (asset, satoshi)) in changes.into_iter().enumerate() {
             let change_index = store_read.cache.indexes.internal + i as u32 + 1;
-            let change_address = self.derive_address(&self.xpub, [1, change_index])?;
+            let change = self.add_change(tx, change_index, satoshi, asset)?;
             info!(
                 "adding change to {} of {} asset {:?}",
-                &change_address, satoshi, asset
+                redact(&change.address),
+                redact(satoshi),
+                asset
             );
-            add_output(&mut tx, &change_address, *satoshi, asset.to_hex())?;
+            change_outputs.push(change);
         }
+        Ok(change_outputs)
+    }
 
-        // randomize inputs and outputs, BIP69 has been rejected because lacks wallets adoption
-        scramble(&mut tx);
+    /// Add an output paying `satoshi` of `asset` to the internal address `change_index`, the
+    /// vout of the returned change is the one of the output as added
+    fn add_change(
+        &self,
+        tx: &mut elements::Transaction,
+        change_index: u32,
+        satoshi: u64,
+        asset: elements::issuance::AssetId,
+    ) -> Result<ChangeOutput, Error> {
+        let address = self.derive_address(&self.xpub, [1, change_index])?;
+        add_output(tx, &address, satoshi, asset)?;
+        Ok(ChangeOutput {
+            vout: tx.output.len() as u32 - 1,
+            address,
+            asset,
+            satoshi: Amount::from_sat(satoshi),
+            derivation_path: DerivationPath::from(vec![
+                ChildNumber::from(1),
+                ChildNumber::from(change_index),
+            ]),
+        })
+    }
 
-        let policy_asset = Some(elements::confidential::Asset::Explicit(
-            self.config.policy_asset(),
-        ));
-        let fee_val = fee(
-            &tx,
-            &store_read.cache.all_txs,
-            &store_read.cache.unblinded,
-            &policy_asset,
-        )?; // recompute exact fee_val from built tx
-        add_fee_output(&mut tx, fee_val, &policy_asset)?;
+    /// Move all the wallet funds to the addresses returned by `next_address`, for instance the
+    /// next addresses of a new wallet, to rotate the wallet keys.
+    ///
+    /// Every transaction spends utxos of a single asset, at most `SWEEP_MAX_INPUTS` of them.
+    /// Fees are paid with policy asset utxos and their remainder is sent to a new address too,
+    /// so that no change is left on this wallet. The transactions are unsigned and do not
+    /// depend on each other, the remaining policy asset is swept last.
+    pub fn sweep_to<F>(
+        &self,
+        mut next_address: F,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<Vec<TransactionDetails>, Error>
+    where
+        F: FnMut() -> Result<elements::Address, Error>,
+    {
+        let fee_rate = self.config.fee_rate(fee_rate)?.as_sat_per_vbyte();
+        let lock_time = self.lock_time(None)?;
+        let policy_asset = self.config.policy_asset_id();
+        let policy_asset_explicit = Some(Asset::Explicit(policy_asset));
+
+        let mut by_asset: HashMap<elements::issuance::AssetId, Vec<UnblindedTXO>> = HashMap::new();
+        for utxo in self
+            .utxos()?
+            .into_iter()
+            .filter(|u| !u.commitments_mismatch)
+        {
+            by_asset.entry(utxo.unblinded.asset).or_default().push(utxo);
+        }
+        // biggest policy asset utxos are popped first to pay fees
+        let mut policy_utxos = by_asset.remove(&policy_asset).unwrap_or_default();
+        policy_utxos.sort_by_key(|u| u.unblinded.value);
+        let mut assets: Vec<_> = by_asset.into_iter().collect();
+        assets.sort_by_key(|(asset, _)| *asset);
 
-        info!("created tx fee {:?}", fee_val);
+        let store_read = self.store.read()?;
+        let mut txs = vec![];
+        let mut push_tx = |mut tx: elements::Transaction, fee: u64| -> Result<(), Error> {
+            add_fee_output(&mut tx, fee, &policy_asset_explicit)?;
+            scramble(&mut tx);
+            let mut satoshi = my_balance_changes(&tx, &store_read.cache.unblinded);
+            for (_, v) in satoshi.iter_mut() {
+                *v = v.abs();
+            }
+            info!("created sweep tx fee {:?}", redact(fee));
+            txs.push(TransactionDetails::new(
+                tx,
+                satoshi,
+                fee.into(),
+                None,
+                SPVVerifyResult::NotVerified,
+            ));
+            Ok(())
+        };
 
-        let mut satoshi = my_balance_changes(&tx, &store_read.cache.unblinded);
+        for (asset, utxos) in assets {
+            // leave room for the inputs paying the fee
+            for chunk in utxos.chunks(SWEEP_MAX_INPUTS - SWEEP_FEE_INPUTS) {
+                let mut tx = elements::Transaction {
+                    version: 2,
+                    lock_time,
+                    input: vec![],
+                    output: vec![],
+                };
+                for utxo in chunk {
+                    add_input(&mut tx, utxo.txo.outpoint);
+                }
+                let value = chunk
+                    .iter()
+                    .try_fold(0u64, |sum, u| sum.checked_add(u.unblinded.value))
+                    .ok_or(Error::AmountOverflow)?;
+                add_output(&mut tx, &next_address()?, value, asset)?;
+
+                // the fee estimation includes the output with the remainder of the fee inputs
+                let mut fee_inputs_value = 0u64;
+                let mut fee;
+                loop {
+                    fee = estimated_fee(&tx, fee_rate, 1, self.address_type());
+                    if fee_inputs_value > fee + DUST_VALUE {
+                        break;
+                    }
+                    if tx.input.len() >= SWEEP_MAX_INPUTS {
+                        return Err(Error::Generic(
+                            "sweep: fee inputs exceed the transaction inputs limit".into(),
+                        ));
+                    }
+                    let utxo = policy_utxos.pop().ok_or(Error::InsufficientFunds)?;
+                    fee_inputs_value = fee_inputs_value
+                        .checked_add(utxo.unblinded.value)
+                        .ok_or(Error::AmountOverflow)?;
+                    add_input(&mut tx, utxo.txo.outpoint);
+                }
+                add_output(
+                    &mut tx,
+                    &next_address()?,
+                    fee_inputs_value - fee,
+                    policy_asset,
+                )?;
+                push_tx(tx, fee)?;
+            }
+        }
 
-        for (_, v) in satoshi.iter_mut() {
-            *v = v.abs();
+        policy_utxos.reverse();
+        for chunk in policy_utxos.chunks(SWEEP_MAX_INPUTS) {
+            let mut tx = elements::Transaction {
+                version: 2,
+                lock_time,
+                input: vec![],
+                output: vec![],
+            };
+            for utxo in chunk {
+                add_input(&mut tx, utxo.txo.outpoint);
+            }
+            let value = chunk
+                .iter()
+                .try_fold(0u64, |sum, u| sum.checked_add(u.unblinded.value))
+                .ok_or(Error::AmountOverflow)?;
+            let fee = estimated_fee(&tx, fee_rate, 1, self.address_type());
+            if value <= fee + DUST_VALUE {
+                info!("sweep: skipping {} utxos not covering the fee", chunk.len());
+                continue;
+            }
+            add_output(&mut tx, &next_address()?, value - fee, policy_asset)?;
+            push_tx(tx, fee)?;
         }
 
-        // Also return changes used?
-        Ok(TransactionDetails::new(
-            tx,
-            satoshi,
-            fee_val,
-            None,
-            SPVVerifyResult::NotVerified,
-        ))
+        Ok(txs)
     }
-    // TODO when we can serialize psbt
-    //pub fn sign(&self, psbt: PartiallySignedTransaction) -> Result<PartiallySignedTransaction, Error> { Err(Error::Generic("NotImplemented".to_string())) }
 
-    pub fn internal_sign_elements(
+    /// Create a signed transaction sweeping into the wallet the unspent confidential outputs
+    /// locked by the P2SH-P2WPKH script of the WIF key `wif`, such as the outputs of paper or
+    /// gift wallets, which are unblinded with `blinding_key`.
+    ///
+    /// The outputs history is downloaded with `client`, every swept asset is sent to a new
+    /// wallet address and the fee is paid with the swept policy asset.
+    pub fn sweep_external_key(
         &self,
-        tx: &elements::Transaction,
-        input_index: usize,
-        derivation_path: &DerivationPath,
-        value: Value,
-        xprv: ExtendedPrivKey,
-        sighash_type: Option<elements::SigHashType>,
-    ) -> (Script, Vec<Vec<u8>>) {
-        let xprv = xprv.derive_priv(&self.secp, &derivation_path).unwrap();
-        let private_key = &xprv.private_key;
-        let public_key = &PublicKey::from_private_key(&self.secp, private_key);
-
-        let script_code = p2pkh_script(public_key);
-        let sighash_type = sighash_type.unwrap_or(elements::SigHashType::All);
-        let sighash = elements::sighash::SigHashCache::new(tx).segwitv0_sighash(
-            input_index,
-            &script_code,
-            value,
-            sighash_type,
+        client: &dyn BlockchainBackend,
+        wif: &str,
+        blinding_key: &secp256k1::SecretKey,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<TransactionDetails, Error> {
+        let fee_rate = self.config.fee_rate(fee_rate)?.as_sat_per_vbyte();
+        let policy_asset = self.config.policy_asset_id();
+        let private_key = PrivateKey::from_wif(wif)?;
+        let public_key = PublicKey::from_private_key(&self.secp, &private_key);
+        let script_pubkey = p2shwpkh_script(&public_key);
+
+        let history = client
+            .scripts_history(&[script_pubkey.clone()])?
+            .pop()
+            .unwrap_or_default();
+        let txids: Vec<Txid> = history.iter().map(|h| h.txid).collect();
+        let txs = client.transactions(&txids)?;
+        // the transactions spending the outputs of the key are in its history too
+        let spent: HashSet<elements::OutPoint> = txs
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|i| i.previous_output))
+            .collect();
+        let mut prevouts = HashMap::new();
+        for tx in txs.iter() {
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                let outpoint = elements::OutPoint {
+                    txid,
+                    vout: vout as u32,
+                };
+                if output.script_pubkey != script_pubkey || spent.contains(&outpoint) {
+                    continue;
+                }
+                match output.unblind(&self.secp, *blinding_key) {
+                    Ok(secrets) => {
+                        prevouts.insert(outpoint, (output.clone(), secrets));
+                    }
+                    Err(_) => info!("sweep_external_key: cannot unblind {}, ignoring", outpoint),
+                }
+            }
+        }
+        if prevouts.is_empty() {
+            return Err(Error::Generic(
+                "sweep_external_key: no unspent outputs to sweep".into(),
+            ));
+        }
+
+        let mut values: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
+        for (_, secrets) in prevouts.values() {
+            add_amount(&mut values, secrets.asset, secrets.value)?;
+        }
+        let policy_value = values.remove(&policy_asset).unwrap_or(0);
+        let mut assets: Vec<_> = values.into_iter().collect();
+        assets.sort();
+
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for outpoint in prevouts.keys() {
+            add_input(&mut tx, *outpoint);
+        }
+        // check the funds before deriving the addresses of the outputs, counted as changes, so
+        // that a failing sweep does not advance the index; the key inputs are P2SH-P2WPKH
+        if assets.len() >= u8::MAX as usize {
+            return Err(Error::Generic(
+                "sweep_external_key: too many assets to sweep".into(),
+            ));
+        }
+        let fee = estimated_fee(
+            &tx,
+            fee_rate,
+            assets.len() as u8 + 1,
+            AddressType::P2shP2wpkh,
         );
-        let message = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+        if policy_value <= fee + DUST_VALUE {
+            return Err(Error::InsufficientFunds);
+        }
+        let mut satoshi = HashMap::new();
+        for (asset, value) in assets {
+            add_output(&mut tx, &self.next_address()?, value, asset)?;
+            satoshi.insert(asset, value as i64);
+        }
+        add_output(
+            &mut tx,
+            &self.next_address()?,
+            policy_value - fee,
+            policy_asset,
+        )?;
+        satoshi.insert(policy_asset, (policy_value - fee) as i64);
+        add_fee_output(&mut tx, fee, &Some(Asset::Explicit(policy_asset)))?;
+        scramble(&mut tx);
+
+        let inputs: Vec<_> = tx
+            .input
+            .iter()
+            .map(|i| prevouts[&i.previous_output].clone())
+            .collect();
+        self.blind_tx_with_inputs(&mut tx, &inputs)?;
+
+        let script_code = p2pkh_script(&public_key);
+        for (i, (txout, _)) in inputs.iter().enumerate() {
+            let (_, signature) = private_key.sign(
+                &tx,
+                i,
+                &script_code,
+                txout.value,
+                elements::SigHashType::All,
+            )?;
+            tx.input[i].script_sig = p2shwpkh_script_sig(&public_key);
+            tx.input[i].witness.script_witness = vec![signature, public_key.to_bytes()];
+        }
+        info!(
+            "created external key sweep tx inputs {} fee {:?}",
+            inputs.len(),
+            redact(fee)
+        );
+
+        Ok(TransactionDetails::new(
+            tx,
+            satoshi,
+            fee.into(),
+            None,
+            SPVVerifyResult::NotVerified,
+        ))
+    }
+
+    /// Create a transaction spending the wallet policy asset outputs of the unconfirmed
+    /// `parent_txid` to a change address, with a fee bringing the fee rate of the parent and
+    /// child package to `target_fee_rate`.
+    pub fn create_cpfp(
+        &self,
+        parent_txid: &Txid,
+        target_fee_rate: FeeRate,
+    ) -> Result<TransactionDetails, Error> {
+        info!("create_cpfp {} {}", parent_txid, target_fee_rate);
+        let fee_rate = self.config.fee_rate(Some(target_fee_rate))?;
+        let policy_asset = self.config.policy_asset_id();
+        let parent_utxos: Vec<UnblindedTXO> = self
+            .utxos()?
+            .into_iter()
+            .filter(|u| u.txo.outpoint.txid == *parent_txid && u.unblinded.asset == policy_asset)
+            .collect();
+
+        let store_read = self.store.read()?;
+        match store_read.cache.heights.get(parent_txid) {
+            None => return Err(Error::Generic("cpfp: unknown parent transaction".into())),
+            Some(Some(_)) => {
+                return Err(Error::Generic(
+                    "cpfp: parent transaction is confirmed".into(),
+                ))
+            }
+            Some(None) => (),
+        }
+        let parent = store_read
+            .cache
+            .all_txs
+            .get(parent_txid)?
+            .ok_or_else(|| Error::Generic("expected tx".into()))?;
+        if parent_utxos.is_empty() {
+            return Err(Error::Generic(
+                "cpfp: parent transaction has no spendable wallet outputs".into(),
+            ));
+        }
+        let parent_fee = Amount::checked_sum(
+            parent
+                .output
+                .iter()
+                .filter(|o| o.is_fee())
+                .map(|o| Amount::from_sat(o.minimum_value())),
+        )
+        .ok_or(Error::AmountOverflow)?;
+        let parent_vbytes = cached_vbytes(&parent) as u64;
+
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for utxo in parent_utxos.iter() {
+            add_input(&mut tx, utxo.txo.outpoint);
+        }
+        let value = Amount::checked_sum(
+            parent_utxos
+                .iter()
+                .map(|u| Amount::from_sat(u.unblinded.value)),
+        )
+        .ok_or(Error::AmountOverflow)?;
+        let change_index = store_read.cache.indexes.internal + 1;
+        let change_address = self.derive_address(&self.xpub, [1, change_index])?;
+        add_output(&mut tx, &change_address, value.as_sat(), policy_asset)?;
+        add_fee_output(&mut tx, 0, &Some(Asset::Explicit(policy_asset)))?;
+        // the child pays the fee the package misses at the target fee rate, and at least its own
+        let child_vbytes = estimated_vbytes(&tx, self.address_type()) as u64;
+        let package_fee = parent_vbytes
+            .checked_add(child_vbytes)
+            .and_then(|vbytes| fee_rate.fee(vbytes))
+            .ok_or(Error::AmountOverflow)?;
+        let child_fee = fee_rate.fee(child_vbytes).ok_or(Error::AmountOverflow)?;
+        let fee = package_fee.saturating_sub(parent_fee).max(child_fee);
+        let change = value
+            .checked_sub(fee)
+            .filter(|change| change.as_sat() > DUST_VALUE)
+            .ok_or(Error::InsufficientFunds)?;
+        tx.output[0].value = Value::Explicit(change.as_sat());
+        tx.output[1].value = Value::Explicit(fee.as_sat());
+        info!(
+            "created cpfp tx fee {:?}, parent fee {:?}",
+            redact(fee.as_sat()),
+            redact(parent_fee.as_sat())
+        );
+
+        let mut satoshi = my_balance_changes(&tx, &store_read.cache.unblinded);
+        for (_, v) in satoshi.iter_mut() {
+            *v = v.abs();
+        }
+        Ok(TransactionDetails::new(
+            tx,
+            satoshi,
+            fee,
+            None,
+            SPVVerifyResult::NotVerified,
+        ))
+    }
+
+    // TODO when we can serialize psbt
+    //pub fn sign(&self, psbt: PartiallySignedTransaction) -> Result<PartiallySignedTransaction, Error> { Err(Error::Generic("NotImplemented".to_string())) }
+
+    /// Script sig and witness of input `input_index` of `tx` spending a P2SH-P2WPKH output,
+    /// signed with `sighash_type`, SIGHASH_ALL if None. Sighash types with the
+    /// `SIGHASH_RANGEPROOF` flag are rejected unless `Config::dynafed_active`.
+    pub fn internal_sign_elements(
+        &self,
+        tx: &elements::Transaction,
+        input_index: usize,
+        derivation_path: &DerivationPath,
+        value: Value,
+        xprv: ExtendedPrivKey,
+        sighash_type: Option<SigHashFlags>,
+    ) -> Result<(Script, Vec<Vec<u8>>), Error> {
+        let sighash_type = sighash_type.unwrap_or_else(|| elements::SigHashType::All.into());
+        sighash_type.check(self.config.dynafed_active)?;
+        let xprv = xprv.derive_priv(&self.secp, &derivation_path)?;
+        let private_key = &xprv.private_key;
+        let public_key = &PublicKey::from_private_key(&self.secp, private_key);
+
+        let script_code = p2pkh_script(public_key);
+        let sighash = segwitv0_sighash(tx, input_index, &script_code, value, sighash_type);
+        let message = secp256k1::Message::from_slice(&sighash[..])?;
         let signature = self.secp.sign(&message, &private_key.key);
         let mut signature = signature.serialize_der().to_vec();
-        signature.push(sighash_type as u8);
+        signature.push(sighash_type.as_u8());
 
-        let script_sig = p2shwpkh_script_sig(public_key);
+        let script_sig = self.address_type().script_sig(public_key);
         let witness = vec![signature, public_key.to_bytes()];
         info!(
             "added size len: script_sig:{} witness:{}",
             script_sig.len(),
             witness.iter().map(|v| v.len()).sum::<usize>()
         );
-        (script_sig, witness)
+        Ok((script_sig, witness))
+    }
+
+    /// Sign input `input_index` spending an output locked by `template` to the key at
+    /// `derivation_path`
+    fn internal_sign_template(
+        &self,
+        tx: &elements::Transaction,
+        input_index: usize,
+        derivation_path: &DerivationPath,
+        value: Value,
+        xprv: ExtendedPrivKey,
+        template: &dyn SpendableScript,
+    ) -> Result<(Script, Vec<Vec<u8>>), Error> {
+        let (public_key, witness_script, signature) = self.template_signature(
+            tx,
+            input_index,
+            derivation_path,
+            value,
+            xprv,
+            template,
+            elements::SigHashType::All,
+        )?;
+        let mut witness = template.satisfy(signature, &public_key);
+        witness.push(witness_script.into_bytes());
+        Ok((Script::new(), witness))
+    }
+
+    /// Wallet public key, witness script and wallet signature of input `input_index` of `tx`
+    /// spending an output of `template`
+    fn template_signature(
+        &self,
+        tx: &elements::Transaction,
+        input_index: usize,
+        derivation_path: &DerivationPath,
+        value: Value,
+        xprv: ExtendedPrivKey,
+        template: &dyn SpendableScript,
+        sighash_type: elements::SigHashType,
+    ) -> Result<(PublicKey, Script, Vec<u8>), Error> {
+        let xprv = xprv.derive_priv(&self.secp, &derivation_path)?;
+        let private_key = &xprv.private_key;
+        let public_key = PublicKey::from_private_key(&self.secp, private_key);
+
+        let witness_script = template.witness_script(&public_key);
+        let sighash = elements::sighash::SigHashCache::new(tx).segwitv0_sighash(
+            input_index,
+            &witness_script,
+            value,
+            sighash_type,
+        );
+        let message = secp256k1::Message::from_slice(&sighash[..])?;
+        let signature = self.secp.sign(&message, &private_key.key);
+        let mut signature = signature.serialize_der().to_vec();
+        signature.push(sighash_type as u8);
+        Ok((public_key, witness_script, signature))
+    }
+
+    /// True for outputs of script templates needing the signatures of cosigners
+    fn needs_cosigners(&self, utxo: &UnblindedTXO, store_read: &StoreMeta) -> bool {
+        let script = &utxo.txo.script_pubkey;
+        match (
+            store_read.script_template(script),
+            store_read.cache.paths.get(script),
+        ) {
+            (Some(template), Some(path)) => match self.xpub.derive_pub(&self.secp, path) {
+                Ok(derived) => !template.cosigners(&derived.public_key).is_empty(),
+                Err(_) => false,
+            },
+            _ => false,
+        }
     }
 
     pub fn sign_with_mnemonic(
@@ -512,9 +2210,14 @@ impl WalletCtx {
         xprv: ExtendedPrivKey,
     ) -> Result<(), Error> {
         info!("sign");
-        let store_read = self.store.read()?;
         // FIXME: is blinding here the right thing to do?
-        self.blind_tx(tx)?;
+        // transactions returned by fund_tx are already blinded
+        if needs_blinding(tx) {
+            self.blind_tx(tx)?;
+        }
+        let inputs: Vec<usize> = (0..tx.input.len()).collect();
+        self.approve_signing(SignKind::Transaction, tx, &inputs)?;
+        let store_read = self.store.read()?;
 
         for i in 0..tx.input.len() {
             let prev_output = tx.input[i].previous_output;
@@ -522,7 +2225,7 @@ impl WalletCtx {
             let prev_tx = store_read
                 .cache
                 .all_txs
-                .get(&prev_output.txid)
+                .get(&prev_output.txid)?
                 .ok_or_else(|| Error::Generic("expected tx".into()))?;
             let out = prev_tx.output[prev_output.vout as usize].clone();
             let derivation_path: DerivationPath = store_read
@@ -532,13 +2235,76 @@ impl WalletCtx {
                 .ok_or_else(|| Error::Generic("can't find derivation path".into()))?
                 .clone();
 
-            let (script_sig, witness) =
-                self.internal_sign_elements(&tx, i, &derivation_path, out.value, xprv, None);
+            let (script_sig, witness) = match store_read.script_template(&out.script_pubkey) {
+                Some(template) => self.internal_sign_template(
+                    &tx,
+                    i,
+                    &derivation_path,
+                    out.value,
+                    xprv,
+                    template,
+                )?,
+                None => {
+                    self.internal_sign_elements(&tx, i, &derivation_path, out.value, xprv, None)?
+                }
+            };
 
             tx.input[i].script_sig = script_sig;
             tx.input[i].witness.script_witness = witness;
         }
+        drop(store_read);
+        self.signed(tx)
+    }
 
+    /// Sign the inputs of `tx` spending wallet outputs with `signer`, holding the wallet keys or
+    /// asking them to a remote service, see `Signer::sign_wallet_input`.
+    ///
+    /// Like `sign_with_xprv`, `tx` is blinded first if needed and the signing must be approved.
+    pub fn sign_with_signer(
+        &self,
+        tx: &mut elements::Transaction,
+        signer: &dyn Signer,
+    ) -> Result<(), Error> {
+        info!("sign with signer");
+        let sighash_type = elements::SigHashType::All;
+        let inputs: Vec<usize> = self.sighashes(tx)?.into_iter().map(|(i, _, _)| i).collect();
+        self.approve_signing(SignKind::Transaction, tx, &inputs)?;
+        for &i in inputs.iter() {
+            let (out, derivation_path, script_code) = {
+                let store_read = self.store.read()?;
+                let (out, derivation_path) = self
+                    .wallet_prevout(&store_read, tx, i)?
+                    .ok_or_else(|| Error::Generic("input does not spend a wallet output".into()))?;
+                let public_key = self
+                    .xpub
+                    .derive_pub(&self.secp, &derivation_path)?
+                    .public_key;
+                let script_code = self.script_code(&store_read, &out, &public_key);
+                (out, derivation_path, script_code)
+            };
+            let (public_key, signature) = signer.sign_wallet_input(
+                tx,
+                i,
+                &derivation_path,
+                &script_code,
+                out.value,
+                sighash_type,
+            )?;
+            let (flag, der) = signature
+                .split_last()
+                .ok_or_else(|| Error::Generic("empty signature".into()))?;
+            if *flag != sighash_type as u8 {
+                return Err(Error::Generic(format!("unexpected sighash type {}", flag)));
+            }
+            let signature = secp256k1::Signature::from_der(der)?;
+            // checks the key and the signature
+            self.apply_signature(tx, i, &signature, &public_key)?;
+        }
+        self.signed(tx)
+    }
+
+    /// Log the signed `tx` and reserve its change addresses
+    fn signed(&self, tx: &elements::Transaction) -> Result<(), Error> {
         let fee: u64 = tx
             .output
             .iter()
@@ -549,85 +2315,910 @@ impl WalletCtx {
             "transaction final size is {} bytes and {} vbytes and fee is {}",
             tx.get_size(),
             tx.get_weight() / 4,
-            fee
+            redact(fee)
         );
         info!(
             "FINALTX inputs:{} outputs:{}",
             tx.input.len(),
             tx.output.len()
         );
-        /*
-        drop(store_read);
+        // The next sync would update the internal index but we increment the internal index also
+        // here after sign so that if we immediately create another tx we are not reusing addresses
+        // This implies signing multiple times without broadcasting leads to gaps in the internal chain
         let mut store_write = self.store.write()?;
-
-        let changes_used = request.changes_used.unwrap_or(0);
-        if changes_used > 0 {
-            info!("tx used {} changes", changes_used);
-            // The next sync would update the internal index but we increment the internal index also
-            // here after sign so that if we immediately create another tx we are not reusing addresses
-            // This implies signing multiple times without broadcasting leads to gaps in the internal chain
-            store_write.cache.indexes.internal += changes_used;
+        let last_change = tx
+            .output
+            .iter()
+            .filter_map(|o| store_write.cache.paths.get(&o.script_pubkey))
+            .filter_map(|path| match path.as_ref() {
+                [ChildNumber::Normal { index: 1 }, ChildNumber::Normal { index }] => Some(*index),
+                _ => None,
+            })
+            .max();
+        if let Some(last_change) = last_change {
+            if last_change > store_write.cache.indexes.internal {
+                info!("tx used changes up to {}", last_change);
+                store_write.cache.indexes.internal = last_change;
+                store_write.cache_changed()?;
+            }
         }
-        */
+        store_write.pending_labels_signed(tx)?;
+        store_write.requests_update(tx)?;
 
         Ok(())
     }
 
     fn blind_tx(&self, tx: &mut elements::Transaction) -> Result<(), Error> {
-        // TODO: take a PSET
-        let mut pset = elements::pset::PartiallySignedTransaction::from_tx(tx.clone());
-        let mut inp_txout_sec: Vec<Option<elements::TxOutSecrets>> = vec![];
+        let inputs = self.inputs_secrets(tx)?;
+        self.blind_tx_with_inputs(tx, &inputs)
+    }
 
+    /// Previous outputs of the inputs of `tx` and their secrets, all inputs must be of the wallet
+    fn inputs_secrets(
+        &self,
+        tx: &elements::Transaction,
+    ) -> Result<Vec<(elements::TxOut, elements::TxOutSecrets)>, Error> {
         let store_read = self.store.read()?;
-        for input in pset.inputs.iter_mut() {
-            let previous_output =
-                elements::OutPoint::new(input.previous_txid, input.previous_output_index);
+        let mut inputs = vec![];
+        for input in tx.input.iter() {
+            let previous_output = input.previous_output;
             let unblinded = store_read
                 .cache
                 .unblinded
                 .get(&previous_output)
                 .ok_or_else(|| Error::Generic("cannot find unblinded values".into()))?;
-            inp_txout_sec.push(Some(unblinded.clone()));
+            let prev_tx = store_read
+                .cache
+                .all_txs
+                .get(&previous_output.txid)?
+                .ok_or_else(|| Error::Generic("expected tx".into()))?;
+            let txout = prev_tx.output[previous_output.vout as usize].clone();
+            inputs.push((txout, unblinded.clone()));
+        }
+        Ok(inputs)
+    }
+
+    /// Blind the outputs of `tx`, see `blind_once`, `inputs` are the previous outputs and their
+    /// secrets in the order of the tx inputs. Failures are retried with fresh randomness, see
+    /// `blind_with_retries`.
+    fn blind_tx_with_inputs(
+        &self,
+        tx: &mut elements::Transaction,
+        inputs: &[(elements::TxOut, elements::TxOutSecrets)],
+    ) -> Result<(), Error> {
+        let secrets: Vec<_> = inputs.iter().map(|(_, secrets)| secrets.clone()).collect();
+        blind_with_retries(tx, |tx| self.blind_once(&secrets, None, tx))
+    }
+
+    /// Unblind output `vout` of a wallet transaction paying `address`, which is not on chain,
+    /// with the sender ephemeral key derived from the seed, see `blind_once`.
+    ///
+    /// It works on restored wallets too, for the outputs sent to others which the wallet could
+    /// not unblind otherwise.
+    pub fn unblind_sent_output(
+        &self,
+        txid: &Txid,
+        vout: u32,
+        address: &elements::Address,
+    ) -> Result<elements::TxOutSecrets, Error> {
+        let store_read = self.store.read()?;
+        let tx = store_read
+            .cache
+            .all_txs
+            .get(txid)?
+            .ok_or_else(|| Error::Generic(format!("unknown transaction {}", txid)))?;
+        let output = tx
+            .output
+            .get(vout as usize)
+            .ok_or_else(|| Error::Generic(format!("{} has no output {}", txid, vout)))?;
+        let receiver_pk = match address.blinding_pubkey {
+            Some(pk) if address.script_pubkey() == output.script_pubkey => pk,
+            _ => return Err(Error::InvalidAddress),
+        };
+        let (value_commitment, asset_generator, sender_pk, rangeproof) = match (
+            output.value,
+            output.asset,
+            output.nonce,
+            output.witness.rangeproof.as_ref(),
+        ) {
+            (
+                Value::Confidential(value),
+                Asset::Confidential(asset),
+                Nonce::Confidential(sender_pk),
+                Some(rangeproof),
+            ) => (value, asset, sender_pk, rangeproof),
+            _ => return Err(Error::Generic("output is not confidential".into())),
+        };
 
+        let sender_sk = self.blinder.ephemeral_key(&blinding_hash(tx), vout)?;
+        if secp256k1::PublicKey::from_secret_key(&self.secp, &sender_sk) != sender_pk {
+            return Err(Error::Generic(
+                "output was not blinded by the wallet".into(),
+            ));
+        }
+        let shared_secret = make_shared_secret(&receiver_pk, &sender_sk);
+        let (opening, _) = rangeproof.rewind(
+            &self.secp,
+            value_commitment,
+            shared_secret,
+            output.script_pubkey.as_bytes(),
+            asset_generator,
+        )?;
+        let (asset, asset_bf) = parse_rangeproof_message(&opening.message)?;
+        Ok(elements::TxOutSecrets::new(
+            asset,
+            elements::confidential::AssetBlindingFactor::from_slice(asset_bf.as_ref())?,
+            opening.value,
+            elements::confidential::ValueBlindingFactor::from_slice(
+                opening.blinding_factor.as_ref(),
+            )?,
+        ))
+    }
+
+    /// Sign the inputs of `tx` at `indexes`, which must spend wallet outputs, for `kind` once
+    /// approved, see `approve_signing`
+    fn sign_inputs(
+        &self,
+        tx: &mut elements::Transaction,
+        xprv: ExtendedPrivKey,
+        indexes: &[usize],
+        kind: SignKind,
+    ) -> Result<(), Error> {
+        self.approve_signing(kind, tx, indexes)?;
+        let store_read = self.store.read()?;
+        for &i in indexes {
+            let prev_output = tx.input[i].previous_output;
             let prev_tx = store_read
                 .cache
                 .all_txs
-                .get(&input.previous_txid)
+                .get(&prev_output.txid)?
                 .ok_or_else(|| Error::Generic("expected tx".into()))?;
-            let txout = prev_tx.output[input.previous_output_index as usize].clone();
-            input.witness_utxo = Some(txout);
+            let out = prev_tx.output[prev_output.vout as usize].clone();
+            let derivation_path: DerivationPath = store_read
+                .cache
+                .paths
+                .get(&out.script_pubkey)
+                .ok_or_else(|| Error::Generic("can't find derivation path".into()))?
+                .clone();
+
+            let (script_sig, witness) = match store_read.script_template(&out.script_pubkey) {
+                Some(template) => self.internal_sign_template(
+                    &tx,
+                    i,
+                    &derivation_path,
+                    out.value,
+                    xprv,
+                    template,
+                )?,
+                None => {
+                    self.internal_sign_elements(&tx, i, &derivation_path, out.value, xprv, None)?
+                }
+            };
+
+            tx.input[i].script_sig = script_sig;
+            tx.input[i].witness.script_witness = witness;
+        }
+        Ok(())
+    }
+
+    /// Previous output spent by input `index` of `tx` and the derivation path of its key, `None`
+    /// if the input does not spend a wallet output
+    fn wallet_prevout(
+        &self,
+        store_read: &StoreMeta,
+        tx: &elements::Transaction,
+        index: usize,
+    ) -> Result<Option<(elements::TxOut, DerivationPath)>, Error> {
+        let prev_output = match tx.input.get(index) {
+            Some(input) => input.previous_output,
+            None => return Ok(None),
+        };
+        let prev_tx = match store_read.cache.all_txs.get(&prev_output.txid)? {
+            Some(prev_tx) => prev_tx,
+            None => return Ok(None),
+        };
+        let out = match prev_tx.output.get(prev_output.vout as usize) {
+            Some(out) => out.clone(),
+            None => return Ok(None),
+        };
+        Ok(store_read
+            .cache
+            .paths
+            .get(&out.script_pubkey)
+            .map(|derivation_path| (out, derivation_path.clone())))
+    }
+
+    /// Script code signed by the inputs spending `out`, locked to `public_key`
+    fn script_code(
+        &self,
+        store_read: &StoreMeta,
+        out: &elements::TxOut,
+        public_key: &PublicKey,
+    ) -> Script {
+        match store_read.script_template(&out.script_pubkey) {
+            Some(template) => template.witness_script(public_key),
+            None => p2pkh_script(public_key),
+        }
+    }
+
+    /// What external signers must sign: for every input of `tx` spending a wallet output, its
+    /// index, the derivation path of the key and the SIGHASH_ALL sighash.
+    ///
+    /// Since the sighashes commit to the outputs, `tx` is blinded first if needed and must not
+    /// be changed until the signatures are applied with `apply_signature`.
+    pub fn sighashes(
+        &self,
+        tx: &mut elements::Transaction,
+    ) -> Result<Vec<(usize, DerivationPath, elements::SigHash)>, Error> {
+        if needs_blinding(tx) {
+            self.blind_tx(tx)?;
+        }
+        let store_read = self.store.read()?;
+        let mut sighashes = vec![];
+        for i in 0..tx.input.len() {
+            let (out, derivation_path) = match self.wallet_prevout(&store_read, tx, i)? {
+                Some(prevout) => prevout,
+                None => continue,
+            };
+            let public_key = self
+                .xpub
+                .derive_pub(&self.secp, &derivation_path)?
+                .public_key;
+            let script_code = self.script_code(&store_read, &out, &public_key);
+            let sighash = elements::sighash::SigHashCache::new(&*tx).segwitv0_sighash(
+                i,
+                &script_code,
+                out.value,
+                elements::SigHashType::All,
+            );
+            sighashes.push((i, derivation_path, sighash));
         }
+        Ok(sighashes)
+    }
 
-        for output in pset.outputs.iter_mut() {
-            // Elements Core when adding a new confidential output puts the receiver blinding key
-            // in the nonce field, then when blinding this is replaced by the sender ephemeral
-            // public key (ecdh_pubkey). We do the same in transaction creation. However when
-            // creating the PSET from the transaction, the value stored in the nonce field is the
-            // receiver blinding key not the ecdh_pubkey, so we swap them.
-            std::mem::swap(&mut output.blinding_key, &mut output.ecdh_pubkey);
-            // We are the owner of all inputs and outputs
-            output.blinder_index = Some(0);
+    /// Complete input `index` of `tx` with the SIGHASH_ALL `signature` of `public_key` produced
+    /// by an external signer for the sighash returned by `sighashes`
+    pub fn apply_signature(
+        &self,
+        tx: &mut elements::Transaction,
+        index: usize,
+        signature: &secp256k1::Signature,
+        public_key: &PublicKey,
+    ) -> Result<(), Error> {
+        let store_read = self.store.read()?;
+        let (out, derivation_path) = self
+            .wallet_prevout(&store_read, tx, index)?
+            .ok_or_else(|| Error::Generic("input does not spend a wallet output".into()))?;
+        let expected = self
+            .xpub
+            .derive_pub(&self.secp, &derivation_path)?
+            .public_key;
+        if &expected != public_key {
+            return Err(Error::Generic("public key does not match the input".into()));
         }
 
-        let inp_txout_sec: Vec<_> = inp_txout_sec.iter().map(|e| e.as_ref()).collect();
-        pset.blind_last(&mut rand::thread_rng(), &self.secp, &inp_txout_sec[..])?;
-        *tx = pset.extract_tx()?;
+        let script_code = self.script_code(&store_read, &out, public_key);
+        let sighash_type = elements::SigHashType::All;
+        let sighash = elements::sighash::SigHashCache::new(&*tx).segwitv0_sighash(
+            index,
+            &script_code,
+            out.value,
+            sighash_type,
+        );
+        let message = secp256k1::Message::from_slice(&sighash[..])?;
+        self.secp.verify(&message, signature, &public_key.key)?;
+        let mut signature = signature.serialize_der().to_vec();
+        signature.push(sighash_type as u8);
+
+        let (script_sig, witness) = match store_read.script_template(&out.script_pubkey) {
+            Some(template) => {
+                let mut witness = template.satisfy(signature, public_key);
+                witness.push(script_code.into_bytes());
+                (Script::new(), witness)
+            }
+            None => (
+                self.address_type().script_sig(public_key),
+                vec![signature, public_key.to_bytes()],
+            ),
+        };
+        tx.input[index].script_sig = script_sig;
+        tx.input[index].witness.script_witness = witness;
         Ok(())
     }
 
-    pub fn get_address(&self) -> Result<elements::Address, Error> {
-        let pointer = {
-            let store = &mut self.store.write()?.cache;
-            store.indexes.external += 1;
-            store.indexes.external
+    /// Payjoin proposal for `tx`, unblinded and unsigned as returned by `create_tx`
+    pub fn payjoin_proposal(&self, tx: &elements::Transaction) -> Result<PayjoinProposal, Error> {
+        let inputs = self
+            .inputs_secrets(tx)?
+            .iter()
+            .map(|(txout, secrets)| PayjoinInput::new(txout, secrets.clone()))
+            .collect();
+        Ok(PayjoinProposal::new(tx, inputs))
+    }
+
+    /// Receiver side of a payjoin: add a wallet utxo of the paid asset, one coin selection could
+    /// spend, to the proposal, increasing the output paying the wallet by its value, then blind
+    /// the transaction and sign the added input. The added input is not paid with a higher fee,
+    /// so the fee rate gets lower.
+    pub fn payjoin_receive(
+        &self,
+        proposal: &PayjoinProposal,
+        mnemonic: &str,
+    ) -> Result<elements::Transaction, Error> {
+        let mut tx = proposal.transaction()?;
+        let mut inputs = vec![];
+        for input in proposal.inputs() {
+            inputs.push((input.txout()?, input.secrets()));
+        }
+        if inputs.len() != tx.input.len() {
+            return Err(Error::Generic(
+                "payjoin: inputs do not match the transaction".into(),
+            ));
+        }
+
+        let utxos = self.utxos()?;
+        let store_read = self.store.read()?;
+        if tx
+            .input
+            .iter()
+            .any(|i| store_read.cache.unblinded.contains_key(&i.previous_output))
+        {
+            return Err(Error::Generic(
+                "payjoin: the proposal spends wallet outputs".into(),
+            ));
+        }
+        // the proposal is not blinded, the payment to the wallet is explicit
+        let (vout, asset, value) = tx
+            .output
+            .iter()
+            .enumerate()
+            .find_map(|(vout, o)| match (o.asset, o.value) {
+                (Asset::Explicit(asset), Value::Explicit(value))
+                    if store_read.cache.paths.contains_key(&o.script_pubkey) =>
+                {
+                    Some((vout, asset, value))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| Error::Generic("payjoin: no payment to the wallet".into()))?;
+
+        let asset_utxos: Vec<&UnblindedTXO> = utxos
+            .iter()
+            .filter(|u| u.unblinded.asset == asset)
+            .filter(|u| self.is_selectable(u, false, &store_read))
+            .collect();
+        let utxo = asset_utxos
+            .choose(&mut rand::thread_rng())
+            .ok_or(Error::InsufficientFunds)?;
+        let outpoint = utxo.txo.outpoint;
+        let prev_tx = store_read
+            .cache
+            .all_txs
+            .get(&outpoint.txid)?
+            .ok_or_else(|| Error::Generic("expected tx".into()))?;
+        let txout = prev_tx.output[outpoint.vout as usize].clone();
+        drop(store_read);
+
+        let value = value
+            .checked_add(utxo.unblinded.value)
+            .ok_or(Error::AmountOverflow)?;
+        tx.output[vout].value = Value::Explicit(value);
+        // the added input is at a random position
+        let position = rand::thread_rng().gen_range(0, tx.input.len() + 1);
+        add_input(&mut tx, outpoint);
+        let input = tx.input.pop().unwrap(); // safe to unwrap, just added
+        tx.input.insert(position, input);
+        inputs.insert(position, (txout, utxo.unblinded.clone()));
+
+        self.blind_tx_with_inputs(&mut tx, &inputs)?;
+        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
+        self.sign_inputs(&mut tx, xprv, &[position], SignKind::Payjoin)?;
+        Ok(tx)
+    }
+
+    /// Sender side of a payjoin: check the transaction returned by the receiver for the
+    /// `original` proposal and sign the wallet inputs. The wallet must spend the same inputs and
+    /// receive the same change, with a fee not lower than in the original.
+    pub fn payjoin_sign(
+        &self,
+        original: &elements::Transaction,
+        tx: &mut elements::Transaction,
+        mnemonic: &str,
+    ) -> Result<(), Error> {
+        let payjoin_err = |msg: &str| Error::Generic(format!("payjoin: {}", msg));
+        if tx.version != original.version
+            || tx.lock_time != original.lock_time
+            || tx.output.len() != original.output.len()
+        {
+            return Err(payjoin_err("transaction changed"));
+        }
+
+        let store_read = self.store.read()?;
+        let mut own_inputs = vec![];
+        for (i, input) in tx.input.iter().enumerate() {
+            let original_input = original
+                .input
+                .iter()
+                .find(|o| o.previous_output == input.previous_output);
+            match original_input {
+                Some(o) if o.sequence == input.sequence => own_inputs.push(i),
+                Some(_) => return Err(payjoin_err("input sequence changed")),
+                None if store_read
+                    .cache
+                    .unblinded
+                    .contains_key(&input.previous_output) =>
+                {
+                    return Err(payjoin_err("added an input of the wallet"))
+                }
+                None => (),
+            }
+        }
+        if own_inputs.len() != original.input.len() {
+            return Err(payjoin_err("removed an input"));
+        }
+
+        let own_outputs = original
+            .output
+            .iter()
+            .filter(|o| store_read.cache.paths.contains_key(&o.script_pubkey));
+        for output in own_outputs {
+            let kept = tx.output.iter().any(|o| {
+                let receiver_sk = match self.blinder.blinding_key(&o.script_pubkey) {
+                    Ok(receiver_sk) => receiver_sk,
+                    Err(_) => return false,
+                };
+                o.script_pubkey == output.script_pubkey
+                    && o.unblind(&self.secp, receiver_sk).ok().map_or(false, |s| {
+                        output.asset == Asset::Explicit(s.asset)
+                            && output.value == Value::Explicit(s.value)
+                    })
+            });
+            if !kept {
+                return Err(payjoin_err("change output changed"));
+            }
+        }
+        drop(store_read);
+
+        let fee = |tx: &elements::Transaction| -> Result<u64, Error> {
+            tx.output
+                .iter()
+                .filter(|o| o.is_fee())
+                .try_fold(0u64, |sum, o| {
+                    sum.checked_add(o.minimum_value())
+                        .ok_or(Error::AmountOverflow)
+                })
         };
+        if fee(tx)? < fee(original)? {
+            return Err(payjoin_err("fee lowered"));
+        }
+        if tx
+            .output
+            .iter()
+            .any(|o| !o.is_fee() && !o.value.is_confidential())
+        {
+            return Err(payjoin_err("unblinded output"));
+        }
+
+        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
+        self.sign_inputs(tx, xprv, &own_inputs, SignKind::Payjoin)
+    }
+
+    /// Next unused external address, the external index is incremented only if the derivation
+    /// succeeds.
+    pub fn next_address(&self) -> Result<elements::Address, Error> {
+        let mut store_write = self.store.write()?;
+        let pointer = store_write.cache.indexes.external + 1;
+        let address = self.derive_address(&self.xpub, [0, pointer])?;
+        store_write.cache.indexes.external = pointer;
+        store_write.cache_changed()?;
+        Ok(address)
+    }
+
+    /// Track and spend the outputs locked by `template` to the wallet keys, returns the index
+    /// of the template to get its addresses. Templates are not persisted, they must be
+    /// registered again, in the same order, every time the wallet is loaded, meanwhile their
+    /// outputs are not listed by `utxos`.
+    pub fn register_script(&self, template: Arc<dyn SpendableScript>) -> Result<usize, Error> {
+        self.store.write()?.register_template(template)
+    }
+
+    /// Confidential address of the template at `template_index` for the external key at
+    /// `pointer`
+    pub fn template_address(
+        &self,
+        template_index: usize,
+        pointer: u32,
+    ) -> Result<elements::Address, Error> {
+        let mut store_write = self.store.write()?;
+        store_write.ensure_scripts(0, pointer)?;
+        let template = store_write
+            .template(template_index)
+            .ok_or_else(|| Error::Generic(format!("unknown template {}", template_index)))?;
+        let path = [
+            ChildNumber::Normal { index: 0 },
+            ChildNumber::Normal { index: pointer },
+        ];
+        let derived = self.xpub.derive_pub(&self.secp, &path)?;
+        let witness_script = template.witness_script(&derived.public_key);
+        let script = template_script_pubkey(template, &derived.public_key);
+        let blinding_key = self.blinder.blinding_key(&script)?;
+        let public_key = secp256k1::PublicKey::from_secret_key(&self.secp, &blinding_key);
+        Ok(elements::Address::p2wsh(
+            &witness_script,
+            Some(public_key),
+            self.config.network().address_params(),
+        ))
+    }
+
+    /// External address `offset` positions after the next one, without consuming it.
+    pub fn peek_address(&self, offset: u32) -> Result<elements::Address, Error> {
+        let pointer = self.store.read()?.cache.indexes.external + 1 + offset;
         self.derive_address(&self.xpub, [0, pointer])
     }
 
+    /// Add `label` to the wallet `address`, utxos received on it could be selected with
+    /// `CreateTransactionOpt::from_labels`
+    pub fn add_label(&self, address: &elements::Address, label: &str) -> Result<(), Error> {
+        let script = address.script_pubkey();
+        let mut store_write = self.store.write()?;
+        if !store_write.cache.paths.contains_key(&script) {
+            return Err(Error::Generic(format!(
+                "address {} is not of the wallet",
+                address
+            )));
+        }
+        store_write.label_insert(script, label)
+    }
+
+    pub fn remove_label(&self, address: &elements::Address, label: &str) -> Result<(), Error> {
+        self.store
+            .write()?
+            .label_remove(&address.script_pubkey(), label)
+    }
+
+    /// Labels of the wallet `address`
+    pub fn labels(&self, address: &elements::Address) -> Result<HashSet<String>, Error> {
+        Ok(self.store.read()?.labels(&address.script_pubkey()))
+    }
+
+    /// Add `name` to the address book with its `address` and the `assets` it accepts, any asset
+    /// if empty, replacing the contact with the same name. Contacts can be paid with
+    /// `Destination::new_contact`.
+    pub fn add_contact(
+        &self,
+        name: &str,
+        address: &str,
+        assets: Vec<elements::issuance::AssetId>,
+    ) -> Result<(), Error> {
+        let contact = Contact::new(name, address, assets)?;
+        self.store.write()?.contact_insert(contact)
+    }
+
+    pub fn remove_contact(&self, name: &str) -> Result<Option<Contact>, Error> {
+        self.store.write()?.contact_remove(name)
+    }
+
+    /// Address book contacts, sorted by name
+    pub fn list_contacts(&self) -> Result<Vec<Contact>, Error> {
+        Ok(self.store.read()?.contacts())
+    }
+
+    /// Contacts whose name matches `query`, closest first, see `Contact::match_score`
+    pub fn find_contacts(&self, query: &str) -> Result<Vec<Contact>, Error> {
+        let mut matches: Vec<(u8, Contact)> = self
+            .list_contacts()?
+            .into_iter()
+            .filter_map(|c| c.match_score(query).map(|score| (score, c)))
+            .collect();
+        // the sort is stable, contacts with the same score stay sorted by name
+        matches.sort_by_key(|(score, _)| *score);
+        Ok(matches.into_iter().map(|(_, c)| c).collect())
+    }
+
+    /// Assign the wallet utxo `outpoint` to the virtual `account`, the assignment prevails over
+    /// the labels of the address the utxo was received on
+    pub fn assign_utxo(&self, outpoint: elements::OutPoint, account: &str) -> Result<(), Error> {
+        let mut store_write = self.store.write()?;
+        if !store_write.cache.utxos.contains(&outpoint) {
+            return Err(Error::Generic(format!("{} is not a wallet utxo", outpoint)));
+        }
+        store_write.utxo_account_insert(outpoint, account)
+    }
+
+    /// Utxos of the virtual `account`, see `StoreMeta::in_account`
+    pub fn account_utxos(&self, account: &str) -> Result<Vec<UnblindedTXO>, Error> {
+        let mut utxos = self.utxos()?;
+        let store_read = self.store.read()?;
+        utxos.retain(|u| store_read.in_account(&u.txo.outpoint, &u.txo.script_pubkey, account));
+        Ok(utxos)
+    }
+
+    /// Balance of every asset of the virtual `account`
+    pub fn account_balance(
+        &self,
+        account: &str,
+    ) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        let mut result = HashMap::new();
+        result.entry(self.config.policy_asset_id()).or_insert(0);
+        for u in self.account_utxos(account)?.iter() {
+            if u.reissuance_token_of.is_none() && !u.commitments_mismatch {
+                *result.entry(u.unblinded.asset).or_default() += u.unblinded.value;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Balance of every asset of every virtual account with utxos, by account name. Accounts do
+    /// not share utxos, so the balances sum to the wallet `balance`.
+    pub fn accounts_balance(
+        &self,
+    ) -> Result<HashMap<String, HashMap<elements::issuance::AssetId, u64>>, Error> {
+        let utxos = self.utxos()?;
+        let store_read = self.store.read()?;
+        let mut result: HashMap<String, HashMap<_, u64>> = HashMap::new();
+        for u in utxos.iter() {
+            if u.reissuance_token_of.is_none() && !u.commitments_mismatch {
+                let account = store_read.utxo_account(&u.txo.outpoint, &u.txo.script_pubkey);
+                add_amount(
+                    result.entry(account.to_string()).or_default(),
+                    u.unblinded.asset,
+                    u.unblinded.value,
+                )?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Create a transaction spending only utxos of the virtual `account`, the change addresses
+    /// are labelled `account` so that the change stays in the account
+    pub fn account_create_tx(
+        &self,
+        account: &str,
+        opt: &mut CreateTransactionOpt,
+    ) -> Result<TransactionDetails, Error> {
+        if opt.utxos.is_some() {
+            return Err(Error::Generic(
+                "account transactions select their own utxos".into(),
+            ));
+        }
+        opt.utxos = Some(self.account_utxos(account)?);
+        let details = self.create_tx(opt);
+        opt.utxos = None;
+        let details = details?;
+
+        let network = self.config.network();
+        let addressees: HashSet<Script> = opt
+            .addressees
+            .iter()
+            .map(|a| a.script_pubkey(network))
+            .collect::<Result<_, Error>>()?;
+        let mut store_write = self.store.write()?;
+        for output in details.transaction.output.iter() {
+            let script = &output.script_pubkey;
+            if store_write.cache.paths.contains_key(script) && !addressees.contains(script) {
+                store_write.label_insert(script.clone(), account)?;
+            }
+        }
+        Ok(details)
+    }
+
+    /// Create an invoice requesting `amount` of `asset` on a new address, expiring after `expiry`
+    /// seconds.
+    pub fn create_invoice(
+        &self,
+        asset: elements::issuance::AssetId,
+        amount: Amount,
+        memo: &str,
+        expiry: u64,
+    ) -> Result<Invoice, Error> {
+        // the external index could go back to the last used one after a sync, skip addresses
+        // of invoices not yet paid
+        let address = loop {
+            let address = self.next_address()?;
+            let store_read = self.store.read()?;
+            if store_read.invoices().values().all(|i| i.address != address) {
+                break address;
+            }
+        };
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill(&mut id);
+        let invoice = Invoice {
+            id: hex::encode(id),
+            address,
+            asset,
+            amount,
+            memo: memo.to_string(),
+            created_at: now()?,
+            expiry,
+        };
+        self.store.write()?.invoice_insert(invoice.clone())?;
+        Ok(invoice)
+    }
+
+    /// Status of the invoice `id`, computed from the wallet transactions paying its address.
+    pub fn invoice_status(&self, id: &str) -> Result<InvoiceStatus, Error> {
+        let store_read = self.store.read()?;
+        let invoice = store_read
+            .invoice(id)
+            .ok_or_else(|| Error::Generic(format!("unknown invoice {}", id)))?;
+        let received = payments_to(&store_read, &invoice.address, &invoice.asset)?
+            .iter()
+            .fold(Amount::ZERO, |acc, p| acc.saturating_add(p.satoshi));
+        let expired = now()? >= invoice.created_at.saturating_add(invoice.expiry);
+        Ok(InvoiceStatus::new(invoice.amount, received, expired))
+    }
+
+    /// Wallet outputs of `asset` paying `address`, confirmed first by height, then unconfirmed.
+    pub fn payments_to(
+        &self,
+        address: &elements::Address,
+        asset: &elements::issuance::AssetId,
+    ) -> Result<Vec<Payment>, Error> {
+        payments_to(&self.store.read()?, address, asset)
+    }
+
+    /// Chain and index of the derivation path of `script`, None if the script is not (yet) in
+    /// the wallet cache.
+    pub fn index_of(&self, script: &Script) -> Result<Option<(u32, u32)>, Error> {
+        let store_read = self.store.read()?;
+        let index = match store_read.cache.paths.get(script).map(|p| p.as_ref()) {
+            Some([ChildNumber::Normal { index: chain }, ChildNumber::Normal { index }]) => {
+                Some((*chain, *index))
+            }
+            _ => None,
+        };
+        Ok(index)
+    }
+
+    pub fn index_of_address(
+        &self,
+        address: &elements::Address,
+    ) -> Result<Option<(u32, u32)>, Error> {
+        self.index_of(&address.script_pubkey())
+    }
+
+    /// Max index used for the external (0) or internal (1) chain.
+    pub fn max_used_index(&self, chain: u32) -> Result<u32, Error> {
+        let indexes = &self.store.read()?.cache.indexes;
+        match chain {
+            0 => Ok(indexes.external),
+            1 => Ok(indexes.internal),
+            _ => Err(Error::Generic(format!("invalid chain {}", chain))),
+        }
+    }
+
+    /// Derive and cache the scripts of the external (0) or internal (1) chain up to
+    /// `up_to_index` included. Blinding keys are not cached, deriving them from a script is a
+    /// single HMAC.
+    pub fn ensure_addresses(&self, chain: u32, up_to_index: u32) -> Result<(), Error> {
+        let mut store_write = self.store.write()?;
+        if !store_write.ensure_scripts(chain, up_to_index)?.is_empty() {
+            store_write.cache_changed()?;
+        }
+        Ok(())
+    }
+
     pub fn liquidex_assets(&self) -> Result<HashSet<elements::issuance::AssetId>, Error> {
         Ok(self.store.read()?.liquidex_assets())
     }
 
+    /// Assets received by the wallet and LiquiDEX assets
+    pub fn known_assets(&self) -> Result<HashSet<elements::issuance::AssetId>, Error> {
+        Ok(self.store.read()?.known_assets())
+    }
+
+    /// Metadata of `asset` from the registry cache or the embedded snapshot, with its icon
+    pub fn asset_metadata(
+        &self,
+        asset: &elements::issuance::AssetId,
+    ) -> Result<Option<AssetMetadata>, Error> {
+        let store_read = self.store.read()?;
+        let mut metadata = match store_read.asset_metadata(asset) {
+            Some(cached) => cached.metadata.clone(),
+            None => match embedded_metadata(asset) {
+                Some(metadata) => metadata,
+                None => return Ok(None),
+            },
+        };
+        if let Some(icon) = store_read.asset_icon(asset) {
+            metadata.icon = Some(icon.clone());
+        }
+        Ok(Some(metadata))
+    }
+
+    /// Fetch from the registry the metadata of `assets` and the icons bundle, only the entries
+    /// changed since the last refresh are downloaded. The icons of `assets` and of the known
+    /// assets are kept. Returns the number of metadata updated.
+    #[cfg(feature = "registry")]
+    pub fn refresh_asset_metadata(
+        &self,
+        registry: &RegistryClient,
+        assets: &[elements::issuance::AssetId],
+    ) -> Result<usize, Error> {
+        let mut updated = 0;
+        for asset in assets {
+            let etag = self
+                .store
+                .read()?
+                .asset_metadata(asset)
+                .and_then(|cached| cached.etag.clone());
+            if let Some(cached) = registry.metadata(asset, etag.as_deref())? {
+                self.store.write()?.asset_metadata_insert(*asset, cached)?;
+                updated += 1;
+            }
+        }
+        // the bundle has the icons of every registered asset, only the wallet ones are kept
+        let store_read = self.store.read()?;
+        let mut wallet_assets = store_read.known_assets();
+        wallet_assets.extend(assets.iter().cloned());
+        // icons not kept by the last refresh may be in a bundle unchanged since then
+        let etag = if assets.iter().all(|a| store_read.asset_icon(a).is_some()) {
+            store_read.icons_etag().cloned()
+        } else {
+            None
+        };
+        drop(store_read);
+        if let Some((mut icons, etag)) = registry.icons(etag.as_deref())? {
+            icons.retain(|asset, _| wallet_assets.contains(asset));
+            self.store.write()?.asset_icons_set(icons, etag)?;
+        }
+        Ok(updated)
+    }
+
+    /// Record the reissuance tokens of `assets` from the issuances reported by the registry,
+    /// fetching the issuance txs from `client`, so that the tokens received by the wallet are
+    /// recognized. Returns the number of tokens recorded.
+    pub fn fetch_reissuance_tokens(
+        &self,
+        client: &dyn BlockchainBackend,
+        assets: &[elements::issuance::AssetId],
+    ) -> Result<usize, Error> {
+        let mut txins = vec![];
+        {
+            let store_read = self.store.read()?;
+            for asset in assets {
+                if store_read.is_reissuable(asset) {
+                    continue;
+                }
+                let txin = store_read
+                    .asset_metadata(asset)
+                    .and_then(|cached| cached.issuance_txin);
+                if let Some(txin) = txin {
+                    txins.push((*asset, txin));
+                }
+            }
+        }
+        if txins.is_empty() {
+            return Ok(0);
+        }
+        let txids: Vec<Txid> = txins.iter().map(|(_, (txid, _))| *txid).collect();
+        let txs = client.transactions(&txids)?;
+        let mut recorded = 0;
+        let mut store_write = self.store.write()?;
+        for ((asset, (_, vin)), tx) in txins.iter().zip(txs.iter()) {
+            let input = match tx.input.get(*vin as usize) {
+                Some(input) if input.has_issuance() => input,
+                _ => continue,
+            };
+            // the registry is not trusted, the issuance must be of the asset
+            let (issued, token) = input.issuance_ids();
+            if issued == *asset && store_write.reissuance_token_insert(token, issued)? {
+                recorded += 1;
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Record `token` as the reissuance token of `asset`, see
+    /// `StoreMeta::reissuance_token_insert`
+    pub fn reissuance_token_insert(
+        &self,
+        token: elements::issuance::AssetId,
+        asset: elements::issuance::AssetId,
+    ) -> Result<bool, Error> {
+        self.store.write()?.reissuance_token_insert(token, asset)
+    }
+
     pub fn liquidex_assets_insert(
         &self,
         asset: elements::issuance::AssetId,
@@ -642,38 +3233,302 @@ impl WalletCtx {
         self.store.write()?.liquidex_assets_remove(asset)
     }
 
+    /// Prices of the completed LiquiDEX swaps between `asset_a` and `asset_b`, in units of
+    /// `asset_b` per unit of `asset_a`, aggregated in candles of `interval` seconds.
+    /// Proposals cancelled by the maker are excluded, see `StoreMeta::liquidex_swaps_completed`.
+    pub fn liquidex_price_history(
+        &self,
+        asset_a: &elements::issuance::AssetId,
+        asset_b: &elements::issuance::AssetId,
+        interval: u32,
+    ) -> Result<Vec<PriceCandle>, Error> {
+        let store_read = self.store.read()?;
+        let completed = store_read.liquidex_swaps_completed()?;
+        let mut points = vec![];
+        for (maker_utxo, swap) in store_read.liquidex_swaps() {
+            let time = match completed
+                .get(maker_utxo)
+                .and_then(|height| store_read.cache.headers.get(height))
+            {
+                Some(header) => header.time,
+                None => continue,
+            };
+            let (value_a, value_b) =
+                if swap.sent_asset == *asset_a && swap.received_asset == *asset_b {
+                    (swap.sent_value.as_sat(), swap.received_value.as_sat())
+                } else if swap.sent_asset == *asset_b && swap.received_asset == *asset_a {
+                    (swap.received_value.as_sat(), swap.sent_value.as_sat())
+                } else {
+                    continue;
+                };
+            if value_a == 0 {
+                continue;
+            }
+            points.push((time, value_b as f64 / value_a as f64, value_a));
+        }
+        points.sort_by_key(|(time, _, _)| *time);
+        ohlc(&points, interval)
+    }
+
+    /// Export LiquiDEX assets as JSON signed with the wallet master key.
+    pub fn liquidex_assets_export(&self, mnemonic: &str) -> Result<String, Error> {
+        let master_xprv = mnemonic2master_xprv(mnemonic, self.config.network())?;
+        if master_xprv.fingerprint(&self.secp) != self.master_fingerprint {
+            return Err(Error::Generic("mnemonic does not match the wallet".into()));
+        }
+        let list = LiquidexAssetList::sign(
+            &self.liquidex_assets()?,
+            &master_xprv.private_key.key,
+            &self.secp,
+        );
+        Ok(serde_json::to_string(&list)?)
+    }
+
+    /// Import LiquiDEX assets exported with `liquidex_assets_export`, the list must be signed by
+    /// `signer` or, if None, by this wallet. Returns the number of assets added.
+    pub fn liquidex_assets_import(
+        &self,
+        list: &str,
+        signer: Option<&PublicKey>,
+    ) -> Result<usize, Error> {
+        let list: LiquidexAssetList = serde_json::from_str(list)?;
+        let pubkey = PublicKey::new(list.verify(&self.secp)?);
+        let trusted = match signer {
+            Some(signer) => *signer == pubkey,
+            None => {
+                let hash = hash160::Hash::hash(&pubkey.to_bytes());
+                Fingerprint::from(&hash[..4]) == self.master_fingerprint
+            }
+        };
+        if !trusted {
+            return Err(Error::InvalidSignature);
+        }
+        self.store.write()?.liquidex_assets_extend(&list.assets)
+    }
+
     pub fn liquidex_make(
         &self,
         opt: &LiquidexMakeOpt,
         mnemonic: &str,
     ) -> Result<LiquidexProposal, Error> {
-        let address = self.get_address()?;
+        self.liquidex_make_at(opt, mnemonic, None)
+    }
+
+    /// Make again the `proposal` made by the wallet at `new_rate`, spending the same utxo and
+    /// receiving on the same address. The swap recorded for the utxo is replaced, however the
+    /// old proposal can be taken until the utxo is spent.
+    pub fn liquidex_reprice(
+        &self,
+        proposal: &LiquidexProposal,
+        new_rate: f64,
+        mnemonic: &str,
+    ) -> Result<LiquidexProposal, Error> {
+        let tx = proposal.transaction()?;
+        let received = proposal.verify_output_commitment(&self.secp)?;
+        let (input, output) = match (tx.input.first(), tx.output.first()) {
+            (Some(input), Some(output)) => (input, output),
+            _ => {
+                return Err(Error::Generic(
+                    "LiquiDEX error unexpected inputs or outputs".into(),
+                ))
+            }
+        };
+        let utxo = input.previous_output;
         let store_read = self.store.read()?;
+        if !store_read.liquidex_swaps().contains_key(&utxo) {
+            return Err(Error::Generic(
+                "LiquiDEX proposal not made by the wallet".into(),
+            ));
+        }
+        let pointer = match store_read
+            .cache
+            .paths
+            .get(&output.script_pubkey)
+            .map(|path| path.as_ref())
+        {
+            Some([ChildNumber::Normal { index: 0 }, ChildNumber::Normal { index }]) => *index,
+            _ => {
+                return Err(Error::Generic(
+                    "LiquiDEX proposal not made by the wallet".into(),
+                ))
+            }
+        };
+        drop(store_read);
+        let opt = LiquidexMakeOpt {
+            utxo,
+            asset_id: received.asset,
+            rate: new_rate,
+        };
+        self.liquidex_make_at(&opt, mnemonic, Some(pointer))
+    }
+
+    /// Unblinded value and output of the utxo spent by the proposal of `opt`
+    fn liquidex_maker_input(
+        &self,
+        store_read: &StoreMeta,
+        opt: &LiquidexMakeOpt,
+    ) -> Result<(elements::TxOutSecrets, elements::TxOut), Error> {
         let unblinded_input = store_read
             .cache
             .unblinded
             .get(&opt.utxo)
-            .ok_or_else(|| Error::Generic("cannot find unblinded values".into()))?;
+            .filter(|_| store_read.cache.utxos.contains(&opt.utxo))
+            .ok_or_else(|| Error::UtxoNotFound(opt.utxo))?
+            .clone();
+        // the maker must be able to unblind the output receiving the asset
+        if opt.asset_id != self.config.policy_asset_id()
+            && !store_read.known_assets().contains(&opt.asset_id)
+        {
+            return Err(Error::UnknownAsset(opt.asset_id));
+        }
+        let prev_tx = store_read
+            .cache
+            .all_txs
+            .get(&opt.utxo.txid)?
+            .ok_or_else(|| Error::Generic("expected tx".into()))?;
+        let out = prev_tx.output[opt.utxo.vout as usize].clone();
+        Ok((unblinded_input, out))
+    }
+
+    /// Make a proposal that can be taken for any amount up to `max_amount` of the `opt.utxo`
+    /// asset at `opt.rate`. The proposal is not signed: the taker builds the transaction and the
+    /// maker signs it with `liquidex_fill`.
+    pub fn liquidex_make_partial(
+        &self,
+        opt: &LiquidexMakeOpt,
+        max_amount: Amount,
+    ) -> Result<LiquidexProposal, Error> {
+        let mut store_write = self.store.write()?;
+        let store_read = &*store_write;
+        let (unblinded_input, out) = self.liquidex_maker_input(store_read, opt)?;
+        if unblinded_input.asset == opt.asset_id {
+            return Err(Error::Generic(
+                "partial LiquiDEX proposals must swap different assets".into(),
+            ));
+        }
+        if max_amount == Amount::ZERO || max_amount > Amount::from_sat(unblinded_input.value) {
+            return Err(Error::InvalidAmount);
+        }
+        if store_read.script_template(&out.script_pubkey).is_some() {
+            return Err(Error::Generic(
+                "partial LiquiDEX proposals cannot spend script templates".into(),
+            ));
+        }
+        let max_receive = liquidex_receive_value(opt.rate, max_amount)?;
 
-        let receive_value = (opt.rate * unblinded_input.value as f64) as u64;
+        let pointer = store_read.cache.indexes.external + 1;
+        let address = self.derive_address(&self.xpub, [0, pointer])?;
         let mut tx = elements::Transaction {
             version: 2,
             lock_time: 0,
             input: vec![],
             output: vec![],
         };
-        add_input(&mut tx, opt.utxo.clone());
-        add_output(&mut tx, &address, receive_value, opt.asset_id.to_hex())?;
+        add_input(&mut tx, opt.utxo);
+        let partial = LiquidexPartial {
+            address: address.to_string(),
+            asset_id: opt.asset_id,
+            max_amount,
+            max_receive,
+        };
+        let proposal = LiquidexProposal::new_partial(&tx, unblinded_input, partial.clone());
+        store_write.cache.indexes.external = pointer;
+        store_write.cache_changed()?;
+        store_write.liquidex_partial_insert(opt.utxo, partial)?;
+        Ok(proposal)
+    }
+
+    /// Maker side of a partial proposal: sign the maker input of `tx`, built by the taker, if it
+    /// pays the maker at the proposal rate. Each partial proposal is filled once.
+    pub fn liquidex_fill(
+        &self,
+        tx: &elements::Transaction,
+        mnemonic: &str,
+    ) -> Result<elements::Transaction, Error> {
+        let maker_utxo = tx
+            .input
+            .first()
+            .ok_or_else(|| Error::Generic("LiquiDEX fill without inputs".into()))?
+            .previous_output;
+        let store_read = self.store.read()?;
+        let partial = store_read
+            .liquidex_partial(&maker_utxo)
+            .cloned()
+            .ok_or_else(|| {
+                Error::Generic(format!("no partial LiquiDEX proposal for {}", maker_utxo))
+            })?;
+        let maker_input = store_read
+            .cache
+            .unblinded
+            .get(&maker_utxo)
+            .filter(|_| store_read.cache.utxos.contains(&maker_utxo))
+            .cloned()
+            .ok_or_else(|| Error::UtxoNotFound(maker_utxo))?;
+        drop(store_read);
+
+        let script = elements::Address::from_str(&partial.address)?.script_pubkey();
+        let receiver_sk = self.blinder.blinding_key(&script)?;
+        let mut received = Amount::ZERO;
+        let mut change = Amount::ZERO;
+        for output in tx.output.iter().filter(|o| o.script_pubkey == script) {
+            let unblinded = output
+                .unblind(&self.secp, receiver_sk)
+                .map_err(|_| Error::Generic("UnblindError".into()))?;
+            let value = Amount::from_sat(unblinded.value);
+            if unblinded.asset == partial.asset_id {
+                received = received.checked_add(value).ok_or(Error::InvalidAmount)?;
+            } else if unblinded.asset == maker_input.asset {
+                change = change.checked_add(value).ok_or(Error::InvalidAmount)?;
+            }
+        }
+        let amount = Amount::from_sat(maker_input.value)
+            .checked_sub(change)
+            .ok_or(Error::InvalidAmount)?;
+        let required = partial.receive_value(amount).ok_or(Error::InvalidAmount)?;
+        if received < required {
+            return Err(Error::Generic(
+                "LiquiDEX fill pays less than the proposal rate".into(),
+            ));
+        }
+
+        let mut tx = tx.clone();
+        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
+        self.sign_inputs(&mut tx, xprv, &[0], SignKind::LiquidexFill)?;
+
+        let mut store_write = self.store.write()?;
+        if store_write.liquidex_partial_remove(&maker_utxo)?.is_none() {
+            return Err(Error::Generic(
+                "partial LiquiDEX proposal already filled".into(),
+            ));
+        }
+        let swap = LiquidexSwap {
+            sent_asset: maker_input.asset,
+            sent_value: amount,
+            received_asset: partial.asset_id,
+            received_value: received,
+        };
+        store_write.liquidex_swap_insert(maker_utxo, swap)?;
+        Ok(tx)
+    }
 
-        let unblinded_output = liquidex_blind(&self.master_blinding, &mut tx, &self.secp)?;
+    /// Make a proposal receiving on the external address at `pointer`, or on a new address if
+    /// None
+    fn liquidex_make_at(
+        &self,
+        opt: &LiquidexMakeOpt,
+        mnemonic: &str,
+        pointer: Option<u32>,
+    ) -> Result<LiquidexProposal, Error> {
+        // the key derivation is slow, done before locking the store
+        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
+        // the address is reserved only if the proposal is made, the lock is released only while
+        // asking the approval
+        let store_write = self.store.write()?;
+        let store_read = &*store_write;
+        let (unblinded_input, out) = self.liquidex_maker_input(store_read, opt)?;
+        let receive_value =
+            liquidex_receive_value(opt.rate, Amount::from_sat(unblinded_input.value))?;
 
-        // FIXME: sign with sighash single || anyonecanpay !!
-        let prev_tx = store_read
-            .cache
-            .all_txs
-            .get(&opt.utxo.txid)
-            .ok_or_else(|| Error::Generic("expected tx".into()))?;
-        let out = prev_tx.output[opt.utxo.vout as usize].clone();
         let derivation_path: DerivationPath = store_read
             .cache
             .paths
@@ -681,40 +3536,257 @@ impl WalletCtx {
             .ok_or_else(|| Error::Generic("can't find derivation path".into()))?
             .clone();
 
-        let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
-        let sighash_type = Some(elements::SigHashType::SinglePlusAnyoneCanPay);
-        let (script_sig, witness) =
-            self.internal_sign_elements(&tx, 0, &derivation_path, out.value, xprv, sighash_type);
+        let pointer = pointer.unwrap_or(store_read.cache.indexes.external + 1);
+        let address = self.derive_address(&self.xpub, [0, pointer])?;
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, opt.utxo.clone());
+        add_output(&mut tx, &address, receive_value.as_sat(), opt.asset_id)?;
 
-        tx.input[0].script_sig = script_sig;
-        tx.input[0].witness.script_witness = witness;
+        let unblinded_output = liquidex_blind(&*self.blinder, &mut tx, &self.secp)?;
+        let spent = self.signing_spent(store_read, &tx, &[0])?;
+        drop(store_write);
+        self.approve_spending(SignKind::LiquidexMake, &tx, &[0], spent)?;
+        let mut store_write = self.store.write()?;
+        if !store_write.cache.utxos.contains(&opt.utxo) {
+            return Err(Error::UtxoNotFound(opt.utxo));
+        }
+        let store_read = &*store_write;
+
+        let sighash_type = elements::SigHashType::SinglePlusAnyoneCanPay;
+        let mut pset = None;
+        match store_read.script_template(&out.script_pubkey) {
+            Some(template) => {
+                let (public_key, witness_script, signature) = self.template_signature(
+                    &tx,
+                    0,
+                    &derivation_path,
+                    out.value,
+                    xprv,
+                    template,
+                    sighash_type,
+                )?;
+                if template.cosigners(&public_key).is_empty() {
+                    let mut witness = template.satisfy(signature, &public_key);
+                    witness.push(witness_script.into_bytes());
+                    tx.input[0].witness.script_witness = witness;
+                } else {
+                    // the cosigners signatures are added by `liquidex_cosign`
+                    let mut partial =
+                        elements::pset::PartiallySignedTransaction::from_tx(tx.clone());
+                    partial.inputs[0].witness_script = Some(witness_script);
+                    partial.inputs[0].witness_utxo = Some(out.clone());
+                    partial.inputs[0].partial_sigs.insert(public_key, signature);
+                    pset = Some(partial);
+                }
+            }
+            None => {
+                let (script_sig, witness) = self.internal_sign_elements(
+                    &tx,
+                    0,
+                    &derivation_path,
+                    out.value,
+                    xprv,
+                    Some(sighash_type.into()),
+                )?;
+                tx.input[0].script_sig = script_sig;
+                tx.input[0].witness.script_witness = witness;
+            }
+        }
 
-        let proposal = LiquidexProposal::new(&tx, unblinded_input.clone(), unblinded_output);
+        let mut proposal = LiquidexProposal::new(&tx, unblinded_input.clone(), unblinded_output);
+        proposal.set_pset(pset.as_ref());
+        let swap = LiquidexSwap {
+            sent_asset: unblinded_input.asset,
+            sent_value: Amount::from_sat(unblinded_input.value),
+            received_asset: opt.asset_id,
+            received_value: receive_value,
+        };
+        if pointer > store_write.cache.indexes.external {
+            store_write.cache.indexes.external = pointer;
+            store_write.cache_changed()?;
+        }
+        store_write.liquidex_swap_insert(opt.utxo, swap)?;
         Ok(proposal)
     }
 
+    /// Add to the `proposal` made spending a multisig output the signature of `signer`, one of
+    /// the cosigners. The returned proposal can be taken once all the cosigners signed it.
+    pub fn liquidex_cosign(
+        &self,
+        proposal: &LiquidexProposal,
+        signer: &dyn Signer,
+    ) -> Result<LiquidexProposal, Error> {
+        let mut pset = proposal
+            .pset()?
+            .ok_or_else(|| Error::Generic("LiquiDEX proposal is already signed".into()))?;
+        let mut tx = proposal.transaction()?;
+        if pset.inputs.len() != 1 || tx.input.len() != 1 {
+            return Err(Error::Generic("LiquiDEX error unexpected inputs".into()));
+        }
+        let input = &mut pset.inputs[0];
+        let (witness_script, prevout) = match (&input.witness_script, &input.witness_utxo) {
+            (Some(witness_script), Some(prevout)) => (witness_script.clone(), prevout.clone()),
+            _ => {
+                return Err(Error::Generic(
+                    "LiquiDEX proposal misses the prevout".into(),
+                ))
+            }
+        };
+        let mut spent = HashMap::new();
+        let maker_input = proposal.get_input()?;
+        add_amount(&mut spent, maker_input.asset, maker_input.value)?;
+        self.approve_spending(SignKind::LiquidexCosign, &tx, &[0], spent)?;
+
+        let store_read = self.store.read()?;
+        let template = store_read
+            .script_template(&prevout.script_pubkey)
+            .ok_or_else(|| Error::Generic("LiquiDEX maker output is not multisig".into()))?;
+        let wallet_key = *input
+            .partial_sigs
+            .keys()
+            .find(|k| template.witness_script(k) == witness_script)
+            .ok_or_else(|| {
+                Error::Generic("LiquiDEX proposal misses the wallet signature".into())
+            })?;
+        let cosigners = template.cosigners(&wallet_key);
+
+        let sighash_type = elements::SigHashType::SinglePlusAnyoneCanPay;
+        let (public_key, signature) =
+            signer.sign(&tx, 0, &witness_script, prevout.value, sighash_type)?;
+        if !cosigners.contains(&public_key) {
+            return Err(Error::Generic("LiquiDEX signer is not a cosigner".into()));
+        }
+        input.partial_sigs.insert(public_key, signature);
+
+        let mut cosigned = proposal.clone();
+        if cosigners.iter().all(|k| input.partial_sigs.contains_key(k)) {
+            let mut witness = template.satisfy_cosigned(&input.partial_sigs, &wallet_key);
+            witness.push(witness_script.into_bytes());
+            tx.input[0].witness.script_witness = witness;
+            cosigned.set_transaction(&tx);
+            cosigned.set_pset(None);
+        } else {
+            cosigned.set_pset(Some(&pset));
+        }
+        Ok(cosigned)
+    }
+
+    /// Take `proposal`, whose maker input spends `maker_prevout`
     pub fn liquidex_take(
         &self,
         proposal: &LiquidexProposal,
+        maker_prevout: &elements::TxOut,
         mnemonic: &str,
     ) -> Result<elements::Transaction, Error> {
+        if proposal.pset()?.is_some() {
+            return Err(Error::Generic(
+                "LiquiDEX proposal misses the cosigners signatures".into(),
+            ));
+        }
         let mut tx = proposal.transaction()?;
         // verify output commitment
         let maker_output = proposal.verify_output_commitment(&self.secp)?;
+        // verify previous output commitment
+        let maker_input = proposal.verify_input_commitment(&self.secp, maker_prevout)?;
 
-        // TODO: verify previous output commitment
-        let maker_input = proposal.get_input()?;
+        let address = self.next_address()?;
+        add_output(&mut tx, &address, maker_input.value, maker_input.asset)?;
 
-        let address = self.get_address()?;
-        add_output(
-            &mut tx,
-            &address,
-            maker_input.value,
-            maker_input.asset.to_hex(),
-        )?;
+        let input_outpoint = tx.input[0].previous_output.clone();
+        let own_proposal =
+            self.liquidex_take_complete(&mut tx, &maker_input, Some(&maker_output), mnemonic)?;
+        if !own_proposal {
+            let swap = LiquidexSwap {
+                sent_asset: maker_output.asset,
+                sent_value: Amount::from_sat(maker_output.value),
+                received_asset: maker_input.asset,
+                received_value: Amount::from_sat(maker_input.value),
+            };
+            self.store
+                .write()?
+                .liquidex_swap_insert(input_outpoint, swap)?;
+        }
+        Ok(tx)
+    }
+
+    /// Take `amount` of the maker asset from the partial `proposal`, whose maker input spends
+    /// `maker_prevout`. `fill` forwards the transaction to the maker, that signs it with
+    /// `liquidex_fill`, and returns it signed.
+    pub fn liquidex_take_partial(
+        &self,
+        proposal: &LiquidexProposal,
+        maker_prevout: &elements::TxOut,
+        amount: Amount,
+        mnemonic: &str,
+        fill: &dyn Fn(&elements::Transaction) -> Result<elements::Transaction, Error>,
+    ) -> Result<elements::Transaction, Error> {
+        let partial = proposal
+            .partial()
+            .ok_or_else(|| Error::Generic("LiquiDEX proposal is not partial".into()))?;
+        let mut tx = proposal.transaction()?;
+        if tx.input.len() != 1 || !tx.output.is_empty() {
+            return Err(Error::Generic(
+                "LiquiDEX error unexpected partial transaction".into(),
+            ));
+        }
+        let maker_input = proposal.verify_input_commitment(&self.secp, maker_prevout)?;
+        let maker_value = Amount::from_sat(maker_input.value);
+        let received = partial
+            .receive_value(amount)
+            .filter(|_| amount <= maker_value)
+            .ok_or(Error::InvalidAmount)?;
+        let maker_address = elements::Address::from_str(&partial.address)?;
+        if maker_address.blinding_pubkey.is_none() {
+            return Err(Error::InvalidAddress);
+        }
 
-        // satoshi/byte
-        let fee_rate = 0.1;
+        // the taker blinds the maker outputs, the first one must be the received asset
+        add_output(&mut tx, &maker_address, received.as_sat(), partial.asset_id)?;
+        if amount < maker_value {
+            let change = maker_value.saturating_sub(amount);
+            add_output(&mut tx, &maker_address, change.as_sat(), maker_input.asset)?;
+        }
+        let address = self.next_address()?;
+        add_output(&mut tx, &address, amount.as_sat(), maker_input.asset)?;
+
+        let input_outpoint = tx.input[0].previous_output;
+        let own_proposal = self.liquidex_take_complete(&mut tx, &maker_input, None, mnemonic)?;
+        let signed = fill(&tx)?;
+        if signed.txid() != tx.txid() {
+            return Err(Error::Generic(
+                "LiquiDEX maker changed the transaction".into(),
+            ));
+        }
+        if !own_proposal {
+            let swap = LiquidexSwap {
+                sent_asset: partial.asset_id,
+                sent_value: received,
+                received_asset: maker_input.asset,
+                received_value: amount,
+            };
+            self.store
+                .write()?
+                .liquidex_swap_insert(input_outpoint, swap)?;
+        }
+        Ok(signed)
+    }
+
+    /// Add the taker inputs, outputs and fee to `tx` and sign the taker inputs, returns whether
+    /// the maker input belongs to the wallet. `maker_output` is None for partial proposals.
+    fn liquidex_take_complete(
+        &self,
+        tx: &mut elements::Transaction,
+        maker_input: &elements::TxOutSecrets,
+        maker_output: Option<&elements::TxOutSecrets>,
+        mnemonic: &str,
+    ) -> Result<bool, Error> {
+        // satoshi/byte, the default one within the configured bounds
+        let fee_rate = self.config.fee_rate(None)?.as_sat_per_vbyte();
 
         let utxos = self.utxos()?;
 
@@ -723,19 +3795,21 @@ impl WalletCtx {
         // If the wallet is taking a proposal made by the wallet itself,
         // do not add the "maker" input again.
         let input_outpoint = tx.input[0].previous_output.clone();
-        if utxos.iter().any(|u| u.txo.outpoint == input_outpoint) {
+        let own_proposal = utxos.iter().any(|u| u.txo.outpoint == input_outpoint);
+        if own_proposal {
             used_utxo.insert(input_outpoint);
         }
         loop {
             let mut needs = liquidex_needs(
-                &maker_input,
-                &maker_output,
-                &tx,
+                maker_input,
+                maker_output,
+                tx,
                 fee_rate,
-                &self.config.policy_asset(),
+                &self.config.policy_asset_id(),
                 &store_read.cache.unblinded,
-            );
-            info!("needs: {:?}", needs);
+                self.address_type(),
+            )?;
+            info!("needs: {:?}", redact(&needs));
             if needs.is_empty() {
                 break;
             }
@@ -745,78 +3819,111 @@ impl WalletCtx {
             let mut asset_utxos: Vec<&UnblindedTXO> = utxos
                 .iter()
                 .filter(|u| u.unblinded.asset == asset && !used_utxo.contains(&u.txo.outpoint))
+                .filter(|u| !u.commitments_mismatch)
                 .collect();
 
-            info!("asset utxos: {:?}", asset_utxos);
+            info!("asset utxos: {:?}", redact(&asset_utxos));
             asset_utxos.sort_by(|a, b| a.unblinded.value.cmp(&b.unblinded.value));
             let utxo = asset_utxos.pop().ok_or(Error::InsufficientFunds)?;
 
             used_utxo.insert(utxo.txo.outpoint.clone());
-            add_input(&mut tx, utxo.txo.outpoint.clone());
+            add_input(tx, utxo.txo.outpoint.clone());
         }
 
         let estimated_fee = estimated_fee(
-            &tx,
+            tx,
             fee_rate,
-            liquidex_estimated_changes(&maker_input, &tx, &store_read.cache.unblinded),
+            liquidex_estimated_changes(maker_input, tx, &store_read.cache.unblinded)?,
+            self.address_type(),
         );
         let changes = liquidex_changes(
-            &maker_input,
-            &maker_output,
-            &tx,
+            maker_input,
+            maker_output,
+            tx,
             estimated_fee,
-            &self.config.policy_asset(),
+            &self.config.policy_asset_id(),
             &store_read.cache.unblinded,
-        );
+        )?;
         for (i, (asset, satoshi)) in changes.iter().enumerate() {
             let change_index = store_read.cache.indexes.internal + i as u32 + 1;
             let change_address = self.derive_address(&self.xpub, [1, change_index])?;
-            add_output(&mut tx, &change_address, *satoshi, asset.to_hex())?;
+            add_output(tx, &change_address, *satoshi, *asset)?;
         }
 
         let fee_value = liquidex_fee(
-            &maker_input,
-            &maker_output,
-            &tx,
-            &self.config.policy_asset(),
+            maker_input,
+            maker_output,
+            tx,
+            &self.config.policy_asset_id(),
             &store_read.cache.unblinded,
-        );
+        )?;
+        // the fee pays at most the policy asset dust not worth a change more than the estimate
+        if fee_value > estimated_fee.saturating_add(DUST_VALUE) {
+            return Err(Error::Generic(format!(
+                "LiquiDEX fee {} above the estimated {}",
+                fee_value, estimated_fee
+            )));
+        }
 
         let fee_output = elements::TxOut {
-            asset: Asset::Explicit(self.config.policy_asset()),
+            asset: Asset::Explicit(self.config.policy_asset_id()),
             value: Value::Explicit(fee_value),
             ..Default::default()
         };
         tx.output.push(fee_output);
+        drop(store_read);
 
         // Blind tx
-        self.liquidex_take_blind(&maker_input, &maker_output, &mut tx)?;
+        self.liquidex_take_blind(maker_input, maker_output, tx)?;
         // Sign inputs
-        self.liquidex_take_sign(&mut tx, mnemonic)?;
-        Ok(tx)
+        self.liquidex_take_sign(tx, mnemonic)?;
+        check_standardness(tx, self.config.policy_asset_id(), self.address_type())?;
+        Ok(own_proposal)
     }
 
+    /// Blind the taker outputs of `tx`, failures are retried with fresh randomness, see
+    /// `blind_with_retries`
     fn liquidex_take_blind(
         &self,
         maker_input: &elements::TxOutSecrets,
-        maker_output: &elements::TxOutSecrets,
+        maker_output: Option<&elements::TxOutSecrets>,
+        tx: &mut elements::Transaction,
+    ) -> Result<(), Error> {
+        let mut inputs = vec![maker_input.clone()];
+        let store_read = self.store.read()?;
+        for input in tx.input.iter().skip(1) {
+            let unblinded = store_read
+                .cache
+                .unblinded
+                .get(&input.previous_output)
+                .ok_or_else(|| Error::Generic("cannot find unblinded values".into()))?;
+            inputs.push(unblinded.clone());
+        }
+        drop(store_read);
+        blind_with_retries(tx, |tx| self.blind_once(&inputs, maker_output, tx))
+    }
+
+    /// Blind the outputs of `tx` having explicit values and the receiver blinding key in the
+    /// nonce, the others stay unconfidential. `inputs` are the secrets of the previous outputs in
+    /// the order of the tx inputs.
+    ///
+    /// Blinding factors are random, sender ephemeral keys are derived by the `Blinder` from
+    /// `blinding_hash` and the output index, so that the outputs sent could be unblinded again
+    /// from the seed with `unblind_sent_output`. The first output of a LiquiDEX proposal, already
+    /// committed with the `maker_output` secrets, gets new proofs only.
+    fn blind_once(
+        &self,
+        inputs: &[elements::TxOutSecrets],
+        maker_output: Option<&elements::TxOutSecrets>,
         tx: &mut elements::Transaction,
     ) -> Result<(), Error> {
+        if inputs.len() != tx.input.len() {
+            return Err(Error::Generic("cannot find unblinded values".into()));
+        }
         let mut input_domain = vec![];
         let mut input_commitment_secrets = vec![];
         let mut output_commitment_secrets = vec![];
-        let store_read = self.store.read()?;
-        for (idx, input) in tx.input.iter().enumerate() {
-            let unblinded = if idx == 0 {
-                maker_input
-            } else {
-                store_read
-                    .cache
-                    .unblinded
-                    .get(&input.previous_output)
-                    .ok_or_else(|| Error::Generic("cannot find unblinded values".into()))?
-            };
-
+        for unblinded in inputs.iter() {
             let asset_tag = secp256k1_zkp::Tag::from(unblinded.asset.into_inner().into_inner());
             let asset_generator = secp256k1_zkp::Generator::new_blinded(
                 &self.secp,
@@ -835,19 +3942,31 @@ impl WalletCtx {
         let ct_exp = 0;
         let ct_bits = 52;
 
-        let out_num = tx.output.len();
-        let hash_prevouts = get_hash_prevout(&tx);
+        // the value blinder of the last blinded output balances the transaction
+        let last_blinded = tx.output.iter().rposition(|o| {
+            !o.is_fee()
+                && matches!(
+                    (o.value, o.asset, o.nonce),
+                    (
+                        Value::Explicit(_),
+                        Asset::Explicit(_),
+                        Nonce::Confidential(_)
+                    )
+                )
+        });
+        let tx_hash = blinding_hash(&tx);
         let mut rng = rand::thread_rng();
         for (i, mut output) in tx.output.iter_mut().enumerate() {
             if !output.is_fee() {
-                match (i, output.value, output.asset, output.nonce) {
+                match (i, output.value, output.asset, output.nonce, maker_output) {
                     (
                         0,
                         Value::Confidential(_),
                         Asset::Confidential(_),
                         Nonce::Confidential(receiver_blinding_pk),
+                        Some(maker_output),
                     ) => {
-                        let sender_sk = secp256k1::SecretKey::new(&mut rng);
+                        let sender_sk = self.blinder.ephemeral_key(&tx_hash, i as u32)?;
                         let shared_secret = make_shared_secret(&receiver_blinding_pk, &sender_sk);
 
                         let asset = maker_output.asset;
@@ -893,7 +4012,8 @@ impl WalletCtx {
                             ct_exp,
                             ct_bits,
                             asset_generator,
-                        )?;
+                        )
+                        .map_err(|e| blinding_failed(i, e))?;
 
                         let surjectionproof = secp256k1_zkp::SurjectionProof::new(
                             &self.secp,
@@ -901,7 +4021,8 @@ impl WalletCtx {
                             asset_tag,
                             asset_blinder,
                             &input_domain,
-                        )?;
+                        )
+                        .map_err(|e| blinding_failed(i, e))?;
 
                         output.witness.surjection_proof = Some(surjectionproof);
                         output.witness.rangeproof = Some(rangeproof);
@@ -911,22 +4032,17 @@ impl WalletCtx {
                         Value::Explicit(value),
                         Asset::Explicit(asset),
                         Nonce::Confidential(receiver_blinding_pk),
+                        _,
                     ) => {
-                        let sender_sk = secp256k1::SecretKey::new(&mut rng);
+                        let sender_sk = self.blinder.ephemeral_key(&tx_hash, i as u32)?;
                         let sender_pk =
                             secp256k1::PublicKey::from_secret_key(&self.secp, &sender_sk);
                         let shared_secret = make_shared_secret(&receiver_blinding_pk, &sender_sk);
 
-                        let asset_blinder =
-                            derive_blinder(&self.master_blinding, &hash_prevouts, i as u32, true)?;
+                        let asset_blinder = secp256k1_zkp::Tweak::new(&mut rng);
 
-                        let value_blinder = if i < out_num - 2 {
-                            let value_blinder = derive_blinder(
-                                &self.master_blinding,
-                                &hash_prevouts,
-                                i as u32,
-                                false,
-                            )?;
+                        let value_blinder = if Some(i) != last_blinded {
+                            let value_blinder = secp256k1_zkp::Tweak::new(&mut rng);
 
                             output_commitment_secrets.push(secp256k1_zkp::CommitmentSecrets::new(
                                 value,
@@ -978,7 +4094,8 @@ impl WalletCtx {
                             ct_exp,
                             ct_bits,
                             asset_generator,
-                        )?;
+                        )
+                        .map_err(|e| blinding_failed(i, e))?;
 
                         let surjectionproof = secp256k1_zkp::SurjectionProof::new(
                             &self.secp,
@@ -986,7 +4103,8 @@ impl WalletCtx {
                             asset_tag,
                             asset_blinder,
                             &input_domain,
-                        )?;
+                        )
+                        .map_err(|e| blinding_failed(i, e))?;
 
                         output.nonce =
                             elements::confidential::Nonce::from_commitment(&sender_pk.serialize())?;
@@ -999,7 +4117,8 @@ impl WalletCtx {
                         output.witness.surjection_proof = Some(surjectionproof);
                         output.witness.rangeproof = Some(rangeproof);
                     }
-                    _ => panic!("create_tx created things not right"),
+                    (_, Value::Explicit(_), Asset::Explicit(_), Nonce::Null, _) => {}
+                    _ => return Err(Error::Generic(format!("cannot blind output {}", i))),
                 }
             }
         }
@@ -1012,41 +4131,663 @@ impl WalletCtx {
         mnemonic: &str,
     ) -> Result<(), Error> {
         let xprv = mnemonic2xprv(mnemonic, self.config.clone())?;
-        let store_read = self.store.read()?;
+        // the first input is the maker one
+        let indexes: Vec<usize> = (1..tx.input.len()).collect();
+        self.sign_inputs(tx, xprv, &indexes, SignKind::LiquidexTake)
+    }
+}
 
-        for i in 1..tx.input.len() {
-            let prev_output = tx.input[i].previous_output;
-            let prev_tx = store_read
-                .cache
-                .all_txs
-                .get(&prev_output.txid)
-                .ok_or_else(|| Error::Generic("expected tx".into()))?;
-            let out = prev_tx.output[prev_output.vout as usize].clone();
-            let derivation_path: DerivationPath = store_read
-                .cache
-                .paths
-                .get(&out.script_pubkey)
-                .ok_or_else(|| Error::Generic("can't find derivation path".into()))?
-                .clone();
+/// Blind `tx` with `blind`, which is given a copy of the unblinded `tx` and retried with fresh
+/// randomness up to `BLINDING_ATTEMPTS` times if it fails creating the proofs, that is with
+/// `Error::BlindingFailed`. `tx` is changed only on success, otherwise the last failure is
+/// returned with the output failing. Other errors do not depend on the randomness and are
+/// returned at once.
+fn blind_with_retries<F>(tx: &mut elements::Transaction, mut blind: F) -> Result<(), Error>
+where
+    F: FnMut(&mut elements::Transaction) -> Result<(), Error>,
+{
+    let mut last_failure = (None, String::new());
+    for attempt in 1..=BLINDING_ATTEMPTS {
+        let mut blinded = tx.clone();
+        match blind(&mut blinded) {
+            Ok(()) => {
+                *tx = blinded;
+                return Ok(());
+            }
+            Err(Error::BlindingFailed { output, reason, .. }) => {
+                info!(
+                    "blinding attempt {} failed on output {:?}: {}",
+                    attempt, output, reason
+                );
+                last_failure = (output, reason);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let (output, reason) = last_failure;
+    Err(Error::BlindingFailed {
+        output,
+        attempts: BLINDING_ATTEMPTS,
+        reason,
+    })
+}
 
-            let (script_sig, witness) =
-                self.internal_sign_elements(&tx, i, &derivation_path, out.value, xprv, None);
+/// Failure creating the proofs of output `vout`
+fn blinding_failed(vout: usize, error: secp256k1_zkp::Error) -> Error {
+    Error::BlindingFailed {
+        output: Some(vout),
+        attempts: 1,
+        reason: format!("{:?}", error),
+    }
+}
 
-            tx.input[i].script_sig = script_sig;
-            tx.input[i].witness.script_witness = witness;
+/// False for outputs of script templates with a relative timelock not expired yet
+fn is_mature(utxo: &UnblindedTXO, store_read: &StoreMeta) -> bool {
+    match store_read.script_template(&utxo.txo.script_pubkey) {
+        None => true,
+        Some(template) => {
+            let blocks = relative_lock_blocks(template.sequence());
+            let next_height = store_read.cache.tip.0 + 1;
+            blocks == 0 || utxo.txo.height.map_or(false, |h| next_height >= h + blocks)
         }
+    }
+}
 
-        Ok(())
+/// Set the vout of `changes` to the one of the output paying their address in `tx`, once the
+/// outputs have been reordered
+fn locate_changes(tx: &elements::Transaction, changes: &mut [ChangeOutput]) {
+    for change in changes.iter_mut() {
+        let script = change.address.script_pubkey();
+        if let Some(vout) = tx.output.iter().position(|o| o.script_pubkey == script) {
+            change.vout = vout as u32;
+        }
     }
 }
 
-fn address_params(net: ElementsNetwork) -> &'static elements::AddressParams {
-    match net {
-        ElementsNetwork::Liquid => &elements::AddressParams::LIQUID,
-        ElementsNetwork::ElementsRegtest => &elements::AddressParams::ELEMENTS,
+fn payments_to(
+    store_read: &StoreMeta,
+    address: &elements::Address,
+    asset: &elements::issuance::AssetId,
+) -> Result<Vec<Payment>, Error> {
+    let script = address.script_pubkey();
+    let mut payments = vec![];
+    for (txid, height) in store_read.cache.heights.iter() {
+        let tx = store_read
+            .cache
+            .all_txs
+            .get(txid)?
+            .ok_or_else(fn_err(&format!("payments_to no tx {}", txid)))?;
+        for (vout, output) in tx.output.iter().enumerate() {
+            if output.script_pubkey != script {
+                continue;
+            }
+            let outpoint = elements::OutPoint::new(*txid, vout as u32);
+            if let Some(unblinded) = store_read.cache.unblinded.get(&outpoint) {
+                if unblinded.asset == *asset {
+                    payments.push(Payment {
+                        txid: txid.to_string(),
+                        vout: vout as u32,
+                        asset: *asset,
+                        satoshi: Amount::from_sat(unblinded.value),
+                        height: *height,
+                    });
+                }
+            }
+        }
     }
+    payments.sort_by(|a, b| {
+        let key = |p: &Payment| (p.height.unwrap_or(u32::MAX), p.txid.clone(), p.vout);
+        key(a).cmp(&key(b))
+    });
+    Ok(payments)
 }
 
 fn get_hash_prevout(tx: &elements::Transaction) -> elements::bitcoin::hashes::sha256d::Hash {
     elements::sighash::SigHashCache::new(tx).hash_prevouts()
 }
+
+/// Hash of the fields of `tx` that blinding does not change, the prevouts, the input sequences,
+/// the output scripts, the explicit fee values and the locktime. The sender ephemeral keys are
+/// derived from it, replacements of a transaction get different keys unless they pay the same
+/// scripts with the same fee.
+fn blinding_hash(tx: &elements::Transaction) -> elements::bitcoin::hashes::sha256d::Hash {
+    let mut engine = elements::bitcoin::hashes::sha256d::Hash::engine();
+    engine.input(&get_hash_prevout(tx)[..]);
+    for input in tx.input.iter() {
+        engine.input(&input.sequence.to_le_bytes());
+    }
+    for output in tx.output.iter() {
+        engine.input(&elements::encode::serialize(&output.script_pubkey));
+        if output.is_fee() {
+            engine.input(&elements::encode::serialize(&output.value));
+        }
+    }
+    engine.input(&tx.lock_time.to_le_bytes());
+    elements::bitcoin::hashes::sha256d::Hash::from_engine(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::DerivationTemplate;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_separate_view_key() {
+        let spend = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let view = "legal winner thank year wave sausage worth useful legal winner thank yellow";
+        let mut config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        // dual and view_only are the same wallet, open at the same time
+        config.ignore_store_lock = true;
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+
+        let single = WalletCtx::from_mnemonic(spend, &data_root, config.clone()).unwrap();
+        let dual = WalletCtx::from_mnemonics(spend, view, &data_root, config.clone()).unwrap();
+        let view_only = WalletCtx::from_view_key(
+            single.xpub(),
+            single.master_fingerprint(),
+            view,
+            &data_root,
+            config,
+        )
+        .unwrap();
+
+        assert_eq!(single.xpub(), dual.xpub());
+        assert_eq!(single.master_fingerprint(), dual.master_fingerprint());
+        let view_key = MasterBlindingKey::new(&mnemonic2seed(view).unwrap());
+        let dual_key = dual.blinder.master_blinding_key().unwrap();
+        assert_eq!(dual_key.0, view_key.0);
+        assert_ne!(single.blinder.master_blinding_key().unwrap().0, dual_key.0);
+        assert_ne!(single.wallet_id(), dual.wallet_id());
+        assert_eq!(dual.wallet_id(), view_only.wallet_id());
+    }
+
+    /// Addresses at m/0/0 and m/0/1 of the Green singlesig subaccounts 0 and 1 of the mnemonic,
+    /// derived separately with coin type 1776 and the SLIP-77 blinding keys
+    #[test]
+    fn test_green_subaccount_addresses() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let expected = [
+            (
+                0,
+                [
+                    "VJLJAE8QCJv94uhUcsPXqp3tXj7w3EGTtNphLVJX8tgEUVvtU86owGG8NWcPZe7oYSmeMjihLCP3ZW5T",
+                    "VJLFndqFMLpuZ3T4U5ZKe9LbXvigUtwT7wRDc8AiSGUxHJJpkDvabSfEGkW7PMmT8WQxVRXDLuhFUwp8",
+                ],
+            ),
+            (
+                1,
+                [
+                    "lq1qqd3s6cmklf84c73lwgqpefled2ge3mvn4kxyfn590zs5ceepjdwrgfe0247rp5h4yzmdftsahhw64uy8pzfe706xqrgc3s48t",
+                    "lq1qqw4klmqrn3mtvy5acsfa9pj97smzap8damhk87k3l2kmh0rjze4pj3xvus3c5gaz89r2kd393c4fvurwxf06qqvzjqffal8pu",
+                ],
+            ),
+        ];
+        for (subaccount, addresses) in expected.iter() {
+            let mut config = Config::new_mainnet(false, false, false, "localhost:50001").unwrap();
+            config.derivation = Some(
+                DerivationTemplate::green_subaccount(
+                    crate::network::ElementsNetwork::Liquid,
+                    *subaccount,
+                )
+                .unwrap(),
+            );
+            let dir = TempDir::new("unit_test").unwrap();
+            let data_root = format!("{}", dir.path().display());
+            let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config).unwrap();
+            for (pointer, address) in addresses.iter().enumerate() {
+                let derived = wallet.derive_address(&wallet.xpub, [0, pointer as u32]);
+                assert_eq!(derived.unwrap().to_string(), *address);
+            }
+        }
+    }
+
+    /// Blinder not exposing the master blinding key, as a hardware device
+    struct DeviceBlinder(MasterBlindingKey);
+
+    impl Blinder for DeviceBlinder {
+        fn blinding_key(&self, script_pubkey: &Script) -> Result<secp256k1::SecretKey, Error> {
+            self.0.blinding_key(script_pubkey)
+        }
+
+        fn blinding_factor(
+            &self,
+            hash_prevouts: &elements::bitcoin::hashes::sha256d::Hash,
+            vout: u32,
+            is_asset_blinder: bool,
+        ) -> Result<secp256k1_zkp::Tweak, Error> {
+            self.0
+                .blinding_factor(hash_prevouts, vout, is_asset_blinder)
+        }
+
+        fn tagged_secret(&self, tag: &[u8], data: &[u8]) -> Result<[u8; 32], Error> {
+            self.0.tagged_secret(tag, data)
+        }
+
+        fn id(&self) -> Result<sha256::Hash, Error> {
+            self.0.id()
+        }
+    }
+
+    #[test]
+    fn test_from_blinder() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let device_dir = TempDir::new("unit_test").unwrap();
+        let device_data_root = format!("{}", device_dir.path().display());
+
+        let software =
+            WalletCtx::from_mnemonics(mnemonic, mnemonic, &data_root, config.clone()).unwrap();
+        let master_blinding = MasterBlindingKey::new(&mnemonic2seed(mnemonic).unwrap());
+        let device = WalletCtx::from_blinder(
+            software.xpub(),
+            software.master_fingerprint(),
+            Arc::new(DeviceBlinder(master_blinding)),
+            &device_data_root,
+            config,
+        )
+        .unwrap();
+
+        assert_eq!(software.wallet_id(), device.wallet_id());
+        assert_eq!(
+            software.derive_address(&software.xpub, [0, 0]).unwrap(),
+            device.derive_address(&device.xpub, [0, 0]).unwrap()
+        );
+        assert!(software.export_backup("password").is_ok());
+        assert!(device.export_backup("password").is_err());
+    }
+
+    #[test]
+    fn test_decode_tx() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        let policy_asset = config.policy_asset_id();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config).unwrap();
+        wallet.store.write().unwrap().ensure_scripts(0, 1).unwrap();
+
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let mine = wallet.derive_address(&wallet.xpub, [0, 1]).unwrap();
+        let other = wallet.derive_address(&wallet.xpub, [0, 100]).unwrap();
+        add_output(&mut tx, &mine, 1_000, policy_asset).unwrap();
+        add_output(&mut tx, &other, 2_000, policy_asset).unwrap();
+        add_fee_output(&mut tx, 300, &Some(Asset::Explicit(policy_asset))).unwrap();
+
+        let hex = hex::encode(elements::encode::serialize(&tx));
+        let decoded = wallet.decode_tx(&hex).unwrap();
+        assert_eq!(decoded.txid, tx.txid().to_string());
+        assert_eq!(decoded.fee, Amount::from_sat(300));
+        assert!(decoded.inputs[0].derivation_path.is_none());
+        assert_eq!(
+            decoded.outputs[0].derivation_path,
+            Some(DerivationPath::from_str("m/0/1").unwrap())
+        );
+        assert!(decoded.outputs[1].derivation_path.is_none());
+        assert_eq!(
+            decoded.outputs[2].script_kind,
+            crate::scripts::ScriptKind::Fee
+        );
+        assert_eq!(decoded.balances.get(&policy_asset), Some(&1_000));
+        assert!(wallet.decode_tx("00").is_err());
+    }
+
+    #[test]
+    fn test_sign_rangeproof_sighash() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let mut config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        config.ignore_store_lock = true;
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+        config.dynafed_active = true;
+        let dynafed = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let xprv = mnemonic2xprv(mnemonic, config).unwrap();
+        let path = DerivationPath::from_str("m/0/1").unwrap();
+        let value = Value::Explicit(1_000);
+        let flags = SigHashFlags::new(elements::SigHashType::All, true);
+
+        assert!(matches!(
+            wallet.internal_sign_elements(&tx, 0, &path, value, xprv, Some(flags)),
+            Err(Error::InvalidSigHashType(0x41))
+        ));
+        let (_, witness) = dynafed
+            .internal_sign_elements(&tx, 0, &path, value, xprv, Some(flags))
+            .unwrap();
+        assert_eq!(witness[0].last(), Some(&0x41));
+        let (_, witness) = wallet
+            .internal_sign_elements(&tx, 0, &path, value, xprv, None)
+            .unwrap();
+        assert_eq!(witness[0].last(), Some(&0x01));
+    }
+
+    #[test]
+    fn test_approve_without_lock() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let mut wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config).unwrap();
+
+        // the approver can use the wallet while it decides
+        let store = wallet.store.clone();
+        let approver = move |request: &SignRequest| {
+            assert!(store.try_write().is_ok(), "store locked while approving");
+            SignApproval::Reject(format!("{:?}", request.kind))
+        };
+        wallet.set_sign_approver(Some(Arc::new(approver)));
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        let xprv = mnemonic2xprv(mnemonic, wallet.config.clone()).unwrap();
+        assert!(matches!(
+            wallet.sign_with_xprv(&mut tx, xprv),
+            Err(Error::SigningRejected(reason)) if reason == "Transaction"
+        ));
+    }
+
+    #[test]
+    fn test_blind_with_retries() {
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        let failure = || Error::BlindingFailed {
+            output: Some(1),
+            attempts: 1,
+            reason: "rangeproof".into(),
+        };
+
+        // a failure is retried, tx is changed only by the successful attempt
+        let mut calls = 0;
+        blind_with_retries(&mut tx, |tx| {
+            calls += 1;
+            tx.lock_time = calls;
+            if calls < BLINDING_ATTEMPTS as u32 {
+                Err(failure())
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap();
+        assert_eq!(tx.lock_time, BLINDING_ATTEMPTS as u32);
+
+        let mut calls = 0;
+        let result = blind_with_retries(&mut tx, |tx| {
+            calls += 1;
+            tx.lock_time = 0;
+            Err(failure())
+        });
+        assert_eq!(calls, BLINDING_ATTEMPTS);
+        assert_eq!(tx.lock_time, BLINDING_ATTEMPTS as u32);
+        assert!(matches!(
+            result,
+            Err(Error::BlindingFailed {
+                output: Some(1),
+                attempts: BLINDING_ATTEMPTS,
+                ..
+            })
+        ));
+
+        // other errors are not retried
+        let mut calls = 0;
+        let result = blind_with_retries(&mut tx, |_| {
+            calls += 1;
+            Err(Error::InsufficientFunds)
+        });
+        assert_eq!(calls, 1);
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+
+        // neither are the secp errors not creating the proofs, such as invalid blinding factors
+        let mut calls = 0;
+        let result = blind_with_retries(&mut tx, |_| {
+            calls += 1;
+            Err(secp256k1_zkp::Tweak::from_slice(&[0xff; 32])
+                .unwrap_err()
+                .into())
+        });
+        assert_eq!(calls, 1);
+        assert!(matches!(result, Err(Error::Secp256k1Zkp(_))));
+    }
+
+    #[test]
+    fn test_blind_once() {
+        use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        let policy_asset = config.policy_asset_id();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config).unwrap();
+
+        let mut rng = rand::thread_rng();
+        let input = elements::TxOutSecrets::new(
+            policy_asset,
+            AssetBlindingFactor::new(&mut rng),
+            10_000,
+            ValueBlindingFactor::new(&mut rng),
+        );
+        let input_generator = secp256k1_zkp::Generator::new_blinded(
+            &wallet.secp,
+            secp256k1_zkp::Tag::from(policy_asset.into_inner().into_inner()),
+            input.asset_bf.into_inner(),
+        );
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let receiver = wallet.derive_address(&wallet.xpub, [0, 100]).unwrap();
+        let change = wallet.derive_address(&wallet.xpub, [1, 1]).unwrap();
+        add_output(&mut tx, &receiver, 1_000, policy_asset).unwrap();
+        add_output(&mut tx, &change, 8_700, policy_asset).unwrap();
+        add_fee_output(&mut tx, 300, &Some(Asset::Explicit(policy_asset))).unwrap();
+        let unblinded = tx.clone();
+
+        let mut blinded = unblinded.clone();
+        wallet
+            .blind_once(&[input.clone()], None, &mut blinded)
+            .unwrap();
+        for output in blinded.output.iter().filter(|o| !o.is_fee()) {
+            let (value, asset) = match (output.value, output.asset) {
+                (Value::Confidential(value), Asset::Confidential(asset)) => (value, asset),
+                _ => panic!("output not blinded"),
+            };
+            let surjection_proof = output.witness.surjection_proof.as_ref().unwrap();
+            assert!(surjection_proof.verify(&wallet.secp, asset, &[input_generator]));
+            let rangeproof = output.witness.rangeproof.as_ref().unwrap();
+            assert!(rangeproof
+                .verify(&wallet.secp, value, output.script_pubkey.as_bytes(), asset)
+                .is_ok());
+        }
+
+        // the outputs sent are unblinded again from the seed
+        let txid = blinded.txid();
+        wallet
+            .store
+            .write()
+            .unwrap()
+            .cache_txs(vec![(txid, blinded.clone())]);
+        let secrets = wallet.unblind_sent_output(&txid, 0, &receiver).unwrap();
+        assert_eq!(secrets.value, 1_000);
+        assert_eq!(secrets.asset, policy_asset);
+
+        // blinding again gets new blinders, a replacement paying a different fee gets a new
+        // sender key
+        let mut again = unblinded.clone();
+        wallet
+            .blind_once(&[input.clone()], None, &mut again)
+            .unwrap();
+        assert_ne!(again.output[0].value, blinded.output[0].value);
+        assert_eq!(again.output[0].nonce, blinded.output[0].nonce);
+        let mut replacement = unblinded;
+        replacement.output[1].value = Value::Explicit(8_600);
+        replacement.output[2].value = Value::Explicit(400);
+        wallet.blind_once(&[input], None, &mut replacement).unwrap();
+        assert_ne!(replacement.output[0].nonce, blinded.output[0].nonce);
+    }
+
+    #[test]
+    fn test_derivation_template() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let mut config = Config::new_regtest(
+            false,
+            false,
+            false,
+            "127.0.0.1:50001",
+            "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225",
+        )
+        .unwrap();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+        let bip49 = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+
+        // same keys with native segwit addresses
+        config.derivation =
+            Some(DerivationTemplate::new("m/49'/1'/0'", AddressType::P2wpkh).unwrap());
+        let p2wpkh = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+        assert_eq!(bip49.xpub(), p2wpkh.xpub());
+        assert_ne!(bip49.wallet_id(), p2wpkh.wallet_id());
+        let address = p2wpkh.derive_address(&p2wpkh.xpub, [0, 0]).unwrap();
+        assert!(address.script_pubkey().is_v0_p2wpkh());
+        assert!(address.blinding_pubkey.is_some());
+        assert!(bip49
+            .derive_address(&bip49.xpub, [0, 0])
+            .unwrap()
+            .script_pubkey()
+            .is_p2sh());
+
+        config.derivation = Some(DerivationTemplate::bip84(
+            ElementsNetwork::ElementsRegtest,
+            0,
+        ));
+        let bip84 = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+        assert_ne!(bip49.xpub(), bip84.xpub());
+        assert_eq!(
+            bip84.account_xprv(mnemonic).unwrap(),
+            mnemonic2master_xprv(mnemonic, config.network())
+                .unwrap()
+                .derive_priv(
+                    &bip84.secp,
+                    &DerivationPath::from_str("m/84'/1'/0'").unwrap()
+                )
+                .unwrap()
+        );
+        let store_read = bip84.store.read().unwrap();
+        let (script, _) = store_read.get_script_batch(0, 0).unwrap().value[0].clone();
+        assert!(script.is_v0_p2wpkh());
+    }
+
+    #[test]
+    fn test_xpub_network() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let config = Config::new_mainnet(false, false, false, "127.0.0.1:50001").unwrap();
+        let dir = TempDir::new("unit_test").unwrap();
+        let data_root = format!("{}", dir.path().display());
+
+        let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()).unwrap();
+        assert!(wallet.xpub().to_string().starts_with("xpub"));
+        let manager = WalletManager::new(&data_root);
+        assert_eq!(
+            manager.list_wallets(ElementsNetwork::Liquid).unwrap(),
+            vec![wallet.wallet_id().to_string()]
+        );
+        assert!(manager
+            .list_wallets(ElementsNetwork::ElementsRegtest)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone()),
+            Err(Error::StoreLocked(_))
+        ));
+        let xprv = wallet.account_xprv(mnemonic).unwrap();
+        assert!(xprv.to_string().starts_with("xprv"));
+
+        // the wallet id is the one of the wallets created with testnet version bytes
+        let testnet_xpub = legacy_xpub(&wallet.xpub());
+        assert!(testnet_xpub.to_string().starts_with("tpub"));
+        assert_eq!(
+            wallet.wallet_id(),
+            compute_wallet_id(&testnet_xpub, &config, None).unwrap()
+        );
+        let (xpub, master_fingerprint) = (wallet.xpub(), wallet.master_fingerprint());
+        // the store is locked until the wallet is dropped
+        drop(wallet);
+        let view_only = WalletCtx::from_view_key(
+            testnet_xpub,
+            master_fingerprint,
+            mnemonic,
+            &data_root,
+            config,
+        )
+        .unwrap();
+        assert_eq!(view_only.xpub(), xpub);
+    }
+}
@@ -1,60 +1,151 @@
+mod amount;
+mod approval;
+#[cfg(feature = "async")]
+mod async_wallet;
+mod backend;
+mod backup;
+mod blinder;
+mod broadcast;
+mod cancel;
+mod coin_selection;
+mod coinjoin;
+#[cfg(feature = "elementsd")]
+mod elementsd;
 mod error;
+#[cfg(feature = "esplora")]
+mod esplora;
 mod headers;
 mod interface;
 mod liquidex;
+mod manager;
+mod metrics;
 mod model;
 mod network;
+mod payjoin;
+mod persister;
+#[cfg(feature = "pinning")]
+mod pinning;
+mod privacy;
+mod progress;
+pub mod recovery;
+mod redact;
+mod registry;
+mod retry;
 mod scripts;
+mod sighash;
+mod signer;
+mod spendable;
 mod store;
+#[cfg(feature = "test_util")]
+pub mod test_util;
 mod transaction;
+mod tx_cache;
 mod utils;
-
+#[cfg(feature = "webhooks")]
+mod webhook;
+
+pub use crate::amount::{Amount, FeeRate};
+pub use crate::approval::{SignApproval, SignApprover, SignKind, SignRequest};
+#[cfg(feature = "async")]
+pub use crate::async_wallet::AsyncElectrumWallet;
+pub use crate::backend::{BlockchainBackend, History};
+pub use crate::blinder::Blinder;
+pub use crate::broadcast::{
+    spawn_broadcast_worker, BroadcastEvent, QueuedTx, MAX_BROADCAST_ATTEMPTS,
+};
+pub use crate::cancel::CancelToken;
+pub use crate::coin_selection::{CoinSelection, CoinSelector, FeeModel};
+pub use crate::coinjoin::{combine_psets, verify_blinding, CoinjoinParty, CoinjoinPset};
+#[cfg(feature = "elementsd")]
+pub use crate::elementsd::ElementsRpcClient;
 pub use crate::error::Error;
-pub use crate::liquidex::{LiquidexMakeOpt, LiquidexProposal};
+#[cfg(feature = "esplora")]
+pub use crate::esplora::EsploraClient;
+pub use crate::liquidex::{
+    LiquidexAssetList, LiquidexMakeOpt, LiquidexPartial, LiquidexProposal, LiquidexSwap,
+    PriceCandle,
+};
+pub use crate::manager::WalletManager;
+pub use crate::metrics::Metrics;
 pub use crate::model::{
-    CreateTransactionOpt, Destination, GetTransactionsOpt, SPVVerifyResult, TransactionDetails,
-    UnblindedTXO, TXO,
+    AddressInfo, Burn, ChangeOutput, Contact, Counterparty, CreateTransactionOpt, DecodedInput,
+    DecodedOutput, DecodedTx, Destination, GetTransactionsOpt, Invoice, InvoiceStatus, Payment,
+    PendingIncoming, PendingIncomingTx, SPVVerifyResult, SyncReport, TransactionDetails,
+    TxOrdering, TxType, UnblindedTXO, UtxoSort, UtxosOpt, Versioned, WalletStats, MODEL_VERSION,
+    TXO,
+};
+pub use crate::network::{Backend, Config, DerivationTemplate, ElectrumUrl, ElementsNetwork};
+pub use crate::payjoin::{PayjoinProposal, PayjoinUri};
+#[cfg(feature = "sled")]
+pub use crate::persister::SledPersister;
+pub use crate::persister::{FilePersister, MemoryPersister, Persister};
+pub use crate::privacy::PrivacyWarning;
+pub use crate::progress::{SyncProgress, SyncStage};
+pub use crate::redact::{log_redaction, set_log_redaction};
+#[cfg(feature = "registry")]
+pub use crate::registry::RegistryClient;
+pub use crate::registry::{embedded_metadata, AssetMetadata, CachedAssetMetadata};
+pub use crate::retry::RetryPolicy;
+pub use crate::scripts::{AddressType, ScriptKind};
+pub use crate::sighash::{SigHashFlags, SIGHASH_RANGEPROOF};
+#[cfg(feature = "remote-signer")]
+pub use crate::signer::remote::{RemoteSignRequest, RemoteSignResponse, RemoteSigner};
+pub use crate::signer::Signer;
+pub use crate::spendable::{CsvScript, MultisigScript, SpendableScript};
+pub use crate::store::{StoreSnapshot, DEFAULT_ACCOUNT};
+pub use crate::transaction::{
+    changes, fee, my_balance_changes, needs, weight_breakdown, InputWeight, OutputWeight,
+    WeightBreakdown,
 };
+pub use crate::tx_cache::{TxCache, TxLookup};
 pub use crate::utils::tx_to_hex;
-
-use network::*;
+#[cfg(feature = "webhooks")]
+use crate::webhook::is_new_payment;
+#[cfg(feature = "webhooks")]
+pub use crate::webhook::{PaymentNotification, ReceivedOutput, Webhook, SIGNATURE_HEADER};
 
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hasher;
-use std::time::Instant;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::blinder::Blinder;
 use crate::headers::Verifier;
 //use crate::interface::{make_shared_secret, parse_rangeproof_message, WalletCtx};
-use crate::interface::WalletCtx;
+use crate::interface::{now, WalletCtx};
 use crate::liquidex::liquidex_unblind;
+use crate::metrics::CountingBackend;
 use crate::model::*;
-use crate::network::Config;
+use crate::progress::ProgressReporter;
+use crate::redact::redact;
 use crate::store::{Indexes, Store, BATCH_SIZE};
 use crate::transaction::*;
-use crate::ElementsNetwork;
 
-use log::{debug, info, trace, warn};
+use tracing::{debug, info, info_span, trace, warn};
 
 use elements::bitcoin::hashes::hex::ToHex;
 use elements::bitcoin::secp256k1;
-use elements::bitcoin::util::bip32::DerivationPath;
+use elements::bitcoin::util::bip32::{
+    ChildNumber, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint,
+};
 use elements::{BlockHash, Script, Txid};
 
 use elements;
 use elements::confidential::{self, Asset, Nonce};
-use elements::slip77::MasterBlindingKey;
-
-use electrum_client::GetHistoryRes;
-use electrum_client::{Client, ElectrumApi};
 
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
+/// Interval between the syncs of `wait_for_payment`
+pub const PAYMENT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 struct Syncer {
     pub store: Store,
-    pub master_blinding: MasterBlindingKey,
+    pub blinder: Arc<dyn Blinder>,
     pub config: Config,
+    pub cancel: CancelToken,
+    progress: Arc<ProgressReporter>,
     secp: secp256k1::Secp256k1<secp256k1::All>,
 }
 
@@ -68,38 +159,30 @@ struct Headers {
     pub verifier: Verifier,
 }
 
-fn try_get_fee_estimates(client: &Client) -> Result<Vec<FeeEstimate>, Error> {
-    let relay_fee = (client.relay_fee()? * 100_000_000.0) as u64;
-    let blocks: Vec<usize> = (1..25).collect();
-    // max is covering a rounding errors in production electrs which sometimes cause a fee
-    // estimates lower than relay fee
-    let mut estimates: Vec<FeeEstimate> = client
-        .batch_estimate_fee(blocks)?
-        .iter()
-        .map(|e| FeeEstimate(relay_fee.max((*e * 100_000_000.0) as u64)))
-        .collect();
-    estimates.insert(0, FeeEstimate(relay_fee));
-    Ok(estimates)
-}
-
 impl Tipper {
-    pub fn tip(&self, client: &Client) -> Result<u32, Error> {
-        let header = client.block_headers_subscribe_raw()?;
-        let height = header.height as u32;
+    pub fn tip(&self, client: &dyn BlockchainBackend) -> Result<u32, Error> {
+        let (height, block_header) = client.tip()?;
+        self.set_tip(height, block_header)?;
+        Ok(height)
+    }
+
+    /// Save the tip fetched from the server, if it changed
+    pub fn set_tip(&self, height: u32, block_header: elements::BlockHeader) -> Result<(), Error> {
         let tip_height = self.store.read()?.cache.tip.0;
         if height != tip_height {
-            let block_header: elements::BlockHeader =
-                elements::encode::deserialize(&header.header)?;
             let hash: BlockHash = block_header.block_hash();
             info!("saving in store new tip {:?}", (height, hash));
-            self.store.write()?.cache.tip = (height, hash);
+            let mut store_write = self.store.write()?;
+            store_write.cache.tip = (height, hash);
+            store_write.cache.headers.insert(height, block_header);
+            store_write.cache_changed()?;
         }
-        Ok(height)
+        Ok(())
     }
 }
 
 impl Headers {
-    pub fn get_proofs(&mut self, client: &Client) -> Result<usize, Error> {
+    pub fn get_proofs(&mut self, client: &dyn BlockchainBackend) -> Result<usize, Error> {
         let store_read = self.store.read()?;
         let needs_proof: Vec<(Txid, u32)> = self
             .store
@@ -116,10 +199,7 @@ impl Headers {
 
         let mut txs_verified = HashMap::new();
         for (txid, height) in needs_proof {
-            let proof = client.transaction_get_merkle(
-                &elements::bitcoin::Txid::from_hash(txid.as_hash()),
-                height as usize,
-            )?;
+            let proof = client.merkle_proof(&txid, height)?;
             let verified = if let Some(header) = self.store.read()?.cache.headers.get(&height) {
                 self.verifier.verify_tx_proof(&txid, proof, &header).is_ok()
             } else {
@@ -134,7 +214,9 @@ impl Headers {
             }
         }
         let proofs_done = txs_verified.len();
-        self.store.write()?.cache.txs_verif.extend(txs_verified);
+        let mut store_write = self.store.write()?;
+        store_write.cache.txs_verif.extend(txs_verified);
+        store_write.cache_changed()?;
         Ok(proofs_done)
     }
 }
@@ -143,10 +225,12 @@ impl Headers {
 struct DownloadTxResult {
     txs: Vec<(Txid, elements::Transaction)>,
     unblinds: Vec<(elements::OutPoint, elements::TxOutSecrets)>,
+    /// wallet outputs which could not be unblinded
+    failed_rewinds: Vec<elements::OutPoint>,
 }
 
 impl Syncer {
-    pub fn sync(&self, client: &Client) -> Result<bool, Error> {
+    pub fn sync(&self, client: &dyn BlockchainBackend) -> Result<bool, Error> {
         debug!("start sync");
         let start = Instant::now();
 
@@ -161,24 +245,22 @@ impl Syncer {
         for i in wallet_chains {
             let mut batch_count = 0;
             loop {
+                // scripts are derivable data, they could be cached before the end of the sync
+                let last_index = (batch_count + 1) * BATCH_SIZE - 1;
+                scripts.extend(self.store.write()?.ensure_scripts(i, last_index)?);
                 let batch = self.store.read()?.get_script_batch(i, batch_count)?;
-                let scripts_bitcoin: Vec<elements::bitcoin::Script> = batch
-                    .value
-                    .iter()
-                    .map(|e| elements::bitcoin::Script::from(e.0.clone().into_bytes()))
-                    .collect();
-                let scripts_bitcoin: Vec<&elements::bitcoin::Script> =
-                    scripts_bitcoin.iter().map(|e| e).collect();
-                let result: Vec<Vec<GetHistoryRes>> =
-                    client.batch_script_get_history(scripts_bitcoin)?;
-                if !batch.cached {
-                    scripts.extend(batch.value);
-                }
+                let batch_scripts: Vec<Script> = batch.value.iter().map(|e| e.0.clone()).collect();
+                self.progress
+                    .update(|p| p.scripts.1 += batch_scripts.len())?;
+                let result: Vec<Vec<History>> = client.scripts_history(&batch_scripts)?;
+                self.progress
+                    .update(|p| p.scripts.0 += batch_scripts.len())?;
                 let max = result
                     .iter()
                     .enumerate()
                     .filter(|(_, v)| !v.is_empty())
-                    .map(|(i, _)| i as u32)
+                    // template scripts follow the wallet scripts in the same order
+                    .map(|(i, _)| i as u32 % BATCH_SIZE)
                     .max();
                 if let Some(max) = max {
                     if i == 0 {
@@ -188,7 +270,7 @@ impl Syncer {
                     }
                 };
 
-                let flattened: Vec<GetHistoryRes> = result.into_iter().flatten().collect();
+                let flattened: Vec<History> = result.into_iter().flatten().collect();
                 trace!("{}/batch({}) {:?}", i, batch_count, flattened.len());
 
                 if flattened.is_empty() {
@@ -201,7 +283,7 @@ impl Syncer {
                     // but we threat those tx the same
                     let height = el.height.max(0);
                     heights_set.insert(height as u32);
-                    let txid = elements::Txid::from_hash(el.tx_hash.as_hash());
+                    let txid = el.txid;
                     if height == 0 {
                         txid_height.insert(txid, None);
                     } else {
@@ -212,11 +294,18 @@ impl Syncer {
                 }
 
                 batch_count += 1;
+                self.cancel.check()?;
             }
         }
 
-        let new_txs = self.download_txs(&history_txs_id, &scripts, &client)?;
-        let headers = self.download_headers(&heights_set, &client)?;
+        self.progress
+            .update(|p| p.stage = SyncStage::Transactions)?;
+        let new_txs = self.download_txs(&history_txs_id, &scripts, client)?;
+        self.cancel.check()?;
+        self.progress.update(|p| p.stage = SyncStage::Headers)?;
+        let headers = self.download_headers(&heights_set, client)?;
+        // the store is written only if the sync is not cancelled, so it's left consistent
+        self.cancel.check()?;
 
         let store_indexes = self.store.read()?.cache.indexes.clone();
 
@@ -233,8 +322,9 @@ impl Syncer {
             );
             let mut store_write = self.store.write()?;
             store_write.cache.indexes = last_used;
-            store_write.cache.all_txs.extend(new_txs.txs.into_iter());
-            store_write.cache.unblinded.extend(new_txs.unblinds);
+            store_write.cache_txs(new_txs.txs);
+            store_write.cache_unblinded(new_txs.unblinds)?;
+            store_write.cache_failed_rewinds(new_txs.failed_rewinds);
             store_write.cache.headers.extend(headers);
 
             // height map is used for the live list of transactions, since due to reorg or rbf tx
@@ -242,12 +332,9 @@ impl Syncer {
             store_write.cache.heights.clear();
             store_write.cache.heights.extend(txid_height.into_iter());
 
-            store_write
-                .cache
-                .scripts
-                .extend(scripts.clone().into_iter().map(|(a, b)| (b, a)));
-            store_write.cache.paths.extend(scripts.into_iter());
-            store_write.flush()?;
+            // scripts and their paths are already cached by ensure_scripts
+            store_write.update_indexes()?;
+            store_write.cache_changed()?;
             true
         } else {
             false
@@ -261,10 +348,166 @@ impl Syncer {
         Ok(changed)
     }
 
+    /// Sync matching the wallet scripts against the compact block filters of the blocks after the
+    /// last synced one, only the blocks matching are downloaded so that the server doesn't learn
+    /// the wallet scripts. Unconfirmed transactions are not seen.
+    // TODO: handle reorgs, blocks already scanned are never scanned again
+    pub fn sync_filters(&self, client: &dyn BlockchainBackend) -> Result<bool, Error> {
+        debug!("start sync with block filters");
+        let start = Instant::now();
+
+        let (tip_height, _) = client.tip()?;
+        let store_read = self.store.read()?;
+        let from_height = store_read.cache.filter_height + 1;
+        let store_indexes = store_read.cache.indexes.clone();
+        let mut wallet_scripts = store_read.cache.paths.clone();
+        // every wallet output is rewound, successfully or not, when its tx is cached
+        let mut owned_outpoints: HashSet<_> = store_read
+            .cache
+            .unblinded
+            .keys()
+            .chain(store_read.cache.failed_rewinds.iter())
+            .cloned()
+            .collect();
+        drop(store_read);
+
+        let mut last_used = store_indexes.clone();
+        let mut batches = [0u32; 2];
+        let mut scripts = HashMap::new();
+        self.extend_scripts(&last_used, &mut batches, &mut wallet_scripts, &mut scripts)?;
+
+        let mut new_txs = DownloadTxResult::default();
+        let mut txid_height = HashMap::new();
+        let mut headers = vec![];
+        for height in from_height..=tip_height {
+            self.cancel.check()?;
+            let (block_hash, filter) = client.block_filter(height)?;
+            let filter = elements::bitcoin::util::bip158::BlockFilter::new(&filter);
+            let bitcoin_hash = elements::bitcoin::BlockHash::from_hash(block_hash.as_hash());
+            let mut query = wallet_scripts.keys().map(|s| s.as_bytes());
+            if !filter.match_any(&bitcoin_hash, &mut query)? {
+                continue;
+            }
+
+            let block = client.block(height)?;
+            trace!("block {} at height {} matches", block_hash, height);
+            let mut found = HashSet::new();
+            loop {
+                for tx in block.txdata.iter() {
+                    let txid = tx.txid();
+                    if found.contains(&txid) {
+                        continue;
+                    }
+                    let spends = tx
+                        .input
+                        .iter()
+                        .any(|i| owned_outpoints.contains(&i.previous_output));
+                    let mut pays = false;
+                    for (vout, output) in tx.output.iter().enumerate() {
+                        if let Some(path) = wallet_scripts.get(&output.script_pubkey) {
+                            pays = true;
+                            owned_outpoints.insert(elements::OutPoint::new(txid, vout as u32));
+                            if let [ChildNumber::Normal { index: chain }, ChildNumber::Normal { index }] =
+                                path.as_ref()
+                            {
+                                if *chain == 0 {
+                                    last_used.external = last_used.external.max(*index);
+                                } else {
+                                    last_used.internal = last_used.internal.max(*index);
+                                }
+                            }
+                        }
+                    }
+                    if spends || pays {
+                        found.insert(txid);
+                        self.unblind_outputs(tx, &scripts, &mut new_txs)?;
+                        let mut tx = tx.clone();
+                        strip_witness(&mut tx);
+                        new_txs.txs.push((txid, tx));
+                        txid_height.insert(txid, Some(height));
+                    }
+                }
+                // outputs in this block could pay scripts derived after an index was found used
+                if !self.extend_scripts(
+                    &last_used,
+                    &mut batches,
+                    &mut wallet_scripts,
+                    &mut scripts,
+                )? {
+                    break;
+                }
+            }
+            if !found.is_empty() {
+                headers.push((height, block.header.clone()));
+            }
+        }
+
+        self.cancel.check()?;
+        let changed = !new_txs.txs.is_empty() || store_indexes != last_used || !scripts.is_empty();
+        let mut store_write = self.store.write()?;
+        store_write.cache.filter_height = tip_height;
+        if changed {
+            info!(
+                "There are changes in the store new_txs:{:?} headers:{:?}",
+                new_txs.txs.iter().map(|tx| tx.0).collect::<Vec<Txid>>(),
+                headers,
+            );
+            store_write.cache.indexes = last_used;
+            store_write.cache_txs(new_txs.txs);
+            store_write.cache_unblinded(new_txs.unblinds)?;
+            store_write.cache_failed_rewinds(new_txs.failed_rewinds);
+            store_write.cache.headers.extend(headers);
+            store_write.cache.heights.extend(txid_height.into_iter());
+            // scripts and their paths are already cached by ensure_scripts
+            store_write.update_indexes()?;
+        }
+        store_write.cache_changed()?;
+        trace!(
+            "changes:{} elapsed {}",
+            changed,
+            start.elapsed().as_millis()
+        );
+
+        Ok(changed)
+    }
+
+    /// Derive the scripts needed to have at least a batch of unused scripts after the last used
+    /// index of both chains, returns true if new scripts have been added to `wallet_scripts`
+    fn extend_scripts(
+        &self,
+        last_used: &Indexes,
+        batches: &mut [u32; 2],
+        wallet_scripts: &mut HashMap<Script, DerivationPath>,
+        scripts: &mut HashMap<Script, DerivationPath>,
+    ) -> Result<bool, Error> {
+        let mut extended = false;
+        for (chain, last) in [last_used.external, last_used.internal].iter().enumerate() {
+            while batches[chain] * BATCH_SIZE <= last + BATCH_SIZE {
+                let last_index = (batches[chain] + 1) * BATCH_SIZE - 1;
+                self.store
+                    .write()?
+                    .ensure_scripts(chain as u32, last_index)?;
+                let batch = self
+                    .store
+                    .read()?
+                    .get_script_batch(chain as u32, batches[chain])?;
+                for (script, path) in batch.value {
+                    if !wallet_scripts.contains_key(&script) {
+                        wallet_scripts.insert(script.clone(), path.clone());
+                        scripts.insert(script, path);
+                        extended = true;
+                    }
+                }
+                batches[chain] += 1;
+            }
+        }
+        Ok(extended)
+    }
+
     fn download_headers(
         &self,
         heights_set: &HashSet<u32>,
-        client: &Client,
+        client: &dyn BlockchainBackend,
     ) -> Result<Vec<(u32, elements::BlockHeader)>, Error> {
         let mut result = vec![];
         let mut heights_in_db: HashSet<u32> = self
@@ -279,12 +522,10 @@ impl Syncer {
         let heights_to_download: Vec<u32> =
             heights_set.difference(&heights_in_db).cloned().collect();
         if !heights_to_download.is_empty() {
-            let headers_bytes_downloaded =
-                client.batch_block_header_raw(heights_to_download.clone())?;
-            let mut headers_downloaded: Vec<elements::BlockHeader> = vec![];
-            for vec in headers_bytes_downloaded {
-                headers_downloaded.push(elements::encode::deserialize(&vec)?);
-            }
+            let to_download = heights_to_download.len();
+            self.progress.update(|p| p.headers.1 += to_download)?;
+            let headers_downloaded = client.headers(&heights_to_download)?;
+            self.progress.update(|p| p.headers.0 += to_download)?;
             info!("headers_downloaded {:?}", &headers_downloaded);
             for (header, height) in headers_downloaded
                 .into_iter()
@@ -301,93 +542,115 @@ impl Syncer {
         &self,
         history_txs_id: &HashSet<Txid>,
         scripts: &HashMap<Script, DerivationPath>,
-        client: &Client,
+        client: &dyn BlockchainBackend,
     ) -> Result<DownloadTxResult, Error> {
-        let mut txs = vec![];
-        let mut unblinds = vec![];
+        let mut result = DownloadTxResult::default();
 
         let mut txs_in_db = self.store.read()?.cache.all_txs.keys().cloned().collect();
         let txs_to_download: Vec<&Txid> = history_txs_id.difference(&txs_in_db).collect();
         if !txs_to_download.is_empty() {
-            let txs_bitcoin: Vec<elements::bitcoin::Txid> = txs_to_download
-                .iter()
-                .map(|t| elements::bitcoin::Txid::from_hash(t.as_hash()))
-                .collect();
-            let txs_bitcoin: Vec<&elements::bitcoin::Txid> =
-                txs_bitcoin.iter().map(|t| t).collect();
-            let txs_bytes_downloaded = client.batch_transaction_get_raw(txs_bitcoin)?;
-            let mut txs_downloaded: Vec<elements::Transaction> = vec![];
-            for vec in txs_bytes_downloaded {
-                let tx: elements::Transaction = elements::encode::deserialize(&vec)?;
-                txs_downloaded.push(tx);
-            }
+            let txs_to_download: Vec<Txid> = txs_to_download.into_iter().cloned().collect();
+            let txs_downloaded = client.transactions(&txs_to_download)?;
             info!("txs_downloaded {:?}", txs_downloaded.len());
+            self.progress
+                .update(|p| p.txs_downloaded += txs_downloaded.len())?;
             let previous_txs_to_download = HashSet::new();
             for mut tx in txs_downloaded.into_iter() {
                 let txid = tx.txid();
                 txs_in_db.insert(txid);
 
-                info!("compute OutPoint Unblinded");
-                for (i, output) in tx.output.iter().enumerate() {
-                    // could be the searched script it's not yet in the store, because created in the current run, thus it's searched also in the `scripts`
-                    if self
-                        .store
-                        .read()?
-                        .cache
-                        .paths
-                        .contains_key(&output.script_pubkey)
-                        || scripts.contains_key(&output.script_pubkey)
-                    {
-                        let vout = i as u32;
-                        let outpoint = elements::OutPoint {
-                            txid: tx.txid(),
-                            vout,
-                        };
-
-                        match self.try_unblind(outpoint, output.clone()) {
-                            Ok(unblinded) => unblinds.push((outpoint, unblinded)),
-                            Err(_) => info!("{} cannot unblind, ignoring (could be sender messed up with the blinding process)", outpoint),
-                        }
-                        // let unblinded = _liquidex_unblind(&master_blinding_key, &tx, 0, &secp, &assets).unwrap();
-
-                        // TODO: consider skipping this more frequently
-                        match self.try_liquidex_unblind(&tx, i as u32) {
-                            Ok(unblinded) => unblinds.push((outpoint, unblinded)),
-                            Err(_) => info!("LiquiDEX: {} cannot unblind, ignoring", outpoint),
-                        }
-                    }
-                }
+                self.unblind_outputs(&tx, scripts, &mut result)?;
                 strip_witness(&mut tx);
-                txs.push((txid, tx));
+                result.txs.push((txid, tx));
             }
 
             let txs_to_download: Vec<&Txid> =
                 previous_txs_to_download.difference(&txs_in_db).collect();
             if !txs_to_download.is_empty() {
-                let txs_bitcoin: Vec<elements::bitcoin::Txid> = txs_to_download
-                    .iter()
-                    .map(|t| elements::bitcoin::Txid::from_hash(t.as_hash()))
-                    .collect();
-                let txs_bitcoin: Vec<&elements::bitcoin::Txid> =
-                    txs_bitcoin.iter().map(|t| t).collect();
-                let txs_bytes_downloaded = client.batch_transaction_get_raw(txs_bitcoin)?;
-                for vec in txs_bytes_downloaded {
-                    let mut tx: elements::Transaction = elements::encode::deserialize(&vec)?;
+                let txs_to_download: Vec<Txid> = txs_to_download.into_iter().cloned().collect();
+                for mut tx in client.transactions(&txs_to_download)? {
                     strip_witness(&mut tx);
-                    txs.push((tx.txid(), tx));
+                    result.txs.push((tx.txid(), tx));
                 }
             }
-            Ok(DownloadTxResult { txs, unblinds })
+            Ok(result)
         } else {
             Ok(DownloadTxResult::default())
         }
     }
 
+    /// Unblind the outputs of `tx` paying a wallet script adding them to `result`, `scripts` are
+    /// the wallet scripts not yet in the store. Outputs already rewound, successfully or not, are
+    /// skipped, the failed rewinds are added to `result` unless they could succeed later.
+    fn unblind_outputs(
+        &self,
+        tx: &elements::Transaction,
+        scripts: &HashMap<Script, DerivationPath>,
+        result: &mut DownloadTxResult,
+    ) -> Result<(), Error> {
+        info!("compute OutPoint Unblinded");
+        for (i, output) in tx.output.iter().enumerate() {
+            // could be the searched script it's not yet in the store, because created in the current run, thus it's searched also in the `scripts`
+            if self
+                .store
+                .read()?
+                .cache
+                .paths
+                .contains_key(&output.script_pubkey)
+                || scripts.contains_key(&output.script_pubkey)
+            {
+                let vout = i as u32;
+                let outpoint = elements::OutPoint {
+                    txid: tx.txid(),
+                    vout,
+                };
+                if self.store.read()?.cache.is_rewound(&outpoint) {
+                    trace!("{} already rewound", outpoint);
+                    continue;
+                }
+
+                let mut unblinded = false;
+                // failures not caused by the output, such as a blinder not available, are not
+                // cached so that the next sync retries the rewind
+                let mut transient = false;
+                match self.try_unblind(outpoint, output.clone()) {
+                    Ok(secrets) => {
+                        result.unblinds.push((outpoint, secrets));
+                        unblinded = true;
+                    }
+                    Err(Error::CannotUnblind(_)) => info!("{} cannot unblind, ignoring (could be sender messed up with the blinding process)", outpoint),
+                    Err(e) => {
+                        warn!("{} cannot unblind now, retrying with the next sync: {:?}", outpoint, e);
+                        transient = true;
+                    }
+                }
+                // let unblinded = _liquidex_unblind(&master_blinding_key, &tx, 0, &secp, &assets).unwrap();
+
+                // TODO: consider skipping this more frequently
+                match self.try_liquidex_unblind(tx, i as u32) {
+                    Ok(secrets) => {
+                        result.unblinds.push((outpoint, secrets));
+                        unblinded = true;
+                    }
+                    Err(_) => info!("LiquiDEX: {} cannot unblind, ignoring", outpoint),
+                }
+                if !unblinded && !transient {
+                    result.failed_rewinds.push(outpoint);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn try_unblind(
         &self,
         outpoint: elements::OutPoint,
         output: elements::TxOut,
     ) -> Result<elements::TxOutSecrets, Error> {
+        if let Some(txout_secrets) = explicit_secrets(&output) {
+            info!("Unconfidential outpoint:{}", outpoint);
+            return Ok(txout_secrets);
+        }
         match (output.asset, output.value, output.nonce) {
             (
                 Asset::Confidential(_),
@@ -396,26 +659,24 @@ impl Syncer {
             ) => {
                 // TODO: use a shared ctx
                 let secp = elements::bitcoin::secp256k1::Secp256k1::new();
-                let receiver_sk = self
-                    .master_blinding
-                    .derive_blinding_key(&output.script_pubkey);
-                // TODO: implement UnblindError and remove Generic
+                let receiver_sk = self.blinder.blinding_key(&output.script_pubkey)?;
                 let txout_secrets = output
                     .unblind(&secp, receiver_sk)
-                    .map_err(|_| Error::Generic("UnblindError".into()))?;
+                    .map_err(|_| Error::CannotUnblind(outpoint))?;
 
                 info!(
                     "Unblinded outpoint:{} asset:{} value:{}",
                     outpoint,
                     &txout_secrets.asset.to_hex(),
-                    txout_secrets.value,
+                    redact(txout_secrets.value),
                 );
 
                 Ok(txout_secrets)
             }
-            _ => Err(Error::Generic(
-                "received unconfidential or null asset/value/nonce".into(),
-            )),
+            _ => {
+                info!("{} has null asset/value/nonce", outpoint);
+                Err(Error::CannotUnblind(outpoint))
+            }
         }
     }
 
@@ -425,14 +686,23 @@ impl Syncer {
         vout: u32,
     ) -> Result<elements::TxOutSecrets, Error> {
         info!("LiquiDEX try unblind: {:?}:{}", tx.txid(), vout);
-        let assets = self.store.read()?.liquidex_assets();
-        liquidex_unblind(&self.master_blinding, &tx, vout, &self.secp, &assets)
+        let assets = self.store.read()?.known_assets();
+        liquidex_unblind(&*self.blinder, &tx, vout, &self.secp, &assets)
     }
 }
 
+/// Wallet synced with the server of `Config::backend`: the high level API most integrators
+/// need, such as `address`, `balance`, `transactions`, `send` and the LiquiDEX calls.
+///
+/// Calls needing fresh data sync first, then delegate to the internal `WalletCtx`, the engine
+/// building and signing the transactions without network access.
 pub struct ElectrumWallet {
     config: Config,
     wallet: WalletCtx,
+    #[cfg(feature = "webhooks")]
+    webhook: Option<Webhook>,
+    metrics: Option<Arc<dyn Metrics>>,
+    progress: Arc<ProgressReporter>,
 }
 
 impl ElectrumWallet {
@@ -467,23 +737,155 @@ impl ElectrumWallet {
         Self::new(config, data_root, mnemonic)
     }
 
-    fn new(config: Config, data_root: &str, mnemonic: &str) -> Result<Self, Error> {
+    pub fn new(config: Config, data_root: &str, mnemonic: &str) -> Result<Self, Error> {
         let wallet = WalletCtx::from_mnemonic(mnemonic, &data_root, config.clone())?;
+        Ok(Self::with_wallet(config, wallet))
+    }
+
+    /// Restore the Blockstream Green singlesig `subaccount` of `mnemonic`, see
+    /// `DerivationTemplate::green_subaccount`. Transactions are signed passing the mnemonic to
+    /// `sign_tx` as for the other wallets.
+    pub fn new_green(
+        mut config: Config,
+        data_root: &str,
+        mnemonic: &str,
+        subaccount: u32,
+    ) -> Result<Self, Error> {
+        config.derivation = Some(DerivationTemplate::green_subaccount(
+            config.network(),
+            subaccount,
+        )?);
+        Self::new(config, data_root, mnemonic)
+    }
 
-        Ok(Self { config, wallet })
+    /// Create a wallet signing with the keys derived from `spend_mnemonic` and unblinding with the
+    /// SLIP-77 master blinding key derived from `view_mnemonic`, transactions are signed passing
+    /// the spend mnemonic to `sign_tx`.
+    pub fn new_with_view_mnemonic(
+        config: Config,
+        data_root: &str,
+        spend_mnemonic: &str,
+        view_mnemonic: &str,
+    ) -> Result<Self, Error> {
+        let wallet =
+            WalletCtx::from_mnemonics(spend_mnemonic, view_mnemonic, data_root, config.clone())?;
+        Ok(Self::with_wallet(config, wallet))
     }
 
-    pub fn policy_asset(&self) -> elements::issuance::AssetId {
-        self.wallet.config.policy_asset()
+    /// Create a wallet which can sync and unblind its transactions, but not sign them, from the
+    /// account xpub of the spend key and the mnemonic of the view key.
+    pub fn new_view_only(
+        config: Config,
+        data_root: &str,
+        xpub: ExtendedPubKey,
+        master_fingerprint: Fingerprint,
+        view_mnemonic: &str,
+    ) -> Result<Self, Error> {
+        let wallet = WalletCtx::from_view_key(
+            xpub,
+            master_fingerprint,
+            view_mnemonic,
+            data_root,
+            config.clone(),
+        )?;
+        Ok(Self::with_wallet(config, wallet))
+    }
+
+    /// Create a wallet which can sync and unblind its transactions, but not sign them, from the
+    /// account xpub of the spend key and a `blinder` holding the master blinding key, such as a
+    /// hardware device.
+    pub fn new_with_blinder(
+        config: Config,
+        data_root: &str,
+        xpub: ExtendedPubKey,
+        master_fingerprint: Fingerprint,
+        blinder: Arc<dyn Blinder>,
+    ) -> Result<Self, Error> {
+        let wallet =
+            WalletCtx::from_blinder(xpub, master_fingerprint, blinder, data_root, config.clone())?;
+        Ok(Self::with_wallet(config, wallet))
+    }
+
+    fn with_wallet(config: Config, wallet: WalletCtx) -> Self {
+        Self {
+            config,
+            wallet,
+            #[cfg(feature = "webhooks")]
+            webhook: None,
+            metrics: None,
+            progress: Arc::new(ProgressReporter::default()),
+        }
+    }
+
+    /// Create a wallet saving its data with `persister`, for instance a `MemoryPersister` for a
+    /// wallet that does not touch the disk.
+    pub fn with_persister(
+        config: Config,
+        persister: Box<dyn Persister>,
+        mnemonic: &str,
+    ) -> Result<Self, Error> {
+        let wallet = WalletCtx::from_mnemonic_with_store(mnemonic, persister, config.clone())?;
+        Ok(Self::with_wallet(config, wallet))
+    }
+
+    /// Restore a watch-only wallet from a backup created with `export_backup`, transactions
+    /// can be signed passing the mnemonic to `sign_tx`.
+    pub fn from_backup(
+        config: Config,
+        data_root: &str,
+        backup: &[u8],
+        password: &str,
+    ) -> Result<Self, Error> {
+        let wallet = WalletCtx::from_backup(backup, password, data_root, config.clone())?;
+        Ok(Self::with_wallet(config, wallet))
+    }
+
+    /// Encrypted backup with the xpub, the master blinding key and the wallet settings, such as
+    /// invoices and LiquiDEX swaps, but not the seed.
+    pub fn export_backup(&self, password: &str) -> Result<Vec<u8>, Error> {
+        self.wallet.export_backup(password)
+    }
+
+    /// Policy asset of the network, which pays the fees
+    pub fn policy_asset_id(&self) -> elements::issuance::AssetId {
+        self.wallet.config.policy_asset_id()
+    }
+
+    /// Identifier of the wallet, it is also the name of the store directory.
+    pub fn wallet_id(&self) -> &str {
+        self.wallet.wallet_id()
+    }
+
+    /// Fingerprint of the master key the wallet keys are derived from.
+    pub fn master_fingerprint(&self) -> Fingerprint {
+        self.wallet.master_fingerprint()
+    }
+
+    /// Account extended public key, serialized as xpub on Liquid and tpub on regtest.
+    pub fn xpub(&self) -> ExtendedPubKey {
+        self.wallet.xpub()
+    }
+
+    /// Account extended private key derived from `mnemonic`, serialized as xprv on Liquid and
+    /// tprv on regtest.
+    pub fn account_xprv(&self, mnemonic: &str) -> Result<ExtendedPrivKey, Error> {
+        self.wallet.account_xprv(mnemonic)
     }
 
     pub fn update_fee_estimates(&self) {
+        let _span = info_span!("update_fee_estimates", wallet_id = %self.wallet_id()).entered();
         info!("building client");
-        if let Ok(fee_client) = self.config.electrum_url().build_client() {
+        if let Ok(fee_client) = self.config.build_client() {
             info!("building built end");
             let fee_store = self.wallet.store.clone();
-            match try_get_fee_estimates(&fee_client) {
-                Ok(fee_estimates) => fee_store.write().unwrap().cache.fee_estimates = fee_estimates,
+            match fee_client.fee_estimates() {
+                Ok(fee_estimates) => {
+                    let mut store_write = fee_store.write().unwrap();
+                    store_write.cache.fee_estimates = fee_estimates;
+                    if let Err(e) = store_write.cache_changed() {
+                        warn!("can't save fee estimates {:?}", e);
+                    }
+                }
                 Err(e) => warn!("can't update fee estimates {:?}", e),
             };
         }
@@ -495,9 +897,8 @@ impl ElectrumWallet {
             store: self.wallet.store.clone(),
             config: self.config.clone(),
         };
-        let tipper_url = self.config.electrum_url();
-        if let Ok(client) = tipper_url.build_client() {
-            match tipper.tip(&client) {
+        if let Ok(client) = self.config.build_client() {
+            match tipper.tip(&*client) {
                 Ok(_) => (),
                 Err(e) => {
                     warn!("exception in tipper {:?}", e);
@@ -508,7 +909,8 @@ impl ElectrumWallet {
     }
 
     pub fn update_spv(&self) -> Result<(), Error> {
-        let verifier = Verifier::new(self.config.network());
+        let _span = info_span!("update_spv", wallet_id = %self.wallet_id()).entered();
+        let verifier = self.config.spv_verifier();
 
         let mut headers = Headers {
             store: self.wallet.store.clone(),
@@ -516,9 +918,9 @@ impl ElectrumWallet {
         };
 
         self.update_tip()?;
-        if let Ok(client) = self.config.electrum_url().build_client() {
+        if let Ok(client) = self.config.build_client() {
             info!("getting proofs");
-            match headers.get_proofs(&client) {
+            match headers.get_proofs(&*client) {
                 Ok(found) => {
                     if found > 0 {
                         info!("found proof {}", found)
@@ -530,21 +932,207 @@ impl ElectrumWallet {
         Ok(())
     }
 
+    /// Header at `height`, downloaded and cached if not in the store.
+    /// If spv is enabled the header is verified.
+    pub fn get_header(&self, height: u32) -> Result<elements::BlockHeader, Error> {
+        if let Some(header) = self.wallet.get_header(height)? {
+            return Ok(header);
+        }
+        let client = self.config.build_client()?;
+        let header = client
+            .headers(&[height])?
+            .pop()
+            .ok_or_else(|| Error::Generic(format!("no header at height {}", height)))?;
+        if header.height != height {
+            return Err(Error::InvalidHeaders);
+        }
+        if self.config.spv_enabled {
+            self.config.spv_verifier().verify_header(&header)?;
+        }
+        self.wallet
+            .store
+            .write()?
+            .cache
+            .headers
+            .insert(height, header.clone());
+        Ok(header)
+    }
+
+    /// Verify that the wallet transaction `txid` is included in the block at its height with a
+    /// merkle proof from the server, the result is cached and reported in the transactions list.
+    /// Returns `InProgress` if the transaction is unconfirmed.
+    pub fn verify_tx_inclusion(&self, txid: &Txid) -> Result<SPVVerifyResult, Error> {
+        let _span = info_span!("verify_tx_inclusion", wallet_id = %self.wallet_id(), txid = %txid)
+            .entered();
+        let height = self.wallet.store.read()?.cache.heights.get(txid).cloned();
+        let height = match height {
+            None => return Err(Error::Generic(format!("unknown transaction {}", txid))),
+            Some(None) => return Ok(SPVVerifyResult::InProgress),
+            Some(Some(height)) => height,
+        };
+        let header = self.get_header(height)?;
+        let client = self.config.build_client()?;
+        let proof = client.merkle_proof(txid, height)?;
+        let verifier = self.config.spv_verifier();
+        let result = match verifier.verify_tx_proof(txid, proof, &header) {
+            Ok(()) => SPVVerifyResult::Verified,
+            Err(_) => SPVVerifyResult::NotVerified,
+        };
+        self.wallet
+            .store
+            .write()?
+            .cache
+            .txs_verif
+            .insert(*txid, result.clone());
+        Ok(result)
+    }
+
+    /// Notify `webhook` of the transactions received on wallet addresses found by the next syncs,
+    /// unconfirmed or confirmed after the tip known before the sync. The first sync of a wallet
+    /// notifies only the unconfirmed ones, without its history.
+    #[cfg(feature = "webhooks")]
+    pub fn set_webhook(&mut self, webhook: Option<Webhook>) {
+        self.webhook = webhook;
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn notify_received(
+        &self,
+        webhook: &Webhook,
+        known: &HashSet<Txid>,
+        previous_tip: Option<u32>,
+    ) -> Result<(), Error> {
+        let new_txs: Vec<(Txid, Option<u32>)> = self
+            .wallet
+            .store
+            .read()?
+            .cache
+            .heights
+            .iter()
+            .filter(|(txid, height)| {
+                !known.contains(*txid) && is_new_payment(**height, previous_tip)
+            })
+            .map(|(txid, height)| (*txid, *height))
+            .collect();
+        for (txid, height) in new_txs {
+            let outputs: Vec<ReceivedOutput> = self
+                .wallet
+                .received_outputs(&txid)?
+                .into_iter()
+                .map(|(vout, address, unblinded)| ReceivedOutput {
+                    vout,
+                    address: address.to_string(),
+                    asset: unblinded.asset.to_hex(),
+                    value: unblinded.value,
+                })
+                .collect();
+            if outputs.is_empty() {
+                continue;
+            }
+            let notification = PaymentNotification {
+                txid: txid.to_string(),
+                height,
+                outputs,
+            };
+            if let Err(e) = webhook.notify(&notification) {
+                warn!("can't notify webhook of {}, {:?}", txid, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Select the inputs and the changes of the transactions created by `create_tx` with
+    /// `coin_selector`, None restores the default selection spending the biggest utxos first.
+    pub fn set_coin_selector(&mut self, coin_selector: Option<Arc<dyn CoinSelector>>) {
+        self.wallet.set_coin_selector(coin_selector);
+    }
+
+    /// Ask `sign_approver` before signing with the wallet keys, in `sign_tx`, payjoins and
+    /// LiquiDEX, None signs without asking.
+    pub fn set_sign_approver(&mut self, sign_approver: Option<Arc<dyn SignApprover>>) {
+        self.wallet.set_sign_approver(sign_approver);
+    }
+
+    /// Report operational metrics to `metrics`.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.metrics = metrics;
+    }
+
+    /// Progress of the current or of the last sync
+    pub fn sync_progress(&self) -> Result<SyncProgress, Error> {
+        self.progress.snapshot()
+    }
+
+    /// Send every update of the sync progress to `sender`, until it is replaced or the receiver
+    /// is dropped.
+    pub fn set_sync_progress_sender(
+        &self,
+        sender: Option<std::sync::mpsc::Sender<SyncProgress>>,
+    ) -> Result<(), Error> {
+        self.progress.set_sender(sender)
+    }
+
     pub fn sync(&self) -> Result<(), Error> {
+        self.sync_cancellable(&CancelToken::new())
+    }
+
+    /// Sync that could be aborted with `cancel`, returning `Error::Cancelled` and leaving the store
+    /// as before the sync.
+    pub fn sync_cancellable(&self, cancel: &CancelToken) -> Result<(), Error> {
+        let _span = info_span!("sync", wallet_id = %self.wallet_id()).entered();
+        #[cfg(feature = "webhooks")]
+        let (known, previous_tip) = {
+            let store_read = self.wallet.store.read()?;
+            let known: HashSet<Txid> = store_read.cache.heights.keys().cloned().collect();
+            let tip_height = store_read.cache.tip.0;
+            let synced = store_read.cache.last_sync.is_some() || tip_height > 0;
+            (known, Some(tip_height).filter(|_| synced))
+        };
         let syncer = Syncer {
             store: self.wallet.store.clone(),
-            master_blinding: self.wallet.master_blinding.clone(),
+            blinder: self.wallet.blinder.clone(),
             config: self.config.clone(),
+            cancel: cancel.clone(),
+            progress: self.progress.clone(),
             secp: secp256k1::Secp256k1::new(),
         };
+        self.progress.update(|p| {
+            *p = SyncProgress {
+                stage: SyncStage::Scripts,
+                ..Default::default()
+            }
+        })?;
 
-        if let Ok(client) = self.config.electrum_url().build_client() {
-            match syncer.sync(&client) {
+        if let Ok(client) = self.config.build_client() {
+            let start = Instant::now();
+            let client = CountingBackend::new(&*client);
+            let result = if self.config.block_filters {
+                syncer.sync_filters(&client)
+            } else {
+                syncer.sync(&client)
+            };
+            if let Some(metrics) = &self.metrics {
+                metrics.sync(start.elapsed(), client.requests());
+                let store_read = self.wallet.store.read()?;
+                metrics.cache_size(store_read.cache.all_txs.len(), store_read.cache.paths.len());
+            }
+            if result.is_ok() {
+                let mut store_write = self.wallet.store.write()?;
+                store_write.cache.last_sync = Some(now()?);
+                store_write.cache_changed()?;
+            }
+            match result {
                 Ok(true) => info!("there are new transcations"),
                 Ok(false) => (),
+                Err(Error::Cancelled) => return Err(Error::Cancelled),
                 Err(e) => warn!("Error during sync, {:?}", e),
             }
         }
+        self.progress.update(|p| p.stage = SyncStage::Done)?;
+        #[cfg(feature = "webhooks")]
+        if let Some(webhook) = &self.webhook {
+            self.notify_received(webhook, &known, previous_tip)?;
+        }
         Ok(())
     }
 
@@ -571,12 +1159,251 @@ impl ElectrumWallet {
 
     pub fn balance(&self) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
         self.sync()?;
-        self.wallet.balance()
+        let balance = self.wallet.balance()?;
+        if let Some(metrics) = &self.metrics {
+            for (asset, satoshi) in balance.iter() {
+                metrics.balance(asset, *satoshi);
+            }
+        }
+        Ok(balance)
+    }
+
+    /// Satoshi of `asset` sent by the wallet txs to OP_RETURN outputs, removing them from the
+    /// supply. Burns are also listed in `TransactionDetails::burns`.
+    pub fn total_burned(&self, asset: &elements::issuance::AssetId) -> Result<Amount, Error> {
+        let _span = info_span!("total_burned", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.total_burned(asset)
+    }
+
+    /// Balance of every asset after the block at `height`, see `WalletCtx::balance_at`.
+    pub fn balance_at(
+        &self,
+        height: u32,
+    ) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        let _span = info_span!("balance_at", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.balance_at(height)
+    }
+
+    /// Reissuance tokens held by the wallet, with the asset they reissue and their amount. They
+    /// are not in `balance` and they are not spent by `create_tx` unless
+    /// `CreateTransactionOpt::spend_reissuance_tokens` is set. Tokens are recognized from the
+    /// issuances of the wallet txs, from the registry with `refresh_asset_metadata` or once
+    /// recorded with `reissuance_token_insert`.
+    pub fn reissuance_tokens_balance(
+        &self,
+    ) -> Result<HashMap<elements::issuance::AssetId, (elements::issuance::AssetId, u64)>, Error>
+    {
+        self.sync()?;
+        self.wallet.reissuance_tokens_balance()
     }
 
     pub fn address(&self) -> Result<elements::Address, Error> {
         self.sync()?;
-        self.wallet.get_address()
+        self.wallet.next_address()
+    }
+
+    /// External address `offset` positions after the one returned by the next `address` call,
+    /// it does not consume any address.
+    pub fn peek_address(&self, offset: u32) -> Result<elements::Address, Error> {
+        self.sync()?;
+        self.wallet.peek_address(offset)
+    }
+
+    /// Like `address`, with the unconfidential address, the blinding key and the script pubkey.
+    pub fn address_info(&self) -> Result<AddressInfo, Error> {
+        Ok(AddressInfo::new(self.address()?))
+    }
+
+    /// Like `peek_address`, with the unconfidential address, the blinding key and the script
+    /// pubkey.
+    pub fn peek_address_info(&self, offset: u32) -> Result<AddressInfo, Error> {
+        Ok(AddressInfo::new(self.peek_address(offset)?))
+    }
+
+    /// Add `label` to the wallet `address`, to spend only from labelled addresses with
+    /// `CreateTransactionOpt::from_labels`.
+    pub fn add_label(&self, address: &elements::Address, label: &str) -> Result<(), Error> {
+        self.wallet.add_label(address, label)
+    }
+
+    pub fn remove_label(&self, address: &elements::Address, label: &str) -> Result<(), Error> {
+        self.wallet.remove_label(address, label)
+    }
+
+    /// Labels of the wallet `address`
+    pub fn labels(&self, address: &elements::Address) -> Result<HashSet<String>, Error> {
+        self.wallet.labels(address)
+    }
+
+    /// Add `name` to the address book, see `WalletCtx::add_contact`.
+    pub fn add_contact(
+        &self,
+        name: &str,
+        address: &str,
+        assets: Vec<elements::issuance::AssetId>,
+    ) -> Result<(), Error> {
+        self.wallet.add_contact(name, address, assets)
+    }
+
+    pub fn remove_contact(&self, name: &str) -> Result<Option<Contact>, Error> {
+        self.wallet.remove_contact(name)
+    }
+
+    /// Address book contacts, sorted by name
+    pub fn list_contacts(&self) -> Result<Vec<Contact>, Error> {
+        self.wallet.list_contacts()
+    }
+
+    /// Contacts whose name matches `query`, closest first
+    pub fn find_contacts(&self, query: &str) -> Result<Vec<Contact>, Error> {
+        self.wallet.find_contacts(query)
+    }
+
+    /// Assign the wallet utxo `outpoint` to the virtual `account`.
+    ///
+    /// Virtual accounts partition the wallet utxos: a utxo belongs to the account it has been
+    /// assigned to or, if not assigned, to the accounts named as the labels of its address.
+    pub fn assign_utxo(&self, outpoint: elements::OutPoint, account: &str) -> Result<(), Error> {
+        self.sync()?;
+        self.wallet.assign_utxo(outpoint, account)
+    }
+
+    pub fn account_utxos(&self, account: &str) -> Result<Vec<UnblindedTXO>, Error> {
+        self.sync()?;
+        self.wallet.account_utxos(account)
+    }
+
+    pub fn account_balance(
+        &self,
+        account: &str,
+    ) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+        self.sync()?;
+        self.wallet.account_balance(account)
+    }
+
+    /// Balance of every virtual account with utxos, the utxos not in an account are in
+    /// `DEFAULT_ACCOUNT`
+    pub fn accounts_balance(
+        &self,
+    ) -> Result<HashMap<String, HashMap<elements::issuance::AssetId, u64>>, Error> {
+        self.sync()?;
+        self.wallet.accounts_balance()
+    }
+
+    /// Create a transaction spending only from the virtual `account`, keeping the change in it
+    pub fn account_create_tx(
+        &self,
+        account: &str,
+        opt: &mut CreateTransactionOpt,
+    ) -> Result<TransactionDetails, Error> {
+        let _span = info_span!("account_create_tx", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.account_create_tx(account, opt)
+    }
+
+    /// Create an invoice requesting `amount` of `asset` on a new address, expiring after `expiry`
+    /// seconds.
+    pub fn create_invoice(
+        &self,
+        asset: elements::issuance::AssetId,
+        amount: Amount,
+        memo: &str,
+        expiry: u64,
+    ) -> Result<Invoice, Error> {
+        self.sync()?;
+        self.wallet.create_invoice(asset, amount, memo, expiry)
+    }
+
+    /// Status of the invoice `id` after a sync.
+    pub fn invoice_status(&self, id: &str) -> Result<InvoiceStatus, Error> {
+        self.sync()?;
+        self.wallet.invoice_status(id)
+    }
+
+    /// Wallet outputs of `asset` paying `address` after a sync.
+    pub fn payments_to(
+        &self,
+        address: &elements::Address,
+        asset: &elements::issuance::AssetId,
+    ) -> Result<Vec<Payment>, Error> {
+        self.sync()?;
+        self.wallet.payments_to(address, asset)
+    }
+
+    /// Sync every `PAYMENT_POLL_INTERVAL` until the outputs of `asset` paying `address` sum to at
+    /// least `min_amount`, returning them, or None if they do not within `timeout`. The wait
+    /// could be aborted with `cancel`, returning `Error::Cancelled`.
+    ///
+    /// Outputs received before the call count too, use a fresh address for every payment.
+    pub fn wait_for_payment(
+        &self,
+        address: &elements::Address,
+        min_amount: Amount,
+        asset: &elements::issuance::AssetId,
+        timeout: Duration,
+        cancel: &CancelToken,
+    ) -> Result<Option<Vec<Payment>>, Error> {
+        let _span = info_span!("wait_for_payment", wallet_id = %self.wallet_id()).entered();
+        let start = Instant::now();
+        loop {
+            self.sync_cancellable(cancel)?;
+            let payments = self.wallet.payments_to(address, asset)?;
+            let received = payments
+                .iter()
+                .fold(Amount::ZERO, |acc, p| acc.saturating_add(p.satoshi));
+            if received >= min_amount {
+                return Ok(Some(payments));
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Ok(None);
+            }
+            cancel.sleep(PAYMENT_POLL_INTERVAL.min(timeout - elapsed))?;
+        }
+    }
+
+    /// Chain and index of the derivation path of `script`, None if the script is not known by the
+    /// wallet.
+    pub fn index_of(&self, script: &Script) -> Result<Option<(u32, u32)>, Error> {
+        self.wallet.index_of(script)
+    }
+
+    /// Chain and index of the derivation path of `address`, None if the address is not known by
+    /// the wallet.
+    pub fn index_of_address(
+        &self,
+        address: &elements::Address,
+    ) -> Result<Option<(u32, u32)>, Error> {
+        self.wallet.index_of_address(address)
+    }
+
+    /// Max index used for the external (0) or internal (1) chain.
+    pub fn max_used_index(&self, chain: u32) -> Result<u32, Error> {
+        self.wallet.max_used_index(chain)
+    }
+
+    /// Derive and cache the scripts of the external (0) or internal (1) chain up to
+    /// `up_to_index` included, so that syncs and address requests don't derive them.
+    pub fn ensure_addresses(&self, chain: u32, up_to_index: u32) -> Result<(), Error> {
+        self.wallet.ensure_addresses(chain, up_to_index)
+    }
+
+    /// Track and spend the outputs locked by `template` to the wallet keys, such as a
+    /// `CsvScript`, returns the template index. Templates must be registered again, in the same
+    /// order, every time the wallet is loaded.
+    pub fn register_script(&self, template: Arc<dyn SpendableScript>) -> Result<usize, Error> {
+        self.wallet.register_script(template)
+    }
+
+    /// Address of the template at `template_index` for the external key at `pointer`.
+    pub fn template_address(
+        &self,
+        template_index: usize,
+        pointer: u32,
+    ) -> Result<elements::Address, Error> {
+        self.wallet.template_address(template_index, pointer)
     }
 
     pub fn transactions(&self, opt: &GetTransactionsOpt) -> Result<Vec<TransactionDetails>, Error> {
@@ -584,15 +1411,184 @@ impl ElectrumWallet {
         self.wallet.list_tx(opt)
     }
 
+    /// Transactions confirmed at `height` or after and the unconfirmed ones, newest first
+    pub fn transactions_since(&self, height: u32) -> Result<Vec<TransactionDetails>, Error> {
+        self.sync()?;
+        self.wallet.list_tx_since(height)
+    }
+
+    /// Decode the transaction `hex` annotating its wallet inputs and outputs, see
+    /// `WalletCtx::decode_tx`.
+    pub fn decode_tx(&self, hex: &str) -> Result<DecodedTx, Error> {
+        self.sync()?;
+        self.wallet.decode_tx(hex)
+    }
+
+    /// Unblind output `vout` of the wallet transaction `txid` paying `address`, such as a payment
+    /// sent by a wallet restored from the seed, see `WalletCtx::unblind_sent_output`.
+    pub fn unblind_sent_output(
+        &self,
+        txid: &Txid,
+        vout: u32,
+        address: &elements::Address,
+    ) -> Result<elements::TxOutSecrets, Error> {
+        self.sync()?;
+        self.wallet.unblind_sent_output(txid, vout, address)
+    }
+
+    /// Counts of the synced transactions and of their outputs by kind of script, see
+    /// `WalletCtx::sync_report`.
+    pub fn sync_report(&self) -> Result<SyncReport, Error> {
+        self.wallet.sync_report()
+    }
+
+    /// Summary of the wallet state, it does not sync, see `WalletCtx::stats`.
+    pub fn stats(&self) -> Result<WalletStats, Error> {
+        self.wallet.stats()
+    }
+
+    /// Unconfirmed payments to the wallet and their fee rates, see
+    /// `WalletCtx::pending_incoming`. Update the fee estimates with `update_fee_estimates` to
+    /// compare the fee rates with the current ones.
+    pub fn pending_incoming(&self) -> Result<PendingIncoming, Error> {
+        let _span = info_span!("pending_incoming", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.pending_incoming()
+    }
+
+    /// Immutable copy of the wallet store, it does not sync.
+    pub fn store_snapshot(&self) -> Result<StoreSnapshot, Error> {
+        self.wallet.store_snapshot()
+    }
+
+    /// Save the wallet changes as soon as they are made, the default, or only on `flush` and
+    /// when the wallet is dropped. Enabling it saves the pending changes.
+    pub fn set_autoflush(&self, autoflush: bool) -> Result<(), Error> {
+        self.wallet.set_autoflush(autoflush)
+    }
+
+    /// Save the wallet changes not saved yet, it does not sync.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.wallet.flush()
+    }
+
     // actually should list all coins, not only the unspent ones
     pub fn utxos(&self) -> Result<Vec<UnblindedTXO>, Error> {
         self.sync()?;
-        self.wallet.utxos()
+        let utxos = self.wallet.utxos()?;
+        if let Some(metrics) = &self.metrics {
+            metrics.utxo_count(utxos.len());
+        }
+        Ok(utxos)
     }
 
+    /// Utxos filtered by asset, confirmations and value, sorted by value or age
+    pub fn utxos_opt(&self, opt: &UtxosOpt) -> Result<Vec<UnblindedTXO>, Error> {
+        self.sync()?;
+        self.wallet.utxos_opt(opt)
+    }
+
+    /// Create an unsigned transaction paying the addressees, which may have different assets.
+    ///
+    /// Fee is paid in the policy asset, a change output is added for every asset with a
+    /// remainder and reported in `TransactionDetails::changes`. All outputs are blinded when
+    /// signing, every input is part of the surjection proof domain.
     pub fn create_tx(&self, opt: &mut CreateTransactionOpt) -> Result<TransactionDetails, Error> {
+        let _span = info_span!("create_tx", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        #[cfg(feature = "registry")]
+        if let (true, Some(registry_url)) = (opt.check_assets, &self.config.registry_url) {
+            let registry = RegistryClient::new(
+                registry_url,
+                self.config.timeout,
+                self.config.client_id.as_deref(),
+            );
+            let policy_asset = self.config.policy_asset_id();
+            let assets: Vec<_> = opt
+                .addressees
+                .iter()
+                .map(|a| a.asset_or(policy_asset))
+                .collect();
+            self.wallet.lookup_assets(&registry, &assets)?;
+        }
+        let details = self.wallet.create_tx(opt)?;
+        if let Some(metrics) = &self.metrics {
+            let tx = &details.transaction;
+            metrics.tx_created(tx.input.len(), tx.output.len(), details.fee.as_sat());
+        }
+        Ok(details)
+    }
+
+    /// Create, sign and broadcast the transaction paying `opt.addressees`, returning it signed.
+    pub fn send(
+        &self,
+        opt: &mut CreateTransactionOpt,
+        mnemonic: &str,
+    ) -> Result<TransactionDetails, Error> {
+        let mut details = self.create_tx(opt)?;
+        let txid = details.transaction.txid();
+        if self.wallet.store.read()?.cache.heights.contains_key(&txid) {
+            // already sent with the same `opt.request_id`
+            return Ok(details);
+        }
+        self.sign_tx(&mut details.transaction, mnemonic)?;
+        self.broadcast_tx(&details.transaction)?;
+        details.txid = details.transaction.txid().to_string();
+        Ok(details)
+    }
+
+    /// Create transactions moving all the wallet funds to the addresses returned by
+    /// `next_address`, such as `|| new_wallet.address()`, see `WalletCtx::sweep_to`.
+    pub fn sweep_to<F>(
+        &self,
+        next_address: F,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<Vec<TransactionDetails>, Error>
+    where
+        F: FnMut() -> Result<elements::Address, Error>,
+    {
+        let _span = info_span!("sweep_to", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.sweep_to(next_address, fee_rate)
+    }
+
+    /// Create a signed transaction sweeping into the wallet the confidential outputs of the
+    /// external key `wif`, such as a paper or gift wallet, see `WalletCtx::sweep_external_key`.
+    pub fn sweep_external_key(
+        &self,
+        wif: &str,
+        blinding_key: &secp256k1::SecretKey,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<TransactionDetails, Error> {
+        let _span = info_span!("sweep_external_key", wallet_id = %self.wallet_id()).entered();
         self.sync()?;
-        self.wallet.create_tx(opt)
+        let client = self.config.build_client()?;
+        self.wallet
+            .sweep_external_key(&*client, wif, blinding_key, fee_rate)
+    }
+
+    /// Create a transaction bumping the fee of the unconfirmed `parent_txid` spending its
+    /// outputs, see `WalletCtx::create_cpfp`.
+    pub fn create_cpfp(
+        &self,
+        parent_txid: &Txid,
+        target_fee_rate: FeeRate,
+    ) -> Result<TransactionDetails, Error> {
+        let _span = info_span!("create_cpfp", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.create_cpfp(parent_txid, target_fee_rate)
+    }
+
+    /// Add wallet inputs and changes to the externally built `tx` having outputs only, then
+    /// blind it, see `WalletCtx::fund_tx`.
+    pub fn fund_tx(
+        &self,
+        tx: elements::Transaction,
+        fee_rate: Option<FeeRate>,
+    ) -> Result<TransactionDetails, Error> {
+        let _span = info_span!("fund_tx", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        self.wallet.fund_tx(tx, fee_rate)
     }
 
     pub fn sign_tx(
@@ -600,14 +1596,152 @@ impl ElectrumWallet {
         transaction: &mut elements::Transaction,
         mnemonic: &str,
     ) -> Result<(), Error> {
+        let _span = info_span!("sign_tx", wallet_id = %self.wallet_id()).entered();
         self.wallet.sign_with_mnemonic(transaction, mnemonic)
     }
 
+    /// Sign the wallet inputs of `transaction` with `signer`, such as a `RemoteSigner` of the
+    /// service holding the wallet keys, see `WalletCtx::sign_with_signer`.
+    pub fn sign_tx_with_signer(
+        &self,
+        transaction: &mut elements::Transaction,
+        signer: &dyn Signer,
+    ) -> Result<(), Error> {
+        let _span = info_span!("sign_tx_with_signer", wallet_id = %self.wallet_id()).entered();
+        self.wallet.sign_with_signer(transaction, signer)
+    }
+
+    /// Sighashes of the wallet inputs of `transaction` for external signers, see
+    /// `WalletCtx::sighashes`.
+    pub fn sighashes(
+        &self,
+        transaction: &mut elements::Transaction,
+    ) -> Result<Vec<(usize, DerivationPath, elements::SigHash)>, Error> {
+        let _span = info_span!("sighashes", wallet_id = %self.wallet_id()).entered();
+        self.wallet.sighashes(transaction)
+    }
+
+    /// Add to input `index` of `transaction` the `signature` of `public_key` made by an external
+    /// signer.
+    pub fn apply_signature(
+        &self,
+        transaction: &mut elements::Transaction,
+        index: usize,
+        signature: &secp256k1::Signature,
+        public_key: &elements::bitcoin::PublicKey,
+    ) -> Result<(), Error> {
+        let _span = info_span!("apply_signature", wallet_id = %self.wallet_id()).entered();
+        self.wallet
+            .apply_signature(transaction, index, signature, public_key)
+    }
+
     pub fn broadcast_tx(&self, transaction: &elements::Transaction) -> Result<(), Error> {
+        self.broadcast_tx_cancellable(transaction, &CancelToken::new())
+    }
+
+    /// Broadcast that could be aborted with `cancel` until the transaction is sent to the server.
+    pub fn broadcast_tx_cancellable(
+        &self,
+        transaction: &elements::Transaction,
+        cancel: &CancelToken,
+    ) -> Result<(), Error> {
+        let _span =
+            info_span!("broadcast_tx", wallet_id = %self.wallet_id(), txid = %transaction.txid())
+                .entered();
         info!("broadcast_transaction {:#?}", transaction.txid());
-        let client = self.config.electrum_url().build_client()?;
-        client.transaction_broadcast_raw(&elements::encode::serialize(transaction))?;
-        Ok(())
+        cancel.check()?;
+        let result = self.config.build_client().and_then(|client| {
+            cancel.check()?;
+            client.broadcast(transaction)
+        });
+        if let (Some(metrics), Err(e)) = (&self.metrics, &result) {
+            metrics.broadcast_failure(e);
+        }
+        if result.is_ok() {
+            // transactions signed externally replace the unsigned ones of their request
+            self.wallet.store.write()?.requests_update(transaction)?;
+        }
+        result
+    }
+
+    /// Add `transaction` to the persisted broadcast queue, sent by `process_broadcast_queue`
+    /// and by the worker started with `spawn_broadcast_worker` until it succeeds.
+    pub fn queue_tx(&self, transaction: &elements::Transaction) -> Result<(), Error> {
+        let _span =
+            info_span!("queue_tx", wallet_id = %self.wallet_id(), txid = %transaction.txid())
+                .entered();
+        let queued = QueuedTx::new(transaction.clone(), now()?);
+        self.wallet.store.write()?.broadcast_queue_insert(queued)
+    }
+
+    /// Transactions waiting in the broadcast queue
+    pub fn queued_txs(&self) -> Result<Vec<QueuedTx>, Error> {
+        Ok(self
+            .wallet
+            .store
+            .read()?
+            .broadcast_queue()
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    /// Broadcast the queued transactions due by now, retrying the failed ones with an
+    /// exponential backoff. Transactions already seen by the wallet leave the queue without
+    /// being broadcast.
+    pub fn process_broadcast_queue(&self) -> Result<Vec<BroadcastEvent>, Error> {
+        let _span = info_span!("process_broadcast_queue", wallet_id = %self.wallet_id()).entered();
+        let now = now()?;
+        let due: Vec<QueuedTx> = {
+            let store_read = self.wallet.store.read()?;
+            store_read
+                .broadcast_queue()
+                .values()
+                .filter(|q| q.next_attempt <= now)
+                .cloned()
+                .collect()
+        };
+
+        let mut events = vec![];
+        for queued in due {
+            let txid = queued.transaction.txid();
+            let known = self.wallet.store.read()?.cache.heights.contains_key(&txid);
+            let result = if known {
+                Ok(())
+            } else {
+                self.broadcast_tx(&queued.transaction)
+            };
+            let mut store_write = self.wallet.store.write()?;
+            match result {
+                Ok(()) => {
+                    store_write.broadcast_queue_remove(&txid)?;
+                    events.push(BroadcastEvent::Broadcasted(txid));
+                }
+                Err(e) => {
+                    let error = e.to_string();
+                    // the lock was released while broadcasting, meanwhile the transaction may have
+                    // left the queue, for instance broadcast by the worker, it must not come back
+                    let mut queued = match store_write.broadcast_queue().get(&txid) {
+                        Some(queued) => queued.clone(),
+                        None => continue,
+                    };
+                    if queued.failed(error.clone(), now) {
+                        events.push(BroadcastEvent::Retrying {
+                            txid,
+                            attempts: queued.attempts,
+                            next_attempt: queued.next_attempt,
+                            error,
+                        });
+                        store_write.broadcast_queue_insert(queued)?;
+                    } else {
+                        warn!("dropping {} from the broadcast queue: {}", txid, error);
+                        store_write.broadcast_queue_remove(&txid)?;
+                        events.push(BroadcastEvent::Failed { txid, error });
+                    }
+                }
+            }
+        }
+        Ok(events)
     }
 
     /// LiquiDEX assets that might be received from proposal made by the wallet.
@@ -615,6 +1749,58 @@ impl ElectrumWallet {
         self.wallet.liquidex_assets()
     }
 
+    /// Assets received by the wallet and LiquiDEX assets, the candidate assets of the LiquiDEX
+    /// outputs unblinded by sync.
+    pub fn known_assets(&self) -> Result<HashSet<elements::issuance::AssetId>, Error> {
+        self.wallet.known_assets()
+    }
+
+    /// Metadata of `asset`, such as name, ticker and precision, with its icon if fetched.
+    ///
+    /// It does not reach the registry: the metadata come from the last
+    /// `refresh_asset_metadata` or, for the popular assets such as L-BTC and USDt, from a
+    /// snapshot embedded in the library. None if the asset is unknown.
+    pub fn asset_metadata(
+        &self,
+        asset: &elements::issuance::AssetId,
+    ) -> Result<Option<AssetMetadata>, Error> {
+        self.wallet.asset_metadata(asset)
+    }
+
+    /// Refresh from the asset registry at `registry_url`, such as
+    /// `https://assets.blockstream.info`, the cached metadata and icons of `assets`, and the
+    /// icons of the `known_assets`. The icons of the other registered assets are not stored.
+    /// ETags avoid downloading again unchanged data. The reissuance tokens of `assets` are
+    /// recorded from their issuance txs, see `WalletCtx::fetch_reissuance_tokens`. Returns the
+    /// number of metadata updated.
+    #[cfg(feature = "registry")]
+    pub fn refresh_asset_metadata(
+        &self,
+        registry_url: &str,
+        assets: &[elements::issuance::AssetId],
+    ) -> Result<usize, Error> {
+        let _span = info_span!("refresh_asset_metadata", wallet_id = %self.wallet_id()).entered();
+        let registry = RegistryClient::new(
+            registry_url,
+            self.config.timeout,
+            self.config.client_id.as_deref(),
+        );
+        let updated = self.wallet.refresh_asset_metadata(&registry, assets)?;
+        let client = self.config.build_client()?;
+        self.wallet.fetch_reissuance_tokens(&*client, assets)?;
+        Ok(updated)
+    }
+
+    /// Record `token` as the reissuance token of `asset`, for tokens received by the wallet whose
+    /// issuance is unknown to it. Returns false if it was already known.
+    pub fn reissuance_token_insert(
+        &self,
+        token: elements::issuance::AssetId,
+        asset: elements::issuance::AssetId,
+    ) -> Result<bool, Error> {
+        self.wallet.reissuance_token_insert(token, asset)
+    }
+
     /// Insert an asset in LiquiDEX assets, returns false if asset was already there.
     pub fn liquidex_assets_insert(
         &self,
@@ -631,23 +1817,201 @@ impl ElectrumWallet {
         self.wallet.liquidex_assets_remove(asset)
     }
 
+    /// Prices of the LiquiDEX swaps between `asset_a` and `asset_b` made or taken by the wallet,
+    /// in units of `asset_b` per unit of `asset_a`, aggregated in candles of `interval` seconds.
+    pub fn liquidex_price_history(
+        &self,
+        asset_a: &elements::issuance::AssetId,
+        asset_b: &elements::issuance::AssetId,
+        interval: u32,
+    ) -> Result<Vec<PriceCandle>, Error> {
+        self.sync()?;
+        self.wallet
+            .liquidex_price_history(asset_a, asset_b, interval)
+    }
+
+    /// Export LiquiDEX assets as JSON signed with the wallet master key, to import them in
+    /// another device.
+    pub fn liquidex_assets_export(&self, mnemonic: &str) -> Result<String, Error> {
+        self.wallet.liquidex_assets_export(mnemonic)
+    }
+
+    /// Import LiquiDEX assets exported as signed JSON by this wallet, or by `signer` if some.
+    /// Returns the number of assets added.
+    pub fn liquidex_assets_import(
+        &self,
+        list: &str,
+        signer: Option<&elements::bitcoin::PublicKey>,
+    ) -> Result<usize, Error> {
+        self.wallet.liquidex_assets_import(list, signer)
+    }
+
     /// Create and sign a LiquiDEX proposal.
     /// The utxo will be swapped with the asset at the rate (price asset to send/price asset to
     /// receive).
+    /// If the utxo is locked by a multisig, such as a `MultisigScript`, the proposal carries a
+    /// PSET with the wallet signature and it must be signed with `liquidex_cosign` before being
+    /// published.
     pub fn liquidex_make(
         &self,
         opt: &LiquidexMakeOpt,
         mnemonic: &str,
     ) -> Result<LiquidexProposal, Error> {
+        let _span = info_span!("liquidex_make", wallet_id = %self.wallet_id()).entered();
         self.wallet.liquidex_make(opt, mnemonic)
     }
 
+    /// Make again a proposal of the wallet at `new_rate`, see `WalletCtx::liquidex_reprice`.
+    pub fn liquidex_reprice(
+        &self,
+        proposal: &LiquidexProposal,
+        new_rate: f64,
+        mnemonic: &str,
+    ) -> Result<LiquidexProposal, Error> {
+        let _span = info_span!("liquidex_reprice", wallet_id = %self.wallet_id()).entered();
+        self.wallet.liquidex_reprice(proposal, new_rate, mnemonic)
+    }
+
+    /// Make a proposal that can be taken for part of the utxo, see
+    /// `WalletCtx::liquidex_make_partial`.
+    pub fn liquidex_make_partial(
+        &self,
+        opt: &LiquidexMakeOpt,
+        max_amount: Amount,
+    ) -> Result<LiquidexProposal, Error> {
+        let _span = info_span!("liquidex_make_partial", wallet_id = %self.wallet_id()).entered();
+        self.wallet.liquidex_make_partial(opt, max_amount)
+    }
+
+    /// Sign the transaction of the taker of a partial proposal, see `WalletCtx::liquidex_fill`.
+    pub fn liquidex_fill(
+        &self,
+        tx: &elements::Transaction,
+        mnemonic: &str,
+    ) -> Result<elements::Transaction, Error> {
+        let _span = info_span!("liquidex_fill", wallet_id = %self.wallet_id()).entered();
+        self.wallet.liquidex_fill(tx, mnemonic)
+    }
+
+    /// Add the signature of the cosigner `signer` to a proposal made spending a multisig utxo,
+    /// see `WalletCtx::liquidex_cosign`.
+    pub fn liquidex_cosign(
+        &self,
+        proposal: &LiquidexProposal,
+        signer: &dyn Signer,
+    ) -> Result<LiquidexProposal, Error> {
+        let _span = info_span!("liquidex_cosign", wallet_id = %self.wallet_id()).entered();
+        self.wallet.liquidex_cosign(proposal, signer)
+    }
+
     /// Take a LiquiDEX proposal.
     pub fn liquidex_take(
         &self,
         proposal: &LiquidexProposal,
         mnemonic: &str,
     ) -> Result<elements::Transaction, Error> {
-        self.wallet.liquidex_take(proposal, mnemonic)
+        let _span = info_span!("liquidex_take", wallet_id = %self.wallet_id()).entered();
+        let maker_prevout = self.liquidex_maker_prevout(proposal)?;
+        self.wallet
+            .liquidex_take(proposal, &maker_prevout, mnemonic)
+    }
+
+    /// Output spent by the maker input of `proposal`, from the wallet cache or the server, its
+    /// commitments are checked against the secrets in the proposal
+    fn liquidex_maker_prevout(
+        &self,
+        proposal: &LiquidexProposal,
+    ) -> Result<elements::TxOut, Error> {
+        let outpoint = proposal
+            .transaction()?
+            .input
+            .get(0)
+            .ok_or_else(|| Error::Generic("LiquiDEX error unexpected inputs".into()))?
+            .previous_output;
+        let cached = self
+            .wallet
+            .store
+            .read()?
+            .cache
+            .all_txs
+            .get(&outpoint.txid)?;
+        let prev_tx = match cached {
+            Some(prev_tx) => (*prev_tx).clone(),
+            None => self
+                .config
+                .build_client()?
+                .transactions(&[outpoint.txid])?
+                .pop()
+                .ok_or_else(|| Error::Generic(format!("cannot find tx {}", outpoint.txid)))?,
+        };
+        if prev_tx.txid() != outpoint.txid {
+            return Err(Error::Generic(format!("cannot find tx {}", outpoint.txid)));
+        }
+        prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or(Error::MissingPreviousOutput(outpoint))
+    }
+
+    /// Take `amount` from a partial proposal, see `WalletCtx::liquidex_take_partial`.
+    pub fn liquidex_take_partial(
+        &self,
+        proposal: &LiquidexProposal,
+        amount: Amount,
+        mnemonic: &str,
+        fill: &dyn Fn(&elements::Transaction) -> Result<elements::Transaction, Error>,
+    ) -> Result<elements::Transaction, Error> {
+        let _span = info_span!("liquidex_take_partial", wallet_id = %self.wallet_id()).entered();
+        let maker_prevout = self.liquidex_maker_prevout(proposal)?;
+        self.wallet
+            .liquidex_take_partial(proposal, &maker_prevout, amount, mnemonic, fill)
+    }
+
+    /// Receiver side of a payjoin: `body` is the JSON `PayjoinProposal` posted by the sender,
+    /// returns the hex of the blinded transaction to respond with, signed for the added input.
+    pub fn payjoin_respond(&self, body: &str, mnemonic: &str) -> Result<String, Error> {
+        let _span = info_span!("payjoin_respond", wallet_id = %self.wallet_id()).entered();
+        self.sync()?;
+        let proposal: PayjoinProposal = serde_json::from_str(body)?;
+        let tx = self.wallet.payjoin_receive(&proposal, mnemonic)?;
+        Ok(tx_to_hex(&tx))
+    }
+
+    /// Pay the payjoin `uri`, posting the unblinded transaction to the receiver endpoint.
+    ///
+    /// The response is checked to spend the same wallet inputs, keep the wallet change and not
+    /// lower the fee, then the wallet inputs are signed. The returned transaction is not
+    /// broadcast.
+    #[cfg(feature = "payjoin")]
+    pub fn create_payjoin_tx(
+        &self,
+        uri: &str,
+        fee_rate: Option<FeeRate>,
+        mnemonic: &str,
+    ) -> Result<elements::Transaction, Error> {
+        let _span = info_span!("create_payjoin_tx", wallet_id = %self.wallet_id()).entered();
+        let uri: PayjoinUri = uri.parse()?;
+        let asset = uri.asset.unwrap_or_else(|| self.config.policy_asset_id());
+        let mut opt = CreateTransactionOpt::default();
+        opt.fee_rate = fee_rate;
+        opt.addressees.push(Destination::new(
+            &uri.address.to_string(),
+            Amount::from_sat(uri.satoshi),
+            &asset.to_hex(),
+        )?);
+        let original = self.create_tx(&mut opt)?.transaction;
+        let proposal = self.wallet.payjoin_proposal(&original)?;
+
+        let response =
+            crate::backend::http_agent(self.config.timeout, self.config.client_id.as_deref())
+                .post(&uri.endpoint)
+                .set("Content-Type", "application/json")
+                .send_string(&serde_json::to_string(&proposal)?)?
+                .into_string()?;
+        let bytes = hex::decode(response.trim())?;
+        let mut tx: elements::Transaction = elements::encode::deserialize(&bytes)?;
+        self.wallet.payjoin_sign(&original, &mut tx, mnemonic)?;
+        Ok(tx)
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::{HashMap, HashSet};
-use std::io::Write;
+#[cfg(feature = "compact")]
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
@@ -7,17 +8,127 @@ use serde::{Deserialize, Serialize};
 use aes_gcm_siv::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
 use aes_gcm_siv::Aes256GcmSiv;
 
+#[cfg(feature = "compact")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "compact")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "compact")]
+use flate2::Compression;
+
 use rand::Rng;
 
-use elements::bitcoin::hashes::{sha256, sha256d, Hash};
+use elements::bitcoin::hashes::{sha256, sha256d, Hash, HashEngine};
+use elements::bitcoin::secp256k1;
 use elements::confidential::{Asset, Nonce, Value};
 use elements::encode::Encodable;
+use elements::pset::PartiallySignedTransaction;
 use elements::secp256k1_zkp::{self, All, Secp256k1};
-use elements::slip77::MasterBlindingKey;
 
+use crate::amount::Amount;
+use crate::blinder::Blinder;
 use crate::error::Error;
-use crate::transaction::{estimated_fee, DUST_VALUE};
-use crate::utils::derive_blinder;
+use crate::scripts::AddressType;
+use crate::transaction::{add_amount, estimated_fee, verify_unblinded, DUST_VALUE, MAX_MONEY};
+
+/// LiquiDEX assets signed by a wallet, to share a list of assets between devices
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LiquidexAssetList {
+    pub assets: Vec<elements::issuance::AssetId>,
+    /// hex public key of the signer
+    pub pubkey: String,
+    /// hex DER signature of the assets
+    pub signature: String,
+}
+
+impl LiquidexAssetList {
+    fn message(assets: &[elements::issuance::AssetId]) -> secp256k1::Message {
+        let mut engine = sha256::Hash::engine();
+        engine.input(b"LiquiDEX assets");
+        for asset in assets {
+            engine.input(&asset.into_inner().into_inner());
+        }
+        secp256k1::Message::from_slice(&sha256::Hash::from_engine(engine)[..])
+            .expect("32 bytes hash")
+    }
+
+    /// Sign `assets` with `key`, assets are sorted so that the same set gives the same list
+    pub fn sign<C: secp256k1::Signing>(
+        assets: &HashSet<elements::issuance::AssetId>,
+        key: &secp256k1::SecretKey,
+        secp: &secp256k1::Secp256k1<C>,
+    ) -> Self {
+        let mut assets: Vec<_> = assets.iter().cloned().collect();
+        assets.sort_by_key(|a| a.into_inner().into_inner());
+        let signature = secp.sign(&Self::message(&assets), key);
+        LiquidexAssetList {
+            assets,
+            pubkey: secp256k1::PublicKey::from_secret_key(secp, key).to_string(),
+            signature: hex::encode(signature.serialize_der()),
+        }
+    }
+
+    /// Verify the signature and return the signer public key
+    pub fn verify<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+    ) -> Result<secp256k1::PublicKey, Error> {
+        let pubkey = secp256k1::PublicKey::from_str(&self.pubkey)?;
+        let signature = secp256k1::Signature::from_der(&hex::decode(&self.signature)?)?;
+        secp.verify(&Self::message(&self.assets), &signature, &pubkey)
+            .map_err(|_| Error::InvalidSignature)?;
+        Ok(pubkey)
+    }
+}
+
+/// LiquiDEX swap made or taken by the wallet, from the wallet point of view
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LiquidexSwap {
+    pub sent_asset: elements::issuance::AssetId,
+    pub sent_value: Amount,
+    pub received_asset: elements::issuance::AssetId,
+    pub received_value: Amount,
+}
+
+/// Open, high, low and close prices of the swaps in the interval starting at `start` (unix time)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PriceCandle {
+    pub start: u32,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// swapped amount of the base asset
+    pub volume: u64,
+}
+
+/// Aggregate `points` (time, price, volume), sorted by time, in candles of `interval` seconds
+pub fn ohlc(points: &[(u32, f64, u64)], interval: u32) -> Result<Vec<PriceCandle>, Error> {
+    let interval = interval.max(1);
+    let mut candles: Vec<PriceCandle> = vec![];
+    for (time, price, volume) in points {
+        let start = time - time % interval;
+        match candles.last_mut() {
+            Some(candle) if candle.start == start => {
+                candle.high = candle.high.max(*price);
+                candle.low = candle.low.min(*price);
+                candle.close = *price;
+                candle.volume = candle
+                    .volume
+                    .checked_add(*volume)
+                    .ok_or(Error::AmountOverflow)?;
+            }
+            _ => candles.push(PriceCandle {
+                start,
+                open: *price,
+                high: *price,
+                low: *price,
+                close: *price,
+                volume: *volume,
+            }),
+        }
+    }
+    Ok(candles)
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct LiquidexMakeOpt {
@@ -39,6 +150,91 @@ impl LiquidexMakeOpt {
     }
 }
 
+/// Version of the compact encoding of a `LiquidexProposal`, in the low bits of its first byte
+#[cfg(feature = "compact")]
+const COMPACT_VERSION: u8 = 0;
+
+/// Flag of the first byte of the compact encoding, set if the rest is gzipped
+#[cfg(feature = "compact")]
+const COMPACT_GZIP: u8 = 0x80;
+
+/// Maximum size of a gzipped proposal once decompressed
+#[cfg(feature = "compact")]
+const COMPACT_MAX_SIZE: u64 = 1_000_000;
+
+/// Reader of the fields of a compact proposal
+#[cfg(feature = "compact")]
+struct CompactReader<'a> {
+    bytes: &'a [u8],
+}
+
+#[cfg(feature = "compact")]
+impl<'a> CompactReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(Error::Generic("LiquiDEX compact proposal truncated".into()));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn secrets(&mut self) -> Result<Vec<LiquidexTxOutSecrets>, Error> {
+        let count = self.u8()?;
+        let mut secrets = vec![];
+        for _ in 0..count {
+            let asset = elements::issuance::AssetId::from_slice(self.take(32)?)?;
+            let asset_bf = elements::confidential::AssetBlindingFactor::from_slice(self.take(32)?)?;
+            let value = self.u64()?;
+            let value_bf = elements::confidential::ValueBlindingFactor::from_slice(self.take(32)?)?;
+            secrets.push(elements::TxOutSecrets::new(asset, asset_bf, value, value_bf).into());
+        }
+        Ok(secrets)
+    }
+}
+
+#[cfg(feature = "compact")]
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend(&(bytes.len() as u32).to_le_bytes());
+    buf.extend(bytes);
+}
+
+#[cfg(feature = "compact")]
+fn write_secrets(buf: &mut Vec<u8>, secrets: &[LiquidexTxOutSecrets]) -> Result<(), Error> {
+    if secrets.len() > u8::MAX as usize {
+        return Err(Error::Generic("LiquiDEX error too many secrets".into()));
+    }
+    buf.push(secrets.len() as u8);
+    for secrets in secrets.iter().map(|s| s.to_txoutsecrets()) {
+        buf.extend(&secrets.asset.into_inner().into_inner());
+        buf.extend(secrets.asset_bf.into_inner().as_ref());
+        buf.extend(&secrets.value.to_le_bytes());
+        buf.extend(secrets.value_bf.into_inner().as_ref());
+    }
+    Ok(())
+}
+
 // Clone of TxOutSecrets, but with the name changed to match the previous struct.
 // This is a temporary solution since soon we should be able to migrate to PSET.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -79,6 +275,41 @@ pub struct LiquidexProposal {
     tx: String,
     inputs: Vec<LiquidexTxOutSecrets>,
     outputs: Vec<LiquidexTxOutSecrets>,
+    /// Hex PSET with the partial signatures of a maker input locked by a multisig, present
+    /// until all the cosigners signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pset: Option<String>,
+    /// Terms of a partial proposal, its transaction has only the unsigned maker input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    partial: Option<LiquidexPartial>,
+}
+
+/// Terms of a LiquiDEX proposal filled for any amount up to `max_amount` of the maker input
+/// asset. The taker builds the transaction and the maker signs it with `liquidex_fill`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LiquidexPartial {
+    /// Confidential address of the maker receiving `asset_id` and the change
+    pub address: String,
+    /// Asset received by the maker
+    pub asset_id: elements::issuance::AssetId,
+    /// Maximum amount of the maker input asset sent to the taker
+    pub max_amount: Amount,
+    /// Amount of `asset_id` received for `max_amount`, smaller fills pay proportionally
+    pub max_receive: Amount,
+}
+
+impl LiquidexPartial {
+    /// Amount the maker must receive for sending `amount`, None if `amount` is zero or above
+    /// `max_amount`. Rounded up, so that the maker never receives less than the rate.
+    pub fn receive_value(&self, amount: Amount) -> Option<Amount> {
+        if amount == Amount::ZERO || amount > self.max_amount {
+            return None;
+        }
+        let max_amount = self.max_amount.as_sat() as u128;
+        let numerator = amount.as_sat() as u128 * self.max_receive.as_sat() as u128;
+        let value = (numerator + max_amount - 1) / max_amount;
+        Some(Amount::from_sat(value as u64))
+    }
 }
 
 impl LiquidexProposal {
@@ -92,15 +323,163 @@ impl LiquidexProposal {
             tx: hex::encode(elements::encode::serialize(tx)),
             inputs: vec![input.into()],
             outputs: vec![output.into()],
+            pset: None,
+            partial: None,
         }
     }
 
+    /// Partial proposal spending the maker input of `tx`, see `LiquidexPartial`
+    pub fn new_partial(
+        tx: &elements::Transaction,
+        input: elements::TxOutSecrets,
+        partial: LiquidexPartial,
+    ) -> Self {
+        Self {
+            version: 0,
+            tx: hex::encode(elements::encode::serialize(tx)),
+            inputs: vec![input.into()],
+            outputs: vec![],
+            pset: None,
+            partial: Some(partial),
+        }
+    }
+
+    /// Terms of the proposal if partial, None if it can only be taken entirely
+    pub fn partial(&self) -> Option<&LiquidexPartial> {
+        self.partial.as_ref()
+    }
+
+    /// PSET collecting the cosigners signatures, None if the proposal is signed
+    pub fn pset(&self) -> Result<Option<PartiallySignedTransaction>, Error> {
+        match &self.pset {
+            Some(pset) => Ok(Some(elements::encode::deserialize(&hex::decode(pset)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn set_pset(&mut self, pset: Option<&PartiallySignedTransaction>) {
+        self.pset = pset.map(|p| hex::encode(elements::encode::serialize(p)));
+    }
+
+    pub(crate) fn set_transaction(&mut self, tx: &elements::Transaction) {
+        self.tx = hex::encode(elements::encode::serialize(tx));
+    }
+
     pub fn transaction(&self) -> Result<elements::Transaction, Error> {
         Ok(elements::encode::deserialize(&hex::decode(
             self.tx.clone(),
         )?)?)
     }
 
+    /// Binary encoding of the proposal in base64url, gzipped if `gzip`, much shorter than the
+    /// JSON one so that it fits in QR codes and chat messages.
+    ///
+    /// The first byte is the encoding version, with `COMPACT_GZIP` set if the rest is gzipped,
+    /// followed by the proposal version, the consensus encoded transaction, the secrets of the
+    /// inputs and of the outputs and the optional PSET and partial terms.
+    #[cfg(feature = "compact")]
+    pub fn to_compact_string(&self, gzip: bool) -> Result<String, Error> {
+        let mut payload = vec![];
+        payload.extend(&self.version.to_le_bytes());
+        write_bytes(&mut payload, &hex::decode(&self.tx)?);
+        write_secrets(&mut payload, &self.inputs)?;
+        write_secrets(&mut payload, &self.outputs)?;
+        match &self.pset {
+            Some(pset) => {
+                payload.push(1);
+                write_bytes(&mut payload, &hex::decode(pset)?);
+            }
+            None => payload.push(0),
+        }
+        match &self.partial {
+            Some(partial) => {
+                payload.push(1);
+                write_bytes(&mut payload, partial.address.as_bytes());
+                payload.extend(&partial.asset_id.into_inner().into_inner());
+                payload.extend(&partial.max_amount.as_sat().to_le_bytes());
+                payload.extend(&partial.max_receive.as_sat().to_le_bytes());
+            }
+            None => payload.push(0),
+        }
+
+        let mut bytes = vec![];
+        if gzip {
+            bytes.push(COMPACT_VERSION | COMPACT_GZIP);
+            let mut encoder = GzEncoder::new(bytes, Compression::best());
+            encoder.write_all(&payload)?;
+            bytes = encoder.finish()?;
+        } else {
+            bytes.push(COMPACT_VERSION);
+            bytes.extend(payload);
+        }
+        Ok(base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Decode a proposal encoded by `to_compact_string`
+    #[cfg(feature = "compact")]
+    pub fn from_compact_string(s: &str) -> Result<Self, Error> {
+        let bytes = base64::decode_config(s.trim(), base64::URL_SAFE_NO_PAD)
+            .map_err(|e| Error::Generic(format!("LiquiDEX compact proposal: {}", e)))?;
+        let (first, rest) = bytes
+            .split_first()
+            .ok_or_else(|| Error::Generic("LiquiDEX compact proposal empty".into()))?;
+        if first & !COMPACT_GZIP != COMPACT_VERSION {
+            return Err(Error::Generic(format!(
+                "LiquiDEX compact proposal version {} not supported",
+                first & !COMPACT_GZIP
+            )));
+        }
+        let mut payload = vec![];
+        if first & COMPACT_GZIP != 0 {
+            GzDecoder::new(rest)
+                .take(COMPACT_MAX_SIZE)
+                .read_to_end(&mut payload)?;
+        } else {
+            payload.extend(rest);
+        }
+
+        let mut reader = CompactReader { bytes: &payload };
+        let version = reader.u32()?;
+        let tx: elements::Transaction = elements::encode::deserialize(reader.bytes()?)?;
+        let inputs = reader.secrets()?;
+        let outputs = reader.secrets()?;
+        let pset = match reader.u8()? {
+            0 => None,
+            _ => {
+                let pset: PartiallySignedTransaction =
+                    elements::encode::deserialize(reader.bytes()?)?;
+                Some(hex::encode(elements::encode::serialize(&pset)))
+            }
+        };
+        let partial = match reader.u8()? {
+            0 => None,
+            _ => {
+                let address = String::from_utf8(reader.bytes()?.to_vec())
+                    .map_err(|_| Error::InvalidAddress)?;
+                let asset_id = elements::issuance::AssetId::from_slice(reader.take(32)?)?;
+                Some(LiquidexPartial {
+                    address,
+                    asset_id,
+                    max_amount: Amount::from_sat(reader.u64()?),
+                    max_receive: Amount::from_sat(reader.u64()?),
+                })
+            }
+        };
+        if !reader.bytes.is_empty() {
+            return Err(Error::Generic(
+                "LiquiDEX compact proposal has trailing bytes".into(),
+            ));
+        }
+        Ok(LiquidexProposal {
+            version,
+            tx: hex::encode(elements::encode::serialize(&tx)),
+            inputs,
+            outputs,
+            pset,
+            partial,
+        })
+    }
+
     pub fn get_input(&self) -> Result<elements::TxOutSecrets, Error> {
         if self.inputs.len() != 1 {
             return Err(Error::Generic(
@@ -111,6 +490,22 @@ impl LiquidexProposal {
         Ok(self.inputs[0].to_txoutsecrets().clone())
     }
 
+    /// Secrets of the maker input, checked against the commitments of `prevout`, the output it
+    /// spends
+    pub fn verify_input_commitment(
+        &self,
+        secp: &Secp256k1<All>,
+        prevout: &elements::TxOut,
+    ) -> Result<elements::TxOutSecrets, Error> {
+        let input = self.get_input()?;
+        if !verify_unblinded(secp, prevout, &input) {
+            return Err(Error::Generic(
+                "LiquiDEX error unexpected input commitments".to_string(),
+            ));
+        }
+        Ok(input)
+    }
+
     pub fn verify_output_commitment(
         &self,
         secp: &Secp256k1<All>,
@@ -159,11 +554,20 @@ impl LiquidexProposal {
     }
 }
 
+/// Amount received selling `value` at `rate`, it must be between 1 and the maximum money
+pub fn liquidex_receive_value(rate: f64, value: Amount) -> Result<Amount, Error> {
+    let receive_value = rate * value.as_sat() as f64;
+    if !receive_value.is_finite() || receive_value < 1.0 || receive_value > MAX_MONEY as f64 {
+        return Err(Error::RateOutOfRange(rate));
+    }
+    Ok(Amount::from_sat(receive_value as u64))
+}
+
 fn _liquidex_derive_blinder(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     previous_outpoint: &elements::OutPoint,
     is_asset_blinder: bool,
-) -> Result<secp256k1_zkp::Tweak, secp256k1_zkp::Error> {
+) -> Result<secp256k1_zkp::Tweak, Error> {
     // LiquiDEX proposals do not know in advance all inputs of
     // final transaction, compute the hash only from the previous
     // outpoint we know is being spent.
@@ -176,45 +580,33 @@ fn _liquidex_derive_blinder(
     // LiquiDEX proposals output vout is choosen by the taker,
     // for the blinder computation use a vout that may not
     // occur in a transaction.
-    derive_blinder(
-        master_blinding_key,
-        &hash_prevout,
-        u32::MAX,
-        is_asset_blinder,
-    )
+    blinder.blinding_factor(&hash_prevout, u32::MAX, is_asset_blinder)
 }
 
 fn liquidex_derive_asset_blinder(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     previous_outpoint: &elements::OutPoint,
 ) -> Result<elements::confidential::AssetBlindingFactor, Error> {
-    let blinder = _liquidex_derive_blinder(master_blinding_key, previous_outpoint, true)?;
+    let blinder = _liquidex_derive_blinder(blinder, previous_outpoint, true)?;
     elements::confidential::AssetBlindingFactor::from_slice(&blinder[..]).map_err(Into::into)
 }
 
 fn liquidex_derive_value_blinder(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     previous_outpoint: &elements::OutPoint,
 ) -> Result<elements::confidential::ValueBlindingFactor, Error> {
-    let blinder = _liquidex_derive_blinder(master_blinding_key, previous_outpoint, false)?;
+    let blinder = _liquidex_derive_blinder(blinder, previous_outpoint, false)?;
     elements::confidential::ValueBlindingFactor::from_slice(&blinder[..]).map_err(Into::into)
 }
 
-fn _liquidex_aes_key(
-    master_blinding_key: &MasterBlindingKey,
-    script: &elements::Script,
-) -> Result<[u8; 32], Error> {
+fn _liquidex_aes_key(blinder: &dyn Blinder, script: &elements::Script) -> Result<[u8; 32], Error> {
     // TODO: consider using tagged hashes
     const TAG: &[u8; 16] = b"liquidex_aes_key";
-    let mut engine = sha256::Hash::engine();
-    engine.write(TAG)?;
-    engine.write(&master_blinding_key.0[..])?;
-    engine.write(&script.as_bytes())?;
-    Ok(sha256::Hash::from_engine(engine).into_inner())
+    blinder.tagged_secret(TAG, script.as_bytes())
 }
 
 fn _liquidex_aes_nonce(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     previous_outpoint: &elements::OutPoint,
     asset: &elements::confidential::Asset,
     value: &elements::confidential::Value,
@@ -230,15 +622,13 @@ fn _liquidex_aes_nonce(
     }
     // TODO: consider using tagged hashes
     const TAG: &[u8; 18] = b"liquidex_aes_nonce";
-    let mut engine = sha256::Hash::engine();
-    engine.write(TAG)?;
-    engine.write(&master_blinding_key.0[..])?;
-    previous_outpoint.consensus_encode(&mut engine)?;
-    engine.write(&asset.commitment().unwrap().serialize())?;
-    engine.write(&value.commitment().unwrap().serialize())?;
-    engine.write(&script.as_bytes())?;
+    let mut data = vec![];
+    previous_outpoint.consensus_encode(&mut data)?;
+    data.extend(&asset.commitment().unwrap().serialize());
+    data.extend(&value.commitment().unwrap().serialize());
+    data.extend(script.as_bytes());
     let mut out = [0u8; 12];
-    out.copy_from_slice(&sha256::Hash::from_engine(engine).into_inner()[..12]);
+    out.copy_from_slice(&blinder.tagged_secret(TAG, &data)?[..12]);
     Ok(out)
 }
 
@@ -246,7 +636,7 @@ fn _liquidex_aes_nonce(
 /// The maker has no control on the rangeproof, thus it can't rely on it to recover the unblinding
 /// data. Use deterministic blinders and use the nonce field to encrypt the output value.
 pub fn liquidex_blind(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     tx: &mut elements::Transaction,
     secp: &Secp256k1<All>,
 ) -> Result<elements::TxOutSecrets, Error> {
@@ -265,10 +655,8 @@ pub fn liquidex_blind(
         }
     };
 
-    let asset_blinder =
-        liquidex_derive_asset_blinder(master_blinding_key, &tx.input[0].previous_output)?;
-    let value_blinder =
-        liquidex_derive_value_blinder(master_blinding_key, &tx.input[0].previous_output)?;
+    let asset_blinder = liquidex_derive_asset_blinder(blinder, &tx.input[0].previous_output)?;
+    let value_blinder = liquidex_derive_value_blinder(blinder, &tx.input[0].previous_output)?;
 
     let asset_tag = secp256k1_zkp::Tag::from(asset.into_inner().into_inner());
     let asset_generator =
@@ -283,12 +671,12 @@ pub fn liquidex_blind(
     tx.output[0].asset = Asset::from_commitment(&asset_generator.serialize())?;
     tx.output[0].value = Value::from_commitment(&value_commitment.serialize())?;
 
-    let key = _liquidex_aes_key(master_blinding_key, &tx.output[0].script_pubkey)?;
+    let key = _liquidex_aes_key(blinder, &tx.output[0].script_pubkey)?;
     let key = GenericArray::from_slice(&key);
     let cipher = Aes256GcmSiv::new(&key);
 
     let aes_nonce = _liquidex_aes_nonce(
-        master_blinding_key,
+        blinder,
         &tx.input[0].previous_output,
         &tx.output[0].asset,
         &tx.output[0].value,
@@ -323,7 +711,7 @@ pub fn liquidex_blind(
 }
 
 pub fn liquidex_unblind(
-    master_blinding_key: &MasterBlindingKey,
+    blinder: &dyn Blinder,
     tx: &elements::Transaction,
     vout: u32,
     secp: &Secp256k1<All>,
@@ -348,19 +736,17 @@ pub fn liquidex_unblind(
     // FIXME: check input has sighash single | anyonecanpay
     // FIXME: check input has a script belonging to the wallet
     // compute blinders
-    let asset_blinder =
-        liquidex_derive_asset_blinder(master_blinding_key, &tx.input[vout].previous_output)?;
-    let value_blinder =
-        liquidex_derive_value_blinder(master_blinding_key, &tx.input[vout].previous_output)?;
+    let asset_blinder = liquidex_derive_asset_blinder(blinder, &tx.input[vout].previous_output)?;
+    let value_blinder = liquidex_derive_value_blinder(blinder, &tx.input[vout].previous_output)?;
 
     // compute key
-    let key = _liquidex_aes_key(master_blinding_key, &tx.output[vout].script_pubkey)?;
+    let key = _liquidex_aes_key(blinder, &tx.output[vout].script_pubkey)?;
     let key = GenericArray::from_slice(&key);
     let cipher = Aes256GcmSiv::new(&key);
 
     // compute aes nonce
     let aes_nonce = _liquidex_aes_nonce(
-        master_blinding_key,
+        blinder,
         &tx.input[vout].previous_output,
         &tx.output[vout].asset,
         &tx.output[vout].value,
@@ -422,59 +808,68 @@ pub fn liquidex_unblind(
     ))
 }
 
+/// `maker_output` is the blinded first output, None for partial proposals whose maker outputs
+/// are blinded by the taker
 fn outputs(
-    maker_output: &elements::TxOutSecrets,
+    maker_output: Option<&elements::TxOutSecrets>,
     tx: &elements::Transaction,
-) -> HashMap<elements::issuance::AssetId, u64> {
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
     let mut outputs: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
     for (idx, output) in tx.output.iter().enumerate() {
-        if idx == 0 {
-            *outputs.entry(maker_output.asset).or_insert(0) += maker_output.value;
-        } else {
-            match (output.asset, output.value) {
-                (Asset::Explicit(asset), Value::Explicit(value)) => {
-                    *outputs.entry(asset).or_insert(0) += value;
-                }
-                _ => panic!("asset and value should be explicit here"),
+        match (maker_output, output.asset, output.value) {
+            (Some(maker_output), _, _) if idx == 0 => {
+                add_amount(&mut outputs, maker_output.asset, maker_output.value)?;
+            }
+            (_, Asset::Explicit(asset), Value::Explicit(value)) => {
+                add_amount(&mut outputs, asset, value)?;
+            }
+            _ => {
+                return Err(Error::Generic(
+                    "asset and value should be explicit here".into(),
+                ))
             }
         }
     }
-    outputs
+    Ok(outputs)
 }
 
 fn inputs(
     maker_input: &elements::TxOutSecrets,
     tx: &elements::Transaction,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> HashMap<elements::issuance::AssetId, u64> {
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
     let mut inputs: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
     for (idx, input) in tx.input.iter().enumerate() {
         if idx == 0 {
-            *inputs.entry(maker_input.asset).or_insert(0) += maker_input.value;
+            add_amount(&mut inputs, maker_input.asset, maker_input.value)?;
         } else {
-            let unblinded = unblinded.get(&input.previous_output).unwrap();
-            *inputs.entry(unblinded.asset).or_insert(0) += unblinded.value;
+            let unblinded = unblinded
+                .get(&input.previous_output)
+                .ok_or(Error::MissingPreviousOutput(input.previous_output))?;
+            add_amount(&mut inputs, unblinded.asset, unblinded.value)?;
         }
     }
-    inputs
+    Ok(inputs)
 }
 
 pub fn liquidex_needs(
     maker_input: &elements::TxOutSecrets,
-    maker_output: &elements::TxOutSecrets,
+    maker_output: Option<&elements::TxOutSecrets>,
     tx: &elements::Transaction,
     fee_rate: f64,
     policy_asset: &elements::issuance::AssetId,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Vec<(elements::issuance::AssetId, u64)> {
-    let mut outputs = outputs(maker_output, tx);
-    let mut inputs = inputs(maker_input, tx, unblinded);
+    address_type: AddressType,
+) -> Result<Vec<(elements::issuance::AssetId, u64)>, Error> {
+    let mut outputs = outputs(maker_output, tx)?;
+    let mut inputs = inputs(maker_input, tx, unblinded)?;
     let estimated_fee = estimated_fee(
         &tx,
         fee_rate,
-        liquidex_estimated_changes(maker_input, &tx, unblinded),
+        liquidex_estimated_changes(maker_input, &tx, unblinded)?,
+        address_type,
     );
-    *outputs.entry(policy_asset.clone()).or_insert(0) += estimated_fee;
+    add_amount(&mut outputs, *policy_asset, estimated_fee)?;
 
     let mut result = vec![];
     for (asset, value) in outputs.iter() {
@@ -485,34 +880,38 @@ pub fn liquidex_needs(
         }
     }
 
-    result
+    Ok(result)
 }
 
 pub fn liquidex_estimated_changes(
     maker_input: &elements::TxOutSecrets,
     tx: &elements::Transaction,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> u8 {
-    inputs(maker_input, tx, unblinded).len() as u8
+) -> Result<u8, Error> {
+    Ok(inputs(maker_input, tx, unblinded)?.len() as u8)
 }
 
 pub fn liquidex_changes(
     maker_input: &elements::TxOutSecrets,
-    maker_output: &elements::TxOutSecrets,
+    maker_output: Option<&elements::TxOutSecrets>,
     tx: &elements::Transaction,
     estimated_fee: u64,
     policy_asset: &elements::issuance::AssetId,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> HashMap<elements::issuance::AssetId, u64> {
-    let mut outputs_asset_amounts = outputs(maker_output, tx);
-    let inputs_asset_amounts = inputs(maker_input, tx, unblinded);
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+    let mut outputs_asset_amounts = outputs(maker_output, tx)?;
+    let inputs_asset_amounts = inputs(maker_input, tx, unblinded)?;
     let mut result: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
     for (asset, value) in inputs_asset_amounts.iter() {
-        let mut sum: u64 = value - outputs_asset_amounts.remove(asset).unwrap_or(0);
+        let mut sum: u64 = value
+            .checked_sub(outputs_asset_amounts.remove(asset).unwrap_or(0))
+            .ok_or(Error::Unbalanced(*asset))?;
         if asset == policy_asset {
             // from a purely privacy perspective could make sense to always create the change output in liquid, so min change = 0
             // however elements core use the dust anyway for 2 reasons: rebasing from core and economical considerations
-            sum -= estimated_fee;
+            sum = sum
+                .checked_sub(estimated_fee)
+                .ok_or(Error::Unbalanced(*asset))?;
             if sum > DUST_VALUE {
                 // we apply dust rules for liquid bitcoin as elements do
                 result.insert(*asset, sum);
@@ -521,27 +920,110 @@ pub fn liquidex_changes(
             result.insert(*asset, sum);
         }
     }
-    assert!(outputs_asset_amounts.is_empty());
-    result
+    if let Some(asset) = outputs_asset_amounts.keys().next() {
+        return Err(Error::Unbalanced(*asset));
+    }
+    Ok(result)
 }
 
 pub fn liquidex_fee(
     maker_input: &elements::TxOutSecrets,
-    maker_output: &elements::TxOutSecrets,
+    maker_output: Option<&elements::TxOutSecrets>,
     tx: &elements::Transaction,
     policy_asset: &elements::issuance::AssetId,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> u64 {
-    assert!(!tx.output.iter().any(|o| o.is_fee()));
-    let outputs = outputs(maker_output, tx);
-    let inputs = inputs(maker_input, tx, unblinded);
-    inputs.get(policy_asset).unwrap() - outputs.get(policy_asset).unwrap()
+) -> Result<u64, Error> {
+    if tx.output.iter().any(|o| o.is_fee()) {
+        return Err(Error::Generic(
+            "LiquiDEX error fee output already added".into(),
+        ));
+    }
+    let outputs = outputs(maker_output, tx)?;
+    let inputs = inputs(maker_input, tx, unblinded)?;
+    let input = inputs.get(policy_asset).cloned().unwrap_or(0);
+    let output = outputs.get(policy_asset).cloned().unwrap_or(0);
+    input
+        .checked_sub(output)
+        .ok_or(Error::Unbalanced(*policy_asset))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::liquidex::{liquidex_blind, liquidex_unblind, LiquidexProposal};
-    use crate::transaction::add_input;
+    use crate::amount::Amount;
+    use crate::error::Error;
+    use crate::liquidex::{
+        liquidex_blind, liquidex_changes, liquidex_fee, liquidex_needs, liquidex_receive_value,
+        liquidex_unblind, ohlc, LiquidexAssetList, LiquidexPartial, LiquidexProposal,
+    };
+    use crate::scripts::AddressType;
+    use crate::transaction::tests::amount;
+    use crate::transaction::{add_input, DUST_VALUE};
+    use elements::confidential::{Asset, AssetBlindingFactor, Value, ValueBlindingFactor};
+    use elements::issuance::AssetId;
+    use elements::{OutPoint, TxOutSecrets};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn test_ohlc() {
+        let points = vec![(10, 2.0, 1), (50, 3.0, 2), (59, 1.0, 3), (60, 4.0, 4)];
+        let candles = ohlc(&points, 60).unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].start, 0);
+        assert_eq!(
+            (
+                candles[0].open,
+                candles[0].high,
+                candles[0].low,
+                candles[0].close
+            ),
+            (2.0, 3.0, 1.0, 1.0)
+        );
+        assert_eq!(candles[0].volume, 6);
+        assert_eq!(candles[1].start, 60);
+        assert_eq!(candles[1].open, 4.0);
+        assert_eq!(candles[1].volume, 4);
+        assert!(ohlc(&[], 60).unwrap().is_empty());
+        assert!(ohlc(&[(0, 1.0, u64::MAX), (1, 1.0, 1)], 60).is_err());
+    }
+
+    #[test]
+    fn test_partial_receive_value() {
+        let partial = LiquidexPartial {
+            address: String::new(),
+            asset_id: elements::issuance::AssetId::default(),
+            max_amount: Amount::from_sat(3_000),
+            max_receive: Amount::from_sat(1_000),
+        };
+        let sat = Amount::from_sat;
+        assert_eq!(partial.receive_value(sat(0)), None);
+        assert_eq!(partial.receive_value(sat(3_001)), None);
+        assert_eq!(partial.receive_value(sat(3_000)), Some(sat(1_000)));
+        assert_eq!(partial.receive_value(sat(1_500)), Some(sat(500)));
+        // rounded up in favor of the maker
+        assert_eq!(partial.receive_value(sat(1)), Some(sat(1)));
+        assert_eq!(liquidex_receive_value(0.5, sat(3)).unwrap(), sat(1));
+        assert!(liquidex_receive_value(0.1, sat(3)).is_err());
+        assert!(liquidex_receive_value(f64::INFINITY, sat(3)).is_err());
+    }
+
+    #[test]
+    fn test_liquidex_asset_list() {
+        let secp = elements::bitcoin::secp256k1::Secp256k1::new();
+        let key = elements::bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let assets: HashSet<_> = (1u8..4)
+            .map(|i| elements::issuance::AssetId::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let list = LiquidexAssetList::sign(&assets, &key, &secp);
+        let pubkey = elements::bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &key);
+        assert_eq!(list.verify(&secp).unwrap(), pubkey);
+        let json = serde_json::to_string(&list).unwrap();
+        let mut list: LiquidexAssetList = serde_json::from_str(&json).unwrap();
+        assert_eq!(list.assets.len(), 3);
+        list.assets.pop();
+        assert!(list.verify(&secp).is_err());
+    }
 
     #[test]
     fn test_liquidex_roundtrip() {
@@ -580,8 +1062,7 @@ mod tests {
         assert_eq!(unblinded.value, value);
     }
 
-    #[test]
-    fn test_liquidex_proposal() {
+    fn taken_proposal() -> LiquidexProposal {
         // Taken proposal:
         // https://blockstream.info/liquid/tx/a43dafc00a6c488085bdf849ca954e4a82f80d56a1c8931873df83d5d22981a4
         let proposal_str = r#"
@@ -601,7 +1082,12 @@ mod tests {
             }]
         }"#;
 
-        let proposal: LiquidexProposal = serde_json::from_str(proposal_str).unwrap();
+        serde_json::from_str(proposal_str).unwrap()
+    }
+
+    #[test]
+    fn test_liquidex_proposal() {
+        let proposal = taken_proposal();
         println!("{:#?}", proposal);
         assert_eq!(proposal.outputs[0].amount, 175);
 
@@ -609,9 +1095,182 @@ mod tests {
         let secp = elements::secp256k1_zkp::Secp256k1::new();
         proposal.verify_output_commitment(&secp).unwrap();
 
+        // the maker input secrets must open the commitments of the output it spends
+        let input = proposal.get_input().unwrap();
+        let commitments = |value: u64| {
+            use elements::secp256k1_zkp::{Generator, PedersenCommitment, Tag};
+            let tag = Tag::from(input.asset.into_inner().into_inner());
+            let generator = Generator::new_blinded(&secp, tag, input.asset_bf.into_inner());
+            elements::TxOut {
+                asset: Asset::Confidential(generator),
+                value: Value::Confidential(PedersenCommitment::new(
+                    &secp,
+                    value,
+                    input.value_bf.into_inner(),
+                    generator,
+                )),
+                ..Default::default()
+            }
+        };
+        assert_eq!(
+            proposal
+                .verify_input_commitment(&secp, &commitments(input.value))
+                .unwrap(),
+            input
+        );
+        assert!(proposal
+            .verify_input_commitment(&secp, &commitments(input.value + 1))
+            .is_err());
+
         // verify that the serialized proposal matches the deserialized one
         let proposal_str2 = serde_json::to_string(&proposal).unwrap();
         let proposal2: LiquidexProposal = serde_json::from_str(&proposal_str2).unwrap();
         assert_eq!(proposal, proposal2);
     }
+
+    #[cfg(feature = "compact")]
+    #[test]
+    fn test_liquidex_compact() {
+        let proposal = taken_proposal();
+        let proposal_str2 = serde_json::to_string(&proposal).unwrap();
+
+        // the compact encodings are shorter and decode to the same proposal
+        for gzip in [false, true].iter() {
+            let compact = proposal.to_compact_string(*gzip).unwrap();
+            assert!(compact.len() < proposal_str2.len());
+            assert!(compact
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(
+                LiquidexProposal::from_compact_string(&compact).unwrap(),
+                proposal
+            );
+            assert!(LiquidexProposal::from_compact_string(&compact[..compact.len() - 4]).is_err());
+        }
+
+        let mut partial = proposal.clone();
+        partial.outputs.clear();
+        partial.partial = Some(LiquidexPartial {
+            address:
+                "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d"
+                    .into(),
+            asset_id: proposal.outputs[0].asset,
+            max_amount: Amount::from_sat(3_000),
+            max_receive: Amount::from_sat(1_000),
+        });
+        let compact = partial.to_compact_string(true).unwrap();
+        assert_eq!(
+            LiquidexProposal::from_compact_string(&compact).unwrap(),
+            partial
+        );
+    }
+
+    #[test]
+    fn test_liquidex_balancing_fuzz() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let assets: Vec<AssetId> = (1..4u8)
+            .map(|i| AssetId::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let policy_asset = assets[0];
+        let secrets = |rng: &mut StdRng| {
+            let asset = assets[rng.gen_range(0, assets.len())];
+            let value = amount(rng);
+            let abf = AssetBlindingFactor::zero();
+            TxOutSecrets::new(asset, abf, value, ValueBlindingFactor::zero())
+        };
+
+        for _ in 0..1_000 {
+            // adversarial proposal, with maker secrets not matching the transaction, taken with
+            // inputs possibly missing from the cache
+            let maker_input = secrets(&mut rng);
+            let maker_output = if rng.gen_bool(0.5) {
+                Some(secrets(&mut rng))
+            } else {
+                None
+            };
+            let mut unblinded = HashMap::new();
+            let mut tx = elements::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![],
+            };
+            for vout in 0..rng.gen_range(1, 5) {
+                let outpoint = OutPoint {
+                    vout,
+                    ..Default::default()
+                };
+                add_input(&mut tx, outpoint);
+                if vout > 0 && rng.gen_bool(0.9) {
+                    unblinded.insert(outpoint, secrets(&mut rng));
+                }
+            }
+            for _ in 0..rng.gen_range(0, 4) {
+                let output = secrets(&mut rng);
+                let (asset, value) = if rng.gen_bool(0.95) {
+                    (Asset::Explicit(output.asset), Value::Explicit(output.value))
+                } else {
+                    (Asset::Null, Value::Null)
+                };
+                tx.output.push(elements::TxOut {
+                    asset,
+                    value,
+                    script_pubkey: vec![0u8; 22].into(),
+                    ..Default::default()
+                });
+            }
+
+            let maker_output = maker_output.as_ref();
+            let fee_rate = rng.gen_range(0.1, 10.0);
+            let _ = liquidex_needs(
+                &maker_input,
+                maker_output,
+                &tx,
+                fee_rate,
+                &policy_asset,
+                &unblinded,
+                AddressType::default(),
+            );
+            let _ = liquidex_fee(&maker_input, maker_output, &tx, &policy_asset, &unblinded);
+
+            let estimated_fee = amount(&mut rng);
+            let changes = match liquidex_changes(
+                &maker_input,
+                maker_output,
+                &tx,
+                estimated_fee,
+                &policy_asset,
+                &unblinded,
+            ) {
+                Ok(changes) => changes,
+                Err(_) => continue,
+            };
+            // the policy asset change leaves the fee and the dust not worth a change output, the
+            // fee is not paid without policy asset inputs
+            let inputs = super::inputs(&maker_input, &tx, &unblinded).unwrap();
+            if !inputs.contains_key(&policy_asset) {
+                continue;
+            }
+            let fee =
+                liquidex_fee(&maker_input, maker_output, &tx, &policy_asset, &unblinded).unwrap();
+            match changes.get(&policy_asset) {
+                Some(change) => assert_eq!(fee - change, estimated_fee),
+                None => assert!(fee >= estimated_fee && fee - estimated_fee <= DUST_VALUE),
+            }
+        }
+
+        let maker_input = secrets(&mut rng);
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, OutPoint::default());
+        add_input(&mut tx, OutPoint::default());
+        assert!(matches!(
+            liquidex_fee(&maker_input, None, &tx, &policy_asset, &HashMap::new()),
+            Err(Error::MissingPreviousOutput(_))
+        ));
+    }
 }
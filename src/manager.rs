@@ -0,0 +1,133 @@
+use crate::error::Error;
+use crate::network::ElementsNetwork;
+use crate::persister::FilePersister;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Wallet stores under a data root, the store of a wallet is in `<data_root>/<network>/<wallet_id>`
+/// so that wallets of different networks are never mixed up.
+pub struct WalletManager {
+    data_root: PathBuf,
+}
+
+impl WalletManager {
+    pub fn new<P: AsRef<Path>>(data_root: P) -> Self {
+        WalletManager {
+            data_root: data_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Directory of the store of the wallet `wallet_id` on `network`
+    pub fn wallet_dir(&self, network: ElementsNetwork, wallet_id: &str) -> PathBuf {
+        self.data_root.join(network.dir_name()).join(wallet_id)
+    }
+
+    /// Ids of the wallets with a store on `network`, sorted
+    pub fn list_wallets(&self, network: ElementsNetwork) -> Result<Vec<String>, Error> {
+        let dir = self.data_root.join(network.dir_name());
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut wallet_ids = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str().filter(|n| is_wallet_id(n)) {
+                wallet_ids.push(name.to_string());
+            }
+        }
+        wallet_ids.sort();
+        Ok(wallet_ids)
+    }
+
+    /// Move the store of `wallet_id` created before the stores were namespaced by network, in
+    /// `<data_root>/<wallet_id>`, to the directory of `network`. The wallet id commits to the
+    /// network, and the store checks the network it was created for when opened.
+    ///
+    /// Unless `ignore_lock`, the store is locked while it is moved, failing with
+    /// `Error::StoreLocked` if a wallet of an older version is using it.
+    pub(crate) fn migrate_legacy_dir(
+        &self,
+        network: ElementsNetwork,
+        wallet_id: &str,
+        ignore_lock: bool,
+    ) -> Result<(), Error> {
+        let legacy = self.data_root.join(wallet_id);
+        let dir = self.wallet_dir(network, wallet_id);
+        if is_wallet_id(wallet_id) && legacy.is_dir() && !dir.exists() {
+            let _lock = match ignore_lock {
+                true => None,
+                false => Some(FilePersister::new(&legacy)?),
+            };
+            info!("Moving store {:?} to {:?}", legacy, dir);
+            std::fs::create_dir_all(self.data_root.join(network.dir_name()))?;
+            std::fs::rename(legacy, dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wallet ids are the hex of a sha256
+fn is_wallet_id(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_list_wallets() {
+        let dir = TempDir::new("unit_test").unwrap();
+        let manager = WalletManager::new(dir.path());
+        assert!(manager
+            .list_wallets(ElementsNetwork::Liquid)
+            .unwrap()
+            .is_empty());
+
+        let ids = ["b".repeat(64), "a".repeat(64), "c".repeat(64)];
+        std::fs::create_dir_all(manager.wallet_dir(ElementsNetwork::Liquid, &ids[0])).unwrap();
+        std::fs::create_dir_all(manager.wallet_dir(ElementsNetwork::Liquid, &ids[1])).unwrap();
+        std::fs::create_dir_all(manager.wallet_dir(ElementsNetwork::ElementsRegtest, &ids[2]))
+            .unwrap();
+        std::fs::create_dir_all(manager.wallet_dir(ElementsNetwork::Liquid, "other")).unwrap();
+        assert_eq!(
+            manager.list_wallets(ElementsNetwork::Liquid).unwrap(),
+            vec![ids[1].clone(), ids[0].clone()]
+        );
+        assert_eq!(
+            manager
+                .list_wallets(ElementsNetwork::ElementsRegtest)
+                .unwrap(),
+            vec![ids[2].clone()]
+        );
+
+        // stores in the data root are moved to the directory of their network
+        let legacy_id = "d".repeat(64);
+        std::fs::create_dir_all(dir.path().join(&legacy_id)).unwrap();
+        std::fs::write(dir.path().join(&legacy_id).join("store"), b"data").unwrap();
+        // not while another wallet uses the store
+        let locked = FilePersister::new(dir.path().join(&legacy_id)).unwrap();
+        assert!(matches!(
+            manager.migrate_legacy_dir(ElementsNetwork::ElementsRegtest, &legacy_id, false),
+            Err(Error::StoreLocked(_))
+        ));
+        assert!(dir.path().join(&legacy_id).exists());
+        drop(locked);
+        manager
+            .migrate_legacy_dir(ElementsNetwork::ElementsRegtest, &legacy_id, false)
+            .unwrap();
+        assert!(!dir.path().join(&legacy_id).exists());
+        let moved = manager.wallet_dir(ElementsNetwork::ElementsRegtest, &legacy_id);
+        assert_eq!(std::fs::read(moved.join("store")).unwrap(), b"data");
+        assert_eq!(
+            manager
+                .list_wallets(ElementsNetwork::ElementsRegtest)
+                .unwrap(),
+            vec![ids[2].clone(), legacy_id]
+        );
+    }
+}
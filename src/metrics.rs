@@ -0,0 +1,92 @@
+use crate::backend::{BlockchainBackend, History};
+use crate::error::Error;
+use crate::model::FeeEstimate;
+
+use electrum_client::GetMerkleRes;
+use elements::{BlockHash, BlockHeader, Script, Txid};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Receiver of the wallet operational metrics, every method does nothing by default so that
+/// implementors could record only what they need.
+pub trait Metrics: Send + Sync {
+    /// A sync completed in `duration` making `requests` requests to the backend
+    fn sync(&self, _duration: Duration, _requests: u64) {}
+
+    /// Number of transactions and scripts in the cache after a sync
+    fn cache_size(&self, _txs: usize, _scripts: usize) {}
+
+    /// Number of wallet utxos
+    fn utxo_count(&self, _count: usize) {}
+
+    /// Wallet balance of `asset`
+    fn balance(&self, _asset: &elements::issuance::AssetId, _satoshi: u64) {}
+
+    /// A transaction has been created with `inputs`, `outputs` and `fee`
+    fn tx_created(&self, _inputs: usize, _outputs: usize, _fee: u64) {}
+
+    /// Broadcast of a transaction failed
+    fn broadcast_failure(&self, _error: &Error) {}
+}
+
+/// Backend counting the requests made to the wrapped backend
+pub(crate) struct CountingBackend<'a> {
+    inner: &'a dyn BlockchainBackend,
+    requests: AtomicU64,
+}
+
+impl<'a> CountingBackend<'a> {
+    pub fn new(inner: &'a dyn BlockchainBackend) -> Self {
+        CountingBackend {
+            inner,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    fn count(&self) -> &dyn BlockchainBackend {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.inner
+    }
+}
+
+impl<'a> BlockchainBackend for CountingBackend<'a> {
+    fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+        self.count().tip()
+    }
+
+    fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+        self.count().scripts_history(scripts)
+    }
+
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+        self.count().transactions(txids)
+    }
+
+    fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+        self.count().headers(heights)
+    }
+
+    fn merkle_proof(&self, txid: &Txid, height: u32) -> Result<GetMerkleRes, Error> {
+        self.count().merkle_proof(txid, height)
+    }
+
+    fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error> {
+        self.count().broadcast(tx)
+    }
+
+    fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+        self.count().fee_estimates()
+    }
+
+    fn block_filter(&self, height: u32) -> Result<(BlockHash, Vec<u8>), Error> {
+        self.count().block_filter(height)
+    }
+
+    fn block(&self, height: u32) -> Result<elements::Block, Error> {
+        self.count().block(height)
+    }
+}
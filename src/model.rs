@@ -1,14 +1,53 @@
+use crate::amount::{Amount, FeeRate};
 use crate::error::Error;
+use crate::network::ElementsNetwork;
+use crate::privacy::PrivacyWarning;
+use crate::scripts::{pegout_script, ScriptKind};
+use crate::transaction::DUST_VALUE;
 
 use elements::Script;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use elements::bitcoin::hashes::hex::FromHex;
+use elements::bitcoin::util::bip32::DerivationPath;
 use elements::OutPoint;
 use std::fmt::{Debug, Display};
 use std::str::FromStr;
 
+/// Version of the JSON representation of the public models, increased on changes that older
+/// clients cannot read. Adding a field with a default is not one of them.
+pub const MODEL_VERSION: u32 = 1;
+
+/// A public model with the `MODEL_VERSION` of its JSON representation, for the clients
+/// exchanging models across processes or storing them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Versioned<T> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub value: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(value: T) -> Self {
+        Versioned {
+            version: MODEL_VERSION,
+            value,
+        }
+    }
+
+    /// The model, failing if it was serialized by a newer version of the library
+    pub fn into_inner(self) -> Result<T, Error> {
+        if self.version > MODEL_VERSION {
+            return Err(Error::Generic(format!(
+                "model version {} is newer than the supported {}",
+                self.version, MODEL_VERSION
+            )));
+        }
+        Ok(self.value)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TXO {
     pub outpoint: OutPoint,
@@ -30,6 +69,197 @@ impl TXO {
 pub struct UnblindedTXO {
     pub txo: TXO,
     pub unblinded: elements::TxOutSecrets,
+    /// The asset reissued by this output, if it is a reissuance token
+    #[serde(default)]
+    pub reissuance_token_of: Option<elements::issuance::AssetId>,
+    /// The unblinded values do not match the output commitments, the output is neither counted
+    /// in the balance nor spent
+    #[serde(default)]
+    pub commitments_mismatch: bool,
+    /// Confirmations at the current tip, 0 if unconfirmed
+    #[serde(default)]
+    pub confirmations: u32,
+    /// Days since the block confirming the output, None if unconfirmed or if its header is not
+    /// cached
+    #[serde(default)]
+    pub age_days: Option<u32>,
+    /// The output was received unconfidential, its asset and value are public
+    #[serde(default)]
+    pub explicit: bool,
+}
+
+/// Summary of the synced wallet transactions
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncReport {
+    pub txs: usize,
+    pub unconfirmed_txs: usize,
+    /// Outputs of the wallet transactions by kind of script
+    pub script_kinds: HashMap<ScriptKind, usize>,
+    /// Outputs of the wallet transactions with a script of unknown kind
+    pub unknown_scripts: Vec<OutPoint>,
+}
+
+/// Summary of the wallet state, for dashboards and diagnostics
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WalletStats {
+    pub txs: usize,
+    pub unconfirmed_txs: usize,
+    /// Number of utxos by asset
+    pub utxos: HashMap<elements::issuance::AssetId, usize>,
+    /// Last index of the external chain (m/0/*) issued as an address or used
+    pub external_index: u32,
+    /// Last index of the internal chain (m/1/*) used for a change
+    pub internal_index: u32,
+    /// Bytes taken by the store, None if the persister cannot tell
+    pub store_size: Option<u64>,
+    /// Unix time of the end of the last sync, None if never synced
+    pub last_sync: Option<u64>,
+    /// Confirmed transactions with a verified SPV proof, 0 if SPV is disabled
+    pub spv_verified_txs: usize,
+}
+
+impl WalletStats {
+    /// Ratio of the confirmed transactions with a verified SPV proof, None without confirmed
+    /// transactions
+    pub fn spv_coverage(&self) -> Option<f64> {
+        let confirmed = self.txs - self.unconfirmed_txs;
+        if confirmed == 0 {
+            None
+        } else {
+            Some(self.spv_verified_txs as f64 / confirmed as f64)
+        }
+    }
+}
+
+/// Unconfirmed transaction paying the wallet, see `WalletCtx::pending_incoming`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingIncomingTx {
+    pub txid: String,
+    /// Value received by asset
+    pub received: HashMap<elements::issuance::AssetId, u64>,
+    /// Fee paid by the sender
+    pub fee: Amount,
+    /// Fee rate paid by the sender, on the transaction size estimated from the cached copy
+    /// without witnesses
+    pub fee_rate: FeeRate,
+    /// Smallest confirmation target in blocks of the cached fee estimates met by `fee_rate`,
+    /// None if it is below all of them
+    pub confirmation_target: Option<u32>,
+    /// An input signals replaceability (BIP125), the sender could double spend it easily
+    pub replaceable: bool,
+}
+
+/// Unconfirmed funds received by the wallet, see `WalletCtx::pending_incoming`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PendingIncoming {
+    /// Value received by all the unconfirmed transactions, by asset
+    pub totals: HashMap<elements::issuance::AssetId, u64>,
+    pub txs: Vec<PendingIncomingTx>,
+}
+
+/// Order of the inputs and outputs of a transaction created by `create_tx`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOrdering {
+    /// Random order, so that the change output cannot be told by its position
+    Shuffle,
+    /// Inputs by previous txid (as displayed) and vout, outputs by value and script pubkey,
+    /// then by asset, as in BIP69
+    Bip69,
+    /// Addressees outputs in the given order followed by the changes, inputs in the order
+    /// they are selected
+    Untouched,
+}
+
+impl Default for TxOrdering {
+    fn default() -> Self {
+        TxOrdering::Shuffle
+    }
+}
+
+/// Order of the utxos returned by `utxos_opt`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtxoSort {
+    /// Largest first, as returned by `utxos`
+    ValueDesc,
+    ValueAsc,
+    /// Most confirmed first, unconfirmed last
+    OldestFirst,
+    /// Unconfirmed first
+    NewestFirst,
+}
+
+impl Default for UtxoSort {
+    fn default() -> Self {
+        UtxoSort::ValueDesc
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UtxosOpt {
+    #[serde(default)]
+    pub sort: UtxoSort,
+    /// Only utxos of this asset
+    #[serde(default)]
+    pub asset: Option<elements::issuance::AssetId>,
+    /// Only utxos with at least these confirmations
+    #[serde(default)]
+    pub min_confirmations: u32,
+    /// Only utxos worth at most this, for instance to find near-dust positions
+    #[serde(default)]
+    pub max_value: Option<u64>,
+}
+
+/// Explicit value of an asset sent to an OP_RETURN output, removed from the asset supply
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burn {
+    pub vout: u32,
+    pub asset: elements::issuance::AssetId,
+    pub satoshi: Amount,
+}
+
+/// Output of a transaction sent by the wallet not locked to a wallet script
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Counterparty {
+    pub vout: u32,
+    pub script_pubkey: Script,
+    /// Address paid as given to `create_tx`, or the unconfidential address of the script if the
+    /// transaction was not created by this wallet. None for scripts without an address.
+    pub address: Option<String>,
+    /// None if blinded and not recorded when the transaction was created
+    pub asset: Option<elements::issuance::AssetId>,
+    /// None if blinded and not recorded when the transaction was created
+    pub satoshi: Option<Amount>,
+}
+
+/// Kind of a wallet transaction, from its balance changes and its scripts
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxType {
+    /// The wallet only received
+    Incoming,
+    /// The wallet only sent, fee excluded
+    Outgoing,
+    /// The wallet sent to itself, only the fee left the wallet
+    Redeposit,
+    /// The wallet sent some assets and received others, such as a LiquiDEX swap
+    Swap,
+    /// The transaction issues or reissues an asset
+    Issuance,
+    /// The transaction burns wallet assets, see `TransactionDetails::burns`
+    Burn,
+    /// The transaction claims coins pegged in from the main chain
+    PegIn,
+    /// The transaction pegs out coins to the main chain
+    PegOut,
+}
+
+/// Output of a transaction created by the wallet paying the change to the wallet internal chain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChangeOutput {
+    pub vout: u32,
+    pub address: elements::Address,
+    pub asset: elements::issuance::AssetId,
+    pub satoshi: Amount,
+    pub derivation_path: DerivationPath,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -37,16 +267,31 @@ pub struct TransactionDetails {
     pub transaction: elements::Transaction,
     pub txid: String,
     pub balances: HashMap<elements::issuance::AssetId, i64>,
-    pub fee: u64,
+    pub fee: Amount,
     pub height: Option<u32>,
     pub spv_verified: SPVVerifyResult,
+    /// Change outputs, only set for transactions returned by `create_tx` and `fund_tx`
+    #[serde(default)]
+    pub change_outputs: Vec<ChangeOutput>,
+    /// Privacy issues of a transaction returned by `create_tx` with `privacy_warnings` set
+    #[serde(default)]
+    pub privacy_warnings: Vec<PrivacyWarning>,
+    /// Burns of the wallet assets
+    #[serde(default)]
+    pub burns: Vec<Burn>,
+    /// Kind of the transaction, only set for transactions returned by `list_tx`
+    #[serde(default)]
+    pub tx_type: Option<TxType>,
+    /// Outputs paying others, set for transactions spending wallet outputs
+    #[serde(default)]
+    pub counterparties: Vec<Counterparty>,
 }
 
 impl TransactionDetails {
     pub fn new(
         transaction: elements::Transaction,
         balances: HashMap<elements::issuance::AssetId, i64>,
-        fee: u64,
+        fee: Amount,
         height: Option<u32>,
         spv_verified: SPVVerifyResult,
     ) -> TransactionDetails {
@@ -58,42 +303,350 @@ impl TransactionDetails {
             fee,
             height,
             spv_verified,
+            change_outputs: vec![],
+            privacy_warnings: vec![],
+            burns: vec![],
+            tx_type: None,
+            counterparties: vec![],
         }
     }
 
+    /// Change by asset, only set for transactions returned by `create_tx` and `fund_tx`
+    pub fn changes(&self) -> HashMap<elements::issuance::AssetId, Amount> {
+        self.change_outputs
+            .iter()
+            .map(|c| (c.asset, c.satoshi))
+            .collect()
+    }
+
     pub fn hex(&self) -> String {
         hex::encode(elements::encode::serialize(&self.transaction))
     }
 }
 
+/// Transaction decoded by `WalletCtx::decode_tx`, with the inputs and outputs of the wallet
+/// annotated
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodedTx {
+    pub txid: String,
+    pub version: u32,
+    pub lock_time: u32,
+    pub weight: usize,
+    /// Sum of the explicit fee outputs
+    pub fee: Amount,
+    pub inputs: Vec<DecodedInput>,
+    pub outputs: Vec<DecodedOutput>,
+    /// Wallet balance changes by asset, counting the wallet inputs and outputs only
+    pub balances: HashMap<elements::issuance::AssetId, i64>,
+}
+
+/// Input of a `DecodedTx`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodedInput {
+    pub outpoint: OutPoint,
+    pub sequence: u32,
+    /// Derivation path of the wallet key locking the spent output, None if it is not a wallet
+    /// output
+    pub derivation_path: Option<DerivationPath>,
+    /// Unblinded values of the spent wallet output
+    pub unblinded: Option<elements::TxOutSecrets>,
+}
+
+/// Output of a `DecodedTx`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecodedOutput {
+    pub script_pubkey: Script,
+    pub script_kind: ScriptKind,
+    /// Unconfidential address of the script, None for fees and scripts without an address
+    pub address: Option<String>,
+    /// Asset of explicit outputs
+    pub asset: Option<elements::issuance::AssetId>,
+    /// Value of explicit outputs
+    pub satoshi: Option<u64>,
+    /// Derivation path of the wallet key locking the output, None if it is not a wallet output
+    pub derivation_path: Option<DerivationPath>,
+    /// Unblinded values of confidential wallet outputs
+    pub unblinded: Option<elements::TxOutSecrets>,
+}
+
+/// Components of a confidential address
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddressInfo {
+    pub address: elements::Address,
+    /// The address without the blinding key, as shown by explorers
+    pub unconfidential: elements::Address,
+    pub blinding_pubkey: Option<elements::bitcoin::secp256k1::PublicKey>,
+    /// Hex of the script pubkey
+    pub script_pubkey: String,
+}
+
+impl AddressInfo {
+    pub fn new(address: elements::Address) -> Self {
+        let mut unconfidential = address.clone();
+        unconfidential.blinding_pubkey = None;
+        AddressInfo {
+            blinding_pubkey: address.blinding_pubkey,
+            script_pubkey: hex::encode(address.script_pubkey().as_bytes()),
+            unconfidential,
+            address,
+        }
+    }
+}
+
+/// Entry of the wallet address book
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Contact {
+    pub name: String,
+    /// Confidential address, or mainchain Bitcoin address paid with a peg-out
+    pub address: String,
+    /// Assets the contact accepts, any asset if empty
+    #[serde(default)]
+    pub assets: Vec<elements::issuance::AssetId>,
+}
+
+impl Contact {
+    /// Fails with `Error::InvalidAddress` if `address` is neither an elements nor a mainchain
+    /// Bitcoin address
+    pub fn new(
+        name: &str,
+        address: &str,
+        assets: Vec<elements::issuance::AssetId>,
+    ) -> Result<Self, Error> {
+        if name.trim().is_empty() {
+            return Err(Error::Generic("contact name is empty".into()));
+        }
+        parse_address(address)?;
+        Ok(Contact {
+            name: name.to_string(),
+            address: address.to_string(),
+            assets,
+        })
+    }
+
+    /// How close `query` is to the contact name ignoring the case, lower is closer: 0 for the
+    /// name, 1 for a prefix, 2 for a substring, 3 for characters of the name in order. None
+    /// if `query` does not match.
+    pub fn match_score(&self, query: &str) -> Option<u8> {
+        let name = self.name.to_lowercase();
+        let query = query.to_lowercase();
+        if name == query {
+            Some(0)
+        } else if name.starts_with(&query) {
+            Some(1)
+        } else if name.contains(&query) {
+            Some(2)
+        } else {
+            let mut chars = name.chars();
+            if query.chars().all(|q| chars.any(|c| c == q)) {
+                Some(3)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Split `address` in an elements address or a mainchain Bitcoin address
+fn parse_address(
+    address: &str,
+) -> Result<
+    (
+        Option<elements::Address>,
+        Option<elements::bitcoin::Address>,
+    ),
+    Error,
+> {
+    match elements::Address::from_str(address) {
+        Ok(address) => Ok((Some(address), None)),
+        Err(_) => {
+            let mainchain_address =
+                elements::bitcoin::Address::from_str(address).map_err(|_| Error::InvalidAddress)?;
+            Ok((None, Some(mainchain_address)))
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Destination {
-    address: elements::Address,
-    satoshi: u64,
-    asset: elements::issuance::AssetId,
+    /// None for peg-outs to `mainchain_address`
+    address: Option<elements::Address>,
+    #[serde(default)]
+    mainchain_address: Option<elements::bitcoin::Address>,
+    satoshi: Amount,
+    /// None for the policy asset
+    #[serde(default)]
+    asset: Option<elements::issuance::AssetId>,
+    /// Address book contact paid, its address is resolved by `create_tx`
+    #[serde(default)]
+    contact: Option<String>,
 }
 
 impl Destination {
-    pub fn new(address: &str, satoshi: u64, asset: &str) -> Result<Self, Error> {
-        let address = elements::Address::from_str(address).map_err(|_| Error::InvalidAddress)?;
-        let asset = elements::issuance::AssetId::from_hex(asset)?;
+    /// `address` could also be a mainchain Bitcoin address, paid with a peg-out if allowed by
+    /// `CreateTransactionOpt::allow_pegouts`
+    ///
+    /// `asset` must be the hex of a 32 bytes asset id, otherwise `Error::InvalidAsset` is
+    /// returned.
+    pub fn new(address: &str, satoshi: Amount, asset: &str) -> Result<Self, Error> {
+        let mut destination = Self::new_policy_asset(address, satoshi)?;
+        destination.asset = Some(parse_asset(asset)?);
+        Ok(destination)
+    }
+
+    /// Pay `satoshi` of the policy asset of the wallet network
+    pub fn new_policy_asset(address: &str, satoshi: Amount) -> Result<Self, Error> {
+        let (address, mainchain_address) = parse_address(address)?;
         Ok(Destination {
             address,
+            mainchain_address,
             satoshi,
-            asset,
+            asset: None,
+            contact: None,
         })
     }
 
-    pub fn address(&self) -> elements::Address {
+    /// Pay the address book contact `name`, `create_tx` fails if the contact does not exist or
+    /// does not accept `asset`
+    pub fn new_contact(name: &str, satoshi: Amount, asset: &str) -> Result<Self, Error> {
+        let asset = parse_asset(asset)?;
+        Ok(Destination {
+            address: None,
+            mainchain_address: None,
+            satoshi,
+            asset: Some(asset),
+            contact: Some(name.to_string()),
+        })
+    }
+
+    /// Name of the address book contact paid, if created with `new_contact`
+    pub fn contact(&self) -> Option<&str> {
+        self.contact.as_deref()
+    }
+
+    /// Pay the address of `contact`
+    pub(crate) fn resolve(
+        &mut self,
+        contact: &Contact,
+        policy_asset: elements::issuance::AssetId,
+    ) -> Result<(), Error> {
+        let asset = self.asset_or(policy_asset);
+        if !contact.assets.is_empty() && !contact.assets.contains(&asset) {
+            return Err(Error::Generic(format!(
+                "contact {} does not accept asset {}",
+                contact.name, asset
+            )));
+        }
+        let (address, mainchain_address) = parse_address(&contact.address)?;
+        self.address = address;
+        self.mainchain_address = mainchain_address;
+        Ok(())
+    }
+
+    /// None for peg-outs
+    pub fn address(&self) -> Option<elements::Address> {
         self.address.clone()
     }
 
-    pub fn satoshi(&self) -> u64 {
+    pub fn mainchain_address(&self) -> Option<elements::bitcoin::Address> {
+        self.mainchain_address.clone()
+    }
+
+    /// Script pubkey of the output paying this destination on `network`
+    pub fn script_pubkey(&self, network: ElementsNetwork) -> Result<Script, Error> {
+        match (&self.address, &self.mainchain_address) {
+            (Some(address), _) => Ok(address.script_pubkey()),
+            (None, Some(mainchain_address)) => Ok(pegout_script(
+                &network.parent_genesis_hash(),
+                &mainchain_address.script_pubkey(),
+            )),
+            (None, None) => Err(Error::InvalidAddress),
+        }
+    }
+
+    pub fn satoshi(&self) -> Amount {
         self.satoshi
     }
 
+    /// Asset paid. If omitted it is the default policy asset of the network of the address,
+    /// which differs from the one of regtest wallets with a custom policy asset: `create_tx`
+    /// pays `asset_or` the wallet policy asset.
     pub fn asset(&self) -> elements::issuance::AssetId {
         self.asset
+            .unwrap_or_else(|| self.network().default_policy_asset())
+    }
+
+    /// None for the policy asset
+    pub fn explicit_asset(&self) -> Option<elements::issuance::AssetId> {
+        self.asset
+    }
+
+    /// Asset paid, `policy_asset` if omitted
+    pub fn asset_or(
+        &self,
+        policy_asset: elements::issuance::AssetId,
+    ) -> elements::issuance::AssetId {
+        self.asset.unwrap_or(policy_asset)
+    }
+
+    /// Network of the address paid, Liquid for the contacts not resolved yet
+    fn network(&self) -> ElementsNetwork {
+        match (&self.address, &self.mainchain_address) {
+            (Some(address), _) if address.params != &elements::AddressParams::LIQUID => {
+                ElementsNetwork::ElementsRegtest
+            }
+            (None, Some(address)) if address.network != elements::bitcoin::Network::Bitcoin => {
+                ElementsNetwork::ElementsRegtest
+            }
+            _ => ElementsNetwork::Liquid,
+        }
+    }
+}
+
+/// Parse the hex of a 32 bytes asset id
+pub(crate) fn parse_asset(asset: &str) -> Result<elements::issuance::AssetId, Error> {
+    let invalid = || Error::InvalidAsset(asset.to_string());
+    if asset.len() != 64 || !asset.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    elements::issuance::AssetId::from_hex(asset).map_err(|_| invalid())
+}
+
+/// Serialize maps keyed by outpoint as JSON objects keyed by `txid:vout`, since JSON keys must
+/// be strings
+mod outpoint_map {
+    use elements::bitcoin::hashes::hex::FromHex;
+    use elements::OutPoint;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer, V: Serialize>(
+        map: &HashMap<OutPoint, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            map.iter()
+                .map(|(k, v)| (format!("{}:{}", k.txid, k.vout), v)),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<OutPoint, V>, D::Error> {
+        let map: HashMap<String, V> = HashMap::deserialize(deserializer)?;
+        map.into_iter()
+            .map(|(k, v)| {
+                let mut parts = k.rsplitn(2, ':');
+                let vout = parts.next().and_then(|vout| vout.parse().ok());
+                let txid = parts
+                    .next()
+                    .and_then(|txid| elements::Txid::from_hex(txid).ok());
+                match (txid, vout) {
+                    (Some(txid), Some(vout)) => Ok((OutPoint { txid, vout }, v)),
+                    _ => Err(D::Error::custom(format!("invalid outpoint {}", k))),
+                }
+            })
+            .collect()
     }
 }
 
@@ -101,9 +654,115 @@ impl Destination {
 pub struct CreateTransactionOpt {
     // TODO: chage type to hold SendAll and be valid
     pub addressees: Vec<Destination>,
-    pub fee_rate: Option<u64>, // in satoshi/kbyte
+    pub fee_rate: Option<FeeRate>,
     pub utxos: Option<Vec<UnblindedTXO>>,
+    /// Fail with `Error::UnknownAsset` if an addressee asset is not known to the wallet nor
+    /// registered in the asset registry, see `Config::registry_url`
+    #[serde(default)]
+    pub check_assets: bool,
+    /// Asset to pay fees with, None for the policy asset which is the only one supported
+    #[serde(default)]
+    pub fee_asset: Option<elements::issuance::AssetId>,
+    /// Return the privacy issues of the transaction in `TransactionDetails::privacy_warnings`
+    #[serde(default)]
+    pub privacy_warnings: bool,
+    /// Allow to select reissuance token utxos, they are skipped otherwise since spending them
+    /// to a recipient gives away the power of reissuing the asset
+    #[serde(default)]
+    pub spend_reissuance_tokens: bool,
+    /// Transaction locktime, None for the current tip height to discourage fee sniping
+    #[serde(default)]
+    pub lock_time: Option<u32>,
+    /// Sequence of the inputs spending these outpoints, other inputs have 0xfffffffe, enabling
+    /// the locktime without signaling RBF. Serialized as an object keyed by `txid:vout`.
+    #[serde(default, with = "outpoint_map")]
+    pub sequences: HashMap<OutPoint, u32>,
+    /// Select only utxos received on addresses having one of these labels, any utxo if empty
+    #[serde(default)]
+    pub from_labels: Vec<String>,
+    /// Spend only these policy asset utxos, paying the fee and the policy asset outputs, so
+    /// that the other policy asset utxos are not touched. They must also be allowed by `utxos`
+    /// and `from_labels`. Utxos of other assets are selected as usual.
+    #[serde(default)]
+    pub fee_utxos: Option<Vec<OutPoint>>,
+    /// Spend only the policy asset utxos of the virtual account `fee_account`, as `fee_utxos`.
+    /// The policy asset change address is labelled `fee_account` when the transaction is signed,
+    /// so that the change stays in the account.
+    #[serde(default)]
+    pub fee_account: Option<String>,
+    /// Pay addressees with a mainchain Bitcoin address with a peg-out output of the policy
+    /// asset, they are rejected with `Error::InvalidAddress` otherwise.
+    /// Networks enforcing PAK proofs on peg-outs, such as Liquid, also require a proof which is
+    /// not created by the wallet.
+    #[serde(default)]
+    pub allow_pegouts: bool,
+    /// Id chosen by the caller to retry safely: the transaction created with the same id is
+    /// returned, signed if it was signed by the wallet, instead of creating another spend
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Order of the inputs and outputs, the fee output is always the last one
+    #[serde(default)]
+    pub ordering: TxOrdering,
+}
+impl CreateTransactionOpt {
+    /// Check the addressees could be paid on `network`, whose policy asset is `policy_asset`
+    pub fn validate(
+        &self,
+        network: ElementsNetwork,
+        policy_asset: elements::issuance::AssetId,
+    ) -> Result<(), Error> {
+        for addressee in self.addressees.iter() {
+            let asset = addressee.asset_or(policy_asset);
+            if !network.is_valid_asset(&asset) {
+                return Err(Error::InvalidAsset(asset.to_string()));
+            }
+            match (addressee.address(), addressee.mainchain_address()) {
+                (Some(address), _) => {
+                    if address.params != network.address_params() {
+                        return Err(Error::InvalidAddress);
+                    }
+                }
+                (None, Some(mainchain_address)) => {
+                    if !self.allow_pegouts || !network.is_mainchain_address(&mainchain_address) {
+                        return Err(Error::InvalidAddress);
+                    }
+                    if asset != policy_asset {
+                        return Err(Error::Generic(
+                            "peg-outs can only send the policy asset".into(),
+                        ));
+                    }
+                }
+                (None, None) => return Err(Error::InvalidAddress),
+            }
+        }
+
+        if self.addressees.is_empty() {
+            return Err(Error::EmptyAddressees);
+        }
+
+        if self.addressees.iter().any(|a| a.satoshi() == Amount::ZERO) {
+            return Err(Error::InvalidAmount);
+        }
+
+        for addressee in self.addressees.iter() {
+            // we apply dust rules for liquid bitcoin as elements do
+            if addressee.asset_or(policy_asset) == policy_asset
+                && addressee.satoshi().as_sat() <= DUST_VALUE
+            {
+                return Err(Error::InvalidAmount);
+            }
+        }
+
+        if let Some(fee_asset) = self.fee_asset {
+            // fees are paid in the policy asset, the only fee asset of the Elements networks
+            if fee_asset != policy_asset {
+                return Err(Error::FeeAssetUnsupported(fee_asset));
+            }
+        }
+        Ok(())
+    }
 }
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct GetTransactionsOpt {
     pub first: usize,
@@ -124,6 +783,62 @@ pub enum SPVVerifyResult {
 #[derive(Serialize, Debug, Clone, Deserialize)]
 pub struct FeeEstimate(pub u64);
 
+/// Request of a payment of `amount` of `asset` on a wallet address
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invoice {
+    pub id: String,
+    pub address: elements::Address,
+    pub asset: elements::issuance::AssetId,
+    pub amount: Amount,
+    pub memo: String,
+    /// creation time, in seconds since the unix epoch
+    pub created_at: u64,
+    /// seconds after creation when the invoice expires if not paid
+    pub expiry: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceStatus {
+    Pending,
+    Paid,
+    Expired,
+    Overpaid,
+}
+
+impl InvoiceStatus {
+    /// Status of an invoice of `amount` that received `received`, `expired` if its expiry elapsed
+    pub fn new(amount: Amount, received: Amount, expired: bool) -> Self {
+        match received.cmp(&amount) {
+            std::cmp::Ordering::Equal => InvoiceStatus::Paid,
+            std::cmp::Ordering::Greater => InvoiceStatus::Overpaid,
+            std::cmp::Ordering::Less if expired => InvoiceStatus::Expired,
+            std::cmp::Ordering::Less => InvoiceStatus::Pending,
+        }
+    }
+}
+
+impl Display for InvoiceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvoiceStatus::Pending => write!(f, "pending"),
+            InvoiceStatus::Paid => write!(f, "paid"),
+            InvoiceStatus::Expired => write!(f, "expired"),
+            InvoiceStatus::Overpaid => write!(f, "overpaid"),
+        }
+    }
+}
+
+/// Wallet output paying an address
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Payment {
+    pub txid: String,
+    pub vout: u32,
+    pub asset: elements::issuance::AssetId,
+    pub satoshi: Amount,
+    /// None if the transaction is unconfirmed
+    pub height: Option<u32>,
+}
+
 impl SPVVerifyResult {
     pub fn as_i32(&self) -> i32 {
         match self {
@@ -148,7 +863,26 @@ impl Display for SPVVerifyResult {
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        AddressInfo, Contact, CreateTransactionOpt, Destination, InvoiceStatus, SPVVerifyResult,
+        TransactionDetails, UnblindedTXO, Versioned, MODEL_VERSION, TXO,
+    };
+    use crate::amount::Amount;
+    use crate::error::Error;
+    use crate::network::ElementsNetwork;
     use elements::bitcoin::hashes::hex::{FromHex, ToHex};
+    use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+    use elements::OutPoint;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    /// Sorted keys of the JSON object serialized from `value`
+    fn keys<T: serde::Serialize>(value: &T) -> Vec<String> {
+        let json = serde_json::to_value(value).unwrap();
+        let mut keys: Vec<String> = json.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
 
     #[test]
     fn test_asset_roundtrip() {
@@ -156,4 +890,245 @@ mod tests {
         let asset = elements::issuance::AssetId::from_hex(&hex).unwrap();
         assert_eq!(asset.to_hex(), hex);
     }
+
+    #[test]
+    fn test_destination_asset() {
+        let address =
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d";
+        let lbtc = "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+        let policy_asset = elements::issuance::AssetId::from_hex(lbtc).unwrap();
+        let satoshi = Amount::from_sat(1_000);
+
+        let destination = Destination::new(address, satoshi, lbtc).unwrap();
+        assert_eq!(destination.asset(), policy_asset);
+        assert_eq!(destination.explicit_asset(), Some(policy_asset));
+        let destination = Destination::new_policy_asset(address, satoshi).unwrap();
+        assert_eq!(destination.explicit_asset(), None);
+        assert_eq!(destination.asset(), policy_asset);
+        assert_eq!(destination.asset_or(policy_asset), policy_asset);
+        let invalids = vec![
+            "".to_string(),
+            "6f02".to_string(),
+            lbtc[1..].to_string(),
+            format!("{}00", lbtc),
+            lbtc.replace('f', "g"),
+        ];
+        for invalid in invalids.iter() {
+            assert!(matches!(
+                Destination::new(address, satoshi, invalid),
+                Err(Error::InvalidAsset(_))
+            ));
+            assert!(Destination::new_contact("Alice", satoshi, invalid).is_err());
+        }
+
+        let mut opt = CreateTransactionOpt::default();
+        assert!(matches!(
+            opt.validate(ElementsNetwork::Liquid, policy_asset),
+            Err(Error::EmptyAddressees)
+        ));
+        opt.addressees.push(destination);
+        assert!(opt.validate(ElementsNetwork::Liquid, policy_asset).is_ok());
+        assert!(matches!(
+            opt.validate(ElementsNetwork::ElementsRegtest, policy_asset),
+            Err(Error::InvalidAsset(_))
+        ));
+        opt.addressees[0] = Destination::new_policy_asset(address, Amount::from_sat(546)).unwrap();
+        assert!(matches!(
+            opt.validate(ElementsNetwork::Liquid, policy_asset),
+            Err(Error::InvalidAmount)
+        ));
+    }
+
+    #[test]
+    fn test_address_info() {
+        let address = elements::Address::from_str(
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d",
+        )
+        .unwrap();
+        let info = AddressInfo::new(address.clone());
+        assert!(info.blinding_pubkey.is_some());
+        assert_eq!(info.blinding_pubkey, address.blinding_pubkey);
+        assert!(info.unconfidential.blinding_pubkey.is_none());
+        assert_eq!(info.unconfidential.script_pubkey(), address.script_pubkey());
+        assert_eq!(
+            info.script_pubkey,
+            hex::encode(address.script_pubkey().as_bytes())
+        );
+    }
+
+    #[test]
+    fn test_contact_match_score() {
+        let address =
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d";
+        let contact = Contact::new("Alice Exchange", address, vec![]).unwrap();
+        assert_eq!(contact.match_score("alice exchange"), Some(0));
+        assert_eq!(contact.match_score("ALI"), Some(1));
+        assert_eq!(contact.match_score("exch"), Some(2));
+        assert_eq!(contact.match_score("aex"), Some(3));
+        assert_eq!(contact.match_score("bob"), None);
+        assert!(Contact::new("Bob", "not an address", vec![]).is_err());
+        assert!(Contact::new(" ", address, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_invoice_status() {
+        let sat = Amount::from_sat;
+        assert_eq!(
+            InvoiceStatus::new(sat(10), sat(0), false),
+            InvoiceStatus::Pending
+        );
+        assert_eq!(
+            InvoiceStatus::new(sat(10), sat(5), false),
+            InvoiceStatus::Pending
+        );
+        assert_eq!(
+            InvoiceStatus::new(sat(10), sat(5), true),
+            InvoiceStatus::Expired
+        );
+        assert_eq!(
+            InvoiceStatus::new(sat(10), sat(10), true),
+            InvoiceStatus::Paid
+        );
+        assert_eq!(
+            InvoiceStatus::new(sat(10), sat(11), false),
+            InvoiceStatus::Overpaid
+        );
+    }
+
+    #[test]
+    fn test_model_schema() {
+        let address =
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d";
+        let asset = elements::issuance::AssetId::from_hex(
+            "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d",
+        )
+        .unwrap();
+        let txid = elements::Txid::from_hex(
+            "0101010101010101010101010101010101010101010101010101010101010101",
+        )
+        .unwrap();
+        let outpoint = OutPoint { txid, vout: 1 };
+
+        let info = AddressInfo::new(elements::Address::from_str(address).unwrap());
+        assert_eq!(
+            keys(&info),
+            [
+                "address",
+                "blinding_pubkey",
+                "script_pubkey",
+                "unconfidential"
+            ]
+        );
+        let txo = TXO::new(outpoint, info.address.script_pubkey(), Some(100));
+        assert_eq!(keys(&txo), ["height", "outpoint", "script_pubkey"]);
+        let utxo = UnblindedTXO {
+            txo,
+            unblinded: elements::TxOutSecrets::new(
+                asset,
+                AssetBlindingFactor::zero(),
+                1_000,
+                ValueBlindingFactor::zero(),
+            ),
+            reissuance_token_of: None,
+            commitments_mismatch: false,
+            confirmations: 1,
+            age_days: None,
+            explicit: false,
+        };
+        assert_eq!(
+            keys(&utxo),
+            [
+                "age_days",
+                "commitments_mismatch",
+                "confirmations",
+                "explicit",
+                "reissuance_token_of",
+                "txo",
+                "unblinded"
+            ]
+        );
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        let details = TransactionDetails::new(
+            tx,
+            HashMap::new(),
+            Amount::from_sat(100),
+            Some(100),
+            SPVVerifyResult::Verified,
+        );
+        assert_eq!(
+            keys(&details),
+            [
+                "balances",
+                "burns",
+                "change_outputs",
+                "counterparties",
+                "fee",
+                "height",
+                "privacy_warnings",
+                "spv_verified",
+                "transaction",
+                "tx_type",
+                "txid"
+            ]
+        );
+        let mut opt = CreateTransactionOpt::default();
+        opt.addressees
+            .push(Destination::new_policy_asset(address, Amount::from_sat(1_000)).unwrap());
+        opt.utxos = Some(vec![utxo]);
+        opt.sequences.insert(outpoint, 0xffff_fffd);
+        assert_eq!(
+            keys(&opt),
+            [
+                "addressees",
+                "allow_pegouts",
+                "check_assets",
+                "fee_account",
+                "fee_asset",
+                "fee_rate",
+                "fee_utxos",
+                "from_labels",
+                "lock_time",
+                "ordering",
+                "privacy_warnings",
+                "request_id",
+                "sequences",
+                "spend_reissuance_tokens",
+                "utxos"
+            ]
+        );
+        let json = serde_json::to_value(&opt).unwrap();
+        assert_eq!(json["sequences"][format!("{}:1", txid)], 0xffff_fffdu32);
+
+        // versioned models roundtrip, the ones of newer versions are rejected
+        let json = serde_json::to_string(&Versioned::new(opt.clone())).unwrap();
+        let versioned: Versioned<CreateTransactionOpt> = serde_json::from_str(&json).unwrap();
+        assert_eq!(versioned.version, MODEL_VERSION);
+        let opt2 = versioned.into_inner().unwrap();
+        assert_eq!(opt2.sequences, opt.sequences);
+        assert_eq!(
+            serde_json::to_string(&opt2).unwrap(),
+            serde_json::to_string(&opt).unwrap()
+        );
+        let mut json = serde_json::to_value(&Versioned::new(details)).unwrap();
+        assert_eq!(json["version"], MODEL_VERSION);
+        assert_eq!(json["fee"], 100);
+        json["version"] = (MODEL_VERSION + 1).into();
+        let newer: Versioned<TransactionDetails> = serde_json::from_value(json).unwrap();
+        assert!(newer.into_inner().is_err());
+
+        // fields added after the first version have defaults
+        let opt: CreateTransactionOpt =
+            serde_json::from_str(r#"{"addressees": [], "fee_rate": null, "utxos": null}"#).unwrap();
+        assert!(opt.sequences.is_empty());
+        assert!(opt.fee_utxos.is_none());
+        assert!(serde_json::from_str::<CreateTransactionOpt>(
+            r#"{"addressees": [], "fee_rate": null, "utxos": null, "sequences": {"x": 1}}"#
+        )
+        .is_err());
+    }
 }
@@ -1,11 +1,23 @@
+use crate::amount::FeeRate;
+use crate::backend::BlockchainBackend;
 use crate::error::Error;
+use crate::headers::Verifier;
+use crate::retry::{CircuitBreakers, RetryPolicy, RetryingBackend};
+use crate::scripts::AddressType;
 
-use elements::bitcoin::hashes::hex::FromHex;
+use elements::bitcoin::hashes::hex::{FromHex, ToHex};
+use elements::bitcoin::util::bip32::{ChildNumber, DerivationPath};
+use elements::BlockHash;
+use serde::{Deserialize, Serialize};
 
 // TODO: policy asset should only be set for ElementsRegtest, fail otherwise
 const LIQUID_POLICY_ASSET_STR: &str =
     "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
 
+/// Policy asset of the regtest chains of elementsd with the default parameters
+const REGTEST_POLICY_ASSET_STR: &str =
+    "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225";
+
 #[derive(Debug, Clone)]
 pub enum ElectrumUrl {
     Tls(String, bool), // the bool value indicates if the domain name should be validated
@@ -14,7 +26,15 @@ pub enum ElectrumUrl {
 
 impl ElectrumUrl {
     pub fn build_client(&self) -> Result<electrum_client::Client, Error> {
-        let builder = electrum_client::ConfigBuilder::new();
+        self.build_client_with_timeout(None)
+    }
+
+    /// Build a client failing requests taking more than `timeout` seconds
+    pub fn build_client_with_timeout(
+        &self,
+        timeout: Option<u8>,
+    ) -> Result<electrum_client::Client, Error> {
+        let builder = electrum_client::ConfigBuilder::new().timeout(timeout)?;
         let (url, builder) = match self {
             ElectrumUrl::Tls(url, validate) => {
                 (format!("ssl://{}", url), builder.validate_domain(*validate))
@@ -25,21 +45,333 @@ impl ElectrumUrl {
     }
 }
 
+/// Server the wallet gets blockchain data from
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Electrum(ElectrumUrl),
+    /// Base url of an esplora HTTP API, for instance https://blockstream.info/liquid/api
+    #[cfg(feature = "esplora")]
+    Esplora(String),
+    /// JSON-RPC url and credentials of an elementsd node
+    #[cfg(feature = "elementsd")]
+    ElementsRpc {
+        url: String,
+        user: String,
+        password: String,
+    },
+}
+
+impl Backend {
+    /// Identifier of the server, without credentials
+    pub fn server_id(&self) -> String {
+        match self {
+            Backend::Electrum(ElectrumUrl::Tls(url, _)) => format!("ssl://{}", url),
+            Backend::Electrum(ElectrumUrl::Plaintext(url)) => format!("tcp://{}", url),
+            #[cfg(feature = "esplora")]
+            Backend::Esplora(url) => url.clone(),
+            #[cfg(feature = "elementsd")]
+            Backend::ElementsRpc { url, .. } => url.clone(),
+        }
+    }
+
+    /// Host and port of the server if it is reached over TLS
+    pub fn tls_server(&self) -> Option<(String, u16)> {
+        let (host_port, default_port) = match self {
+            Backend::Electrum(ElectrumUrl::Tls(url, _)) => (url.as_str(), 50002),
+            #[cfg(feature = "esplora")]
+            Backend::Esplora(url) => (url.strip_prefix("https://")?, 443),
+            #[cfg(feature = "elementsd")]
+            Backend::ElementsRpc { url, .. } => (url.strip_prefix("https://")?, 443),
+            _ => return None,
+        };
+        let host_port = host_port.split('/').next()?;
+        match host_port.rsplit_once(':') {
+            Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+            None => Some((host_port.to_string(), default_port)),
+        }
+    }
+
+    /// Build a client failing requests taking more than `timeout` seconds, identifying with
+    /// `user_agent` if given, as User-Agent for HTTP servers and with `server.version` for
+    /// electrum servers
+    pub fn build_client(
+        &self,
+        timeout: Option<u8>,
+        user_agent: Option<&str>,
+    ) -> Result<Box<dyn BlockchainBackend>, Error> {
+        match self {
+            Backend::Electrum(url) => {
+                let client = url.build_client_with_timeout(timeout)?;
+                if let Some(user_agent) = user_agent {
+                    crate::backend::electrum_client_id(&client, user_agent)?;
+                }
+                Ok(Box::new(client))
+            }
+            #[cfg(feature = "esplora")]
+            Backend::Esplora(url) => Ok(Box::new(crate::esplora::EsploraClient::new(
+                url, timeout, user_agent,
+            ))),
+            #[cfg(feature = "elementsd")]
+            Backend::ElementsRpc {
+                url,
+                user,
+                password,
+            } => Ok(Box::new(crate::elementsd::ElementsRpcClient::new(
+                url, user, password, timeout, user_agent,
+            ))),
+        }
+    }
+
+    /// Like `build_client`, but the connections to TLS servers are rejected during the handshake
+    /// unless the server certificate is one of `pins`, servers not reached over TLS are not
+    /// pinned
+    #[cfg(feature = "pinning")]
+    pub fn build_pinned_client(
+        &self,
+        timeout: Option<u8>,
+        user_agent: Option<&str>,
+        pins: &[elements::bitcoin::hashes::sha256::Hash],
+    ) -> Result<Box<dyn BlockchainBackend>, Error> {
+        let (host, port) = match self.tls_server() {
+            Some(server) => server,
+            None => return self.build_client(timeout, user_agent),
+        };
+        match self {
+            Backend::Electrum(_) => Ok(Box::new(crate::pinning::electrum_client(
+                &host, port, pins, timeout, user_agent,
+            )?)),
+            #[cfg(feature = "esplora")]
+            Backend::Esplora(url) => {
+                let agent = crate::backend::http_agent_builder(timeout, user_agent)
+                    .tls_config(crate::pinning::tls_config(pins))
+                    .build();
+                Ok(Box::new(crate::esplora::EsploraClient::with_agent(
+                    url, agent,
+                )))
+            }
+            #[cfg(feature = "elementsd")]
+            Backend::ElementsRpc {
+                url,
+                user,
+                password,
+            } => {
+                let agent = crate::backend::http_agent_builder(timeout, user_agent)
+                    .tls_config(crate::pinning::tls_config(pins))
+                    .build();
+                Ok(Box::new(crate::elementsd::ElementsRpcClient::with_agent(
+                    url, user, password, agent,
+                )))
+            }
+        }
+    }
+}
+
+/// Fee rate in satoshi per 1000 vbytes used when none is given
+pub const DEFAULT_FEE_RATE: u64 = 100;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     network: ElementsNetwork,
     policy_asset: elements::issuance::AssetId,
-    electrum_url: ElectrumUrl,
+
+    pub backend: Backend,
 
     pub spv_enabled: bool,
+
+    /// Trust headers matching the checkpoints without verifying their signatures. The embedded
+    /// checkpoints cover only the first blocks of the chain, recent headers are still verified
+    /// unless they match `spv_extra_checkpoints`.
+    pub spv_checkpoints: bool,
+
+    /// Checkpoints added to the embedded ones if `spv_checkpoints`, such as recent blocks of a
+    /// trusted node, as (height, block hash)
+    pub spv_extra_checkpoints: Vec<(u32, BlockHash)>,
+
+    /// Sync with compact block filters instead of asking the server the history of the wallet
+    /// scripts, the backend must support block filters
+    pub block_filters: bool,
+
+    /// Seconds after which a request to the server fails, None for no timeout
+    pub timeout: Option<u8>,
+
+    /// Minimum fee rate accepted when creating transactions
+    pub min_fee_rate: Option<FeeRate>,
+
+    /// Maximum fee rate accepted when creating transactions
+    pub max_fee_rate: Option<FeeRate>,
+
+    /// Open the store even if it is in use by another wallet, in this or another process,
+    /// simultaneous writers could corrupt it
+    pub ignore_store_lock: bool,
+
+    /// Name identifying the application to the HTTP servers as User-Agent and to the Electrum
+    /// servers with `server.version`
+    pub client_id: Option<String>,
+
+    /// Sha256 of the DER certificates accepted from the TLS servers, if not empty other
+    /// certificates are rejected during the TLS handshake, whatever their CA, Electrum clients
+    /// fail with `Error::CertificatePinMismatch`
+    #[cfg(feature = "pinning")]
+    pub tls_pins: Vec<elements::bitcoin::hashes::sha256::Hash>,
+
+    /// Asset registry, such as `https://assets.blockstream.info`, where `create_tx` looks up
+    /// the addressee assets unknown to the wallet if `CreateTransactionOpt::check_assets`
+    #[cfg(feature = "registry")]
+    pub registry_url: Option<String>,
+
+    /// Retries of the requests failing with transient errors, None to fail at the first error
+    pub network_retry_policy: Option<RetryPolicy>,
+
+    /// Dynamic federations are active on the network, allowing signatures with the
+    /// `SIGHASH_RANGEPROOF` flag
+    pub dynafed_active: bool,
+
+    /// Derivation of the wallet keys and type of the wallet addresses, None for the BIP49
+    /// P2SH-P2WPKH account 0. Set it to restore wallets created by other software.
+    pub derivation: Option<DerivationTemplate>,
+
+    circuit_breakers: CircuitBreakers,
+}
+
+/// Path of the wallet account key from the master key and type of the addresses of its keys,
+/// which are derived at `<account_path>/0|1/*`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DerivationTemplate {
+    pub account_path: DerivationPath,
+    pub address_type: AddressType,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl DerivationTemplate {
+    pub fn new(account_path: &str, address_type: AddressType) -> Result<Self, Error> {
+        Ok(DerivationTemplate {
+            account_path: account_path.parse()?,
+            address_type,
+        })
+    }
+
+    /// BIP49 P2SH-P2WPKH `account`, the default of the wallet
+    pub fn bip49(network: ElementsNetwork, account: u32) -> Self {
+        Self::bip44_like(49, network, account, AddressType::P2shP2wpkh)
+    }
+
+    /// BIP84 P2WPKH `account`
+    pub fn bip84(network: ElementsNetwork, account: u32) -> Self {
+        Self::bip44_like(84, network, account, AddressType::P2wpkh)
+    }
+
+    /// Blockstream Green (GDK) singlesig `subaccount`, numbered `account * 16 + type` where
+    /// type 0 is BIP49 P2SH-P2WPKH and 1 is BIP84 P2WPKH. Green uses the SLIP-77 master
+    /// blinding key of the seed as the wallet does, so its funds are restored from the mnemonic.
+    pub fn green_subaccount(network: ElementsNetwork, subaccount: u32) -> Result<Self, Error> {
+        let account = subaccount / 16;
+        match subaccount % 16 {
+            0 => Ok(Self::bip49(network, account)),
+            1 => Ok(Self::bip84(network, account)),
+            _ => Err(Error::Generic(format!(
+                "unsupported Green subaccount {}",
+                subaccount
+            ))),
+        }
+    }
+
+    // BIP44: m / purpose' / coin_type' / account' / change / address_index
+    fn bip44_like(
+        purpose: u32,
+        network: ElementsNetwork,
+        account: u32,
+        address_type: AddressType,
+    ) -> Self {
+        let account_path: Vec<ChildNumber> = [purpose, network.coin_type(), account]
+            .iter()
+            .map(|index| ChildNumber::Hardened { index: *index })
+            .collect();
+        DerivationTemplate {
+            account_path: account_path.into(),
+            address_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ElementsNetwork {
     Liquid,
     ElementsRegtest,
 }
 
+impl ElementsNetwork {
+    /// Network of the BIP32 version bytes of the wallet keys: xpub/xprv on Liquid, tpub/tprv on
+    /// regtest
+    pub fn bip32_network(&self) -> elements::bitcoin::Network {
+        match self {
+            ElementsNetwork::Liquid => elements::bitcoin::Network::Bitcoin,
+            ElementsNetwork::ElementsRegtest => elements::bitcoin::Network::Testnet,
+        }
+    }
+
+    /// SLIP-44 coin type of the wallet keys: 1776, liquid bitcoin, on Liquid and 1, as for
+    /// every testnet, on regtest
+    pub fn coin_type(&self) -> u32 {
+        match self {
+            ElementsNetwork::Liquid => 1776,
+            ElementsNetwork::ElementsRegtest => 1,
+        }
+    }
+
+    /// Genesis block hash of the mainchain, committed by the peg-out outputs
+    pub fn parent_genesis_hash(&self) -> elements::bitcoin::BlockHash {
+        let network = match self {
+            ElementsNetwork::Liquid => elements::bitcoin::Network::Bitcoin,
+            ElementsNetwork::ElementsRegtest => elements::bitcoin::Network::Regtest,
+        };
+        elements::bitcoin::blockdata::constants::genesis_block(network).block_hash()
+    }
+
+    /// Name of the directory of the wallet stores of the network, see `WalletManager`
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            ElementsNetwork::Liquid => "liquid",
+            ElementsNetwork::ElementsRegtest => "regtest",
+        }
+    }
+
+    /// Parameters of the addresses of the network
+    pub fn address_params(&self) -> &'static elements::AddressParams {
+        match self {
+            ElementsNetwork::Liquid => &elements::AddressParams::LIQUID,
+            ElementsNetwork::ElementsRegtest => &elements::AddressParams::ELEMENTS,
+        }
+    }
+
+    /// Policy asset of the network, on regtest the one of the chains with the default
+    /// parameters, while `Config::policy_asset_id` is the one of the wallet chain
+    pub fn default_policy_asset(&self) -> elements::issuance::AssetId {
+        let hex = match self {
+            ElementsNetwork::Liquid => LIQUID_POLICY_ASSET_STR,
+            ElementsNetwork::ElementsRegtest => REGTEST_POLICY_ASSET_STR,
+        };
+        elements::issuance::AssetId::from_hex(hex).expect("valid asset id")
+    }
+
+    /// Whether `asset` could exist on the network, the Liquid policy asset does not exist on
+    /// regtest networks
+    pub fn is_valid_asset(&self, asset: &elements::issuance::AssetId) -> bool {
+        match self {
+            ElementsNetwork::Liquid => true,
+            ElementsNetwork::ElementsRegtest => asset.to_hex() != LIQUID_POLICY_ASSET_STR,
+        }
+    }
+
+    /// Whether `address` is of the mainchain, base58 regtest addresses parse as testnet ones
+    pub fn is_mainchain_address(&self, address: &elements::bitcoin::Address) -> bool {
+        match self {
+            ElementsNetwork::Liquid => address.network == elements::bitcoin::Network::Bitcoin,
+            ElementsNetwork::ElementsRegtest => {
+                address.network != elements::bitcoin::Network::Bitcoin
+            }
+        }
+    }
+}
+
 impl Config {
     pub fn new_regtest(
         tls: bool,
@@ -54,8 +386,24 @@ impl Config {
         };
         Ok(Config {
             network: ElementsNetwork::ElementsRegtest,
-            electrum_url,
+            backend: Backend::Electrum(electrum_url),
             spv_enabled,
+            spv_checkpoints: true,
+            spv_extra_checkpoints: vec![],
+            block_filters: false,
+            timeout: None,
+            min_fee_rate: None,
+            max_fee_rate: None,
+            ignore_store_lock: false,
+            client_id: None,
+            #[cfg(feature = "pinning")]
+            tls_pins: vec![],
+            #[cfg(feature = "registry")]
+            registry_url: None,
+            network_retry_policy: None,
+            dynafed_active: false,
+            derivation: None,
+            circuit_breakers: CircuitBreakers::default(),
             policy_asset: elements::issuance::AssetId::from_hex(policy_asset)?,
         })
     }
@@ -72,8 +420,24 @@ impl Config {
         };
         Ok(Config {
             network: ElementsNetwork::Liquid,
-            electrum_url,
+            backend: Backend::Electrum(electrum_url),
             spv_enabled,
+            spv_checkpoints: true,
+            spv_extra_checkpoints: vec![],
+            block_filters: false,
+            timeout: None,
+            min_fee_rate: None,
+            max_fee_rate: None,
+            ignore_store_lock: false,
+            client_id: None,
+            #[cfg(feature = "pinning")]
+            tls_pins: vec![],
+            #[cfg(feature = "registry")]
+            registry_url: None,
+            network_retry_policy: None,
+            dynafed_active: false,
+            derivation: None,
+            circuit_breakers: CircuitBreakers::default(),
             policy_asset: elements::issuance::AssetId::from_hex(LIQUID_POLICY_ASSET_STR)?,
         })
     }
@@ -82,11 +446,163 @@ impl Config {
         self.network
     }
 
-    pub fn policy_asset(&self) -> elements::issuance::AssetId {
+    /// Url of the electrum server, `None` if the backend is not electrum
+    pub fn electrum_url(&self) -> Option<ElectrumUrl> {
+        match &self.backend {
+            Backend::Electrum(url) => Some(url.clone()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Header verifier of the network with the checkpoints of the config
+    pub(crate) fn spv_verifier(&self) -> Verifier {
+        Verifier::new(self.network, self.spv_checkpoints)
+            .with_checkpoints(&self.spv_extra_checkpoints)
+    }
+
+    /// Derivation of the wallet keys, see `Config::derivation`
+    pub fn derivation(&self) -> DerivationTemplate {
+        self.derivation
+            .clone()
+            .unwrap_or_else(|| DerivationTemplate::bip49(self.network, 0))
+    }
+
+    /// Policy asset of the network, validated when the config is created
+    pub fn policy_asset_id(&self) -> elements::issuance::AssetId {
         self.policy_asset
     }
 
-    pub fn electrum_url(&self) -> ElectrumUrl {
-        self.electrum_url.clone()
+    /// Fee rate for a transaction: the requested `fee_rate` if within the configured bounds,
+    /// otherwise `Error::FeeRateOutOfBounds`, or the default fee rate moved within the bounds
+    pub fn fee_rate(&self, fee_rate: Option<FeeRate>) -> Result<FeeRate, Error> {
+        let min = self.min_fee_rate.unwrap_or(FeeRate::ZERO);
+        let max = self.max_fee_rate.unwrap_or(FeeRate::MAX);
+        match fee_rate {
+            Some(fee_rate) if fee_rate < min || fee_rate > max => Err(Error::FeeRateOutOfBounds {
+                fee_rate: fee_rate.as_sat_per_kvb(),
+                min: min.as_sat_per_kvb(),
+                max: max.as_sat_per_kvb(),
+            }),
+            Some(fee_rate) => Ok(fee_rate),
+            None => Ok(FeeRate::from_sat_per_kvb(DEFAULT_FEE_RATE)
+                .max(min)
+                .min(max)),
+        }
+    }
+
+    pub fn build_client(&self) -> Result<Box<dyn BlockchainBackend>, Error> {
+        #[cfg(feature = "pinning")]
+        let client = if self.tls_pins.is_empty() {
+            self.backend
+                .build_client(self.timeout, self.client_id.as_deref())?
+        } else {
+            self.backend.build_pinned_client(
+                self.timeout,
+                self.client_id.as_deref(),
+                &self.tls_pins,
+            )?
+        };
+        #[cfg(not(feature = "pinning"))]
+        let client = self
+            .backend
+            .build_client(self.timeout, self.client_id.as_deref())?;
+        match self.network_retry_policy {
+            Some(policy) => Ok(Box::new(RetryingBackend::new(
+                client,
+                policy,
+                self.backend.server_id(),
+                self.circuit_breakers.clone(),
+            ))),
+            None => Ok(client),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_rate_bounds() {
+        let mut config = Config::new_mainnet(true, true, false, "localhost:50002").unwrap();
+        let rate = FeeRate::from_sat_per_kvb;
+        assert_eq!(config.fee_rate(None).unwrap(), rate(DEFAULT_FEE_RATE));
+        assert_eq!(
+            config.fee_rate(Some(rate(1_000_000))).unwrap(),
+            rate(1_000_000)
+        );
+
+        config.min_fee_rate = Some(rate(200));
+        config.max_fee_rate = Some(rate(1_000));
+        assert_eq!(config.fee_rate(None).unwrap(), rate(200));
+        assert_eq!(config.fee_rate(Some(rate(500))).unwrap(), rate(500));
+        assert!(matches!(
+            config.fee_rate(Some(rate(1_001))),
+            Err(Error::FeeRateOutOfBounds {
+                fee_rate: 1_001,
+                min: 200,
+                max: 1_000
+            })
+        ));
+        assert!(config.fee_rate(Some(rate(100))).is_err());
+    }
+
+    #[test]
+    fn test_derivation_template() {
+        let mut config = Config::new_mainnet(true, true, false, "localhost:50002").unwrap();
+        let default = config.derivation();
+        assert_eq!(default.account_path.to_string(), "m/49'/1776'/0'");
+        assert_eq!(default.address_type, AddressType::P2shP2wpkh);
+
+        let bip84 = DerivationTemplate::bip84(ElementsNetwork::ElementsRegtest, 2);
+        assert_eq!(bip84.account_path.to_string(), "m/84'/1'/2'");
+        assert_eq!(
+            bip84,
+            DerivationTemplate::new("m/84'/1'/2'", AddressType::P2wpkh).unwrap()
+        );
+        config.derivation = Some(bip84.clone());
+        assert_eq!(config.derivation(), bip84);
+        assert!(DerivationTemplate::new("m/84'/x", AddressType::P2wpkh).is_err());
+    }
+
+    #[test]
+    fn test_green_subaccount() {
+        let green =
+            |subaccount| DerivationTemplate::green_subaccount(ElementsNetwork::Liquid, subaccount);
+        assert_eq!(
+            green(0).unwrap(),
+            Config::new_mainnet(true, true, false, "localhost:50002")
+                .unwrap()
+                .derivation()
+        );
+        assert_eq!(
+            green(1).unwrap(),
+            DerivationTemplate::bip84(ElementsNetwork::Liquid, 0)
+        );
+        assert_eq!(
+            green(16).unwrap().account_path.to_string(),
+            "m/49'/1776'/1'"
+        );
+        assert_eq!(
+            green(17).unwrap().account_path.to_string(),
+            "m/84'/1776'/1'"
+        );
+        assert!(green(2).is_err());
+    }
+
+    #[test]
+    fn test_tls_server() {
+        let config = Config::new_mainnet(true, true, false, "blockstream.info:995").unwrap();
+        assert_eq!(
+            config.backend.tls_server(),
+            Some(("blockstream.info".to_string(), 995))
+        );
+        let config = Config::new_mainnet(false, false, false, "localhost:50001").unwrap();
+        assert_eq!(config.backend.tls_server(), None);
+        assert!(matches!(
+            config.electrum_url(),
+            Some(ElectrumUrl::Plaintext(url)) if url == "localhost:50001"
+        ));
     }
 }
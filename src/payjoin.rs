@@ -0,0 +1,161 @@
+use crate::error::Error;
+use crate::liquidex::LiquidexTxOutSecrets;
+
+use elements::bitcoin::hashes::hex::FromHex;
+use elements::bitcoin::util::amount::{Amount, Denomination};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Payment request with a payjoin endpoint, such as
+/// `liquidnetwork:<address>?amount=0.001&assetid=<asset>&pj=https://example.com/pj`.
+///
+/// The amount is in units of 10^8 satoshi, without `assetid` the asset is the policy asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayjoinUri {
+    pub address: elements::Address,
+    pub satoshi: u64,
+    pub asset: Option<elements::issuance::AssetId>,
+    /// Url the payjoin proposal is posted to
+    pub endpoint: String,
+}
+
+impl FromStr for PayjoinUri {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = match s.find(':') {
+            Some(pos) => &s[pos + 1..],
+            None => return Err(Error::Generic("payjoin uri without scheme".into())),
+        };
+        let (address, query) = match rest.find('?') {
+            Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+            None => (rest, ""),
+        };
+        let address = elements::Address::from_str(address).map_err(|_| Error::InvalidAddress)?;
+
+        let mut satoshi = None;
+        let mut asset = None;
+        let mut endpoint = None;
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = match param.find('=') {
+                Some(pos) => (&param[..pos], percent_decode(&param[pos + 1..])?),
+                None => (param, String::new()),
+            };
+            match key {
+                "amount" => {
+                    let amount = Amount::from_str_in(&value, Denomination::Bitcoin)
+                        .map_err(|_| Error::InvalidAmount)?;
+                    satoshi = Some(amount.as_sat());
+                }
+                "assetid" => asset = Some(elements::issuance::AssetId::from_hex(&value)?),
+                "pj" => endpoint = Some(value),
+                _ => (),
+            }
+        }
+
+        Ok(PayjoinUri {
+            address,
+            satoshi: satoshi.ok_or(Error::InvalidAmount)?,
+            asset,
+            endpoint: endpoint
+                .ok_or_else(|| Error::Generic("payjoin uri without endpoint".into()))?,
+        })
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String, Error> {
+    let bytes = s.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            decoded.extend(hex::decode(&bytes[i + 1..i + 3])?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(String::from_utf8(decoded)?)
+}
+
+/// Previous output spent by a payjoin transaction and its secrets, needed to blind the transaction
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PayjoinInput {
+    txout: String,
+    secrets: LiquidexTxOutSecrets,
+}
+
+impl PayjoinInput {
+    pub fn new(txout: &elements::TxOut, secrets: elements::TxOutSecrets) -> Self {
+        PayjoinInput {
+            txout: hex::encode(elements::encode::serialize(txout)),
+            secrets: secrets.into(),
+        }
+    }
+
+    pub fn txout(&self) -> Result<elements::TxOut, Error> {
+        Ok(elements::encode::deserialize(&hex::decode(&self.txout)?)?)
+    }
+
+    pub fn secrets(&self) -> elements::TxOutSecrets {
+        self.secrets.to_txoutsecrets()
+    }
+}
+
+/// Original transaction posted by the payjoin sender: unblinded, unsigned, with the secrets of
+/// its inputs so that the receiver could blind the transaction after adding its input.
+///
+/// Differently from bitcoin payjoins the receiver learns the values of the sender inputs.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PayjoinProposal {
+    tx: String,
+    inputs: Vec<PayjoinInput>,
+}
+
+impl PayjoinProposal {
+    pub fn new(tx: &elements::Transaction, inputs: Vec<PayjoinInput>) -> Self {
+        PayjoinProposal {
+            tx: hex::encode(elements::encode::serialize(tx)),
+            inputs,
+        }
+    }
+
+    pub fn transaction(&self) -> Result<elements::Transaction, Error> {
+        Ok(elements::encode::deserialize(&hex::decode(&self.tx)?)?)
+    }
+
+    /// Previous outputs in the order of the transaction inputs
+    pub fn inputs(&self) -> &[PayjoinInput] {
+        &self.inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payjoin_uri() {
+        let address =
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d";
+        let asset = "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+        let uri = format!(
+            "liquidnetwork:{}?amount=0.001&assetid={}&pj=https%3A%2F%2Fexample.com%2Fpj",
+            address, asset
+        );
+        let uri = PayjoinUri::from_str(&uri).unwrap();
+        assert_eq!(uri.address.to_string(), address);
+        assert_eq!(uri.satoshi, 100_000);
+        assert_eq!(uri.asset.unwrap().to_string(), asset);
+        assert_eq!(uri.endpoint, "https://example.com/pj");
+
+        let no_endpoint = format!("liquidnetwork:{}?amount=0.001", address);
+        assert!(PayjoinUri::from_str(&no_endpoint).is_err());
+        let no_amount = format!("liquidnetwork:{}?pj=https://example.com/pj", address);
+        assert!(matches!(
+            PayjoinUri::from_str(&no_amount),
+            Err(Error::InvalidAmount)
+        ));
+    }
+}
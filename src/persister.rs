@@ -0,0 +1,242 @@
+use crate::error::Error;
+use fs2::FileExt;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Persistence of the (already encrypted) store files.
+pub trait Persister: Send + Sync {
+    /// Load the bytes saved with `name`, None if nothing has been saved yet
+    fn load(&self, name: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Save `bytes` with `name`, replacing the previous value if any
+    fn save(&self, name: &str, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Remove the value saved with `name` if any, persisters not implementing it keep the value
+    fn remove(&self, _name: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Bytes taken by the saved values, None if the persister cannot tell
+    fn size(&self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+}
+
+/// Name of the file locked by the `FilePersister` owning the directory
+const LOCK_FILE: &str = "lock";
+
+/// Persister saving every value in a file in the given directory.
+///
+/// The directory is locked with an advisory lock until the persister is dropped, so that only
+/// one wallet at a time, in any process, writes the files.
+pub struct FilePersister {
+    path: PathBuf,
+    _lock: Option<File>,
+}
+
+impl FilePersister {
+    /// Returns `Error::StoreLocked` if the directory is locked by another persister
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut persister = Self::new_unlocked(path)?;
+        let lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(persister.path.join(LOCK_FILE))?;
+        lock.try_lock_exclusive()
+            .map_err(|_| Error::StoreLocked(persister.path.clone()))?;
+        persister._lock = Some(lock);
+        Ok(persister)
+    }
+
+    /// Persister ignoring the lock of the directory, simultaneous writers could corrupt the files
+    pub fn new_unlocked<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            std::fs::create_dir_all(&path)?;
+        }
+        Ok(FilePersister { path, _lock: None })
+    }
+}
+
+impl Persister for FilePersister {
+    fn load(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let mut store_path = self.path.clone();
+        store_path.push(name);
+        if !store_path.exists() {
+            return Ok(None);
+        }
+        let mut file = File::open(&store_path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        Ok(Some(bytes))
+    }
+
+    fn save(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut store_path = self.path.clone();
+        store_path.push(name);
+        let mut file = File::create(&store_path)?;
+        file.write_all(bytes)?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        let mut store_path = self.path.clone();
+        store_path.push(name);
+        if store_path.exists() {
+            std::fs::remove_file(&store_path)?;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        let mut size = 0;
+        for entry in std::fs::read_dir(&self.path)? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+        Ok(Some(size))
+    }
+}
+
+/// Persister keeping the values in memory, nothing is written on disk.
+/// Clones share the same values, so that a wallet could be reopened while the process is alive.
+#[derive(Default, Clone)]
+pub struct MemoryPersister {
+    values: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryPersister {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for MemoryPersister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // the values are encrypted, their names are enough to tell what is saved
+        let values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        f.debug_set().entries(values.keys()).finish()
+    }
+}
+
+impl Persister for MemoryPersister {
+    fn load(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        let values = self
+            .values
+            .lock()
+            .map_err(|_| Error::Generic("MemoryPersister poisoned".into()))?;
+        Ok(values.get(name).cloned())
+    }
+
+    fn save(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut values = self
+            .values
+            .lock()
+            .map_err(|_| Error::Generic("MemoryPersister poisoned".into()))?;
+        values.insert(name.to_string(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        let mut values = self
+            .values
+            .lock()
+            .map_err(|_| Error::Generic("MemoryPersister poisoned".into()))?;
+        values.remove(name);
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        let values = self
+            .values
+            .lock()
+            .map_err(|_| Error::Generic("MemoryPersister poisoned".into()))?;
+        Ok(Some(values.values().map(|v| v.len() as u64).sum()))
+    }
+}
+
+/// Persister saving the values in an embedded sled database, a save rewrites only the value
+/// changed instead of a whole file and it is atomic. Since the cache is saved with a value per
+/// field and the transactions with a value each, a sync rewrites only the records it changed.
+#[cfg(feature = "sled")]
+pub struct SledPersister {
+    db: sled::Db,
+}
+
+#[cfg(feature = "sled")]
+impl SledPersister {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(SledPersister {
+            db: sled::open(path)?,
+        })
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Persister for SledPersister {
+    fn load(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.db.get(name)?.map(|value| value.to_vec()))
+    }
+
+    fn save(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        self.db.insert(name, bytes)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn remove(&self, name: &str) -> Result<(), Error> {
+        self.db.remove(name)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Option<u64>, Error> {
+        Ok(Some(self.db.size_on_disk()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_file_persister_lock() {
+        let dir = TempDir::new("unit_test").unwrap();
+        let persister = FilePersister::new(dir.path()).unwrap();
+        assert!(matches!(
+            FilePersister::new(dir.path()),
+            Err(Error::StoreLocked(_))
+        ));
+        let unlocked = FilePersister::new_unlocked(dir.path()).unwrap();
+        persister.save("value", &[1]).unwrap();
+        assert_eq!(unlocked.load("value").unwrap(), Some(vec![1]));
+
+        drop(persister);
+        assert!(FilePersister::new(dir.path()).is_ok());
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_persister() {
+        let dir = TempDir::new("unit_test").unwrap();
+        let persister = SledPersister::new(dir.path()).unwrap();
+        assert_eq!(persister.load("value").unwrap(), None);
+        persister.save("value", &[1, 2]).unwrap();
+        persister.save("other", &[3]).unwrap();
+        persister.save("value", &[4]).unwrap();
+        assert_eq!(persister.load("value").unwrap(), Some(vec![4]));
+        persister.remove("other").unwrap();
+        assert!(persister.size().unwrap().is_some());
+        drop(persister);
+
+        let persister = SledPersister::new(dir.path()).unwrap();
+        assert_eq!(persister.load("value").unwrap(), Some(vec![4]));
+        assert_eq!(persister.load("other").unwrap(), None);
+    }
+}
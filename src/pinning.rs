@@ -0,0 +1,130 @@
+use crate::backend::electrum_client_id;
+use crate::error::Error;
+
+use electrum_client::raw_client::RawClient;
+use elements::bitcoin::hashes::{sha256, Hash};
+use rustls::Session;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// TLS stream of the electrum clients accepting only pinned certificates
+pub type PinnedStream = rustls::StreamOwned<rustls::ClientSession, TcpStream>;
+
+/// Verifier accepting only the certificates whose sha256 is pinned, whatever their CA, keeping
+/// the hash of the last certificate rejected
+struct PinningVerifier {
+    pins: Vec<sha256::Hash>,
+    rejected: Mutex<Option<sha256::Hash>>,
+}
+
+impl PinningVerifier {
+    fn new(pins: &[sha256::Hash]) -> Arc<Self> {
+        Arc::new(PinningVerifier {
+            pins: pins.to_vec(),
+            rejected: Mutex::new(None),
+        })
+    }
+
+    fn rejected(&self) -> Option<sha256::Hash> {
+        *self.rejected.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl rustls::ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let cert = presented_certs
+            .first()
+            .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+        let hash = sha256::Hash::hash(&cert.0);
+        if self.pins.contains(&hash) {
+            return Ok(rustls::ServerCertVerified::assertion());
+        }
+        *self.rejected.lock().unwrap_or_else(|e| e.into_inner()) = Some(hash);
+        Err(rustls::TLSError::General(format!(
+            "TLS certificate {} is not pinned",
+            hash
+        )))
+    }
+}
+
+fn client_config(verifier: Arc<PinningVerifier>) -> Arc<rustls::ClientConfig> {
+    let mut config = rustls::ClientConfig::new();
+    config.dangerous().set_certificate_verifier(verifier);
+    Arc::new(config)
+}
+
+/// TLS configuration of the HTTP agents accepting only the certificates in `pins`, the
+/// connections to servers presenting other certificates fail with a transport error
+pub fn tls_config(pins: &[sha256::Hash]) -> Arc<rustls::ClientConfig> {
+    client_config(PinningVerifier::new(pins))
+}
+
+/// Electrum client connected over TLS to `host:port`, failing with
+/// `Error::CertificatePinMismatch` unless the server certificate is one of `pins`.
+///
+/// The certificate is checked during the handshake of the connection used by the client. The
+/// client introduces itself as `client_id` if given.
+pub fn electrum_client(
+    host: &str,
+    port: u16,
+    pins: &[sha256::Hash],
+    timeout: Option<u8>,
+    client_id: Option<&str>,
+) -> Result<RawClient<PinnedStream>, Error> {
+    let verifier = PinningVerifier::new(pins);
+    // IP addresses are not valid names, the name only selects the certificate on shared hosts
+    let name = webpki::DNSNameRef::try_from_ascii_str(host)
+        .or_else(|_| webpki::DNSNameRef::try_from_ascii_str("pinned.invalid"))
+        .map_err(|_| Error::Generic(format!("invalid TLS server name {}", host)))?;
+    let mut session = rustls::ClientSession::new(&client_config(verifier.clone()), name);
+    let mut socket = TcpStream::connect((host, port))?;
+    let timeout = timeout.map(|t| Duration::from_secs(t as u64));
+    socket.set_read_timeout(timeout)?;
+    socket.set_write_timeout(timeout)?;
+    // handshake before the first request so that a certificate not pinned is reported as such
+    while session.is_handshaking() {
+        if let Err(e) = session.complete_io(&mut socket) {
+            return Err(match verifier.rejected() {
+                Some(hash) => Error::CertificatePinMismatch(hash),
+                None => e.into(),
+            });
+        }
+    }
+    let client = RawClient::from(rustls::StreamOwned::new(session, socket));
+    if let Some(client_id) = client_id {
+        electrum_client_id(&client, client_id)?;
+    }
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::ServerCertVerifier;
+
+    #[test]
+    fn test_pinning_verifier() {
+        let cert = rustls::Certificate(vec![1, 2, 3]);
+        let other = rustls::Certificate(vec![4, 5, 6]);
+        let verifier = PinningVerifier::new(&[sha256::Hash::hash(&cert.0)]);
+        let roots = rustls::RootCertStore::empty();
+        let name = webpki::DNSNameRef::try_from_ascii_str("example.com").unwrap();
+
+        assert!(verifier
+            .verify_server_cert(&roots, &[cert], name, &[])
+            .is_ok());
+        assert_eq!(verifier.rejected(), None);
+        assert!(verifier
+            .verify_server_cert(&roots, &[other.clone()], name, &[])
+            .is_err());
+        assert_eq!(verifier.rejected(), Some(sha256::Hash::hash(&other.0)));
+        assert!(verifier.verify_server_cert(&roots, &[], name, &[]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use elements::confidential::Nonce;
+use elements::Script;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Payments multiple of this amount, in satoshi, are considered round
+const ROUND_AMOUNT: u64 = 100_000;
+
+/// Spending more inputs than this links many coins of the wallet together
+const MANY_INPUTS: usize = 5;
+
+/// Transaction features that could reduce the privacy of the wallet or of the receivers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PrivacyWarning {
+    /// Output to a script already in the wallet history or repeated in the transaction, hex of
+    /// the script pubkey
+    AddressReuse(String),
+    /// Payment of a round amount, which tells the change apart from the payment
+    RoundAmount(u64),
+    /// Number of inputs, merging many coins reveals they have the same owner
+    ManyInputs(usize),
+    /// Output with value and asset not blinded, hex of the script pubkey
+    UnblindedOutput(String),
+}
+
+impl fmt::Display for PrivacyWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrivacyWarning::AddressReuse(script) => write!(f, "address reuse of {}", script),
+            PrivacyWarning::RoundAmount(amount) => write!(f, "round amount {}", amount),
+            PrivacyWarning::ManyInputs(inputs) => write!(f, "merging {} inputs", inputs),
+            PrivacyWarning::UnblindedOutput(script) => write!(f, "unblinded output {}", script),
+        }
+    }
+}
+
+/// Privacy warnings of the transaction paying `payments` (script and amount), `used_scripts` are
+/// the scripts of the outputs of the wallet history. `tx` is not blinded yet, its outputs to be
+/// blinded have the receiver blinding key in the nonce.
+pub fn privacy_warnings(
+    tx: &elements::Transaction,
+    payments: &[(Script, u64)],
+    used_scripts: &HashSet<Script>,
+) -> Vec<PrivacyWarning> {
+    let mut warnings = vec![];
+    let mut seen = HashSet::new();
+    for (script, amount) in payments {
+        if used_scripts.contains(script) || !seen.insert(script.clone()) {
+            warnings.push(PrivacyWarning::AddressReuse(hex::encode(script.as_bytes())));
+        }
+        if amount % ROUND_AMOUNT == 0 {
+            warnings.push(PrivacyWarning::RoundAmount(*amount));
+        }
+    }
+    if tx.input.len() > MANY_INPUTS {
+        warnings.push(PrivacyWarning::ManyInputs(tx.input.len()));
+    }
+    for output in tx.output.iter() {
+        // the fee output has an empty script and it's always explicit
+        if !output.script_pubkey.is_empty() && output.nonce == Nonce::Null {
+            let script = hex::encode(output.script_pubkey.as_bytes());
+            warnings.push(PrivacyWarning::UnblindedOutput(script));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::confidential::{Asset, Value};
+
+    #[test]
+    fn test_privacy_warnings() {
+        let script = Script::from(vec![0x51]);
+        let output = elements::TxOut {
+            asset: Asset::Null,
+            value: Value::Explicit(1000),
+            nonce: Nonce::Null,
+            script_pubkey: script.clone(),
+            witness: Default::default(),
+        };
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![Default::default(); MANY_INPUTS + 1],
+            output: vec![output],
+        };
+        let payments = vec![(script.clone(), 1_000), (script.clone(), 200_000)];
+        let warnings = privacy_warnings(&tx, &payments, &HashSet::new());
+        let script_hex = hex::encode(script.as_bytes());
+        assert_eq!(
+            warnings,
+            vec![
+                PrivacyWarning::AddressReuse(script_hex.clone()),
+                PrivacyWarning::RoundAmount(200_000),
+                PrivacyWarning::ManyInputs(MANY_INPUTS + 1),
+                PrivacyWarning::UnblindedOutput(script_hex),
+            ]
+        );
+
+        let tx = elements::Transaction {
+            input: vec![Default::default()],
+            output: vec![],
+            ..tx
+        };
+        let mut used = HashSet::new();
+        assert!(privacy_warnings(&tx, &payments[..1], &used).is_empty());
+        used.insert(script);
+        assert_eq!(privacy_warnings(&tx, &payments[..1], &used).len(), 1);
+    }
+}
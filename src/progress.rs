@@ -0,0 +1,108 @@
+use crate::error::Error;
+
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStage {
+    NotStarted,
+    /// Asking the server the history of the wallet scripts
+    Scripts,
+    /// Downloading the wallet transactions
+    Transactions,
+    /// Downloading the headers of the blocks confirming the wallet transactions
+    Headers,
+    Done,
+}
+
+impl Default for SyncStage {
+    fn default() -> Self {
+        SyncStage::NotStarted
+    }
+}
+
+/// Progress of the current or of the last sync.
+///
+/// The new wallet scripts are found while syncing, so the total of `scripts` grows until the
+/// gap limit of unused scripts is reached.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub stage: SyncStage,
+    /// Headers downloaded and to download
+    pub headers: (usize, usize),
+    /// Scripts whose history has been received and requested
+    pub scripts: (usize, usize),
+    pub txs_downloaded: usize,
+}
+
+/// Holder of the sync progress, sending every update to the channel set by the integrator
+#[derive(Default)]
+pub(crate) struct ProgressReporter {
+    progress: Mutex<SyncProgress>,
+    sender: Mutex<Option<Sender<SyncProgress>>>,
+}
+
+impl ProgressReporter {
+    pub fn snapshot(&self) -> Result<SyncProgress, Error> {
+        Ok(self.lock_progress()?.clone())
+    }
+
+    pub fn set_sender(&self, sender: Option<Sender<SyncProgress>>) -> Result<(), Error> {
+        *self.lock_sender()? = sender;
+        Ok(())
+    }
+
+    /// Apply `f` to the progress and send the result, a closed channel is removed
+    pub fn update<F: FnOnce(&mut SyncProgress)>(&self, f: F) -> Result<(), Error> {
+        let mut progress = self.lock_progress()?;
+        f(&mut progress);
+        let mut sender = self.lock_sender()?;
+        let closed = match sender.as_ref() {
+            Some(sender) => sender.send(progress.clone()).is_err(),
+            None => false,
+        };
+        if closed {
+            *sender = None;
+        }
+        Ok(())
+    }
+
+    fn lock_progress(&self) -> Result<MutexGuard<'_, SyncProgress>, Error> {
+        self.progress
+            .lock()
+            .map_err(|_| Error::Generic("sync progress poisoned".into()))
+    }
+
+    fn lock_sender(&self) -> Result<MutexGuard<'_, Option<Sender<SyncProgress>>>, Error> {
+        self.sender
+            .lock()
+            .map_err(|_| Error::Generic("sync progress poisoned".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_progress_updates() {
+        let reporter = ProgressReporter::default();
+        reporter.update(|p| p.stage = SyncStage::Scripts).unwrap();
+
+        let (sender, receiver) = channel();
+        reporter.set_sender(Some(sender)).unwrap();
+        reporter.update(|p| p.scripts.1 += 20).unwrap();
+        reporter.update(|p| p.scripts.0 += 20).unwrap();
+        let received: Vec<SyncProgress> = receiver.try_iter().collect();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].scripts, (20, 20));
+        assert_eq!(received[1].stage, SyncStage::Scripts);
+        assert_eq!(reporter.snapshot().unwrap(), received[1]);
+
+        drop(receiver);
+        reporter.update(|p| p.stage = SyncStage::Done).unwrap();
+        assert!(reporter.sender.lock().unwrap().is_none());
+    }
+}
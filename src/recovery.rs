@@ -0,0 +1,158 @@
+//! Disaster recovery of the funds of a seed when the wallet store is lost or corrupted.
+
+use crate::backend::BlockchainBackend;
+use crate::cancel::CancelToken;
+use crate::error::Error;
+use crate::interface::WalletCtx;
+use crate::network::{Config, DerivationTemplate};
+use crate::persister::MemoryPersister;
+use crate::progress::ProgressReporter;
+use crate::transaction::add_amount;
+use crate::{Syncer, Tipper};
+
+use elements::bitcoin::secp256k1;
+use elements::issuance::AssetId;
+use elements::BlockHeader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::info;
+
+/// Account numbers without history scanned after the last one with history, as the BIP44
+/// discovery stops after the first one since wallets rarely skip accounts
+pub const ACCOUNT_GAP_LIMIT: u32 = 3;
+
+/// Account of the seed with a history, found by `scan_from_seed`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecoveredAccount {
+    /// Set it as `Config::derivation` to restore the account with `ElectrumWallet::new`
+    pub derivation: DerivationTemplate,
+    /// Store synced by the scan, not serialized: restore the account with
+    /// `ElectrumWallet::with_persister` passing a clone of it to skip the initial sync
+    #[serde(skip)]
+    pub store: MemoryPersister,
+    pub balance: HashMap<AssetId, u64>,
+    pub transactions: usize,
+    pub utxos: usize,
+    /// Last used index of the external chain
+    pub last_external: u32,
+    /// Last used index of the internal chain
+    pub last_internal: u32,
+}
+
+/// Funds of a seed found by `scan_from_seed`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RecoveryReport {
+    pub tip_height: u32,
+    pub accounts: Vec<RecoveredAccount>,
+    /// Sum of the balances of the accounts
+    pub balance: HashMap<AssetId, u64>,
+}
+
+/// Scan the history of the accounts of `mnemonic` on `backend` without an existing store.
+///
+/// The BIP49 and BIP84 accounts, which are also the Blockstream Green singlesig subaccounts, are
+/// scanned in order until `ACCOUNT_GAP_LIMIT` account numbers without history for both address
+/// types, as well as `config.derivation` if set. Every account is synced in a fresh in-memory
+/// store, returned with the account, asking the history of its scripts up to the gap limit of
+/// unused addresses, also with `config.block_filters`, and unblinding its outputs. The tip is
+/// fetched once for all the accounts. The wallet data directory is not touched.
+pub fn scan_from_seed(
+    mnemonic: &str,
+    config: Config,
+    backend: &dyn BlockchainBackend,
+) -> Result<RecoveryReport, Error> {
+    let mut report = RecoveryReport::default();
+    let (tip_height, tip) = backend.tip()?;
+    report.tip_height = tip_height;
+    let mut scanned = vec![];
+    if let Some(derivation) = config.derivation.clone() {
+        scan_account(
+            mnemonic,
+            &config,
+            derivation.clone(),
+            backend,
+            &tip,
+            &mut report,
+        )?;
+        scanned.push(derivation);
+    }
+    let mut unused = 0;
+    for account in 0.. {
+        let mut used = false;
+        let derivations = [
+            DerivationTemplate::bip49(config.network(), account),
+            DerivationTemplate::bip84(config.network(), account),
+        ];
+        for derivation in derivations.iter().cloned() {
+            if scanned.contains(&derivation) {
+                used |= report.accounts.iter().any(|a| a.derivation == derivation);
+                continue;
+            }
+            used |= scan_account(mnemonic, &config, derivation, backend, &tip, &mut report)?;
+        }
+        unused = if used { 0 } else { unused + 1 };
+        if unused >= ACCOUNT_GAP_LIMIT {
+            break;
+        }
+    }
+    for account in report.accounts.iter() {
+        for (asset, satoshi) in account.balance.iter() {
+            add_amount(&mut report.balance, *asset, *satoshi)?;
+        }
+    }
+    Ok(report)
+}
+
+/// Sync the account of `mnemonic` at `derivation`, adding it to `report` if it has a history,
+/// returns whether it has one
+fn scan_account(
+    mnemonic: &str,
+    config: &Config,
+    derivation: DerivationTemplate,
+    backend: &dyn BlockchainBackend,
+    tip: &BlockHeader,
+    report: &mut RecoveryReport,
+) -> Result<bool, Error> {
+    let mut config = config.clone();
+    config.derivation = Some(derivation.clone());
+    let store = MemoryPersister::new();
+    let wallet = WalletCtx::from_mnemonic_with_store(mnemonic, Box::new(store.clone()), config)?;
+    let tipper = Tipper {
+        store: wallet.store.clone(),
+        config: wallet.config.clone(),
+    };
+    tipper.set_tip(report.tip_height, tip.clone())?;
+    let syncer = Syncer {
+        store: wallet.store.clone(),
+        blinder: wallet.blinder.clone(),
+        config: wallet.config.clone(),
+        cancel: CancelToken::new(),
+        progress: Arc::new(ProgressReporter::default()),
+        secp: secp256k1::Secp256k1::new(),
+    };
+    syncer.sync(backend)?;
+
+    let store_read = wallet.store.read()?;
+    let transactions = store_read.cache.heights.len();
+    info!(
+        "recovery scanned {:?}, {} transactions",
+        derivation.account_path, transactions
+    );
+    if transactions == 0 {
+        return Ok(false);
+    }
+    let last_used = store_read.cache.indexes.clone();
+    store_read.flush()?;
+    drop(store_read);
+    report.accounts.push(RecoveredAccount {
+        derivation,
+        store,
+        balance: wallet.balance()?,
+        transactions,
+        utxos: wallet.utxos()?.len(),
+        last_external: last_used.external,
+        last_internal: last_used.internal,
+    });
+    Ok(true)
+}
@@ -0,0 +1,56 @@
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REDACTION: AtomicBool = AtomicBool::new(false);
+
+/// When enabled, addresses and amounts are logged as `<redacted>`.
+pub fn set_log_redaction(enabled: bool) {
+    REDACTION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn log_redaction() -> bool {
+    REDACTION.load(Ordering::Relaxed)
+}
+
+/// Wrapper of a value to log, formatted as `<redacted>` if redaction is enabled
+pub(crate) struct Redacted<T>(T);
+
+pub(crate) fn redact<T>(value: T) -> Redacted<T> {
+    Redacted(value)
+}
+
+impl<T: Display> Display for Redacted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if log_redaction() {
+            write!(f, "<redacted>")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+impl<T: Debug> Debug for Redacted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if log_redaction() {
+            write!(f, "<redacted>")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(format!("{} {:?}", redact(10), redact("a")), "10 \"a\"");
+        set_log_redaction(true);
+        assert_eq!(
+            format!("{} {:?}", redact(10), redact("a")),
+            "<redacted> <redacted>"
+        );
+        set_log_redaction(false);
+    }
+}
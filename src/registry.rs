@@ -0,0 +1,177 @@
+#[cfg(feature = "registry")]
+use crate::error::Error;
+
+use elements::bitcoin::hashes::hex::FromHex;
+use elements::issuance::AssetId;
+use elements::Txid;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "registry")]
+use std::collections::HashMap;
+
+/// Liquid Bitcoin, the policy asset of Liquid
+const LBTC: &str = "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+
+/// Tether USD on Liquid
+const USDT: &str = "ce091c998b83c78bb71a632313ba3760f1763d9cfcffae02258ffa9865a37bd2";
+
+/// Metadata of an asset registered in an asset registry, such as
+/// https://assets.blockstream.info
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AssetMetadata {
+    pub name: String,
+    pub ticker: Option<String>,
+    /// Digits after the decimal point when displaying the amounts
+    pub precision: u8,
+    /// Domain of the issuer
+    pub domain: Option<String>,
+    /// Base64 PNG icon, if fetched
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// Metadata as returned by the registry with the ETag of the response, to refresh it only if
+/// changed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedAssetMetadata {
+    pub metadata: AssetMetadata,
+    pub etag: Option<String>,
+    /// Txid and input index of the issuance of the asset, giving its reissuance token
+    #[serde(default)]
+    pub issuance_txin: Option<(Txid, u32)>,
+}
+
+/// Metadata of the popular Liquid assets, available before the registry is ever reached
+pub fn embedded_metadata(asset: &AssetId) -> Option<AssetMetadata> {
+    let (name, ticker, domain) = if *asset == AssetId::from_hex(LBTC).ok()? {
+        ("Liquid Bitcoin", "L-BTC", None)
+    } else if *asset == AssetId::from_hex(USDT).ok()? {
+        ("Tether USD", "USDt", Some("tether.to"))
+    } else {
+        return None;
+    };
+    Some(AssetMetadata {
+        name: name.into(),
+        ticker: Some(ticker.into()),
+        precision: 8,
+        domain: domain.map(Into::into),
+        icon: None,
+    })
+}
+
+#[cfg(feature = "registry")]
+#[derive(Deserialize)]
+struct RegistryAsset {
+    name: String,
+    ticker: Option<String>,
+    precision: u8,
+    entity: Option<RegistryEntity>,
+    issuance_txin: Option<RegistryTxIn>,
+}
+
+#[cfg(feature = "registry")]
+#[derive(Deserialize)]
+struct RegistryTxIn {
+    txid: Txid,
+    vin: u32,
+}
+
+#[cfg(feature = "registry")]
+#[derive(Deserialize)]
+struct RegistryEntity {
+    domain: Option<String>,
+}
+
+/// Client for an asset registry HTTP API, metadata are at `<url>/<asset id>` and the icons of
+/// all the assets at `<url>/icons.json`
+#[cfg(feature = "registry")]
+pub struct RegistryClient {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "registry")]
+impl RegistryClient {
+    pub fn new(url: &str, timeout: Option<u8>, user_agent: Option<&str>) -> Self {
+        RegistryClient {
+            url: url.trim_end_matches('/').to_string(),
+            agent: crate::backend::http_agent(timeout, user_agent),
+        }
+    }
+
+    /// Body and ETag of `path`, None if unchanged since the response with `etag` or not found
+    fn get(
+        &self,
+        path: &str,
+        etag: Option<&str>,
+    ) -> Result<Option<(String, Option<String>)>, Error> {
+        let url = format!("{}{}", self.url, path);
+        let mut request = self.agent.get(&url);
+        if let Some(etag) = etag {
+            request = request.set("If-None-Match", etag);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if response.status() == 304 {
+            return Ok(None);
+        }
+        let etag = response.header("ETag").map(Into::into);
+        Ok(Some((response.into_string()?, etag)))
+    }
+
+    /// Metadata of `asset`, None if unchanged since the response with `etag` or if the asset is
+    /// not registered
+    pub fn metadata(
+        &self,
+        asset: &AssetId,
+        etag: Option<&str>,
+    ) -> Result<Option<CachedAssetMetadata>, Error> {
+        let (body, etag) = match self.get(&format!("/{}", asset), etag)? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+        let asset: RegistryAsset = serde_json::from_str(&body)?;
+        Ok(Some(CachedAssetMetadata {
+            metadata: AssetMetadata {
+                name: asset.name,
+                ticker: asset.ticker,
+                precision: asset.precision,
+                domain: asset.entity.and_then(|e| e.domain),
+                icon: None,
+            },
+            etag,
+            issuance_txin: asset.issuance_txin.map(|txin| (txin.txid, txin.vin)),
+        }))
+    }
+
+    /// Base64 icons of the registered assets and the ETag of the bundle, None if unchanged since
+    /// the response with `etag`
+    pub fn icons(
+        &self,
+        etag: Option<&str>,
+    ) -> Result<Option<(HashMap<AssetId, String>, Option<String>)>, Error> {
+        match self.get("/icons.json", etag)? {
+            Some((body, etag)) => Ok(Some((serde_json::from_str(&body)?, etag))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_metadata() {
+        let lbtc = AssetId::from_hex(LBTC).unwrap();
+        assert_eq!(embedded_metadata(&lbtc).unwrap().ticker.unwrap(), "L-BTC");
+        let usdt = AssetId::from_hex(USDT).unwrap();
+        assert_eq!(
+            embedded_metadata(&usdt).unwrap().domain.unwrap(),
+            "tether.to"
+        );
+        assert!(embedded_metadata(&AssetId::default()).is_none());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::backend::{BlockchainBackend, History};
+use crate::error::Error;
+use crate::model::FeeEstimate;
+
+use electrum_client::GetMerkleRes;
+use elements::{BlockHash, BlockHeader, Script, Txid};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Retries of the requests to the server failing with transient errors, such as a dropped
+/// connection, and circuit breaker stopping the requests to a server failing repeatedly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first failed request
+    pub max_retries: u32,
+    /// Milliseconds waited before the first retry, doubled at every further retry
+    pub base_delay_ms: u64,
+    /// Maximum milliseconds waited between two retries, before the jitter
+    pub max_delay_ms: u64,
+    /// Consecutive failed requests opening the circuit, 0 to never open it
+    pub breaker_threshold: u32,
+    /// Seconds the open circuit fails the requests without reaching the server, then a request
+    /// is tried again
+    pub breaker_cooldown_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 5_000,
+            breaker_threshold: 5,
+            breaker_cooldown_secs: 30,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Milliseconds to wait before retry number `retry`, starting from 1, without jitter
+    pub fn delay_ms(&self, retry: u32) -> u64 {
+        let exp = retry.saturating_sub(1).min(16);
+        self.base_delay_ms
+            .checked_mul(1 << exp)
+            .unwrap_or(u64::MAX)
+            .min(self.max_delay_ms)
+    }
+}
+
+/// Longest cooldown of an open circuit, longer `RetryPolicy::breaker_cooldown_secs` may not be
+/// representable as an `Instant`
+const MAX_BREAKER_COOLDOWN: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Circuit breakers by server, shared by the clients built from the same `Config`
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CircuitBreakers(Arc<Mutex<HashMap<String, BreakerState>>>);
+
+impl CircuitBreakers {
+    fn check(&self, server: &str) -> Result<(), Error> {
+        let breakers = self.lock()?;
+        match breakers.get(server).and_then(|b| b.open_until) {
+            Some(open_until) if Instant::now() < open_until => {
+                Err(Error::CircuitOpen(server.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record(&self, server: &str, success: bool, policy: &RetryPolicy) -> Result<(), Error> {
+        let mut breakers = self.lock()?;
+        let state = breakers.entry(server.to_string()).or_default();
+        if success {
+            *state = BreakerState::default();
+        } else {
+            state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+            if policy.breaker_threshold > 0
+                && state.consecutive_failures >= policy.breaker_threshold
+            {
+                warn!("too many failures, opening the circuit of {}", server);
+                let cooldown = Duration::from_secs(policy.breaker_cooldown_secs);
+                let now = Instant::now();
+                state.open_until = now
+                    .checked_add(cooldown.min(MAX_BREAKER_COOLDOWN))
+                    .or(Some(now));
+            }
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, BreakerState>>, Error> {
+        self.0
+            .lock()
+            .map_err(|_| Error::Generic("circuit breakers poisoned".into()))
+    }
+}
+
+/// Whether the request failing with `error` could succeed if sent again
+fn is_transient(error: &Error) -> bool {
+    match error {
+        Error::StdIOError(_) => true,
+        // protocol errors are answers of the server, such as a rejected transaction
+        Error::ClientError(electrum_client::Error::Protocol(_)) => false,
+        Error::ClientError(_) => true,
+        Error::HttpTransport(_) => true,
+        _ => false,
+    }
+}
+
+/// Backend retrying the requests of `inner` according to `policy`
+pub(crate) struct RetryingBackend {
+    inner: Box<dyn BlockchainBackend>,
+    policy: RetryPolicy,
+    server: String,
+    breakers: CircuitBreakers,
+}
+
+impl RetryingBackend {
+    pub fn new(
+        inner: Box<dyn BlockchainBackend>,
+        policy: RetryPolicy,
+        server: String,
+        breakers: CircuitBreakers,
+    ) -> Self {
+        RetryingBackend {
+            inner,
+            policy,
+            server,
+            breakers,
+        }
+    }
+
+    fn retry<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: Fn(&dyn BlockchainBackend) -> Result<T, Error>,
+    {
+        self.breakers.check(&self.server)?;
+        let mut retry = 0;
+        loop {
+            let result = f(&*self.inner);
+            match &result {
+                Err(e) if is_transient(e) => {
+                    self.breakers.record(&self.server, false, &self.policy)?;
+                    if retry >= self.policy.max_retries {
+                        return result;
+                    }
+                    self.breakers.check(&self.server)?;
+                    retry += 1;
+                    let delay = self.policy.delay_ms(retry);
+                    let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+                    let delay = delay.saturating_add(jitter);
+                    warn!("request failed with {}, retry {} in {}ms", e, retry, delay);
+                    thread::sleep(Duration::from_millis(delay));
+                }
+                _ => {
+                    self.breakers.record(&self.server, true, &self.policy)?;
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+impl BlockchainBackend for RetryingBackend {
+    fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+        self.retry(|b| b.tip())
+    }
+
+    fn scripts_history(&self, scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+        self.retry(|b| b.scripts_history(scripts))
+    }
+
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+        self.retry(|b| b.transactions(txids))
+    }
+
+    fn headers(&self, heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+        self.retry(|b| b.headers(heights))
+    }
+
+    fn merkle_proof(&self, txid: &Txid, height: u32) -> Result<GetMerkleRes, Error> {
+        self.retry(|b| b.merkle_proof(txid, height))
+    }
+
+    fn broadcast(&self, tx: &elements::Transaction) -> Result<(), Error> {
+        self.retry(|b| b.broadcast(tx))
+    }
+
+    fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+        self.retry(|b| b.fee_estimates())
+    }
+
+    fn block_filter(&self, height: u32) -> Result<(BlockHash, Vec<u8>), Error> {
+        self.retry(|b| b.block_filter(height))
+    }
+
+    fn block(&self, height: u32) -> Result<elements::Block, Error> {
+        self.retry(|b| b.block(height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Backend failing the first `failures` fee estimates requests with `error`
+    struct FlakyBackend {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+        error: fn() -> Error,
+    }
+
+    impl BlockchainBackend for FlakyBackend {
+        fn tip(&self) -> Result<(u32, BlockHeader), Error> {
+            unimplemented!()
+        }
+
+        fn scripts_history(&self, _scripts: &[Script]) -> Result<Vec<Vec<History>>, Error> {
+            unimplemented!()
+        }
+
+        fn transactions(&self, _txids: &[Txid]) -> Result<Vec<elements::Transaction>, Error> {
+            unimplemented!()
+        }
+
+        fn headers(&self, _heights: &[u32]) -> Result<Vec<BlockHeader>, Error> {
+            unimplemented!()
+        }
+
+        fn merkle_proof(&self, _txid: &Txid, _height: u32) -> Result<GetMerkleRes, Error> {
+            unimplemented!()
+        }
+
+        fn broadcast(&self, _tx: &elements::Transaction) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn fee_estimates(&self) -> Result<Vec<FeeEstimate>, Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err((self.error)())
+            } else {
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// Calls made by a request to a backend failing `failures` times with `error`
+    fn retried(policy: RetryPolicy, failures: u32, error: fn() -> Error) -> (bool, u32) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let backend = RetryingBackend::new(
+            Box::new(FlakyBackend {
+                calls: calls.clone(),
+                failures,
+                error,
+            }),
+            policy,
+            "server".into(),
+            CircuitBreakers::default(),
+        );
+        let ok = backend.fee_estimates().is_ok();
+        (ok, calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn test_retrying_backend() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay_ms: 1,
+            max_delay_ms: 1,
+            breaker_threshold: 0,
+            breaker_cooldown_secs: 0,
+        };
+        let io = || Error::StdIOError(std::io::ErrorKind::ConnectionReset.into());
+        let http = || Error::HttpTransport("connection refused".into());
+        assert_eq!(retried(policy, 0, io), (true, 1));
+        assert_eq!(retried(policy, 3, io), (true, 4));
+        assert_eq!(retried(policy, 2, http), (true, 3));
+        assert_eq!(retried(policy, 4, http), (false, 4));
+        // errors answered by the server are not retried
+        assert_eq!(retried(policy, 2, || Error::InvalidHeaders), (false, 1));
+
+        // the open circuit stops the retries
+        let breaking = RetryPolicy {
+            breaker_threshold: 2,
+            breaker_cooldown_secs: 60,
+            ..policy
+        };
+        assert_eq!(retried(breaking, 4, io), (false, 2));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay_ms(1), 200);
+        assert_eq!(policy.delay_ms(3), 800);
+        assert_eq!(policy.delay_ms(u32::MAX), policy.max_delay_ms);
+        let unbounded = RetryPolicy {
+            base_delay_ms: u64::MAX / 2,
+            max_delay_ms: u64::MAX,
+            ..policy
+        };
+        assert_eq!(unbounded.delay_ms(2), u64::MAX - 1);
+        assert_eq!(unbounded.delay_ms(3), u64::MAX);
+
+        let breakers = CircuitBreakers::default();
+        for _ in 0..policy.breaker_threshold - 1 {
+            breakers.record("server", false, &policy).unwrap();
+        }
+        assert!(breakers.check("server").is_ok());
+        breakers.record("server", false, &policy).unwrap();
+        assert!(matches!(
+            breakers.check("server"),
+            Err(Error::CircuitOpen(_))
+        ));
+        assert!(breakers.check("other").is_ok());
+        breakers.record("server", true, &policy).unwrap();
+        assert!(breakers.check("server").is_ok());
+
+        let endless = RetryPolicy {
+            breaker_threshold: 1,
+            breaker_cooldown_secs: u64::MAX,
+            ..policy
+        };
+        breakers.record("server", false, &endless).unwrap();
+        assert!(matches!(
+            breakers.check("server"),
+            Err(Error::CircuitOpen(_))
+        ));
+        breakers
+            .lock()
+            .unwrap()
+            .get_mut("server")
+            .unwrap()
+            .consecutive_failures = u32::MAX;
+        breakers.record("server", false, &endless).unwrap();
+        assert_eq!(
+            breakers.lock().unwrap()["server"].consecutive_failures,
+            u32::MAX
+        );
+    }
+}
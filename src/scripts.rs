@@ -1,8 +1,12 @@
 use elements::bitcoin::hash_types::PubkeyHash;
 use elements::bitcoin::hashes::Hash;
 use elements::bitcoin::PublicKey;
-use elements::script::Builder;
+use elements::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIG, OP_PUSHNUM_1, OP_PUSHNUM_16, OP_RETURN,
+};
+use elements::script::{Builder, Instruction};
 use elements::{Address, AddressParams, Script};
+use serde::{Deserialize, Serialize};
 
 // The following scripts are always using regtest network,
 // it is always ok because I am not interested in the address just in the script
@@ -15,6 +19,110 @@ pub fn p2pkh_script(pk: &PublicKey) -> Script {
     Address::p2pkh(pk, None, &AddressParams::ELEMENTS).script_pubkey()
 }
 
+pub fn p2wpkh_script(pk: &PublicKey) -> Script {
+    Address::p2wpkh(pk, None, &AddressParams::ELEMENTS).script_pubkey()
+}
+
+/// Script of a peg-out output to `mainchain_script` on the chain with `parent_genesis_hash`
+pub fn pegout_script(
+    parent_genesis_hash: &elements::bitcoin::BlockHash,
+    mainchain_script: &elements::bitcoin::Script,
+) -> Script {
+    Builder::new()
+        .push_opcode(elements::opcodes::all::OP_RETURN)
+        .push_slice(&parent_genesis_hash[..])
+        .push_slice(mainchain_script.as_bytes())
+        .into_script()
+}
+
+/// Whether `script` is the script of a peg-out output, `OP_RETURN <genesis hash> <script>`,
+/// like Elements it allows more pushes after the mainchain script, such as the PAK proof
+pub fn is_pegout_script(script: &Script) -> bool {
+    let mut instructions = script.instructions();
+    match (
+        instructions.next(),
+        instructions.next(),
+        instructions.next(),
+    ) {
+        (
+            Some(Ok(Instruction::Op(OP_RETURN))),
+            Some(Ok(Instruction::PushBytes(genesis_hash))),
+            Some(Ok(Instruction::PushBytes(_))),
+        ) => genesis_hash.len() == 32,
+        _ => false,
+    }
+}
+
+/// Largest OP_RETURN output relayed by the nodes, `-datacarriersize`
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// Whether the nodes relay outputs with `script`, following the standard script templates of
+/// Elements: p2pk, p2pkh, p2sh, bare multisig up to 3 keys, OP_RETURN data carriers and
+/// witness programs, with unknown witness versions standard as well.
+pub fn is_standard_script(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    if script.is_op_return() {
+        let mut instructions = script.instructions();
+        instructions.next();
+        return bytes.len() <= MAX_OP_RETURN_RELAY
+            && instructions.all(|i| match i {
+                Ok(Instruction::PushBytes(_)) => true,
+                Ok(Instruction::Op(op)) => op.into_u8() <= OP_PUSHNUM_16.into_u8(),
+                Err(_) => false,
+            });
+    }
+    if script.is_p2pkh() || script.is_p2sh() {
+        return true;
+    }
+    if (4..=42).contains(&bytes.len()) && bytes[1] as usize == bytes.len() - 2 {
+        let program_len = bytes.len() - 2;
+        if bytes[0] == 0 {
+            return program_len == 20 || program_len == 32;
+        }
+        if small_int(bytes[0]).is_some() {
+            return true;
+        }
+    }
+    let instructions: Vec<_> = match script.instructions().collect::<Result<_, _>>() {
+        Ok(instructions) => instructions,
+        Err(_) => return false,
+    };
+    match instructions.as_slice() {
+        [Instruction::PushBytes(key), Instruction::Op(OP_CHECKSIG)] => is_pubkey(key),
+        [Instruction::Op(m), keys @ .., Instruction::Op(n), Instruction::Op(OP_CHECKMULTISIG)] => {
+            let (m, n) = match (small_int(m.into_u8()), small_int(n.into_u8())) {
+                (Some(m), Some(n)) => (m, n),
+                _ => return false,
+            };
+            let keys = keys.iter().all(|key| match key {
+                Instruction::PushBytes(key) => is_pubkey(key),
+                _ => false,
+            });
+            keys && n as usize == instructions.len() - 3 && (1..=n).contains(&m) && n <= 3
+        }
+        _ => false,
+    }
+}
+
+/// Value of the opcodes pushing 1 to 16
+fn small_int(opcode: u8) -> Option<u8> {
+    let first = OP_PUSHNUM_1.into_u8();
+    if (first..=OP_PUSHNUM_16.into_u8()).contains(&opcode) {
+        Some(opcode - first + 1)
+    } else {
+        None
+    }
+}
+
+/// Whether `bytes` have the size and the prefix of a public key
+fn is_pubkey(bytes: &[u8]) -> bool {
+    match bytes.len() {
+        33 => bytes[0] == 0x02 || bytes[0] == 0x03,
+        65 => bytes[0] == 0x04 || bytes[0] == 0x06 || bytes[0] == 0x07,
+        _ => false,
+    }
+}
+
 pub fn p2shwpkh_script_sig(public_key: &PublicKey) -> Script {
     let internal = Builder::new()
         .push_int(0)
@@ -22,3 +130,175 @@ pub fn p2shwpkh_script_sig(public_key: &PublicKey) -> Script {
         .into_script();
     Builder::new().push_slice(internal.as_bytes()).into_script()
 }
+
+/// Type of the single key addresses of the wallet, P2SH-P2WPKH unless the wallet is restored
+/// from software using native segwit addresses, see `DerivationTemplate`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressType {
+    P2shP2wpkh,
+    P2wpkh,
+}
+
+impl Default for AddressType {
+    fn default() -> Self {
+        AddressType::P2shP2wpkh
+    }
+}
+
+impl AddressType {
+    pub fn script_pubkey(&self, pk: &PublicKey) -> Script {
+        match self {
+            AddressType::P2shP2wpkh => p2shwpkh_script(pk),
+            AddressType::P2wpkh => p2wpkh_script(pk),
+        }
+    }
+
+    pub fn address(
+        &self,
+        pk: &PublicKey,
+        blinder: Option<elements::bitcoin::secp256k1::PublicKey>,
+        params: &'static AddressParams,
+    ) -> Address {
+        match self {
+            AddressType::P2shP2wpkh => Address::p2shwpkh(pk, blinder, params),
+            AddressType::P2wpkh => Address::p2wpkh(pk, blinder, params),
+        }
+    }
+
+    /// Script sig of the inputs spending outputs of `pk`, the signature is in the witness
+    pub fn script_sig(&self, pk: &PublicKey) -> Script {
+        match self {
+            AddressType::P2shP2wpkh => p2shwpkh_script_sig(pk),
+            AddressType::P2wpkh => Script::new(),
+        }
+    }
+}
+
+/// Kind of an output script, outputs with scripts of `Unknown` kind, such as future witness
+/// versions, are kept in the store without being interpreted
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptKind {
+    /// Empty script of the fee outputs
+    Fee,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    OpReturn,
+    Unknown,
+}
+
+pub fn script_kind(script: &Script) -> ScriptKind {
+    if script.is_empty() {
+        ScriptKind::Fee
+    } else if script.is_p2pkh() {
+        ScriptKind::P2pkh
+    } else if script.is_p2sh() {
+        ScriptKind::P2sh
+    } else if script.is_v0_p2wpkh() {
+        ScriptKind::P2wpkh
+    } else if script.is_v0_p2wsh() {
+        ScriptKind::P2wsh
+    } else if script.is_op_return() {
+        ScriptKind::OpReturn
+    } else {
+        ScriptKind::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_is_pegout_script() {
+        let genesis_hash = elements::bitcoin::BlockHash::default();
+        let mainchain_script = elements::bitcoin::Script::from(vec![0x51]);
+        let pegout = pegout_script(&genesis_hash, &mainchain_script);
+        assert!(is_pegout_script(&pegout));
+        let empty = pegout_script(&genesis_hash, &elements::bitcoin::Script::new());
+        assert!(is_pegout_script(&empty));
+        let with_proof = Builder::from(pegout.to_bytes())
+            .push_slice(&[2u8; 33])
+            .into_script();
+        assert!(is_pegout_script(&with_proof));
+
+        let op_return = Builder::new().push_opcode(OP_RETURN);
+        assert!(!is_pegout_script(&op_return.clone().into_script()));
+        let short = op_return.clone().push_slice(&[1u8; 20]).push_slice(&[0x51]);
+        assert!(!is_pegout_script(&short.into_script()));
+        let no_script = op_return.clone().push_slice(&[1u8; 32]);
+        assert!(!is_pegout_script(&no_script.clone().into_script()));
+        let opcode = no_script.push_opcode(elements::opcodes::all::OP_CHECKSIG);
+        assert!(!is_pegout_script(&opcode.into_script()));
+        let not_op_return = Builder::new()
+            .push_slice(&[1u8; 32])
+            .push_slice(&[0x51])
+            .into_script();
+        assert!(!is_pegout_script(&not_op_return));
+    }
+
+    #[test]
+    fn test_is_standard_script() {
+        let pk = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        assert!(is_standard_script(&p2pkh_script(&pk)));
+        assert!(is_standard_script(&p2shwpkh_script(&pk)));
+        assert!(is_standard_script(&p2wpkh_script(&pk)));
+        let p2pk = Builder::new()
+            .push_key(&pk)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert!(is_standard_script(&p2pk));
+        let multisig = |m, n| {
+            let mut builder = Builder::new().push_int(m);
+            for _ in 0..n {
+                builder = builder.push_key(&pk);
+            }
+            builder
+                .push_int(n)
+                .push_opcode(OP_CHECKMULTISIG)
+                .into_script()
+        };
+        assert!(is_standard_script(&multisig(1, 1)));
+        assert!(is_standard_script(&multisig(2, 3)));
+        assert!(!is_standard_script(&multisig(3, 2)));
+        assert!(!is_standard_script(&multisig(1, 4)));
+        let witness = |version, len| {
+            Builder::new()
+                .push_int(version)
+                .push_slice(&vec![1u8; len])
+                .into_script()
+        };
+        assert!(is_standard_script(&witness(0, 32)));
+        assert!(!is_standard_script(&witness(0, 25)));
+        assert!(is_standard_script(&witness(1, 32)));
+        assert!(is_standard_script(&witness(16, 2)));
+
+        let op_return = Builder::new().push_opcode(OP_RETURN);
+        assert!(is_standard_script(&op_return.clone().into_script()));
+        let data = op_return.clone().push_slice(&[1u8; 80]).into_script();
+        assert!(is_standard_script(&data));
+        let large = op_return.clone().push_slice(&[1u8; 81]).into_script();
+        assert!(!is_standard_script(&large));
+        let not_push = op_return.push_opcode(OP_CHECKSIG).into_script();
+        assert!(!is_standard_script(&not_push));
+        let pegout = pegout_script(
+            &elements::bitcoin::BlockHash::default(),
+            &elements::bitcoin::Script::from(p2pkh_script(&pk).to_bytes()),
+        );
+        assert!(is_standard_script(&pegout));
+
+        assert!(!is_standard_script(
+            &Builder::new().push_int(1).into_script()
+        ));
+        let bad_key = Builder::new()
+            .push_slice(&[5u8; 33])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        assert!(!is_standard_script(&bad_key));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::error::Error;
+
+use elements::bitcoin::hashes::{sha256d, Hash, HashEngine};
+use elements::confidential::Value;
+use elements::encode::serialize;
+use elements::{Script, SigHash, SigHashType, Transaction};
+
+/// Flag of the signatures committing to the rangeproofs and surjection proofs of the outputs,
+/// consensus valid only once dynamic federations are active
+pub const SIGHASH_RANGEPROOF: u8 = 0x40;
+
+const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// Sighash type of a wallet signature: an `elements::SigHashType`, optionally with the
+/// Elements specific `SIGHASH_RANGEPROOF` flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigHashFlags {
+    sighash_type: SigHashType,
+    rangeproof: bool,
+}
+
+impl SigHashFlags {
+    pub fn new(sighash_type: SigHashType, rangeproof: bool) -> Self {
+        SigHashFlags {
+            sighash_type,
+            rangeproof,
+        }
+    }
+
+    /// Parse the sighash byte appended to the signatures, rejecting the undefined types and,
+    /// unless `dynafed_active`, the `SIGHASH_RANGEPROOF` flag
+    pub fn from_u8(n: u8, dynafed_active: bool) -> Result<Self, Error> {
+        let rangeproof = n & SIGHASH_RANGEPROOF != 0;
+        let sighash_type = match n & !SIGHASH_RANGEPROOF {
+            0x01 => SigHashType::All,
+            0x02 => SigHashType::None,
+            0x03 => SigHashType::Single,
+            0x81 => SigHashType::AllPlusAnyoneCanPay,
+            0x82 => SigHashType::NonePlusAnyoneCanPay,
+            0x83 => SigHashType::SinglePlusAnyoneCanPay,
+            _ => return Err(Error::InvalidSigHashType(n)),
+        };
+        let flags = SigHashFlags::new(sighash_type, rangeproof);
+        flags.check(dynafed_active)?;
+        Ok(flags)
+    }
+
+    /// Byte appended to the signatures
+    pub fn as_u8(&self) -> u8 {
+        let rangeproof = if self.rangeproof {
+            SIGHASH_RANGEPROOF
+        } else {
+            0
+        };
+        self.sighash_type as u8 | rangeproof
+    }
+
+    pub fn sighash_type(&self) -> SigHashType {
+        self.sighash_type
+    }
+
+    pub fn rangeproof(&self) -> bool {
+        self.rangeproof
+    }
+
+    /// Check the flags are consensus valid on a network with dynamic federations active or not
+    pub fn check(&self, dynafed_active: bool) -> Result<(), Error> {
+        if self.rangeproof && !dynafed_active {
+            return Err(Error::InvalidSigHashType(self.as_u8()));
+        }
+        Ok(())
+    }
+
+    fn anyone_can_pay(&self) -> bool {
+        self.sighash_type as u8 & SIGHASH_ANYONECANPAY != 0
+    }
+
+    fn base(&self) -> u8 {
+        self.sighash_type as u8 & !SIGHASH_ANYONECANPAY
+    }
+}
+
+impl From<SigHashType> for SigHashFlags {
+    fn from(sighash_type: SigHashType) -> Self {
+        SigHashFlags::new(sighash_type, false)
+    }
+}
+
+/// Segwit v0 sighash of input `input_index` of `tx` spending an output of `value`, as computed
+/// by Elements Core: with `SIGHASH_RANGEPROOF` the proofs of the outputs signed are committed
+/// after the outputs
+pub fn segwitv0_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    script_code: &Script,
+    value: Value,
+    flags: SigHashFlags,
+) -> SigHash {
+    if !flags.rangeproof {
+        return elements::sighash::SigHashCache::new(tx).segwitv0_sighash(
+            input_index,
+            script_code,
+            value,
+            flags.sighash_type,
+        );
+    }
+
+    let zero = sha256d::Hash::from_inner([0u8; 32]);
+    let hash_all = |bytes: &mut dyn Iterator<Item = Vec<u8>>| {
+        let mut engine = sha256d::Hash::engine();
+        for b in bytes {
+            engine.input(&b);
+        }
+        sha256d::Hash::from_engine(engine)
+    };
+    let is_single = flags.base() == SigHashType::Single as u8;
+    let is_none = flags.base() == SigHashType::None as u8;
+
+    let (hash_prevouts, hash_sequence, hash_issuances) = if flags.anyone_can_pay() {
+        (zero, zero, zero)
+    } else {
+        let hash_prevouts = hash_all(&mut tx.input.iter().map(|i| serialize(&i.previous_output)));
+        let hash_sequence = if is_single || is_none {
+            zero
+        } else {
+            hash_all(&mut tx.input.iter().map(|i| serialize(&i.sequence)))
+        };
+        let hash_issuances = hash_all(&mut tx.input.iter().map(|i| {
+            if i.has_issuance() {
+                serialize(&i.asset_issuance)
+            } else {
+                vec![0u8]
+            }
+        }));
+        (hash_prevouts, hash_sequence, hash_issuances)
+    };
+
+    // proofs of a missing output are serialized as empty
+    let proofs = |index: usize| -> Vec<u8> {
+        let (rangeproof, surjection_proof) = match tx.output.get(index) {
+            Some(output) => (
+                output
+                    .witness
+                    .rangeproof
+                    .as_ref()
+                    .map(|p| p.serialize())
+                    .unwrap_or_default(),
+                output
+                    .witness
+                    .surjection_proof
+                    .as_ref()
+                    .map(|p| p.serialize())
+                    .unwrap_or_default(),
+            ),
+            None => (vec![], vec![]),
+        };
+        let mut bytes = serialize(&rangeproof);
+        bytes.extend(serialize(&surjection_proof));
+        bytes
+    };
+    let (hash_outputs, hash_rangeproofs) = if !is_single && !is_none {
+        (
+            hash_all(&mut tx.output.iter().map(serialize)),
+            hash_all(&mut (0..tx.output.len()).map(&proofs)),
+        )
+    } else if is_single && input_index < tx.output.len() {
+        (
+            hash_all(&mut std::iter::once(serialize(&tx.output[input_index]))),
+            hash_all(&mut std::iter::once(proofs(input_index))),
+        )
+    } else {
+        (zero, zero)
+    };
+
+    let input = &tx.input[input_index];
+    let mut engine = SigHash::engine();
+    engine.input(&serialize(&tx.version));
+    engine.input(&hash_prevouts[..]);
+    engine.input(&hash_sequence[..]);
+    engine.input(&hash_issuances[..]);
+    engine.input(&serialize(&input.previous_output));
+    engine.input(&serialize(script_code));
+    engine.input(&serialize(&value));
+    engine.input(&serialize(&input.sequence));
+    if input.has_issuance() {
+        engine.input(&serialize(&input.asset_issuance));
+    }
+    engine.input(&hash_outputs[..]);
+    engine.input(&hash_rangeproofs[..]);
+    engine.input(&serialize(&tx.lock_time));
+    engine.input(&serialize(&(flags.as_u8() as u32)));
+    SigHash::from_engine(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::bitcoin::hashes::hex::FromHex;
+
+    fn tx() -> Transaction {
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for i in 0..2u32 {
+            crate::transaction::add_input(
+                &mut tx,
+                elements::OutPoint {
+                    txid: elements::Txid::from_hex(
+                        "0101010101010101010101010101010101010101010101010101010101010101",
+                    )
+                    .unwrap(),
+                    vout: i,
+                },
+            );
+        }
+        let asset = elements::issuance::AssetId::from_slice(&[2u8; 32]).unwrap();
+        crate::transaction::add_fee_output(
+            &mut tx,
+            1_000,
+            &Some(elements::confidential::Asset::Explicit(asset)),
+        )
+        .unwrap();
+        tx
+    }
+
+    /// `tx` paying also an output with a rangeproof and a surjection proof, before the fee
+    fn tx_with_proofs() -> Transaction {
+        let mut tx = tx();
+        let asset = elements::issuance::AssetId::from_slice(&[2u8; 32]).unwrap();
+        let mut script = vec![0x00, 0x14];
+        script.extend(&[3u8; 20]);
+        let mut output = elements::TxOut {
+            asset: elements::confidential::Asset::Explicit(asset),
+            value: Value::Explicit(5_000),
+            script_pubkey: Script::from(script),
+            ..Default::default()
+        };
+        // proofs only need to parse, they are hashed and not verified
+        let mut rangeproof = vec![0u8];
+        rangeproof.extend(&[4u8; 64]);
+        let mut surjection_proof = vec![1u8, 0, 1];
+        surjection_proof.extend(&[5u8; 64]);
+        output.witness.rangeproof =
+            Some(elements::secp256k1_zkp::RangeProof::from_slice(&rangeproof).unwrap());
+        output.witness.surjection_proof =
+            Some(elements::secp256k1_zkp::SurjectionProof::from_slice(&surjection_proof).unwrap());
+        tx.output.insert(0, output);
+        tx
+    }
+
+    #[test]
+    fn test_sighash_flags() {
+        for n in [0x01u8, 0x02, 0x03, 0x81, 0x82, 0x83].iter() {
+            assert_eq!(SigHashFlags::from_u8(*n, false).unwrap().as_u8(), *n);
+            let with_rangeproof = SigHashFlags::from_u8(*n | SIGHASH_RANGEPROOF, true).unwrap();
+            assert!(with_rangeproof.rangeproof());
+            assert_eq!(with_rangeproof.as_u8(), *n | SIGHASH_RANGEPROOF);
+            assert!(SigHashFlags::from_u8(*n | SIGHASH_RANGEPROOF, false).is_err());
+        }
+        for n in [0x00u8, 0x04, 0x20, 0x80, 0x84, 0xc0].iter() {
+            assert!(SigHashFlags::from_u8(*n, true).is_err());
+        }
+        assert!(SigHashFlags::new(SigHashType::All, true)
+            .check(false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_segwitv0_sighash() {
+        let tx = tx();
+        let script_code = Script::from(vec![0x51]);
+        let value = Value::Explicit(1_000);
+        let all = SigHashFlags::from(SigHashType::All);
+        assert_eq!(
+            segwitv0_sighash(&tx, 0, &script_code, value, all),
+            elements::sighash::SigHashCache::new(&tx).segwitv0_sighash(
+                0,
+                &script_code,
+                value,
+                SigHashType::All
+            )
+        );
+
+        // the rangeproof flag commits to the output proofs
+        let rangeproof = SigHashFlags::new(SigHashType::All, true);
+        let sighash = segwitv0_sighash(&tx, 0, &script_code, value, rangeproof);
+        assert_ne!(sighash, segwitv0_sighash(&tx, 0, &script_code, value, all));
+        assert_ne!(
+            sighash,
+            segwitv0_sighash(&tx, 1, &script_code, value, rangeproof)
+        );
+        let single = SigHashFlags::new(SigHashType::Single, true);
+        assert_ne!(
+            segwitv0_sighash(&tx, 0, &script_code, value, single),
+            segwitv0_sighash(&tx, 1, &script_code, value, single)
+        );
+    }
+
+    /// Sighashes of `tx_with_proofs` computed separately following `SignatureHash` of Elements
+    /// Core, as the bytes of the double sha256
+    #[test]
+    fn test_segwitv0_sighash_vectors() {
+        let tx = tx_with_proofs();
+        let script_code = Script::from(vec![0x51]);
+        let value = Value::Explicit(1_000);
+        let vectors = [
+            (
+                0,
+                0x01,
+                "e0c53ca2388584cc48901204f53b676694c7d7ecd1ef4472db186b1f8961e487",
+            ),
+            (
+                1,
+                0x83,
+                "cc2c38762c9d4cb72b9f3c63ee319ce312383a0f9975ce2cf9caeaa98c33b573",
+            ),
+            (
+                0,
+                0x41,
+                "19d76020bd575f035ede5494d77aec3f2d8429211c660a54ce15e695b0c0cb61",
+            ),
+            (
+                0,
+                0x43,
+                "2f04ad357758526522e28a32bbf25fabfa5f1c4527f7f709ed55950d7884ce58",
+            ),
+            // the fee output has no proofs, committed as empty
+            (
+                1,
+                0x43,
+                "2381c3ae6b1cf0f4772208b4fa3599fa86b724ed36b08ef37e0916bd99030c62",
+            ),
+            (
+                1,
+                0xc1,
+                "f9902b92350ce183a0a8747aedfb95acce47a438c4bfdf1238b1d0ee29c60e5f",
+            ),
+            (
+                0,
+                0x42,
+                "fc12dd67bff66c6a6dc21b57e7bed6238206d5136ec645ceda4611f438522b83",
+            ),
+        ];
+        for (input_index, n, expected) in vectors.iter() {
+            let flags = SigHashFlags::from_u8(*n, true).unwrap();
+            let sighash = segwitv0_sighash(&tx, *input_index, &script_code, value, flags);
+            assert_eq!(
+                hex::encode(&sighash[..]),
+                *expected,
+                "input {} sighash {:#x}",
+                input_index,
+                n
+            );
+        }
+    }
+}
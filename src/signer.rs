@@ -0,0 +1,66 @@
+use crate::error::Error;
+
+#[cfg(feature = "remote-signer")]
+pub mod remote;
+
+use elements::bitcoin::secp256k1::{Message, Secp256k1};
+use elements::bitcoin::util::bip32::DerivationPath;
+use elements::bitcoin::{PrivateKey, PublicKey};
+use elements::confidential::Value;
+use elements::{Script, SigHashType, Transaction};
+
+/// Holder of a key co-signing the wallet inputs locked by multi-signature scripts, such as the
+/// other key of a `MultisigScript`, or of the wallet keys, see `ElectrumWallet::sign_tx_with_signer`.
+///
+/// It is implemented by `PrivateKey` and, with the `remote-signer` feature, by
+/// `remote::RemoteSigner` sending the sighashes to the service holding the key.
+pub trait Signer: Send + Sync {
+    /// Public key and signature, with `sighash_type` appended, of input `input_index` of `tx`
+    /// spending an output of `value` locked by the P2WSH of `witness_script`
+    fn sign(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        witness_script: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+    ) -> Result<(PublicKey, Vec<u8>), Error>;
+
+    /// Like `sign` for input `input_index` of `tx` spending a wallet output, locked by the key
+    /// derived at `derivation_path` from the wallet xpub, `script_code` is the script signed.
+    /// Signers holding a single key sign with it.
+    fn sign_wallet_input(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        _derivation_path: &DerivationPath,
+        script_code: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+    ) -> Result<(PublicKey, Vec<u8>), Error> {
+        self.sign(tx, input_index, script_code, value, sighash_type)
+    }
+}
+
+impl Signer for PrivateKey {
+    fn sign(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        witness_script: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+    ) -> Result<(PublicKey, Vec<u8>), Error> {
+        let secp = Secp256k1::signing_only();
+        let sighash = elements::sighash::SigHashCache::new(tx).segwitv0_sighash(
+            input_index,
+            witness_script,
+            value,
+            sighash_type,
+        );
+        let message = Message::from_slice(&sighash[..])?;
+        let mut signature = secp.sign(&message, &self.key).serialize_der().to_vec();
+        signature.push(sighash_type as u8);
+        Ok((PublicKey::from_private_key(&secp, self), signature))
+    }
+}
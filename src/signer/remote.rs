@@ -0,0 +1,368 @@
+use crate::backend::http_agent;
+use crate::error::Error;
+use crate::signer::Signer;
+
+use elements::bitcoin::secp256k1::{self, Message, Secp256k1};
+use elements::bitcoin::util::bip32::DerivationPath;
+use elements::bitcoin::PublicKey;
+use elements::confidential::Value;
+use elements::{Script, SigHash, SigHashType, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// Body of the POST request sent to the signing service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSignRequest {
+    /// Hex of the transaction, for services enforcing a policy on what they sign
+    pub tx: String,
+    pub input_index: usize,
+    /// Hex of the witness script locking the spent output
+    pub witness_script: String,
+    /// Hex of the consensus encoding of the value of the spent output
+    pub value: String,
+    pub sighash_type: u8,
+    /// Hex of the segwit v0 sighash to sign, the service must check it matches the fields above
+    pub sighash: String,
+    /// Derivation path from the wallet xpub of the key signing a wallet input, None for the
+    /// inputs co-signed by the key of the service
+    #[serde(default)]
+    pub derivation_path: Option<String>,
+}
+
+/// Body of the response of the signing service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSignResponse {
+    /// Hex of the public key of the signing key
+    pub pubkey: String,
+    /// Hex of the DER signature, without the sighash type
+    pub signature: String,
+}
+
+/// `Signer` delegating the signatures to a remote service, such as a HSM or a policy server.
+///
+/// Every signature is a POST of a `RemoteSignRequest` to the url of the service, authenticated
+/// with a bearer token, answered with a `RemoteSignResponse`. The signature is verified before
+/// being returned, and with `with_pubkey` it must be made by the given key.
+pub struct RemoteSigner {
+    url: String,
+    token: String,
+    pubkey: Option<PublicKey>,
+    agent: ureq::Agent,
+}
+
+impl RemoteSigner {
+    /// Signer sending the requests to `url`, which must be https unless the service is on the
+    /// local host, and failing requests taking more than `timeout` seconds
+    pub fn new(url: &str, token: &str, timeout: Option<u8>) -> Result<Self, Error> {
+        if !url.starts_with("https://") && !is_local_http(url) {
+            return Err(Error::Generic(format!(
+                "remote signer url {} must be https",
+                url
+            )));
+        }
+        Ok(RemoteSigner {
+            url: url.to_string(),
+            token: token.to_string(),
+            pubkey: None,
+            agent: http_agent(timeout, None),
+        })
+    }
+
+    /// Accept only signatures made by `pubkey`
+    pub fn with_pubkey(mut self, pubkey: PublicKey) -> Self {
+        self.pubkey = Some(pubkey);
+        self
+    }
+
+    fn request(
+        tx: &Transaction,
+        input_index: usize,
+        witness_script: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+        derivation_path: Option<&DerivationPath>,
+    ) -> (SigHash, RemoteSignRequest) {
+        let sighash = elements::sighash::SigHashCache::new(tx).segwitv0_sighash(
+            input_index,
+            witness_script,
+            value,
+            sighash_type,
+        );
+        let request = RemoteSignRequest {
+            tx: hex::encode(elements::encode::serialize(tx)),
+            input_index,
+            witness_script: hex::encode(witness_script.as_bytes()),
+            value: hex::encode(elements::encode::serialize(&value)),
+            sighash_type: sighash_type as u8,
+            sighash: hex::encode(&sighash[..]),
+            derivation_path: derivation_path.map(|path| path.to_string()),
+        };
+        (sighash, request)
+    }
+
+    /// Public key and signature of `response`, checking it signs `sighash`
+    fn verify(
+        &self,
+        sighash: &SigHash,
+        response: &RemoteSignResponse,
+    ) -> Result<(PublicKey, secp256k1::Signature), Error> {
+        let pubkey = PublicKey::from_slice(&hex::decode(&response.pubkey)?)
+            .map_err(|e| Error::Generic(format!("remote signer pubkey: {}", e)))?;
+        if self.pubkey.map_or(false, |expected| expected != pubkey) {
+            return Err(Error::Generic(format!(
+                "remote signer signed with unexpected key {}",
+                pubkey
+            )));
+        }
+        let signature = secp256k1::Signature::from_der(&hex::decode(&response.signature)?)?;
+        let message = Message::from_slice(&sighash[..])?;
+        Secp256k1::verification_only()
+            .verify(&message, &signature, &pubkey.key)
+            .map_err(|_| Error::InvalidSignature)?;
+        Ok((pubkey, signature))
+    }
+
+    /// Send `request` and verify the signature of the response
+    fn call(
+        &self,
+        sighash: SigHash,
+        request: RemoteSignRequest,
+    ) -> Result<(PublicKey, Vec<u8>), Error> {
+        let sighash_type = request.sighash_type;
+        let body = serde_json::to_string(&request)?;
+        let response = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .send_string(&body)?
+            .into_string()?;
+        let response: RemoteSignResponse = serde_json::from_str(&response)?;
+        let (pubkey, signature) = self.verify(&sighash, &response)?;
+        let mut signature = signature.serialize_der().to_vec();
+        signature.push(sighash_type);
+        Ok((pubkey, signature))
+    }
+}
+
+/// Whether `url` is plain http to the local host, the host is compared exactly so that names
+/// such as `localhost.example.com` are rejected
+fn is_local_http(url: &str) -> bool {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let authority = rest
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or("");
+    if authority.contains('@') {
+        return false;
+    }
+    // the port follows the last colon, unless it is inside the brackets of an IPv6 address
+    let host = match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => &authority[..i],
+        _ => authority,
+    };
+    ["localhost", "127.0.0.1", "[::1]"].contains(&host)
+}
+
+impl Signer for RemoteSigner {
+    fn sign(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        witness_script: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+    ) -> Result<(PublicKey, Vec<u8>), Error> {
+        let (sighash, request) =
+            Self::request(tx, input_index, witness_script, value, sighash_type, None);
+        self.call(sighash, request)
+    }
+
+    fn sign_wallet_input(
+        &self,
+        tx: &Transaction,
+        input_index: usize,
+        derivation_path: &DerivationPath,
+        script_code: &Script,
+        value: Value,
+        sighash_type: SigHashType,
+    ) -> Result<(PublicKey, Vec<u8>), Error> {
+        let (sighash, request) = Self::request(
+            tx,
+            input_index,
+            script_code,
+            value,
+            sighash_type,
+            Some(derivation_path),
+        );
+        self.call(sighash, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::bitcoin::PrivateKey;
+
+    fn key(n: u8) -> PrivateKey {
+        PrivateKey {
+            compressed: true,
+            network: elements::bitcoin::Network::Regtest,
+            key: secp256k1::SecretKey::from_slice(&[n; 32]).unwrap(),
+        }
+    }
+
+    fn tx() -> Transaction {
+        let mut tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        crate::transaction::add_input(&mut tx, elements::OutPoint::default());
+        tx
+    }
+
+    #[test]
+    fn test_remote_signer_url() {
+        for url in &[
+            "https://signer.example.com",
+            "http://localhost",
+            "http://localhost:3000/sign",
+            "http://127.0.0.1:3000",
+            "http://[::1]:3000",
+        ] {
+            assert!(RemoteSigner::new(url, "token", None).is_ok(), "{}", url);
+        }
+        for url in &[
+            "http://signer.example.com",
+            "http://localhost.example.com",
+            "http://localhost.example.com:3000",
+            "http://localhost@example.com",
+            "http://127.0.0.1.example.com",
+            "ftp://localhost",
+        ] {
+            assert!(RemoteSigner::new(url, "token", None).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_remote_signer_http() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // mock service answering a single request with the signature of key(1)
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let service = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut authorization = None;
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-length:") {
+                    length = line[15..].trim().parse().unwrap();
+                } else if lower.starts_with("authorization:") {
+                    authorization = Some(line[14..].trim().to_string());
+                }
+            }
+            let mut body = vec![0u8; length];
+            reader.read_exact(&mut body).unwrap();
+            let request: RemoteSignRequest = serde_json::from_slice(&body).unwrap();
+            let sighash = hex::decode(&request.sighash).unwrap();
+            let message = Message::from_slice(&sighash).unwrap();
+            let signature = Secp256k1::signing_only().sign(&message, &key(1).key);
+            let secp = Secp256k1::signing_only();
+            let response = serde_json::to_string(&RemoteSignResponse {
+                pubkey: PublicKey::from_private_key(&secp, &key(1)).to_string(),
+                signature: hex::encode(signature.serialize_der()),
+            })
+            .unwrap();
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+            (authorization, request)
+        });
+
+        let signer = RemoteSigner::new(&format!("http://127.0.0.1:{}", port), "token", Some(10))
+            .unwrap()
+            .with_pubkey(PublicKey::from_private_key(
+                &Secp256k1::signing_only(),
+                &key(1),
+            ));
+        let tx = tx();
+        let script = Script::from(vec![0x51]);
+        let value = Value::Explicit(1_000);
+        let path: DerivationPath = "m/0/3".parse().unwrap();
+        let (pubkey, signature) = signer
+            .sign_wallet_input(&tx, 0, &path, &script, value, SigHashType::All)
+            .unwrap();
+        // same signature as the one of the key held by the service, with the sighash type
+        assert_eq!(
+            (pubkey, signature),
+            key(1)
+                .sign(&tx, 0, &script, value, SigHashType::All)
+                .unwrap()
+        );
+
+        let (authorization, request) = service.join().unwrap();
+        assert_eq!(authorization.as_deref(), Some("Bearer token"));
+        assert_eq!(request.derivation_path.as_deref(), Some("m/0/3"));
+        assert_eq!(request.input_index, 0);
+        assert_eq!(request.witness_script, "51");
+    }
+
+    #[test]
+    fn test_remote_signer() {
+        assert!(RemoteSigner::new("http://signer.example.com", "token", None).is_err());
+        let signer = RemoteSigner::new("https://signer.example.com", "token", None).unwrap();
+
+        let tx = tx();
+        let script = Script::from(vec![0x51]);
+        let value = Value::Explicit(1_000);
+        let (sighash, request) =
+            RemoteSigner::request(&tx, 0, &script, value, SigHashType::All, None);
+        assert_eq!(request.sighash_type, 1);
+        assert_eq!(request.witness_script, "51");
+
+        // the service answers with the signature made by its key
+        let (pubkey, signature) = key(1)
+            .sign(&tx, 0, &script, value, SigHashType::All)
+            .unwrap();
+        let response = RemoteSignResponse {
+            pubkey: pubkey.to_string(),
+            signature: hex::encode(&signature[..signature.len() - 1]),
+        };
+        let (verified_pubkey, _) = signer.verify(&sighash, &response).unwrap();
+        assert_eq!(verified_pubkey, pubkey);
+        let signer = signer.with_pubkey(pubkey);
+        assert!(signer.verify(&sighash, &response).is_ok());
+
+        // signatures of other sighashes or of other keys are rejected
+        let (other_sighash, _) =
+            RemoteSigner::request(&tx, 0, &script, value, SigHashType::None, None);
+        assert!(matches!(
+            signer.verify(&other_sighash, &response),
+            Err(Error::InvalidSignature)
+        ));
+        let other_key = PublicKey::from_private_key(&Secp256k1::signing_only(), &key(2));
+        let signer = RemoteSigner::new("http://localhost:3000", "token", None)
+            .unwrap()
+            .with_pubkey(other_key);
+        assert!(signer.verify(&sighash, &response).is_err());
+    }
+}
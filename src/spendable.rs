@@ -0,0 +1,189 @@
+use elements::bitcoin::hashes::Hash;
+use elements::bitcoin::PublicKey;
+use elements::opcodes;
+use elements::script::Builder;
+use elements::Script;
+use std::collections::BTreeMap;
+
+/// Script locking outputs to a wallet key with additional conditions, such as a simple covenant
+/// or a relative timelock.
+///
+/// Once registered the P2WSH outputs of the script for every wallet key are tracked by sync and
+/// spent by the transaction builder.
+pub trait SpendableScript: Send + Sync {
+    /// Witness script of the outputs locked to the wallet `pubkey`
+    fn witness_script(&self, pubkey: &PublicKey) -> Script;
+
+    /// Witness stack satisfying the script given the `signature` of the wallet key, the witness
+    /// script is pushed last by the wallet
+    fn satisfy(&self, signature: Vec<u8>, pubkey: &PublicKey) -> Vec<Vec<u8>>;
+
+    /// nSequence of the inputs spending the outputs, for instance a relative timelock
+    fn sequence(&self) -> u32 {
+        0xffff_fffe
+    }
+
+    /// Keys whose signatures are needed besides the one of the wallet `pubkey`, such as the
+    /// other key of a multisig, they are collected with a `Signer`.
+    ///
+    /// Outputs needing cosigners are not selected by `create_tx`.
+    fn cosigners(&self, _pubkey: &PublicKey) -> Vec<PublicKey> {
+        vec![]
+    }
+
+    /// Witness stack given the `signatures` of the wallet `pubkey` and of the `cosigners` by
+    /// key, the witness script is pushed last by the wallet
+    fn satisfy_cosigned(
+        &self,
+        signatures: &BTreeMap<PublicKey, Vec<u8>>,
+        pubkey: &PublicKey,
+    ) -> Vec<Vec<u8>> {
+        match signatures.get(pubkey) {
+            Some(signature) => self.satisfy(signature.clone(), pubkey),
+            None => vec![],
+        }
+    }
+}
+
+/// Outputs spendable by the wallet key `blocks` blocks after their confirmation
+#[derive(Debug, Clone, Copy)]
+pub struct CsvScript {
+    pub blocks: u16,
+}
+
+impl SpendableScript for CsvScript {
+    fn witness_script(&self, pubkey: &PublicKey) -> Script {
+        Builder::new()
+            .push_int(self.blocks as i64)
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_key(pubkey)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+
+    fn satisfy(&self, signature: Vec<u8>, _pubkey: &PublicKey) -> Vec<Vec<u8>> {
+        vec![signature]
+    }
+
+    fn sequence(&self) -> u32 {
+        self.blocks as u32
+    }
+}
+
+/// Outputs spendable by the wallet key together with the `cosigner` key, a 2-of-2 multisig with
+/// the keys sorted as in BIP67
+#[derive(Debug, Clone, Copy)]
+pub struct MultisigScript {
+    pub cosigner: PublicKey,
+}
+
+impl MultisigScript {
+    fn sorted_keys(&self, pubkey: &PublicKey) -> [PublicKey; 2] {
+        let mut keys = [*pubkey, self.cosigner];
+        keys.sort_by_key(|k| k.to_bytes());
+        keys
+    }
+}
+
+impl SpendableScript for MultisigScript {
+    fn witness_script(&self, pubkey: &PublicKey) -> Script {
+        let [first, second] = self.sorted_keys(pubkey);
+        Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_2)
+            .push_key(&first)
+            .push_key(&second)
+            .push_opcode(opcodes::all::OP_PUSHNUM_2)
+            .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    /// Not enough to spend the output, the cosigner signature is needed too
+    fn satisfy(&self, signature: Vec<u8>, _pubkey: &PublicKey) -> Vec<Vec<u8>> {
+        vec![vec![], signature]
+    }
+
+    fn cosigners(&self, _pubkey: &PublicKey) -> Vec<PublicKey> {
+        vec![self.cosigner]
+    }
+
+    fn satisfy_cosigned(
+        &self,
+        signatures: &BTreeMap<PublicKey, Vec<u8>>,
+        pubkey: &PublicKey,
+    ) -> Vec<Vec<u8>> {
+        // the dummy element consumed by OP_CHECKMULTISIG, then the signatures in keys order
+        let mut witness = vec![vec![]];
+        for key in self.sorted_keys(pubkey).iter() {
+            witness.push(signatures.get(key).cloned().unwrap_or_default());
+        }
+        witness
+    }
+}
+
+/// Script pubkey of the outputs of `template` locked to `pubkey`
+pub fn template_script_pubkey(template: &dyn SpendableScript, pubkey: &PublicKey) -> Script {
+    let witness_script = template.witness_script(pubkey);
+    Script::new_v0_wsh(&elements::WScriptHash::hash(witness_script.as_bytes()))
+}
+
+/// Confirmations needed by an output before being spent by an input with `sequence`, as
+/// defined by BIP68 for relative block timelocks
+pub fn relative_lock_blocks(sequence: u32) -> u32 {
+    let disabled = sequence & (1 << 31) != 0;
+    let time_based = sequence & (1 << 22) != 0;
+    if disabled || time_based {
+        0
+    } else {
+        sequence & 0xffff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_csv_script() {
+        let pubkey = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let template = CsvScript { blocks: 144 };
+        let witness_script = template.witness_script(&pubkey);
+        // <144> OP_CSV OP_DROP <pubkey> OP_CHECKSIG
+        assert_eq!(
+            hex::encode(witness_script.as_bytes()),
+            format!("029000b27521{}ac", pubkey)
+        );
+        assert!(template_script_pubkey(&template, &pubkey).is_v0_p2wsh());
+
+        assert_eq!(relative_lock_blocks(template.sequence()), 144);
+        assert_eq!(relative_lock_blocks(0xffff_fffe), 0);
+        assert_eq!(relative_lock_blocks(1 << 22 | 10), 0);
+    }
+
+    #[test]
+    fn test_multisig_script() {
+        let key = |hex: &str| PublicKey::from_str(hex).unwrap();
+        let wallet = key("03c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5");
+        let cosigner = key("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        let template = MultisigScript { cosigner };
+        // OP_2 <cosigner> <wallet> OP_2 OP_CHECKMULTISIG, keys sorted
+        assert_eq!(
+            hex::encode(template.witness_script(&wallet).as_bytes()),
+            format!("5221{}21{}52ae", cosigner, wallet)
+        );
+        assert_eq!(template.cosigners(&wallet), vec![cosigner]);
+
+        let mut signatures = BTreeMap::new();
+        signatures.insert(wallet, vec![1u8]);
+        signatures.insert(cosigner, vec![2u8]);
+        assert_eq!(
+            template.satisfy_cosigned(&signatures, &wallet),
+            vec![vec![], vec![2u8], vec![1u8]]
+        );
+        assert!(CsvScript { blocks: 1 }.cosigners(&wallet).is_empty());
+    }
+}
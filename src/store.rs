@@ -1,5 +1,15 @@
-use crate::model::{FeeEstimate, SPVVerifyResult};
-use crate::scripts::p2shwpkh_script;
+use crate::broadcast::QueuedTx;
+use crate::liquidex::{LiquidexPartial, LiquidexSwap};
+use crate::model::{
+    Burn, Contact, Counterparty, FeeEstimate, Invoice, SPVVerifyResult, TransactionDetails,
+};
+use crate::network::ElementsNetwork;
+use crate::persister::{FilePersister, Persister};
+use crate::registry::CachedAssetMetadata;
+use crate::scripts::{script_kind, AddressType, ScriptKind};
+use crate::spendable::{template_script_pubkey, SpendableScript};
+use crate::transaction::{burns, explicit_secrets, verify_unblinded};
+use crate::tx_cache::TxCache;
 use crate::Error;
 use aes_gcm_siv::aead::{generic_array::GenericArray, AeadInPlace, NewAead};
 use aes_gcm_siv::Aes256GcmSiv;
@@ -7,29 +17,43 @@ use elements::bitcoin::hashes::sha256;
 use elements::bitcoin::hashes::Hash;
 use elements::bitcoin::secp256k1::{All, Secp256k1};
 use elements::bitcoin::util::bip32::{ChildNumber, DerivationPath, ExtendedPubKey};
+use elements::bitcoin::PublicKey;
 use elements::{BlockHash, Script, Txid};
 use elements::{BlockHeader, OutPoint};
-use log::{info, warn};
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
 use std::str::FromStr;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
+use tracing::{info, trace, warn};
 
 pub const BATCH_SIZE: u32 = 20;
 
+/// Virtual account of the utxos neither assigned to an account nor received on a labelled
+/// address
+pub const DEFAULT_ACCOUNT: &str = "";
+
+/// Number of scripts per chain re-derived when verifying the store
+const VERIFY_SAMPLE: u32 = 5;
+
+/// Name of the list of the fields of the cache, every field is saved with the name `cache_` and
+/// the field name so that a save rewrites only the fields changed
+const CACHE_FIELDS: &str = "cache_fields";
+
+/// Name of the whole cache saved by the stores created before it was split in fields
+const LEGACY_CACHE: &str = "cache";
+
 pub type Store = Arc<RwLock<StoreMeta>>;
 
 /// RawCache is a persisted and encrypted cache of wallet data, contains stuff like wallet transactions
 /// It is fully reconstructable from xpub and data from electrum server (plus master blinding for elements)
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct RawCache {
-    /// contains all my tx and all prevouts
-    pub all_txs: HashMap<Txid, elements::Transaction>,
+    /// contains all my tx and all prevouts, loaded on demand
+    pub all_txs: TxCache,
 
     /// contains all my script up to an empty batch of BATCHSIZE
     pub paths: HashMap<Script, DerivationPath>,
@@ -57,27 +81,194 @@ pub struct RawCache {
 
     /// max used indexes for external derivation /0/* and internal derivation /1/* (change)
     pub indexes: Indexes,
+
+    /// height of the last block scanned by the block filters sync
+    #[serde(default)]
+    pub filter_height: u32,
+
+    /// outpoints spent by the inputs of `all_txs`, updated when txs are cached
+    #[serde(default)]
+    pub spent: HashSet<OutPoint>,
+
+    /// unspent outputs of the wallet txs which have been unblinded, rebuilt on load and updated
+    /// by `update_indexes` after every sync
+    #[serde(skip)]
+    pub utxos: HashSet<OutPoint>,
+
+    /// wallet txs sorted by (height, txid), unconfirmed txs have height u32::MAX, rebuilt like
+    /// `utxos`
+    #[serde(skip)]
+    pub txs_by_height: BTreeSet<(u32, Txid)>,
+
+    /// outpoints whose unblinded values do not match the output commitments
+    #[serde(default)]
+    pub mismatched_commitments: HashSet<OutPoint>,
+
+    /// wallet outputs received unconfidential, whose asset and value are explicit, they are in
+    /// `unblinded` with zero blinding factors
+    #[serde(default)]
+    pub explicit_outputs: HashSet<OutPoint>,
+
+    /// reissuance tokens issued by the cached txs and the asset they reissue, updated when txs
+    /// are cached
+    #[serde(default)]
+    pub reissuance_tokens: HashMap<elements::issuance::AssetId, elements::issuance::AssetId>,
+
+    /// whether `reissuance_tokens` lists the issuances of every cached tx, false for caches
+    /// created before it was saved
+    #[serde(default)]
+    pub issuances_indexed: bool,
+
+    /// outputs of the wallet txs with a script of unknown kind, rebuilt like `utxos`
+    #[serde(skip)]
+    pub unknown_scripts: HashSet<OutPoint>,
+
+    /// burns of the wallet assets by the wallet txs, rebuilt like `utxos`
+    #[serde(skip)]
+    pub burns: HashMap<Txid, Vec<Burn>>,
+
+    /// wallet txs whose outputs are in `unknown_scripts` and `indexed_burns`, so that
+    /// `update_indexes` reads only the txs added since
+    #[serde(skip)]
+    pub indexed_txs: HashSet<Txid>,
+
+    /// burns of any asset by the indexed txs, `burns` keeps the ones of the wallet assets
+    #[serde(skip)]
+    pub indexed_burns: HashMap<Txid, Vec<Burn>>,
+
+    /// unix time of the end of the last sync, saved with the next change of the cache
+    #[serde(default)]
+    pub last_sync: Option<u64>,
+
+    /// wallet outputs whose rangeproof could not be rewound, successful rewinds are in
+    /// `unblinded`, neither are rewound again
+    #[serde(default)]
+    pub failed_rewinds: HashSet<OutPoint>,
+
+    /// `Blinder::id` of the blinding keys which made the cached rewinds, None for caches created
+    /// before it was saved
+    #[serde(default)]
+    pub blinding_key_id: Option<sha256::Hash>,
 }
 
-/// RawStore contains data that are not extractable from xpub+blockchain
-#[derive(Default, Serialize, Deserialize)]
+/// RawStore contains data that are not extractable from xpub+blockchain, such as the wallet
+/// settings, it is persisted encrypted like the RawCache
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct RawStore {
     /// Assets that might be received by a LiquiDEX maker
     liquidex_assets: HashSet<elements::issuance::AssetId>,
+
+    /// Network the store has been created for (None for stores created before this was saved)
+    #[serde(default)]
+    network: Option<ElementsNetwork>,
+
+    /// Invoices created by the wallet, by id
+    #[serde(default)]
+    invoices: HashMap<String, Invoice>,
+
+    /// LiquiDEX swaps made or taken by the wallet, by the maker utxo
+    #[serde(default)]
+    liquidex_swaps: HashMap<OutPoint, LiquidexSwap>,
+
+    /// Partial LiquiDEX proposals made by the wallet and not filled yet, by the maker utxo
+    #[serde(default)]
+    liquidex_partials: HashMap<OutPoint, LiquidexPartial>,
+
+    /// Labels of the wallet scripts, to separate the funds received on them
+    #[serde(default)]
+    labels: HashMap<Script, HashSet<String>>,
+
+    /// Address book of the wallet, by contact name
+    #[serde(default)]
+    contacts: HashMap<String, Contact>,
+
+    /// Utxos manually assigned to a virtual account, by account name
+    #[serde(default)]
+    utxo_accounts: HashMap<OutPoint, String>,
+
+    /// Transactions waiting to be broadcast, kept until broadcast so that they are sent again
+    /// after a restart
+    #[serde(default)]
+    broadcast_queue: HashMap<Txid, QueuedTx>,
+
+    /// Assets received by the wallet, kept with the store so that LiquiDEX outputs of these
+    /// assets are unblinded even after the cache is rebuilt
+    #[serde(default)]
+    known_assets: HashSet<elements::issuance::AssetId>,
+
+    /// Outputs paying others of the txs created by the wallet, by the first outpoint the tx
+    /// spends since the txid changes when blinding and signing. Addresses and blinded values
+    /// cannot be recovered from the blockchain.
+    #[serde(default)]
+    counterparties: HashMap<OutPoint, Vec<Counterparty>>,
+
+    /// Transactions created with a `CreateTransactionOpt::request_id`, by request id
+    #[serde(default)]
+    requests: HashMap<String, TransactionDetails>,
+
+    /// Reissuance tokens not issued by the cached txs, such as the ones received, and the asset
+    /// they reissue
+    #[serde(default)]
+    reissuance_tokens: HashMap<elements::issuance::AssetId, elements::issuance::AssetId>,
+
+    /// Asset metadata fetched from the registry, returned by `asset_metadata` when offline
+    #[serde(default)]
+    asset_metadata: HashMap<elements::issuance::AssetId, CachedAssetMetadata>,
+
+    /// Base64 icons of the wallet assets fetched from the registry, the icons of the other
+    /// assets in the bundle are not kept
+    #[serde(default)]
+    asset_icons: HashMap<elements::issuance::AssetId, String>,
+
+    /// ETag of the icons bundle, to download it again only if changed
+    #[serde(default)]
+    icons_etag: Option<String>,
+}
+
+/// `xpub` with testnet version bytes, which were used for every network by the wallets created
+/// before the keys had the version bytes of their network. The wallet id and the store
+/// encryption key are computed from it so that existing stores keep working.
+pub fn legacy_xpub(xpub: &ExtendedPubKey) -> ExtendedPubKey {
+    ExtendedPubKey {
+        network: elements::bitcoin::Network::Testnet,
+        ..*xpub
+    }
 }
 
 pub struct StoreMeta {
     pub cache: RawCache,
     pub store: RawStore,
     secp: Secp256k1<All>,
-    path: PathBuf,
+    persister: Arc<dyn Persister>,
     cipher: Aes256GcmSiv,
+    /// Key of the hashes naming the saved transactions
+    tx_names_key: [u8; 32],
     first_deriv: [ExtendedPubKey; 2],
+    address_type: AddressType,
+    /// Registered script templates, not persisted
+    templates: Vec<Arc<dyn SpendableScript>>,
+    /// Scripts of the registered templates and the index of their template
+    template_scripts: HashMap<Script, usize>,
+    /// Labels of the change addresses of the created transactions, inserted once they are
+    /// signed, not persisted
+    pending_labels: HashMap<Script, String>,
+    /// Save the store and the cache as soon as they change, true by default
+    autoflush: bool,
+    /// Whether the store changed since it was last saved
+    store_dirty: AtomicBool,
+    /// Whether the cache changed since it was last saved
+    cache_dirty: AtomicBool,
+    /// Hash of the plaintext last saved with every name, unchanged values are not saved again
+    flushed: Mutex<HashMap<String, sha256::Hash>>,
 }
 
+/// Dropping the store saves the pending changes, a failure is only logged: call `flush` before
+/// to handle it
 impl Drop for StoreMeta {
     fn drop(&mut self) {
-        self.flush().unwrap();
+        if let Err(e) = self.flush() {
+            warn!("saving the store on drop failed: {:?}", e);
+        }
     }
 }
 
@@ -87,6 +278,13 @@ pub struct Indexes {
     pub internal: u32, // m/1/*
 }
 
+/// Immutable copy of the wallet data, for integrators needing access to the cached values
+#[derive(Clone)]
+pub struct StoreSnapshot {
+    cache: RawCache,
+    store: RawStore,
+}
+
 #[derive(Default)]
 pub struct ScriptBatch {
     pub cached: bool,
@@ -94,82 +292,189 @@ pub struct ScriptBatch {
 }
 
 impl RawCache {
-    /// create a new RawCache, loading data from a file if any and if there is no error in reading
+    /// Whether the rangeproof of the wallet output `outpoint` has been rewound, successfully or not
+    pub fn is_rewound(&self, outpoint: &OutPoint) -> bool {
+        self.unblinded.contains_key(outpoint) || self.failed_rewinds.contains(outpoint)
+    }
+
+    /// create a new RawCache, loading data from the persister if any and if there is no error in reading
     /// errors such as corrupted file or model change in the db, result in a empty store that will be repopulated
-    fn new<P: AsRef<Path>>(path: P, cipher: &Aes256GcmSiv) -> Self {
-        Self::try_new(path, cipher).unwrap_or_else(|e| {
+    fn new(persister: &dyn Persister, cipher: &Aes256GcmSiv) -> Self {
+        Self::try_new(persister, cipher).unwrap_or_else(|e| {
             warn!("Initialize cache as default {:?}", e);
             Default::default()
         })
     }
 
-    fn try_new<P: AsRef<Path>>(path: P, cipher: &Aes256GcmSiv) -> Result<Self, Error> {
-        let decrypted = load_decrypt("cache", path, cipher)?;
-        let store = serde_cbor::from_slice(&decrypted)?;
-        Ok(store)
+    fn try_new(persister: &dyn Persister, cipher: &Aes256GcmSiv) -> Result<Self, Error> {
+        let fields = match load_decrypt_opt(CACHE_FIELDS, persister, cipher)? {
+            Some(fields) => serde_cbor::from_slice::<Vec<String>>(&fields)?,
+            None => {
+                let decrypted = load_decrypt(LEGACY_CACHE, persister, cipher)?;
+                return Ok(serde_cbor::from_slice(&decrypted)?);
+            }
+        };
+        let mut map = BTreeMap::new();
+        for field in fields {
+            let decrypted = load_decrypt(&cache_field_name(&field), persister, cipher)?;
+            let value: serde_cbor::Value = serde_cbor::from_slice(&decrypted)?;
+            map.insert(serde_cbor::Value::Text(field), value);
+        }
+        Ok(serde_cbor::value::from_value(serde_cbor::Value::Map(map))?)
     }
+
+    /// Fields of the cache by name, as saved by `StoreMeta::flush`
+    fn fields(&self) -> Result<Vec<(String, serde_cbor::Value)>, Error> {
+        match serde_cbor::value::to_value(self)? {
+            serde_cbor::Value::Map(map) => map
+                .into_iter()
+                .map(|(key, value)| match key {
+                    serde_cbor::Value::Text(field) => Ok((field, value)),
+                    _ => Err(Error::Generic("cache field without name".into())),
+                })
+                .collect(),
+            _ => Err(Error::Generic("cache is not a map".into())),
+        }
+    }
+}
+
+/// Name of the value keeping `field` of the cache
+fn cache_field_name(field: &str) -> String {
+    format!("cache_{}", field)
 }
 
 impl RawStore {
-    /// create a new RawStore, loading data from a file if any and if there is no error in reading
+    /// create a new RawStore, loading data from the persister if any and if there is no error in reading
     /// errors such as corrupted file or model change in the db, result in a empty store that will be repopulated
-    fn new<P: AsRef<Path>>(path: P, cipher: &Aes256GcmSiv) -> Self {
-        Self::try_new(path, cipher).unwrap_or_else(|e| {
+    fn new(persister: &dyn Persister, cipher: &Aes256GcmSiv) -> Self {
+        Self::try_new(persister, cipher).unwrap_or_else(|e| {
             warn!("Initialize store as default {:?}", e);
             Default::default()
         })
     }
 
-    fn try_new<P: AsRef<Path>>(path: P, cipher: &Aes256GcmSiv) -> Result<Self, Error> {
-        let decrypted = load_decrypt("store", path, cipher)?;
+    fn try_new(persister: &dyn Persister, cipher: &Aes256GcmSiv) -> Result<Self, Error> {
+        let decrypted = load_decrypt("store", persister, cipher)?;
         let store = serde_cbor::from_slice(&decrypted)?;
         Ok(store)
     }
 }
 
-fn load_decrypt<P: AsRef<Path>>(
+pub(crate) fn load_decrypt(
     name: &str,
-    path: P,
+    persister: &dyn Persister,
     cipher: &Aes256GcmSiv,
 ) -> Result<Vec<u8>, Error> {
+    load_decrypt_opt(name, persister, cipher)?
+        .ok_or_else(|| Error::Generic(format!("{} do not exist", name)))
+}
+
+/// Like `load_decrypt`, None if nothing has been saved with `name`
+pub(crate) fn load_decrypt_opt(
+    name: &str,
+    persister: &dyn Persister,
+    cipher: &Aes256GcmSiv,
+) -> Result<Option<Vec<u8>>, Error> {
     let now = Instant::now();
-    let mut store_path = PathBuf::from(path.as_ref());
-    store_path.push(name);
-    if !store_path.exists() {
-        return Err(Error::Generic(format!("{:?} do not exist", store_path)));
+    let bytes = match persister.load(name)? {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    if bytes.len() < 12 {
+        return Err(Error::Generic(format!("{} is too short", name)));
     }
-    let mut file = File::open(&store_path)?;
-    let mut nonce_bytes = [0u8; 12];
-    file.read_exact(&mut nonce_bytes)?;
-    let nonce = GenericArray::from_slice(&nonce_bytes);
-    let mut ciphertext = vec![];
-    file.read_to_end(&mut ciphertext)?;
+    let (nonce_bytes, ciphertext) = bytes.split_at(12);
+    let nonce = GenericArray::from_slice(nonce_bytes);
+    let mut ciphertext = ciphertext.to_vec();
 
     cipher.decrypt_in_place(nonce, b"", &mut ciphertext)?;
     let plaintext = ciphertext;
 
-    info!(
-        "loading {:?} took {}ms",
-        &store_path,
-        now.elapsed().as_millis()
-    );
-    Ok(plaintext)
+    info!("loading {} took {}ms", name, now.elapsed().as_millis());
+    Ok(Some(plaintext))
+}
+
+/// Encrypt `plaintext` with a random nonce and save it with `name`
+pub(crate) fn encrypt_save(
+    name: &str,
+    mut plaintext: Vec<u8>,
+    persister: &dyn Persister,
+    cipher: &Aes256GcmSiv,
+) -> Result<(), Error> {
+    let mut nonce_bytes = [0u8; 12];
+    thread_rng().fill(&mut nonce_bytes);
+    let nonce = GenericArray::from_slice(&nonce_bytes);
+
+    cipher.encrypt_in_place(nonce, b"", &mut plaintext)?;
+    let ciphertext = plaintext;
+
+    //TODO should avoid rewriting if not changed? it involves saving plaintext (or struct hash)
+    // in the front of the file
+    let mut bytes = nonce_bytes.to_vec();
+    bytes.extend(ciphertext);
+    persister.save(name, &bytes)
+}
+
+/// Reissuance tokens issued by the inputs of `tx` and the asset they reissue
+fn issuances(
+    tx: &elements::Transaction,
+) -> impl Iterator<Item = (elements::issuance::AssetId, elements::issuance::AssetId)> + '_ {
+    tx.input
+        .iter()
+        .filter(|input| input.has_issuance())
+        .map(|input| {
+            let (asset, token) = input.issuance_ids();
+            (token, asset)
+        })
 }
 
 impl StoreMeta {
-    pub fn new<P: AsRef<Path>>(path: P, xpub: ExtendedPubKey) -> Result<StoreMeta, Error> {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        xpub: ExtendedPubKey,
+        network: ElementsNetwork,
+    ) -> Result<StoreMeta, Error> {
+        let persister = FilePersister::new(path)?;
+        Self::new_with_persister(Box::new(persister), xpub, network)
+    }
+
+    pub fn new_with_persister(
+        persister: Box<dyn Persister>,
+        xpub: ExtendedPubKey,
+        network: ElementsNetwork,
+    ) -> Result<StoreMeta, Error> {
+        Self::new_with_address_type(persister, xpub, network, AddressType::default())
+    }
+
+    /// Store of a wallet whose keys have addresses of `address_type`
+    pub fn new_with_address_type(
+        persister: Box<dyn Persister>,
+        xpub: ExtendedPubKey,
+        network: ElementsNetwork,
+        address_type: AddressType,
+    ) -> Result<StoreMeta, Error> {
         let mut enc_key_data = vec![];
         enc_key_data.extend(&xpub.public_key.to_bytes());
         enc_key_data.extend(&xpub.chain_code.to_bytes());
-        enc_key_data.extend(&xpub.network.magic().to_be_bytes());
+        enc_key_data.extend(&legacy_xpub(&xpub).network.magic().to_be_bytes());
         let key_bytes = sha256::Hash::hash(&enc_key_data).into_inner();
         let key = GenericArray::from_slice(&key_bytes);
         let cipher = Aes256GcmSiv::new(&key);
-        let cache = RawCache::new(path.as_ref(), &cipher);
-        let store = RawStore::new(path.as_ref(), &cipher);
-        let path = path.as_ref().to_path_buf();
-        if !path.exists() {
-            std::fs::create_dir_all(&path)?;
+        let mut tx_names_data = key_bytes.to_vec();
+        tx_names_data.extend(b"tx_names");
+        let tx_names_key = sha256::Hash::hash(&tx_names_data).into_inner();
+        let persister: Arc<dyn Persister> = Arc::from(persister);
+        // caches not saved yet or saved as a whole before the split in fields are saved at the
+        // first flush
+        let cache_saved = persister.load(CACHE_FIELDS)?.is_some();
+        let mut cache = RawCache::new(&*persister, &cipher);
+        cache
+            .all_txs
+            .attach(persister.clone(), cipher.clone(), tx_names_key);
+        let mut store = RawStore::new(&*persister, &cipher);
+        let store_network = *store.network.get_or_insert(network);
+        if store_network != network {
+            return Err(Error::NetworkMismatch);
         }
         let secp = Secp256k1::new();
 
@@ -178,58 +483,232 @@ impl StoreMeta {
             xpub.derive_pub(&secp, &[ChildNumber::from(1)])?,
         ];
 
-        Ok(StoreMeta {
+        let mut store = StoreMeta {
             cache,
             store,
             cipher,
+            tx_names_key,
             secp,
-            path,
+            persister,
             first_deriv,
-        })
+            address_type,
+            templates: vec![],
+            template_scripts: HashMap::new(),
+            pending_labels: HashMap::new(),
+            autoflush: true,
+            store_dirty: AtomicBool::new(false),
+            cache_dirty: AtomicBool::new(!cache_saved),
+            flushed: Mutex::new(HashMap::new()),
+        };
+        if let Err(e) = store.verify() {
+            warn!("Initialize cache as default {:?}", e);
+            store.reset_cache()?;
+        }
+        store.drop_template_paths();
+        if store.cache.spent.is_empty() && !store.cache.all_txs.is_empty() {
+            // caches created before the spent outpoints were saved, every tx has inputs so an
+            // empty set means it has never been computed
+            let mut spent = HashSet::new();
+            for tx in store.cache.all_txs.values() {
+                spent.extend(tx?.input.iter().map(|i| i.previous_output));
+            }
+            store.cache.spent = spent;
+            store.cache_dirty.store(true, Ordering::Relaxed);
+        }
+        if !store.cache.issuances_indexed {
+            // caches created before the reissuance tokens were saved
+            let mut reissuance_tokens = HashMap::new();
+            for tx in store.cache.all_txs.values() {
+                reissuance_tokens.extend(issuances(&tx?));
+            }
+            store.cache.reissuance_tokens = reissuance_tokens;
+            store.cache.issuances_indexed = true;
+            store.cache_dirty.store(true, Ordering::Relaxed);
+        }
+        // caches created before the unconfidential outputs were tracked have them as failed
+        // rewinds
+        let mut explicit = vec![];
+        for outpoint in store.cache.failed_rewinds.iter() {
+            let tx = match store.cache.all_txs.get(&outpoint.txid)? {
+                Some(tx) => tx,
+                None => continue,
+            };
+            let output = tx.output.get(outpoint.vout as usize);
+            if let Some(secrets) = output.and_then(explicit_secrets) {
+                explicit.push((*outpoint, secrets));
+            }
+        }
+        if !explicit.is_empty() {
+            store.cache_unblinded(explicit)?;
+            store.cache_dirty.store(true, Ordering::Relaxed);
+        }
+        store.update_indexes()?;
+        Ok(store)
+    }
+
+    /// Empty the cache and save it, removing the saved transactions, the next sync downloads the
+    /// wallet transactions again
+    fn reset_cache(&mut self) -> Result<(), Error> {
+        let stale = std::mem::replace(
+            &mut self.cache,
+            RawCache {
+                issuances_indexed: true,
+                ..Default::default()
+            },
+        );
+        self.cache_dirty.store(true, Ordering::Relaxed);
+        self.cache.all_txs.attach(
+            self.persister.clone(),
+            self.cipher.clone(),
+            self.tx_names_key,
+        );
+        self.flush_cache()?;
+        stale.all_txs.remove_saved()
+    }
+
+    /// Drop the cache if its rewinds have been made with blinding keys other than the ones
+    /// identified by `blinding_key_id`, since the wallet transactions are cached without their
+    /// rangeproofs they are downloaded and unblinded again by the next sync
+    pub fn check_blinding_key(&mut self, blinding_key_id: sha256::Hash) -> Result<(), Error> {
+        match self.cache.blinding_key_id {
+            Some(id) if id == blinding_key_id => return Ok(()),
+            Some(_) => {
+                warn!("Blinding keys changed, initialize cache as default");
+                self.reset_cache()?;
+            }
+            None => (),
+        }
+        self.cache.blinding_key_id = Some(blinding_key_id);
+        self.cache_changed()
+    }
+
+    /// Re-derive a sample of the cached scripts, including the last used ones, and check they
+    /// match `cache.scripts` and `cache.paths`
+    pub fn verify(&self) -> Result<(), Error> {
+        let last_used = [self.cache.indexes.external, self.cache.indexes.internal];
+        for int_or_ext in 0..2u32 {
+            let last = last_used[int_or_ext as usize];
+            let sample = (0..VERIFY_SAMPLE).chain(std::iter::once(last));
+            for j in sample {
+                let path = DerivationPath::from_str(&format!("m/{}/{}", int_or_ext, j))?;
+                let script = match self.cache.scripts.get(&path) {
+                    Some(script) => script,
+                    None => continue,
+                };
+                let first_deriv = &self.first_deriv[int_or_ext as usize];
+                let derived = first_deriv.derive_pub(&self.secp, &[ChildNumber::from(j)])?;
+                if *script != self.address_type.script_pubkey(&derived.public_key)
+                    || self.cache.paths.get(script) != Some(&path)
+                {
+                    return Err(Error::InvalidStore);
+                }
+            }
+        }
+        Ok(())
     }
 
     fn flush_serializable<T: serde::Serialize>(&self, name: &str, value: &T) -> Result<(), Error> {
         let now = Instant::now();
-        let mut nonce_bytes = [0u8; 12];
-        thread_rng().fill(&mut nonce_bytes);
-        let nonce = GenericArray::from_slice(&nonce_bytes);
-        let mut plaintext = serde_cbor::to_vec(value)?;
-
-        self.cipher.encrypt_in_place(nonce, b"", &mut plaintext)?;
-        let ciphertext = plaintext;
-
-        let mut store_path = self.path.clone();
-        store_path.push(name);
-        //TODO should avoid rewriting if not changed? it involves saving plaintext (or struct hash)
-        // in the front of the file
-        let mut file = File::create(&store_path)?;
-        file.write(&nonce_bytes)?;
-        file.write(&ciphertext)?;
+        let plaintext = serde_cbor::to_vec(value)?;
+        let len = plaintext.len();
+        let hash = sha256::Hash::hash(&plaintext);
+        let mut flushed = self
+            .flushed
+            .lock()
+            .map_err(|_| Error::Generic("flushed hashes poisoned".into()))?;
+        if flushed.get(name) == Some(&hash) {
+            trace!("{} unchanged, not flushing", name);
+            return Ok(());
+        }
+        encrypt_save(name, plaintext, &*self.persister, &self.cipher)?;
+        flushed.insert(name.to_string(), hash);
         info!(
-            "flushing {} bytes on {:?} took {}ms",
-            ciphertext.len() + 16,
-            &store_path,
+            "flushing {} bytes on {} took {}ms",
+            len,
+            name,
             now.elapsed().as_millis()
         );
         Ok(())
     }
 
     fn flush_cache(&self) -> Result<(), Error> {
-        self.flush_serializable("cache", &self.cache)?;
+        // the txs are saved first so that the cache never lists a tx not saved
+        self.cache.all_txs.save()?;
+        // the fields are serialized to be compared only if `cache_changed` has been called
+        if !self.cache_dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let first_flush = !self
+            .flushed
+            .lock()
+            .map_err(|_| Error::Generic("flushed hashes poisoned".into()))?
+            .contains_key(CACHE_FIELDS);
+        let mut names = vec![];
+        for (field, value) in self.cache.fields()? {
+            self.flush_serializable(&cache_field_name(&field), &value)?;
+            names.push(field);
+        }
+        // the list is saved last so that it never lists a field not saved
+        self.flush_serializable(CACHE_FIELDS, &names)?;
+        if first_flush {
+            self.persister.remove(LEGACY_CACHE)?;
+        }
+        self.cache_dirty.store(false, Ordering::Relaxed);
         Ok(())
     }
 
     fn flush_store(&self) -> Result<(), Error> {
         self.flush_serializable("store", &self.store)?;
+        self.store_dirty.store(false, Ordering::Relaxed);
         Ok(())
     }
 
+    /// Save the store and the cache, skipping the values unchanged since the last save
     pub fn flush(&self) -> Result<(), Error> {
         self.flush_store()?;
         self.flush_cache()?;
         Ok(())
     }
 
+    /// Save the changes as soon as they are made if `autoflush`, otherwise only on `flush` and
+    /// when the store is dropped, so that the changes of a sync are saved at once. Enabling it
+    /// saves the pending changes. Errors saving on drop are only logged, call `flush` before
+    /// dropping the store to handle them.
+    pub fn set_autoflush(&mut self, autoflush: bool) -> Result<(), Error> {
+        self.autoflush = autoflush;
+        if autoflush && self.is_dirty() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Whether there are changes not saved yet, because autoflush is disabled or the cache has
+    /// been created or migrated on load
+    pub fn is_dirty(&self) -> bool {
+        self.store_dirty.load(Ordering::Relaxed) || self.cache_dirty.load(Ordering::Relaxed)
+    }
+
+    /// Record the wallet settings changed, saving them if autoflush is enabled
+    fn store_changed(&self) -> Result<(), Error> {
+        if self.autoflush {
+            self.flush_store()
+        } else {
+            self.store_dirty.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    /// Record the cache changed, saving it if autoflush is enabled. Changes of the cache not
+    /// recorded are saved only with the next recorded one.
+    pub fn cache_changed(&self) -> Result<(), Error> {
+        self.cache_dirty.store(true, Ordering::Relaxed);
+        if self.autoflush {
+            self.flush_cache()
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn get_script_batch(&self, int_or_ext: u32, batch: u32) -> Result<ScriptBatch, Error> {
         let mut result = ScriptBatch::default();
         result.cached = true;
@@ -248,21 +727,314 @@ impl StoreMeta {
                     result.cached = false;
                     let second_path = [ChildNumber::from(j)];
                     let second_deriv = first_deriv.derive_pub(&self.secp, &second_path)?;
-                    p2shwpkh_script(&second_deriv.public_key)
+                    self.address_type.script_pubkey(&second_deriv.public_key)
                 }
             };
             result.value.push((script, path));
         }
+        if !self.templates.is_empty() {
+            // template scripts follow, in the same order of the paths for every template
+            for template in self.templates.iter() {
+                for j in start..end {
+                    let path = DerivationPath::from_str(&format!("m/{}/{}", int_or_ext, j))?;
+                    let pubkey = self.derive_pubkey(int_or_ext, j)?;
+                    let script = template_script_pubkey(template.as_ref(), &pubkey);
+                    result.value.push((script, path));
+                }
+            }
+        }
         Ok(result)
     }
 
-    pub fn spent(&self) -> Result<HashSet<OutPoint>, Error> {
-        let mut result = HashSet::new();
-        for tx in self.cache.all_txs.values() {
-            let outpoints: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
-            result.extend(outpoints.into_iter());
+    fn derive_pubkey(&self, chain: u32, index: u32) -> Result<PublicKey, Error> {
+        let first_deriv = self
+            .first_deriv
+            .get(chain as usize)
+            .ok_or_else(|| Error::Generic(format!("invalid chain {}", chain)))?;
+        Ok(first_deriv
+            .derive_pub(&self.secp, &[ChildNumber::from(index)])?
+            .public_key)
+    }
+
+    /// Cache the script of the template at `template_index` for the wallet key `chain`/`index`
+    fn insert_template_script(
+        &mut self,
+        template_index: usize,
+        chain: u32,
+        index: u32,
+    ) -> Result<(), Error> {
+        let path = DerivationPath::from_str(&format!("m/{}/{}", chain, index))?;
+        let pubkey = self.derive_pubkey(chain, index)?;
+        let script = template_script_pubkey(self.templates[template_index].as_ref(), &pubkey);
+        self.cache.paths.insert(script.clone(), path);
+        self.template_scripts.insert(script, template_index);
+        Ok(())
+    }
+
+    /// Remove the scripts of the templates from `cache.paths`, since the templates are not
+    /// persisted their scripts are cached again when they are registered
+    fn drop_template_paths(&mut self) {
+        let scripts = &self.cache.scripts;
+        self.cache
+            .paths
+            .retain(|script, path| scripts.get(path) == Some(script));
+    }
+
+    /// Register `template` deriving its scripts for the cached wallet keys, returns its index
+    pub fn register_template(
+        &mut self,
+        template: Arc<dyn SpendableScript>,
+    ) -> Result<usize, Error> {
+        let template_index = self.templates.len();
+        self.templates.push(template);
+        let keys: Vec<(u32, u32)> = self
+            .cache
+            .scripts
+            .keys()
+            .filter_map(|path| match path.as_ref() {
+                [ChildNumber::Normal { index: chain }, ChildNumber::Normal { index }] => {
+                    Some((*chain, *index))
+                }
+                _ => None,
+            })
+            .collect();
+        for (chain, index) in keys {
+            self.insert_template_script(template_index, chain, index)?;
+        }
+        Ok(template_index)
+    }
+
+    pub fn template(&self, template_index: usize) -> Option<&dyn SpendableScript> {
+        self.templates.get(template_index).map(|t| t.as_ref())
+    }
+
+    /// Template locking `script`, if it is the script of a registered template
+    pub fn script_template(&self, script: &Script) -> Option<&dyn SpendableScript> {
+        self.template_scripts
+            .get(script)
+            .and_then(|i| self.template(*i))
+    }
+
+    /// Derive and cache the scripts of the external (0) or internal (1) chain up to
+    /// `up_to_index` included, returning the ones which were not cached
+    pub fn ensure_scripts(
+        &mut self,
+        chain: u32,
+        up_to_index: u32,
+    ) -> Result<Vec<(Script, DerivationPath)>, Error> {
+        let first_deriv = self
+            .first_deriv
+            .get(chain as usize)
+            .ok_or_else(|| Error::Generic(format!("invalid chain {}", chain)))?;
+        let mut derived = vec![];
+        let mut new_indexes = vec![];
+        for j in 0..=up_to_index {
+            let path = DerivationPath::from_str(&format!("m/{}/{}", chain, j))?;
+            if self.cache.scripts.contains_key(&path) {
+                continue;
+            }
+            let second_deriv = first_deriv.derive_pub(&self.secp, &[ChildNumber::from(j)])?;
+            derived.push((
+                self.address_type.script_pubkey(&second_deriv.public_key),
+                path,
+            ));
+            new_indexes.push(j);
+        }
+        for (script, path) in derived.iter() {
+            self.cache.scripts.insert(path.clone(), script.clone());
+            self.cache.paths.insert(script.clone(), path.clone());
+        }
+        for j in new_indexes {
+            for template_index in 0..self.templates.len() {
+                self.insert_template_script(template_index, chain, j)?;
+            }
+        }
+        Ok(derived)
+    }
+
+    /// Outpoints spent by the cached txs
+    pub fn spent(&self) -> &HashSet<OutPoint> {
+        &self.cache.spent
+    }
+
+    /// Add `txs` to the cache, updating the spent outpoints and the reissuance tokens
+    pub fn cache_txs<I: IntoIterator<Item = (Txid, elements::Transaction)>>(&mut self, txs: I) {
+        for (txid, tx) in txs {
+            self.cache
+                .spent
+                .extend(tx.input.iter().map(|i| i.previous_output));
+            self.cache.reissuance_tokens.extend(issuances(&tx));
+            self.cache.all_txs.insert(txid, tx);
         }
-        Ok(result)
+    }
+
+    /// Cache the unblinded values of the outputs of the cached txs, recording the outpoints
+    /// whose values do not match the output commitments and the assets received, which are
+    /// saved with the store
+    pub fn cache_unblinded<I: IntoIterator<Item = (OutPoint, elements::TxOutSecrets)>>(
+        &mut self,
+        unblinds: I,
+    ) -> Result<(), Error> {
+        let mut new_assets = false;
+        for (outpoint, secrets) in unblinds {
+            let tx = self.cache.all_txs.get(&outpoint.txid)?;
+            let output = tx
+                .as_ref()
+                .and_then(|tx| tx.output.get(outpoint.vout as usize));
+            let verified = match output {
+                Some(output) => verify_unblinded(&self.secp, output, &secrets),
+                None => false,
+            };
+            if verified {
+                self.cache.mismatched_commitments.remove(&outpoint);
+                new_assets |= self.store.known_assets.insert(secrets.asset);
+                if output.map_or(false, |o| o.value.is_explicit()) {
+                    self.cache.explicit_outputs.insert(outpoint);
+                }
+            } else {
+                warn!("{} unblinded values do not match the commitments", outpoint);
+                self.cache.mismatched_commitments.insert(outpoint);
+            }
+            self.cache.failed_rewinds.remove(&outpoint);
+            self.cache.unblinded.insert(outpoint, secrets);
+        }
+        if new_assets {
+            self.store_changed()?;
+        }
+        Ok(())
+    }
+
+    /// Remember the wallet outputs whose rangeproof could not be rewound, syncs skip them
+    pub fn cache_failed_rewinds<I: IntoIterator<Item = OutPoint>>(&mut self, outpoints: I) {
+        self.cache.failed_rewinds.extend(outpoints);
+    }
+
+    /// Update the indexes of the unspent outputs, of the txs by height, of the outputs with
+    /// unknown scripts and of the burns, must be called after changing the wallet txs. Only the
+    /// txs not indexed yet are read from `all_txs`. It also prunes the created txs, see
+    /// `prune_created`.
+    pub fn update_indexes(&mut self) -> Result<(), Error> {
+        let cache = &mut self.cache;
+        let heights = &cache.heights;
+        cache.txs_by_height = heights
+            .iter()
+            .map(|(txid, height)| (height.unwrap_or(std::u32::MAX), *txid))
+            .collect();
+
+        // txs replaced or reorged out are not wallet txs anymore
+        cache.indexed_txs.retain(|txid| heights.contains_key(txid));
+        cache
+            .indexed_burns
+            .retain(|txid, _| heights.contains_key(txid));
+        cache
+            .unknown_scripts
+            .retain(|outpoint| heights.contains_key(&outpoint.txid));
+        for txid in heights.keys() {
+            if cache.indexed_txs.contains(txid) {
+                continue;
+            }
+            let tx = match cache.all_txs.get(txid)? {
+                Some(tx) => tx,
+                None => continue,
+            };
+            let tx_burns = burns(&tx);
+            if !tx_burns.is_empty() {
+                cache.indexed_burns.insert(*txid, tx_burns);
+            }
+            for (vout, output) in tx.output.iter().enumerate() {
+                if script_kind(&output.script_pubkey) == ScriptKind::Unknown {
+                    cache
+                        .unknown_scripts
+                        .insert(OutPoint::new(*txid, vout as u32));
+                }
+            }
+            cache.indexed_txs.insert(*txid);
+        }
+
+        // the wallet outputs of the wallet txs are the unblinded ones
+        let spent = &cache.spent;
+        cache.utxos = cache
+            .unblinded
+            .keys()
+            .filter(|outpoint| heights.contains_key(&outpoint.txid) && !spent.contains(outpoint))
+            .cloned()
+            .collect();
+        let known_assets = self.known_assets();
+        self.cache.burns = self
+            .cache
+            .indexed_burns
+            .iter()
+            .filter_map(|(txid, tx_burns)| {
+                let wallet_burns: Vec<Burn> = tx_burns
+                    .iter()
+                    .filter(|burn| known_assets.contains(&burn.asset))
+                    .cloned()
+                    .collect();
+                if wallet_burns.is_empty() {
+                    None
+                } else {
+                    Some((*txid, wallet_burns))
+                }
+            })
+            .collect();
+        self.prune_created()
+    }
+
+    /// Drop the counterparties and the requests of the txs created by the wallet that cannot
+    /// be confirmed anymore, because an input has been spent by another tx or the tx funding it
+    /// has been replaced or reorged out. The ones of the wallet txs are kept with the history.
+    fn prune_created(&mut self) -> Result<(), Error> {
+        if self.store.counterparties.is_empty() && self.store.requests.is_empty() {
+            return Ok(());
+        }
+        let outpoints: HashSet<OutPoint> =
+            self.store
+                .counterparties
+                .keys()
+                .cloned()
+                .chain(self.store.requests.values().flat_map(|details| {
+                    details.transaction.input.iter().map(|i| i.previous_output)
+                }))
+                .collect();
+        let cache = &self.cache;
+        let mut spenders: HashMap<OutPoint, Arc<elements::Transaction>> = HashMap::new();
+        for txid in cache.heights.keys() {
+            if let Some(tx) = cache.all_txs.get(txid)? {
+                for input in tx.input.iter() {
+                    if outpoints.contains(&input.previous_output) {
+                        spenders.insert(input.previous_output, tx.clone());
+                    }
+                }
+            }
+        }
+        // whether `outpoint` is unspent or spent by the tx satisfying `is_created`
+        let spendable =
+            |outpoint: &OutPoint, is_created: &dyn Fn(&elements::Transaction) -> bool| {
+                cache.heights.contains_key(&outpoint.txid)
+                    && spenders.get(outpoint).map_or(true, |tx| is_created(tx))
+            };
+
+        let before = self.store.counterparties.len() + self.store.requests.len();
+        self.store
+            .counterparties
+            .retain(|outpoint, counterparties| {
+                spendable(outpoint, &|tx| {
+                    counterparties.iter().all(|c| {
+                        tx.output.get(c.vout as usize).map(|o| &o.script_pubkey)
+                            == Some(&c.script_pubkey)
+                    })
+                })
+            });
+        self.store.requests.retain(|_, details| {
+            let txid = &details.txid;
+            details.transaction.input.iter().all(|input| {
+                spendable(&input.previous_output, &|tx| tx.txid().to_string() == *txid)
+            })
+        });
+        if self.store.counterparties.len() + self.store.requests.len() != before {
+            self.store_changed()?;
+        }
+        Ok(())
     }
 
     pub fn fee_estimates(&self) -> Vec<FeeEstimate> {
@@ -278,12 +1050,34 @@ impl StoreMeta {
         self.store.liquidex_assets.clone()
     }
 
+    /// Assets received by the wallet and LiquiDEX assets, the candidates when unblinding
+    /// LiquiDEX outputs
+    pub fn known_assets(&self) -> HashSet<elements::issuance::AssetId> {
+        self.store
+            .known_assets
+            .union(&self.store.liquidex_assets)
+            .cloned()
+            .collect()
+    }
+
     pub fn liquidex_assets_insert(
         &mut self,
         asset: elements::issuance::AssetId,
     ) -> Result<bool, Error> {
         let inserted = self.store.liquidex_assets.insert(asset);
-        self.flush_store()?;
+        self.store_changed()?;
+        Ok(inserted)
+    }
+
+    /// Insert all `assets` in LiquiDEX assets, returns the number of assets not already there.
+    pub fn liquidex_assets_extend(
+        &mut self,
+        assets: &[elements::issuance::AssetId],
+    ) -> Result<usize, Error> {
+        let before = self.store.liquidex_assets.len();
+        self.store.liquidex_assets.extend(assets.iter().cloned());
+        let inserted = self.store.liquidex_assets.len() - before;
+        self.store_changed()?;
         Ok(inserted)
     }
 
@@ -292,41 +1086,1193 @@ impl StoreMeta {
         asset: &elements::issuance::AssetId,
     ) -> Result<bool, Error> {
         let removed = self.store.liquidex_assets.remove(asset);
-        self.flush_store()?;
+        self.store_changed()?;
+        Ok(removed)
+    }
+
+    pub fn liquidex_swaps(&self) -> &HashMap<OutPoint, LiquidexSwap> {
+        &self.store.liquidex_swaps
+    }
+
+    /// Heights of the confirmed transactions completing the LiquiDEX swaps, by maker utxo.
+    ///
+    /// A swap is completed by a transaction spending the maker utxo with an input of the
+    /// counterparty, the maker spending its utxo with wallet inputs only cancels the proposal.
+    pub fn liquidex_swaps_completed(&self) -> Result<HashMap<OutPoint, u32>, Error> {
+        let mut completed = HashMap::new();
+        for (txid, height) in self.cache.heights.iter() {
+            let height = match height {
+                Some(height) => *height,
+                None => continue,
+            };
+            let tx = match self.cache.all_txs.get(txid)? {
+                Some(tx) => tx,
+                None => continue,
+            };
+            if tx
+                .input
+                .iter()
+                .all(|i| self.cache.unblinded.contains_key(&i.previous_output))
+            {
+                continue;
+            }
+            for input in tx.input.iter() {
+                if self
+                    .store
+                    .liquidex_swaps
+                    .contains_key(&input.previous_output)
+                {
+                    completed.insert(input.previous_output, height);
+                }
+            }
+        }
+        Ok(completed)
+    }
+
+    pub fn liquidex_swap_insert(
+        &mut self,
+        maker_utxo: OutPoint,
+        swap: LiquidexSwap,
+    ) -> Result<(), Error> {
+        self.store.liquidex_swaps.insert(maker_utxo, swap);
+        self.store_changed()
+    }
+
+    pub fn liquidex_partial(&self, maker_utxo: &OutPoint) -> Option<&LiquidexPartial> {
+        self.store.liquidex_partials.get(maker_utxo)
+    }
+
+    pub fn liquidex_partial_insert(
+        &mut self,
+        maker_utxo: OutPoint,
+        partial: LiquidexPartial,
+    ) -> Result<(), Error> {
+        self.store.liquidex_partials.insert(maker_utxo, partial);
+        self.store_changed()
+    }
+
+    /// Remove the partial proposal of `maker_utxo`, returns it if it was not filled yet
+    pub fn liquidex_partial_remove(
+        &mut self,
+        maker_utxo: &OutPoint,
+    ) -> Result<Option<LiquidexPartial>, Error> {
+        let removed = self.store.liquidex_partials.remove(maker_utxo);
+        self.store_changed()?;
+        Ok(removed)
+    }
+
+    pub fn asset_metadata(
+        &self,
+        asset: &elements::issuance::AssetId,
+    ) -> Option<&CachedAssetMetadata> {
+        self.store.asset_metadata.get(asset)
+    }
+
+    /// The asset reissued by `token`, if it is a reissuance token issued by the cached txs or
+    /// recorded with `reissuance_token_insert`
+    pub fn reissuance_token_of(
+        &self,
+        token: &elements::issuance::AssetId,
+    ) -> Option<elements::issuance::AssetId> {
+        self.cache
+            .reissuance_tokens
+            .get(token)
+            .or_else(|| self.store.reissuance_tokens.get(token))
+            .cloned()
+    }
+
+    /// Whether `asset` is reissued by a token known to the wallet
+    pub fn is_reissuable(&self, asset: &elements::issuance::AssetId) -> bool {
+        self.cache
+            .reissuance_tokens
+            .values()
+            .chain(self.store.reissuance_tokens.values())
+            .any(|issued| issued == asset)
+    }
+
+    /// Record `token` as the reissuance token of `asset`, for the tokens not issued by the wallet
+    /// txs, returns false if it was already known
+    pub fn reissuance_token_insert(
+        &mut self,
+        token: elements::issuance::AssetId,
+        asset: elements::issuance::AssetId,
+    ) -> Result<bool, Error> {
+        if self.reissuance_token_of(&token) == Some(asset) {
+            return Ok(false);
+        }
+        self.store.reissuance_tokens.insert(token, asset);
+        self.store_changed()?;
+        Ok(true)
+    }
+
+    pub fn asset_metadata_insert(
+        &mut self,
+        asset: elements::issuance::AssetId,
+        metadata: CachedAssetMetadata,
+    ) -> Result<(), Error> {
+        self.store.asset_metadata.insert(asset, metadata);
+        self.store_changed()
+    }
+
+    pub fn asset_icon(&self, asset: &elements::issuance::AssetId) -> Option<&String> {
+        self.store.asset_icons.get(asset)
+    }
+
+    pub fn icons_etag(&self) -> Option<&String> {
+        self.store.icons_etag.as_ref()
+    }
+
+    /// Replace the cached icons with the bundle received with `etag`
+    pub fn asset_icons_set(
+        &mut self,
+        icons: HashMap<elements::issuance::AssetId, String>,
+        etag: Option<String>,
+    ) -> Result<(), Error> {
+        self.store.asset_icons = icons;
+        self.store.icons_etag = etag;
+        self.store_changed()
+    }
+
+    pub fn broadcast_queue(&self) -> &HashMap<Txid, QueuedTx> {
+        &self.store.broadcast_queue
+    }
+
+    pub fn broadcast_queue_insert(&mut self, queued: QueuedTx) -> Result<(), Error> {
+        self.store
+            .broadcast_queue
+            .insert(queued.transaction.txid(), queued);
+        self.store_changed()
+    }
+
+    pub fn broadcast_queue_remove(&mut self, txid: &Txid) -> Result<(), Error> {
+        self.store.broadcast_queue.remove(txid);
+        self.store_changed()
+    }
+
+    pub fn invoice(&self, id: &str) -> Option<&Invoice> {
+        self.store.invoices.get(id)
+    }
+
+    pub fn invoices(&self) -> &HashMap<String, Invoice> {
+        &self.store.invoices
+    }
+
+    /// Counterparties recorded when creating a tx spending `outpoint` first
+    pub fn counterparties(&self, outpoint: &OutPoint) -> Option<&Vec<Counterparty>> {
+        self.store.counterparties.get(outpoint)
+    }
+
+    pub fn counterparties_insert(
+        &mut self,
+        outpoint: OutPoint,
+        counterparties: Vec<Counterparty>,
+    ) -> Result<(), Error> {
+        self.store.counterparties.insert(outpoint, counterparties);
+        self.store_changed()
+    }
+
+    pub fn request(&self, request_id: &str) -> Option<&TransactionDetails> {
+        self.store.requests.get(request_id)
+    }
+
+    pub fn request_insert(
+        &mut self,
+        request_id: String,
+        details: TransactionDetails,
+    ) -> Result<(), Error> {
+        self.store.requests.insert(request_id, details);
+        self.store_changed()
+    }
+
+    /// Replace the transaction of the requests spending the same outpoints of `tx`, such as its
+    /// unsigned version, returns whether a request has been updated
+    pub fn requests_update(&mut self, tx: &elements::Transaction) -> Result<bool, Error> {
+        let outpoints: HashSet<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
+        let txid = tx.txid().to_string();
+        let mut updated = false;
+        for details in self.store.requests.values_mut() {
+            let spends_same = details.transaction.input.len() == outpoints.len()
+                && details
+                    .transaction
+                    .input
+                    .iter()
+                    .all(|i| outpoints.contains(&i.previous_output));
+            if spends_same && details.txid != txid {
+                details.transaction = tx.clone();
+                details.txid = txid.clone();
+                updated = true;
+            }
+        }
+        if updated {
+            self.store_changed()?;
+        }
+        Ok(updated)
+    }
+
+    pub fn invoice_insert(&mut self, invoice: Invoice) -> Result<(), Error> {
+        self.store.invoices.insert(invoice.id.clone(), invoice);
+        self.store_changed()
+    }
+
+    /// Labels of the wallet `script`
+    pub fn labels(&self, script: &Script) -> HashSet<String> {
+        self.store.labels.get(script).cloned().unwrap_or_default()
+    }
+
+    /// The wallet `script` has at least one of `labels`
+    pub fn has_label(&self, script: &Script, labels: &[String]) -> bool {
+        self.store
+            .labels
+            .get(script)
+            .map_or(false, |l| labels.iter().any(|label| l.contains(label)))
+    }
+
+    pub fn label_insert(&mut self, script: Script, label: &str) -> Result<(), Error> {
+        self.store
+            .labels
+            .entry(script)
+            .or_default()
+            .insert(label.to_string());
+        self.store_changed()
+    }
+
+    /// Label `script` with `label` once a transaction paying it is signed, see
+    /// `pending_labels_signed`
+    pub fn pending_label_insert(&mut self, script: Script, label: &str) {
+        self.pending_labels.insert(script, label.to_string());
+    }
+
+    /// Insert the pending labels of the outputs of the signed `tx`
+    pub fn pending_labels_signed(&mut self, tx: &elements::Transaction) -> Result<(), Error> {
+        for output in tx.output.iter() {
+            if let Some(label) = self.pending_labels.remove(&output.script_pubkey) {
+                self.label_insert(output.script_pubkey.clone(), &label)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contact(&self, name: &str) -> Option<&Contact> {
+        self.store.contacts.get(name)
+    }
+
+    /// Address book contacts, sorted by name
+    pub fn contacts(&self) -> Vec<Contact> {
+        let mut contacts: Vec<Contact> = self.store.contacts.values().cloned().collect();
+        contacts.sort_by(|a, b| a.name.cmp(&b.name));
+        contacts
+    }
+
+    /// Add `contact`, replacing the contact with the same name
+    pub fn contact_insert(&mut self, contact: Contact) -> Result<(), Error> {
+        self.store.contacts.insert(contact.name.clone(), contact);
+        self.store_changed()
+    }
+
+    pub fn contact_remove(&mut self, name: &str) -> Result<Option<Contact>, Error> {
+        let removed = self.store.contacts.remove(name);
+        self.store_changed()?;
         Ok(removed)
     }
+
+    /// Virtual account of the utxo `outpoint` locked by `script`: the account it has been
+    /// assigned to or, if not assigned, the first label in order of the address it has been
+    /// received on, otherwise `DEFAULT_ACCOUNT`. Every utxo belongs to exactly one account.
+    pub fn utxo_account(&self, outpoint: &OutPoint, script: &Script) -> &str {
+        match self.store.utxo_accounts.get(outpoint) {
+            Some(assigned) => assigned,
+            None => self
+                .store
+                .labels
+                .get(script)
+                .and_then(|l| l.iter().min())
+                .map_or(DEFAULT_ACCOUNT, |l| l.as_str()),
+        }
+    }
+
+    /// The utxo `outpoint` locked by `script` belongs to the virtual `account`, see
+    /// `utxo_account`
+    pub fn in_account(&self, outpoint: &OutPoint, script: &Script, account: &str) -> bool {
+        self.utxo_account(outpoint, script) == account
+    }
+
+    pub fn utxo_account_insert(&mut self, outpoint: OutPoint, account: &str) -> Result<(), Error> {
+        self.store
+            .utxo_accounts
+            .insert(outpoint, account.to_string());
+        self.store_changed()
+    }
+
+    pub fn label_remove(&mut self, script: &Script, label: &str) -> Result<(), Error> {
+        if let Some(labels) = self.store.labels.get_mut(script) {
+            labels.remove(label);
+            if labels.is_empty() {
+                self.store.labels.remove(script);
+            }
+        }
+        self.store_changed()
+    }
+}
+
+impl StoreSnapshot {
+    /// Cached transaction, could be a wallet transaction or a previous transaction of one, fails
+    /// if it cannot be loaded from the store
+    pub fn transaction(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error> {
+        self.cache.all_txs.get(txid)
+    }
+
+    /// Unblinded wallet outputs, if `asset` is some only the outputs of that asset
+    pub fn unblinded(
+        &self,
+        asset: Option<&elements::issuance::AssetId>,
+    ) -> HashMap<OutPoint, elements::TxOutSecrets> {
+        self.cache
+            .unblinded
+            .iter()
+            .filter(|(_, u)| asset.map_or(true, |a| u.asset == *a))
+            .map(|(o, u)| (*o, u.clone()))
+            .collect()
+    }
+
+    /// All the derived scripts with their derivation paths
+    pub fn scripts(&self) -> &HashMap<Script, DerivationPath> {
+        &self.cache.paths
+    }
+
+    /// Wallet transactions with their heights (None if unconfirmed)
+    pub fn heights(&self) -> &HashMap<Txid, Option<u32>> {
+        &self.cache.heights
+    }
+
+    /// Assets that might be received by a LiquiDEX maker
+    pub fn liquidex_assets(&self) -> &HashSet<elements::issuance::AssetId> {
+        &self.store.liquidex_assets
+    }
+
+    /// Invoices created by the wallet, by id
+    pub fn invoices(&self) -> &HashMap<String, Invoice> {
+        &self.store.invoices
+    }
 }
 
 impl StoreMeta {
+    pub fn snapshot(&self) -> StoreSnapshot {
+        StoreSnapshot {
+            cache: self.cache.clone(),
+            store: self.store.clone(),
+        }
+    }
+
+    /// Bytes taken by the saved store, None if the persister cannot tell
+    pub fn size(&self) -> Result<Option<u64>, Error> {
+        self.persister.size()
+    }
+
+    /// Replace the wallet settings with the ones restored from a backup of the same network
+    pub fn restore(&mut self, mut store: RawStore) -> Result<(), Error> {
+        if store.network.is_some() && store.network != self.store.network {
+            return Err(Error::NetworkMismatch);
+        }
+        store.network = self.store.network;
+        self.store = store;
+        self.store_changed()
+    }
+
     pub fn export_cache(&self) -> Result<RawCache, Error> {
         self.flush_cache()?;
-        RawCache::try_new(&self.path, &self.cipher)
+        let mut cache = RawCache::try_new(&*self.persister, &self.cipher)?;
+        cache.all_txs.attach(
+            self.persister.clone(),
+            self.cipher.clone(),
+            self.tx_names_key,
+        );
+        Ok(cache)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::store::StoreMeta;
+    use crate::amount::Amount;
+    use crate::model::{Burn, Contact, Counterparty, SPVVerifyResult, TransactionDetails};
+    use crate::network::ElementsNetwork;
+    use crate::persister::{MemoryPersister, Persister};
+    use crate::spendable::CsvScript;
+    use crate::store::{encrypt_save, StoreMeta, CACHE_FIELDS, DEFAULT_ACCOUNT, LEGACY_CACHE};
+    use crate::transaction::{add_input, explicit_secrets};
+    use crate::Error;
     use elements::bitcoin::hashes::hex::FromHex;
-    use elements::bitcoin::util::bip32::ExtendedPubKey;
+    use elements::bitcoin::hashes::{sha256, Hash};
+    use elements::bitcoin::util::bip32::{DerivationPath, ExtendedPubKey};
+    use elements::issuance::AssetId;
     use elements::Txid;
+    use std::collections::HashMap;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use tempdir::TempDir;
 
+    fn test_xpub() -> ExtendedPubKey {
+        ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4YfG9CySHqKHFbaLcD7hSDyqRUtCmMKNim5fkiJtTnFeqKsRHMHSK5ddFrhqRr3Ghv1JtuWkBzikuBqKu1xCpjQ9YxoPGgqU").unwrap()
+    }
+
+    /// Store of the test xpub kept in `persister`, opening it again gives the same store
+    fn persisted_store<P: Persister + Clone + 'static>(persister: &P) -> StoreMeta {
+        StoreMeta::new_with_persister(
+            Box::new(persister.clone()),
+            test_xpub(),
+            ElementsNetwork::Liquid,
+        )
+        .unwrap()
+    }
+
+    fn memory_store() -> StoreMeta {
+        persisted_store(&MemoryPersister::new())
+    }
+
     #[test]
     fn test_db_roundtrip() {
         let mut dir = TempDir::new("unit_test").unwrap().into_path();
         dir.push("store");
-        let xpub = ExtendedPubKey::from_str("tpubD6NzVbkrYhZ4YfG9CySHqKHFbaLcD7hSDyqRUtCmMKNim5fkiJtTnFeqKsRHMHSK5ddFrhqRr3Ghv1JtuWkBzikuBqKu1xCpjQ9YxoPGgqU").unwrap();
+        let xpub = test_xpub();
+        let txid =
+            Txid::from_hex("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16")
+                .unwrap();
+
+        let mut store = StoreMeta::new(&dir, xpub, ElementsNetwork::Liquid).unwrap();
+        store.cache.heights.insert(txid, Some(1));
+        drop(store);
+
+        let store = StoreMeta::new(&dir, xpub, ElementsNetwork::Liquid).unwrap();
+        assert_eq!(store.cache.heights.get(&txid), Some(&Some(1)));
+        drop(store);
+
+        assert!(matches!(
+            StoreMeta::new(&dir, xpub, ElementsNetwork::ElementsRegtest),
+            Err(Error::NetworkMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let persister = MemoryPersister::new();
+        let mut store = persisted_store(&persister);
+        let batch = store.get_script_batch(0, 0).unwrap();
+        for (script, path) in batch.value {
+            store.cache.paths.insert(script.clone(), path.clone());
+            store.cache.scripts.insert(path, script);
+        }
+        assert!(store.verify().is_ok());
+
+        let path = DerivationPath::from_str("m/0/1").unwrap();
+        let other = store.cache.scripts.get(&path).unwrap().clone();
+        let path = DerivationPath::from_str("m/0/0").unwrap();
+        store.cache.scripts.insert(path, other);
+        assert!(matches!(store.verify(), Err(Error::InvalidStore)));
+        store.flush().unwrap();
+        drop(store);
+
+        // a cache not matching the keys is discarded
+        let store = persisted_store(&persister);
+        assert!(store.cache.scripts.is_empty());
+    }
+
+    #[test]
+    fn test_spent_migration() {
+        let persister = MemoryPersister::new();
+        let prevout = elements::OutPoint::new(
+            Txid::from_hex("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16")
+                .unwrap(),
+            1,
+        );
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![elements::TxIn {
+                previous_output: prevout,
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+
+        let mut store = persisted_store(&persister);
+        store.cache_txs(vec![(tx.txid(), tx)]);
+        assert!(store.spent().contains(&prevout));
+
+        // simulate a cache saved before the spent outpoints were persisted
+        store.cache.spent.clear();
+        drop(store);
+
+        let store = persisted_store(&persister);
+        assert!(store.spent().contains(&prevout));
+    }
+
+    #[derive(Clone, Default)]
+    struct CountingPersister {
+        inner: MemoryPersister,
+        saves: Arc<AtomicUsize>,
+    }
+
+    impl Persister for CountingPersister {
+        fn load(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.inner.load(name)
+        }
+
+        fn save(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+            self.saves.fetch_add(1, Ordering::SeqCst);
+            self.inner.save(name, bytes)
+        }
+
+        fn remove(&self, name: &str) -> Result<(), Error> {
+            self.inner.remove(name)
+        }
+    }
+
+    #[test]
+    fn test_pending_labels() {
+        let mut store = memory_store();
+        let labels = vec!["fees".to_string()];
+        let script = elements::Script::from(vec![0x51]);
+        store.pending_label_insert(script.clone(), &labels[0]);
+        assert!(!store.has_label(&script, &labels));
+
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        store.pending_labels_signed(&tx).unwrap();
+        assert!(!store.has_label(&script, &labels));
+        tx.output.push(elements::TxOut {
+            script_pubkey: script.clone(),
+            ..Default::default()
+        });
+        store.pending_labels_signed(&tx).unwrap();
+        assert!(store.has_label(&script, &labels));
+        assert!(store.pending_labels.is_empty());
+    }
+
+    #[test]
+    fn test_autoflush() {
+        let persister = CountingPersister::default();
+        let reopen = || persisted_store(&persister);
+        let assets: Vec<AssetId> = (1u8..4)
+            .map(|i| AssetId::from_slice(&[i; 32]).unwrap())
+            .collect();
+
+        let mut store = reopen();
+        store.flush().unwrap();
+        let saves = persister.saves.load(Ordering::SeqCst);
+        // unchanged values are not saved again
+        store.flush().unwrap();
+        assert_eq!(persister.saves.load(Ordering::SeqCst), saves);
+        store.liquidex_assets_insert(assets[0]).unwrap();
+        assert_eq!(persister.saves.load(Ordering::SeqCst), saves + 1);
+        assert!(!store.is_dirty());
+
+        store.set_autoflush(false).unwrap();
+        store.liquidex_assets_insert(assets[1]).unwrap();
+        store.liquidex_assets_insert(assets[2]).unwrap();
+        store
+            .cache
+            .heights
+            .insert(Txid::from_slice(&[1u8; 32]).unwrap(), None);
+        store.cache_changed().unwrap();
+        assert!(store.is_dirty());
+        assert_eq!(persister.saves.load(Ordering::SeqCst), saves + 1);
+        assert_eq!(reopen().liquidex_assets().len(), 1);
+
+        // the pending changes are saved at once
+        let saves = persister.saves.load(Ordering::SeqCst);
+        store.set_autoflush(true).unwrap();
+        assert!(!store.is_dirty());
+        assert_eq!(persister.saves.load(Ordering::SeqCst), saves + 2);
+        let reopened = reopen();
+        assert_eq!(reopened.liquidex_assets().len(), 3);
+        assert_eq!(reopened.cache.heights.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_fields() {
+        let persister = CountingPersister::default();
+        let reopen = || persisted_store(&persister);
+        let txid = Txid::from_slice(&[1u8; 32]).unwrap();
+
+        let mut store = reopen();
+        store.flush().unwrap();
+        let saves = persister.saves.load(Ordering::SeqCst);
+        store.cache.heights.insert(txid, Some(1));
+        store.cache_changed().unwrap();
+        // only the changed field is saved
+        assert_eq!(persister.saves.load(Ordering::SeqCst), saves + 1);
+        assert!(persister.load("cache_heights").unwrap().is_some());
+        assert!(persister.load(LEGACY_CACHE).unwrap().is_none());
+
+        // caches saved whole are loaded and saved by field with the next flush
+        let legacy = MemoryPersister::new();
+        let plaintext = serde_cbor::to_vec(&store.cache).unwrap();
+        encrypt_save(LEGACY_CACHE, plaintext, &legacy, &store.cipher).unwrap();
+        drop(store);
+        assert_eq!(reopen().cache.heights.get(&txid), Some(&Some(1)));
+        persister.inner.remove(CACHE_FIELDS).unwrap();
+        persister.inner.remove("cache_heights").unwrap();
+        let legacy = legacy.load(LEGACY_CACHE).unwrap().unwrap();
+        persister.inner.save(LEGACY_CACHE, &legacy).unwrap();
+        let store = reopen();
+        assert_eq!(store.cache.heights.get(&txid), Some(&Some(1)));
+        drop(store);
+        assert!(persister.load(LEGACY_CACHE).unwrap().is_none());
+        assert!(persister.load(CACHE_FIELDS).unwrap().is_some());
+        assert_eq!(reopen().cache.heights.get(&txid), Some(&Some(1)));
+    }
+
+    #[test]
+    fn test_txs_by_height() {
+        let mut store = memory_store();
+        let txids: Vec<Txid> = (1u8..4)
+            .map(|i| Txid::from_slice(&[i; 32]).unwrap())
+            .collect();
+        store.cache.heights.insert(txids[0], Some(10));
+        store.cache.heights.insert(txids[1], None);
+        store.cache.heights.insert(txids[2], Some(5));
+        store.update_indexes().unwrap();
+        let newest_first: Vec<Txid> = store
+            .cache
+            .txs_by_height
+            .iter()
+            .rev()
+            .map(|(_, txid)| *txid)
+            .collect();
+        assert_eq!(newest_first, vec![txids[1], txids[0], txids[2]]);
+    }
+
+    #[test]
+    fn test_unknown_scripts() {
+        let mut store = memory_store();
+        // witness v1 program, not interpreted by the wallet
+        let mut unknown = vec![0x51, 0x20];
+        unknown.extend_from_slice(&[1u8; 32]);
+        let known = elements::Script::new_v0_wpkh(&elements::WPubkeyHash::hash(&[2u8; 33]));
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![
+                elements::TxOut {
+                    script_pubkey: known,
+                    ..Default::default()
+                },
+                elements::TxOut {
+                    script_pubkey: unknown.into(),
+                    ..Default::default()
+                },
+            ],
+        };
+        let txid = tx.txid();
+        store.cache.all_txs.insert(txid, tx);
+        store.cache.heights.insert(txid, Some(1));
+        store.update_indexes().unwrap();
+        let expected: std::collections::HashSet<elements::OutPoint> =
+            vec![elements::OutPoint::new(txid, 1)].into_iter().collect();
+        assert_eq!(store.cache.unknown_scripts, expected);
+
+        // indexed txs are not read again
+        store.cache.all_txs.insert(
+            txid,
+            elements::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![],
+            },
+        );
+        store.update_indexes().unwrap();
+        assert_eq!(store.cache.unknown_scripts, expected);
+        assert!(store.cache.indexed_txs.contains(&txid));
+
+        // txs not in the wallet anymore are dropped from the indexes
+        store.cache.heights.clear();
+        store.update_indexes().unwrap();
+        assert!(store.cache.unknown_scripts.is_empty());
+        assert!(store.cache.indexed_txs.is_empty());
+    }
+
+    #[test]
+    fn test_liquidex_swaps_completed() {
+        let mut store = memory_store();
+        let asset = AssetId::from_slice(&[1u8; 32]).unwrap();
+        let outpoint = |n: u8| elements::OutPoint::new(Txid::from_slice(&[n; 32]).unwrap(), 0);
+        let (cancelled, taken, counterparty) = (outpoint(1), outpoint(2), outpoint(3));
+        for maker_utxo in [cancelled, taken].iter() {
+            let swap = crate::liquidex::LiquidexSwap {
+                sent_asset: asset,
+                sent_value: crate::Amount::from_sat(1_000),
+                received_asset: asset,
+                received_value: crate::Amount::from_sat(2_000),
+            };
+            store.liquidex_swap_insert(*maker_utxo, swap).unwrap();
+            let output = elements::TxOut {
+                asset: elements::confidential::Asset::Explicit(asset),
+                value: elements::confidential::Value::Explicit(1_000),
+                script_pubkey: elements::Script::from(vec![0x51]),
+                ..Default::default()
+            };
+            store
+                .cache
+                .unblinded
+                .insert(*maker_utxo, explicit_secrets(&output).unwrap());
+        }
+
+        let mut height = 0;
+        for inputs in [vec![cancelled], vec![taken, counterparty]].iter() {
+            let mut tx = elements::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![],
+            };
+            for input in inputs.iter() {
+                add_input(&mut tx, *input);
+            }
+            height += 1;
+            let txid = tx.txid();
+            store.cache.all_txs.insert(txid, tx);
+            store.cache.heights.insert(txid, Some(height));
+        }
+        let completed = store.liquidex_swaps_completed().unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed.get(&taken), Some(&2));
+    }
+
+    #[test]
+    fn test_burns() {
+        let mut store = memory_store();
+        let asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        let other = elements::issuance::AssetId::from_slice(&[2u8; 32]).unwrap();
+        store.liquidex_assets_insert(asset).unwrap();
+        let burn = |asset, satoshi, script_pubkey| elements::TxOut {
+            asset: elements::confidential::Asset::Explicit(asset),
+            value: elements::confidential::Value::Explicit(satoshi),
+            script_pubkey,
+            ..Default::default()
+        };
+        let op_return = elements::script::Builder::new()
+            .push_opcode(elements::opcodes::all::OP_RETURN)
+            .into_script();
+        let pegout = crate::scripts::pegout_script(
+            &elements::bitcoin::BlockHash::default(),
+            &elements::bitcoin::Script::new(),
+        );
+        let tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![
+                burn(asset, 0, op_return.clone()),
+                burn(asset, 1_000, op_return.clone()),
+                burn(other, 1_000, op_return),
+                burn(asset, 2_000, pegout),
+            ],
+        };
+        let txid = tx.txid();
+        store.cache.all_txs.insert(txid, tx);
+        store.cache.heights.insert(txid, None);
+        store.update_indexes().unwrap();
+        let expected = vec![Burn {
+            vout: 1,
+            asset,
+            satoshi: Amount::from_sat(1_000),
+        }];
+        assert_eq!(store.cache.burns.get(&txid), Some(&expected));
+    }
+
+    #[test]
+    fn test_reissuance_tokens() {
+        let persister = MemoryPersister::new();
+        let mut store = persisted_store(&persister);
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        tx.input[0].has_issuance = true;
+        tx.input[0].asset_issuance.amount = elements::confidential::Value::Explicit(10);
+        tx.input[0].asset_issuance.inflation_keys = elements::confidential::Value::Explicit(1);
+        let (asset, token) = tx.input[0].issuance_ids();
+
+        store.cache_txs(vec![(tx.txid(), tx)]);
+        assert_eq!(store.cache.reissuance_tokens.get(&token), Some(&asset));
+        assert!(store.cache.reissuance_tokens.get(&asset).is_none());
+
+        // saved with the cache, the txs are not loaded again
+        store.cache_changed().unwrap();
+        drop(store);
+        let store = persisted_store(&persister);
+        assert!(store.cache.issuances_indexed);
+        assert_eq!(store.cache.reissuance_tokens.get(&token), Some(&asset));
+        assert_eq!(store.reissuance_token_of(&token), Some(asset));
+        assert!(store.is_reissuable(&asset));
+    }
+
+    #[test]
+    fn test_received_reissuance_tokens() {
+        let persister = MemoryPersister::new();
+        let reopen = || persisted_store(&persister);
+        let asset = AssetId::from_slice(&[1u8; 32]).unwrap();
+        let token = AssetId::from_slice(&[2u8; 32]).unwrap();
+
+        let mut store = reopen();
+        assert_eq!(store.reissuance_token_of(&token), None);
+        assert!(store.reissuance_token_insert(token, asset).unwrap());
+        assert!(!store.reissuance_token_insert(token, asset).unwrap());
+        assert_eq!(store.reissuance_token_of(&token), Some(asset));
+        assert_eq!(store.reissuance_token_of(&asset), None);
+        assert!(store.is_reissuable(&asset));
+        drop(store);
+
+        // kept with the store, they are not in the cached txs
+        let store = reopen();
+        assert_eq!(store.reissuance_token_of(&token), Some(asset));
+    }
+
+    #[test]
+    fn test_cache_unblinded() {
+        use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+        use elements::secp256k1_zkp::{Generator, PedersenCommitment, Secp256k1, Tag};
+
+        let persister = MemoryPersister::new();
+        let reopen = || persisted_store(&persister);
+        let mut store = reopen();
+        let secp = Secp256k1::new();
+        let asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        let asset_bf = AssetBlindingFactor::from_slice(&[2u8; 32]).unwrap();
+        let value_bf = ValueBlindingFactor::from_slice(&[3u8; 32]).unwrap();
+        let generator = Generator::new_blinded(
+            &secp,
+            Tag::from(asset.into_inner().into_inner()),
+            asset_bf.into_inner(),
+        );
+        let commitment = PedersenCommitment::new(&secp, 1_000, value_bf.into_inner(), generator);
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![elements::TxOut {
+                asset: elements::confidential::Asset::Confidential(generator),
+                value: elements::confidential::Value::Confidential(commitment),
+                ..Default::default()
+            }],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let outpoint = elements::OutPoint {
+            txid: tx.txid(),
+            vout: 0,
+        };
+        store.cache_txs(vec![(tx.txid(), tx)]);
+
+        let secrets = |value| elements::TxOutSecrets {
+            asset,
+            asset_bf,
+            value,
+            value_bf,
+        };
+        store
+            .cache_unblinded(vec![(outpoint, secrets(2_000))])
+            .unwrap();
+        assert!(store.cache.mismatched_commitments.contains(&outpoint));
+        assert!(store.known_assets().is_empty());
+        store
+            .cache_unblinded(vec![(outpoint, secrets(1_000))])
+            .unwrap();
+        assert!(store.known_assets().contains(&asset));
+        assert!(!store.cache.mismatched_commitments.contains(&outpoint));
+        assert_eq!(store.cache.unblinded[&outpoint].value, 1_000);
+        // the assets received are saved without waiting for a flush
+        assert!(reopen().known_assets().contains(&asset));
+    }
+
+    #[test]
+    fn test_explicit_outputs() {
+        let persister = MemoryPersister::new();
+        let asset = AssetId::from_slice(&[1u8; 32]).unwrap();
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![elements::TxOut {
+                asset: elements::confidential::Asset::Explicit(asset),
+                value: elements::confidential::Value::Explicit(1_000),
+                script_pubkey: elements::Script::from(vec![0x51]),
+                ..Default::default()
+            }],
+        };
+        add_input(&mut tx, elements::OutPoint::default());
+        let outpoint = elements::OutPoint::new(tx.txid(), 0);
+        let secrets = explicit_secrets(&tx.output[0]).unwrap();
+        assert_eq!(secrets.value, 1_000);
+
+        let mut store = persisted_store(&persister);
+        store.cache.heights.insert(tx.txid(), Some(100));
+        store.cache_txs(vec![(tx.txid(), tx)]);
+        let mut wrong = secrets;
+        wrong.value = 2_000;
+        store.cache_unblinded(vec![(outpoint, wrong)]).unwrap();
+        assert!(store.cache.mismatched_commitments.contains(&outpoint));
+        assert!(!store.cache.explicit_outputs.contains(&outpoint));
+
+        // simulate a cache saved before the unconfidential outputs were tracked
+        store.cache.unblinded.clear();
+        store.cache.mismatched_commitments.clear();
+        store.cache_failed_rewinds(vec![outpoint]);
+        store.flush().unwrap();
+        drop(store);
+
+        let store = persisted_store(&persister);
+        assert!(store.cache.explicit_outputs.contains(&outpoint));
+        assert!(!store.cache.failed_rewinds.contains(&outpoint));
+        assert!(!store.cache.mismatched_commitments.contains(&outpoint));
+        assert_eq!(store.cache.unblinded[&outpoint].value, 1_000);
+        assert!(store.cache.utxos.contains(&outpoint));
+    }
+
+    #[test]
+    fn test_rewinds_cache() {
+        let persister = MemoryPersister::new();
+        let reopen = || persisted_store(&persister);
+        let mut store = reopen();
+        store.set_autoflush(false).unwrap();
+        let outpoint = elements::OutPoint::default();
+        let key_id = sha256::Hash::hash(b"blinding key");
+
+        store.check_blinding_key(key_id).unwrap();
+        assert!(!store.cache.is_rewound(&outpoint));
+        store.cache_failed_rewinds(vec![outpoint]);
+        assert!(store.cache.is_rewound(&outpoint));
+        store.cache.heights.insert(outpoint.txid, None);
+        store.cache_changed().unwrap();
+        store.flush().unwrap();
+        assert_eq!(reopen().cache.heights.len(), 1);
+
+        // the same keys keep the cache, other keys drop it
+        store.check_blinding_key(key_id).unwrap();
+        assert!(store.cache.is_rewound(&outpoint));
+        store
+            .check_blinding_key(sha256::Hash::hash(b"other key"))
+            .unwrap();
+        assert!(!store.cache.is_rewound(&outpoint));
+        assert!(store.cache.heights.is_empty());
+        assert_eq!(
+            store.cache.blinding_key_id,
+            Some(sha256::Hash::hash(b"other key"))
+        );
+        // the empty cache is saved at once, also without autoflush
+        let reopened = reopen();
+        assert!(reopened.cache.heights.is_empty());
+        assert!(!reopened.cache.is_rewound(&outpoint));
+    }
+
+    #[test]
+    fn test_ensure_scripts() {
+        let mut store = memory_store();
+        assert!(!store.get_script_batch(1, 0).unwrap().cached);
+        assert_eq!(store.ensure_scripts(1, 9).unwrap().len(), 10);
+        assert_eq!(store.ensure_scripts(1, 19).unwrap().len(), 10);
+        assert!(store.ensure_scripts(1, 19).unwrap().is_empty());
+        assert!(store.get_script_batch(1, 0).unwrap().cached);
+        assert!(store.verify().is_ok());
+        assert!(store.ensure_scripts(2, 0).is_err());
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut store = memory_store();
+        let script = elements::Script::from(vec![0u8; 22]);
+        let labels = vec!["savings".to_string()];
+        assert!(!store.has_label(&script, &labels));
+        store.label_insert(script.clone(), "savings").unwrap();
+        store.label_insert(script.clone(), "shop").unwrap();
+        assert!(store.has_label(&script, &labels));
+        assert_eq!(store.labels(&script).len(), 2);
+        store.label_remove(&script, "savings").unwrap();
+        assert!(!store.has_label(&script, &labels));
+        assert!(store.labels(&script).contains("shop"));
+    }
+
+    #[test]
+    fn test_contacts() {
+        let persister = MemoryPersister::new();
+        let mut store = persisted_store(&persister);
+        let address =
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d";
+        store
+            .contact_insert(Contact::new("bob", address, vec![]).unwrap())
+            .unwrap();
+        store
+            .contact_insert(Contact::new("alice", address, vec![]).unwrap())
+            .unwrap();
+        let names: Vec<_> = store.contacts().into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["alice", "bob"]);
+        drop(store);
+
+        let mut store = persisted_store(&persister);
+        assert_eq!(store.contact("bob").unwrap().address, address);
+        assert!(store.contact_remove("bob").unwrap().is_some());
+        assert!(store.contact("bob").is_none());
+        assert!(store.contact_remove("bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_in_account() {
+        let mut store = memory_store();
+        let script = elements::Script::from(vec![0u8; 22]);
+        let outpoint = elements::OutPoint::default();
+        assert!(!store.in_account(&outpoint, &script, "treasury"));
+        assert!(store.in_account(&outpoint, &script, DEFAULT_ACCOUNT));
+        store.label_insert(script.clone(), "treasury").unwrap();
+        assert!(store.in_account(&outpoint, &script, "treasury"));
+        assert!(!store.in_account(&outpoint, &script, DEFAULT_ACCOUNT));
+        // an address with several labels puts its utxos in one account only
+        store.label_insert(script.clone(), "shop").unwrap();
+        assert_eq!(store.utxo_account(&outpoint, &script), "shop");
+        assert!(!store.in_account(&outpoint, &script, "treasury"));
+        store.utxo_account_insert(outpoint, "payroll").unwrap();
+        assert!(!store.in_account(&outpoint, &script, "treasury"));
+        assert!(!store.in_account(&outpoint, &script, "shop"));
+        assert!(store.in_account(&outpoint, &script, "payroll"));
+    }
+
+    #[test]
+    fn test_register_template() {
+        let persister = MemoryPersister::new();
+        let mut store = persisted_store(&persister);
+        store.ensure_scripts(0, 9).unwrap();
+        let template = std::sync::Arc::new(CsvScript { blocks: 10 });
+        assert_eq!(store.register_template(template).unwrap(), 0);
+        assert_eq!(store.cache.paths.len(), 20);
+        store.ensure_scripts(0, 19).unwrap();
+        assert_eq!(store.cache.paths.len(), 40);
+        assert_eq!(store.cache.scripts.len(), 20);
+
+        let batch = store.get_script_batch(0, 0).unwrap();
+        assert_eq!(batch.value.len(), 40);
+        let (template_script, path) = &batch.value[20];
+        assert_eq!(path, &DerivationPath::from_str("m/0/0").unwrap());
+        assert!(store.script_template(template_script).is_some());
+        assert!(store.script_template(&batch.value[0].0).is_none());
+        assert!(store.verify().is_ok());
+        let template_script = template_script.clone();
+        drop(store);
+
+        // the template scripts are known again only once the template is registered
+        let mut store = persisted_store(&persister);
+        assert_eq!(store.cache.paths.len(), 20);
+        assert!(!store.cache.paths.contains_key(&template_script));
+        assert!(store.script_template(&template_script).is_none());
+        let template = std::sync::Arc::new(CsvScript { blocks: 10 });
+        assert_eq!(store.register_template(template).unwrap(), 0);
+        assert_eq!(store.cache.paths.len(), 40);
+        assert!(store.script_template(&template_script).is_some());
+    }
+
+    #[test]
+    fn test_memory_roundtrip() {
+        let persister = MemoryPersister::new();
         let txid =
             Txid::from_hex("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16")
                 .unwrap();
 
-        let mut store = StoreMeta::new(&dir, xpub).unwrap();
+        let mut store = persisted_store(&persister);
         store.cache.heights.insert(txid, Some(1));
         drop(store);
 
-        let store = StoreMeta::new(&dir, xpub).unwrap();
+        let store = persisted_store(&persister);
         assert_eq!(store.cache.heights.get(&txid), Some(&Some(1)));
     }
+
+    #[test]
+    fn test_prune_created() {
+        let mut store = memory_store();
+        let output = |script_pubkey| elements::TxOut {
+            script_pubkey,
+            ..Default::default()
+        };
+        let paid = elements::Script::from(vec![0x51]);
+        let other = elements::Script::from(vec![0x52]);
+        let funding = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![output(paid.clone()), output(paid.clone())],
+        };
+        let funding_txid = funding.txid();
+        let sent = elements::OutPoint::new(funding_txid, 0);
+        let conflicted = elements::OutPoint::new(funding_txid, 1);
+        let spend = |outpoint, script_pubkey| {
+            let mut tx = elements::Transaction {
+                version: 2,
+                lock_time: 0,
+                input: vec![],
+                output: vec![output(script_pubkey)],
+            };
+            add_input(&mut tx, outpoint);
+            tx
+        };
+        let counterparties = vec![Counterparty {
+            vout: 0,
+            script_pubkey: paid.clone(),
+            address: None,
+            asset: None,
+            satoshi: Some(Amount::from_sat(1_000)),
+        }];
+        store
+            .counterparties_insert(sent, counterparties.clone())
+            .unwrap();
+        store
+            .counterparties_insert(conflicted, counterparties.clone())
+            .unwrap();
+        let request = |tx| {
+            TransactionDetails::new(
+                tx,
+                HashMap::new(),
+                Amount::from_sat(100),
+                None,
+                SPVVerifyResult::NotVerified,
+            )
+        };
+        store
+            .request_insert("sent".into(), request(spend(sent, paid.clone())))
+            .unwrap();
+        store
+            .request_insert(
+                "conflicted".into(),
+                request(spend(conflicted, paid.clone())),
+            )
+            .unwrap();
+
+        store.cache.all_txs.insert(funding_txid, funding);
+        store.cache.heights.insert(funding_txid, Some(1));
+        store.update_indexes().unwrap();
+        assert!(store.counterparties(&sent).is_some());
+        assert!(store.counterparties(&conflicted).is_some());
+        assert!(store.request("conflicted").is_some());
+
+        for tx in vec![spend(sent, paid), spend(conflicted, other)] {
+            let txid = tx.txid();
+            store.cache.all_txs.insert(txid, tx);
+            store.cache.heights.insert(txid, None);
+        }
+        store.update_indexes().unwrap();
+        assert_eq!(store.counterparties(&sent), Some(&counterparties));
+        assert!(store.request("sent").is_some());
+        assert!(store.counterparties(&conflicted).is_none());
+        assert!(store.request("conflicted").is_none());
+
+        // the funding tx is reorged out
+        store.cache.heights.remove(&funding_txid);
+        store.update_indexes().unwrap();
+        assert!(store.counterparties(&sent).is_none());
+        assert!(store.request("sent").is_none());
+    }
 }
@@ -0,0 +1,2340 @@
+//! Regtest harness spinning up elementsd and electrs, funding wallets, mining blocks and
+//! checking balances, to exercise wallets end-to-end from integration tests.
+//!
+//! The executables are not downloaded, their paths are given to `TestElectrumServer::new`.
+
+use chrono::Utc;
+use core_rpc::{Auth, Client, RpcApi};
+use electrum_client::ElectrumApi;
+use elements;
+use elements::bitcoin::hashes::hex::{FromHex, ToHex};
+use elements::bitcoin::Amount;
+use elements::BlockHash;
+
+use crate::{
+    CoinSelection, CoinSelector, CreateTransactionOpt, DerivationTemplate, Destination,
+    ElectrumWallet, Error, FeeModel, GetTransactionsOpt, LiquidexMakeOpt, LiquidexProposal,
+    SPVVerifyResult, SignApproval, SignKind, SignRequest, TransactionDetails, TxType, UnblindedTXO,
+};
+
+use log::LevelFilter;
+use log::{info, warn, Metadata, Record};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::Duration;
+use tempdir::TempDir;
+
+const DUST_VALUE: u64 = 546;
+
+static LOGGER: SimpleLogger = SimpleLogger;
+
+//TODO duplicated why I cannot import?
+pub struct SimpleLogger;
+
+impl log::Log for SimpleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            println!(
+                "{} {} - {}",
+                Utc::now().format("%S%.3f"),
+                record.level(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static START: Once = Once::new();
+
+fn node_sendtoaddress(
+    client: &Client,
+    address: &elements::Address,
+    satoshi: u64,
+    asset: Option<elements::issuance::AssetId>,
+) -> String {
+    let amount = Amount::from_sat(satoshi);
+    let btc = amount.to_string_in(elements::bitcoin::util::amount::Denomination::Bitcoin);
+    info!("node_sendtoaddress {} {}", address, btc);
+    let r = match asset {
+        Some(asset) => client
+            .call::<Value>(
+                "sendtoaddress",
+                &[
+                    address.to_string().into(),
+                    btc.into(),
+                    "".into(),
+                    "".into(),
+                    false.into(),
+                    false.into(),
+                    1.into(),
+                    "UNSET".into(),
+                    asset.to_string().into(),
+                ],
+            )
+            .unwrap(),
+        None => client
+            .call::<Value>("sendtoaddress", &[address.to_string().into(), btc.into()])
+            .unwrap(),
+    };
+    info!("node_sendtoaddress result {:?}", r);
+    r.as_str().unwrap().to_string()
+}
+
+fn node_getnewaddress(client: &Client, kind: Option<&str>) -> elements::Address {
+    let kind = kind.unwrap_or("p2sh-segwit");
+    let addr: Value = client
+        .call("getnewaddress", &["label".into(), kind.into()])
+        .unwrap();
+    elements::Address::from_str(&addr.as_str().unwrap()).unwrap()
+}
+
+fn node_generate(client: &Client, block_num: u32) {
+    let address = node_getnewaddress(client, None).to_string();
+    let r = client
+        .call::<Value>("generatetoaddress", &[block_num.into(), address.into()])
+        .unwrap();
+    info!("generate result {:?}", r);
+}
+
+fn node_issueasset(client: &Client, satoshi: u64) -> String {
+    let amount = Amount::from_sat(satoshi);
+    let btc = amount.to_string_in(elements::bitcoin::util::amount::Denomination::Bitcoin);
+    let r = client
+        .call::<Value>("issueasset", &[btc.into(), 0.into()])
+        .unwrap();
+    info!("node_issueasset result {:?}", r);
+    r.get("asset").unwrap().as_str().unwrap().to_string()
+}
+
+fn to_unconfidential(address: &elements::Address) -> elements::Address {
+    let mut address_unconf = address.clone();
+    address_unconf.blinding_pubkey = None;
+    address_unconf
+}
+
+pub struct TestElectrumServer {
+    node: electrsd::bitcoind::BitcoinD,
+    pub electrs: electrsd::ElectrsD,
+}
+
+impl TestElectrumServer {
+    pub fn new(is_debug: bool, electrs_exec: String, node_exec: String) -> Self {
+        START.call_once(|| {
+            let filter = if is_debug {
+                LevelFilter::Info
+            } else {
+                LevelFilter::Off
+            };
+            log::set_logger(&LOGGER)
+                .map(|()| log::set_max_level(filter))
+                .expect("cannot initialize logging");
+        });
+
+        let args = vec![
+            "-fallbackfee=0.0001",
+            "-dustrelayfee=0.00000001",
+            "-chain=liquidregtest",
+            "-initialfreecoins=2100000000",
+            "-validatepegin=0",
+        ];
+        let network = "liquidregtest";
+
+        let conf = electrsd::bitcoind::Conf {
+            args,
+            view_stdout: is_debug,
+            p2p: electrsd::bitcoind::P2P::Yes,
+            network,
+        };
+
+        let node = electrsd::bitcoind::BitcoinD::with_conf(&node_exec, &conf).unwrap();
+        info!("node spawned");
+
+        node_generate(&node.client, 1);
+        // send initialfreecoins from wallet "" to the wallet created by BitcoinD::new
+        let node_url = format!("http://127.0.0.1:{}/wallet/", node.params.rpc_socket.port());
+        let client =
+            Client::new(&node_url, Auth::CookieFile(node.params.cookie_file.clone())).unwrap();
+        let address = node_getnewaddress(&node.client, None);
+        client
+            .call::<Value>(
+                "sendtoaddress",
+                &[
+                    address.to_string().into(),
+                    "21".into(),
+                    "".into(),
+                    "".into(),
+                    true.into(),
+                ],
+            )
+            .unwrap();
+
+        let args = if is_debug { vec!["-v"] } else { vec![] };
+        let conf = electrsd::Conf {
+            args,
+            view_stderr: is_debug,
+            http_enabled: false,
+            network,
+        };
+        let electrs = electrsd::ElectrsD::with_conf(&electrs_exec, &node, &conf).unwrap();
+        info!("Electrs spawned");
+
+        node_generate(&node.client, 100);
+        electrs.trigger().unwrap();
+
+        let mut i = 120;
+        loop {
+            assert!(i > 0, "1 minute without updates");
+            i -= 1;
+            let height = electrs.client.block_headers_subscribe_raw().unwrap().height;
+            if height == 101 {
+                break;
+            } else {
+                warn!("height: {}", height);
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        info!("Electrs synced with node");
+
+        Self { node, electrs }
+    }
+
+    /// stop the bitcoin node in the test session
+    pub fn stop(&mut self) {
+        self.node.client.stop().unwrap();
+    }
+
+    pub fn node_getnewaddress(&self, kind: Option<&str>) -> elements::Address {
+        node_getnewaddress(&self.node.client, kind)
+    }
+
+    fn node_sendtoaddress(
+        &self,
+        address: &elements::Address,
+        satoshi: u64,
+        asset: Option<elements::issuance::AssetId>,
+    ) -> String {
+        node_sendtoaddress(&self.node.client, address, satoshi, asset)
+    }
+    fn node_issueasset(&self, satoshi: u64) -> elements::issuance::AssetId {
+        let asset = node_issueasset(&self.node.client, satoshi);
+        elements::issuance::AssetId::from_hex(&asset).unwrap()
+    }
+    fn node_generate(&self, block_num: u32) {
+        node_generate(&self.node.client, block_num);
+        self.electrs.trigger().unwrap();
+    }
+
+    pub fn fund_btc(&mut self, address: &elements::Address, satoshi: u64) -> String {
+        let txid = self.node_sendtoaddress(address, satoshi, None);
+        txid
+    }
+
+    pub fn fund_asset(
+        &mut self,
+        address: &elements::Address,
+        satoshi: u64,
+    ) -> (String, elements::issuance::AssetId) {
+        let asset = self.node_issueasset(satoshi);
+        let txid = self.node_sendtoaddress(address, satoshi, Some(asset.clone()));
+        (txid, asset)
+    }
+
+    /// balance in satoshi of the node
+    fn _node_balance(&self, asset: Option<String>) -> u64 {
+        let balance: Value = self.node.client.call("getbalance", &[]).unwrap();
+        let unconfirmed_balance: Value =
+            self.node.client.call("getunconfirmedbalance", &[]).unwrap();
+        let asset_or_policy = asset.or(Some("bitcoin".to_string())).unwrap();
+        let balance = match balance.get(&asset_or_policy) {
+            Some(Value::Number(s)) => s.as_f64().unwrap(),
+            _ => 0.0,
+        };
+        let unconfirmed_balance = match unconfirmed_balance.get(&asset_or_policy) {
+            Some(Value::Number(s)) => s.as_f64().unwrap(),
+            _ => 0.0,
+        };
+        ((balance + unconfirmed_balance) * 100_000_000.0) as u64
+    }
+
+    pub fn send_tx_to_unconf(&mut self, address: &elements::Address) -> String {
+        let unconf_address = to_unconfidential(address);
+        let txid = self.node_sendtoaddress(&unconf_address, 10_000, None);
+        txid
+    }
+
+    /// ask the blockcain tip to electrs
+    fn electrs_tip(&mut self) -> usize {
+        for _ in 0..10 {
+            match self.electrs.client.block_headers_subscribe_raw() {
+                Ok(header) => return header.height,
+                Err(e) => {
+                    warn!("electrs_tip {:?}", e); // fixme, for some reason it errors once every two try
+                    thread::sleep(Duration::from_millis(500));
+                }
+            }
+        }
+        panic!("electrs_tip always return error")
+    }
+
+    /// mine a block with the node
+    pub fn mine_block(&mut self) -> u32 {
+        let initial_height = self.electrs_tip();
+        info!("mine_block initial_height {}", initial_height);
+        self.node_generate(1);
+        let mut i = 120;
+        let new_height = loop {
+            assert!(i > 0, "1 minute without updates");
+            i -= 1;
+            // apparently even if wallet status changed (thus new height come in)
+            // it could happend this is the old height (maybe due to caching) thus we loop wait
+            let new_height = self.electrs_tip();
+            if new_height != initial_height {
+                break new_height;
+            }
+            info!("height still the same");
+            thread::sleep(Duration::from_millis(500));
+        };
+        info!("mine_block new_height {}", new_height);
+        assert_eq!(initial_height + 1, new_height);
+        new_height as u32
+    }
+}
+
+/// Coin selector spending the smallest policy asset utxos first
+struct SmallestFirst;
+
+impl CoinSelector for SmallestFirst {
+    fn select(
+        &self,
+        candidates: &[UnblindedTXO],
+        targets: &HashMap<elements::issuance::AssetId, u64>,
+        fee_model: &FeeModel,
+    ) -> Result<CoinSelection, Error> {
+        let policy_asset = fee_model.policy_asset();
+        if targets.keys().any(|asset| *asset != policy_asset) {
+            return Err(Error::Generic(
+                "SmallestFirst pays the policy asset only".into(),
+            ));
+        }
+        let target = targets.get(&policy_asset).cloned().unwrap_or(0);
+        let mut utxos: Vec<_> = candidates
+            .iter()
+            .filter(|u| u.unblinded.asset == policy_asset)
+            .collect();
+        utxos.sort_by_key(|u| u.unblinded.value);
+
+        let mut selection = CoinSelection::default();
+        let mut sum = 0;
+        for utxo in utxos {
+            selection.inputs.push(utxo.txo.outpoint);
+            sum += utxo.unblinded.value;
+            let fee = fee_model.fee(selection.inputs.len(), 1).as_sat();
+            if sum > target + fee + DUST_VALUE {
+                selection.changes.insert(policy_asset, sum - target - fee);
+                return Ok(selection);
+            }
+        }
+        Err(Error::Generic("insufficient funds".into()))
+    }
+}
+
+/// Coin selector leaving no fee
+struct NoFeeSelector;
+
+impl CoinSelector for NoFeeSelector {
+    fn select(
+        &self,
+        candidates: &[UnblindedTXO],
+        targets: &HashMap<elements::issuance::AssetId, u64>,
+        fee_model: &FeeModel,
+    ) -> Result<CoinSelection, Error> {
+        let mut selection = SmallestFirst.select(candidates, targets, fee_model)?;
+        let fee = fee_model.fee(selection.inputs.len(), 1).as_sat();
+        *selection
+            .changes
+            .get_mut(&fee_model.policy_asset())
+            .unwrap() += fee;
+        Ok(selection)
+    }
+}
+
+pub struct TestElectrumWallet {
+    mnemonic: String,
+    electrum_wallet: ElectrumWallet,
+    tx_status: u64,
+    _block_status: (u32, BlockHash),
+    _db_root_dir: TempDir,
+}
+
+impl TestElectrumWallet {
+    pub fn new(electrs_url: &str, mnemonic: String) -> Self {
+        let tls = false;
+        let validate_domain = false;
+        let spv_enabled = true;
+        let policy_asset_hex = &"5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225";
+        let _db_root_dir = TempDir::new("electrum_integration_tests").unwrap();
+
+        let db_root = format!("{}", _db_root_dir.path().display());
+
+        let electrum_wallet = ElectrumWallet::new_regtest(
+            policy_asset_hex,
+            electrs_url,
+            tls,
+            validate_domain,
+            spv_enabled,
+            &db_root,
+            &mnemonic,
+        )
+        .unwrap();
+        electrum_wallet.update_fee_estimates();
+
+        let tx_status = electrum_wallet.tx_status().unwrap();
+        assert_eq!(tx_status, 15130871412783076140);
+        let mut i = 120;
+        let _block_status = loop {
+            assert!(i > 0, "1 minute without updates");
+            i -= 1;
+            let block_status = electrum_wallet.block_status().unwrap();
+            if block_status.0 == 101 {
+                break block_status;
+            } else {
+                thread::sleep(Duration::from_millis(500));
+            }
+        };
+        assert_eq!(_block_status.0, 101);
+
+        Self {
+            mnemonic,
+            electrum_wallet,
+            tx_status,
+            _block_status,
+            _db_root_dir,
+        }
+    }
+
+    pub fn policy_asset(&self) -> elements::issuance::AssetId {
+        self.electrum_wallet.policy_asset_id()
+    }
+
+    pub fn electrum_wallet(&self) -> &ElectrumWallet {
+        &self.electrum_wallet
+    }
+
+    /// Wait until tx appears in tx list (max 1 min)
+    pub fn wait_for_tx(&mut self, txid: &str) {
+        let mut opt = GetTransactionsOpt::default();
+        opt.count = 100;
+        for _ in 0..120 {
+            let list = self.electrum_wallet.transactions(&opt).unwrap();
+            if list.iter().any(|e| e.txid == txid) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        panic!("Wallet does not have {} in its list", txid);
+    }
+
+    /// wait wallet tx status to change (max 1 min)
+    fn wallet_wait_tx_status_change(&mut self) {
+        for _ in 0..120 {
+            if let Ok(new_status) = self.electrum_wallet.tx_status() {
+                if self.tx_status != new_status {
+                    self.tx_status = new_status;
+                    break;
+                }
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// wait wallet block status to change (max 1 min)
+    fn _wallet_wait_block_status_change(&mut self) {
+        for _ in 0..120 {
+            if let Ok(new_status) = self.electrum_wallet.block_status() {
+                if self._block_status != new_status {
+                    self._block_status = new_status;
+                    break;
+                }
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// wait until wallet has a certain blockheight (max 1 min)
+    pub fn wait_for_block(&mut self, new_height: u32) {
+        for _ in 0..120 {
+            if let Ok((height, _)) = self.electrum_wallet.block_status() {
+                if height == new_height {
+                    break;
+                }
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// asset balance in satoshi
+    pub fn balance(&self, asset: &elements::issuance::AssetId) -> u64 {
+        let balance = self.electrum_wallet.balance().unwrap();
+        info!("balance: {:?}", balance);
+        *balance.get(asset).unwrap_or(&0u64)
+    }
+
+    fn balance_btc(&self) -> u64 {
+        self.balance(&self.policy_asset())
+    }
+
+    fn get_tx_from_list(&mut self, txid: &str) -> TransactionDetails {
+        self.electrum_wallet.update_spv().unwrap();
+        let mut opt = GetTransactionsOpt::default();
+        opt.count = 100;
+        let list = self.electrum_wallet.transactions(&opt).unwrap();
+        let filtered_list: Vec<TransactionDetails> =
+            list.iter().filter(|e| e.txid == txid).cloned().collect();
+        assert!(
+            !filtered_list.is_empty(),
+            "just made tx {} is not in tx list",
+            txid
+        );
+        filtered_list.first().unwrap().clone()
+    }
+
+    pub fn get_fee(&mut self, txid: &str) -> u64 {
+        self.get_tx_from_list(txid).fee.as_sat()
+    }
+
+    pub fn fund_btc(&mut self, server: &mut TestElectrumServer) {
+        let init_balance = self.balance_btc();
+        let satoshi: u64 = 1_000_000;
+        let address = self.electrum_wallet.address().unwrap();
+        let txid = server.fund_btc(&address, satoshi);
+        self.wallet_wait_tx_status_change();
+        let balance = init_balance + self.balance_btc();
+        // node is allowed to make tx below dust with dustrelayfee, but wallet should not see
+        // this as spendable, thus the balance should not change
+        let satoshi = if satoshi < DUST_VALUE {
+            init_balance
+        } else {
+            init_balance + satoshi
+        };
+        assert_eq!(balance, satoshi);
+        let wallet_tx = self.get_tx_from_list(&txid);
+        assert_eq!(txid, wallet_tx.txid);
+        assert_eq!(wallet_tx.tx_type, Some(TxType::Incoming));
+        let utxos = self.electrum_wallet.utxos().unwrap();
+        assert_eq!(utxos.len(), 1);
+    }
+
+    pub fn fund_asset(&mut self, server: &mut TestElectrumServer) -> elements::issuance::AssetId {
+        let num_utxos_before = self.electrum_wallet.utxos().unwrap().len();
+        let satoshi = 10_000;
+        let address = self.electrum_wallet.address().unwrap();
+        let (txid, asset) = server.fund_asset(&address, satoshi);
+        self.wait_for_tx(&txid);
+
+        let balance_asset = self.balance(&asset);
+        assert_eq!(balance_asset, satoshi);
+        let wallet_tx = self.get_tx_from_list(&txid);
+        assert_eq!(txid, wallet_tx.txid);
+        assert_eq!(wallet_tx.tx_type, Some(TxType::Incoming));
+        let utxos = self.electrum_wallet.utxos().unwrap();
+        assert_eq!(utxos.len(), num_utxos_before + 1);
+        assert!(self
+            .electrum_wallet
+            .known_assets()
+            .unwrap()
+            .contains(&asset));
+        asset
+    }
+
+    /// send a tx to `address` signing the inputs as an external signer would
+    pub fn send_tx_external_signer(&mut self, address: &elements::Address, satoshi: u64) {
+        let init_sat = self.balance_btc();
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &self.policy_asset().to_string(),
+            )
+            .unwrap(),
+        );
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let mut tx = tx_details.transaction.clone();
+
+        let sighashes = self.electrum_wallet.sighashes(&mut tx).unwrap();
+        assert_eq!(sighashes.len(), tx.input.len());
+        let secp = elements::bitcoin::secp256k1::Secp256k1::new();
+        let xprv =
+            crate::interface::mnemonic2xprv(&self.mnemonic, self.electrum_wallet.config.clone())
+                .unwrap();
+        for (index, path, sighash) in sighashes {
+            let key = xprv.derive_priv(&secp, &path).unwrap().private_key;
+            let public_key = elements::bitcoin::PublicKey::from_private_key(&secp, &key);
+            let message = elements::bitcoin::secp256k1::Message::from_slice(&sighash[..]).unwrap();
+            let signature = secp.sign(&message, &key.key);
+            let wrong_key =
+                elements::bitcoin::PublicKey::from_private_key(&secp, &xprv.private_key);
+            assert!(self
+                .electrum_wallet
+                .apply_signature(&mut tx, index, &signature, &wrong_key)
+                .is_err());
+            self.electrum_wallet
+                .apply_signature(&mut tx, index, &signature, &public_key)
+                .unwrap();
+        }
+
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+        assert_eq!(
+            self.balance_btc(),
+            init_sat - satoshi - tx_details.fee.as_sat(),
+            "balance after external signing does not match"
+        );
+    }
+
+    /// send a tx signed by a `Signer` holding the wallet xprv, as a remote signer would
+    pub fn send_tx_with_signer(&mut self, address: &elements::Address, satoshi: u64) {
+        struct XprvSigner(elements::bitcoin::util::bip32::ExtendedPrivKey);
+
+        impl crate::Signer for XprvSigner {
+            fn sign(
+                &self,
+                tx: &elements::Transaction,
+                input_index: usize,
+                witness_script: &elements::Script,
+                value: elements::confidential::Value,
+                sighash_type: elements::SigHashType,
+            ) -> Result<(elements::bitcoin::PublicKey, Vec<u8>), Error> {
+                crate::Signer::sign(
+                    &self.0.private_key,
+                    tx,
+                    input_index,
+                    witness_script,
+                    value,
+                    sighash_type,
+                )
+            }
+
+            fn sign_wallet_input(
+                &self,
+                tx: &elements::Transaction,
+                input_index: usize,
+                derivation_path: &elements::bitcoin::util::bip32::DerivationPath,
+                script_code: &elements::Script,
+                value: elements::confidential::Value,
+                sighash_type: elements::SigHashType,
+            ) -> Result<(elements::bitcoin::PublicKey, Vec<u8>), Error> {
+                let secp = elements::bitcoin::secp256k1::Secp256k1::new();
+                let key = self.0.derive_priv(&secp, derivation_path)?.private_key;
+                crate::Signer::sign(&key, tx, input_index, script_code, value, sighash_type)
+            }
+        }
+
+        let init_sat = self.balance_btc();
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &self.policy_asset().to_string(),
+            )
+            .unwrap(),
+        );
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let mut tx = tx_details.transaction.clone();
+        let xprv =
+            crate::interface::mnemonic2xprv(&self.mnemonic, self.electrum_wallet.config.clone())
+                .unwrap();
+
+        // a key not deriving the wallet keys is rejected
+        let mut wrong_tx = tx.clone();
+        let wrong_signer = XprvSigner(
+            xprv.derive_priv(
+                &elements::bitcoin::secp256k1::Secp256k1::new(),
+                &"m/1"
+                    .parse::<elements::bitcoin::util::bip32::DerivationPath>()
+                    .unwrap(),
+            )
+            .unwrap(),
+        );
+        assert!(self
+            .electrum_wallet
+            .sign_tx_with_signer(&mut wrong_tx, &wrong_signer)
+            .is_err());
+
+        self.electrum_wallet
+            .sign_tx_with_signer(&mut tx, &XprvSigner(xprv))
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+        assert_eq!(
+            self.balance_btc(),
+            init_sat - satoshi - tx_details.fee.as_sat(),
+            "balance after signing with a signer does not match"
+        );
+    }
+
+    /// check the balance replayed at the tip height, all wallet txs must be confirmed
+    pub fn balance_at(&mut self) {
+        let (tip, _) = self.electrum_wallet.block_status().unwrap();
+        let balance = self.electrum_wallet.balance().unwrap();
+        assert_eq!(self.electrum_wallet.balance_at(tip).unwrap(), balance);
+        let genesis_balance = self.electrum_wallet.balance_at(0).unwrap();
+        assert_eq!(genesis_balance.get(&self.policy_asset()), Some(&0));
+        assert_eq!(genesis_balance.len(), 1);
+    }
+
+    /// check the utxos confirmations and their sorting by age, all wallet txs must be confirmed
+    pub fn utxos_age(&mut self) {
+        let mut opt = crate::UtxosOpt::default();
+        opt.min_confirmations = 1;
+        let confirmed = self.electrum_wallet.utxos_opt(&opt).unwrap();
+        assert_eq!(confirmed.len(), self.utxos().len());
+
+        opt.sort = crate::UtxoSort::OldestFirst;
+        let oldest_first = self.electrum_wallet.utxos_opt(&opt).unwrap();
+        for pair in oldest_first.windows(2) {
+            assert!(pair[0].confirmations >= pair[1].confirmations);
+            assert!(pair[0].txo.height <= pair[1].txo.height);
+        }
+
+        opt.max_value = Some(DUST_VALUE * 2);
+        opt.asset = Some(self.policy_asset());
+        for utxo in self.electrum_wallet.utxos_opt(&opt).unwrap() {
+            assert!(utxo.unblinded.value <= DUST_VALUE * 2);
+            assert_eq!(utxo.unblinded.asset, self.policy_asset());
+        }
+    }
+
+    /// check the progress reported by a sync
+    pub fn sync_progress(&mut self) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.electrum_wallet
+            .set_sync_progress_sender(Some(sender))
+            .unwrap();
+        self.electrum_wallet.sync().unwrap();
+        self.electrum_wallet.set_sync_progress_sender(None).unwrap();
+        let updates: Vec<crate::SyncProgress> = receiver.try_iter().collect();
+        assert_eq!(updates.first().unwrap().stage, crate::SyncStage::Scripts);
+        let progress = self.electrum_wallet.sync_progress().unwrap();
+        assert_eq!(updates.last(), Some(&progress));
+        assert_eq!(progress.stage, crate::SyncStage::Done);
+        assert!(progress.scripts.1 > 0);
+        assert_eq!(progress.scripts.0, progress.scripts.1);
+        assert_eq!(progress.headers.0, progress.headers.1);
+    }
+
+    /// check the wallet stats against the utxos, all wallet txs must be confirmed
+    pub fn stats(&mut self) {
+        self.electrum_wallet.sync().unwrap();
+        let stats = self.electrum_wallet.stats().unwrap();
+        assert!(stats.txs > 0);
+        assert_eq!(stats.unconfirmed_txs, 0);
+        assert!(stats.spv_verified_txs <= stats.txs);
+        assert!(stats.external_index > 0);
+        assert!(stats.last_sync.is_some());
+        let mut utxos = std::collections::HashMap::new();
+        for utxo in self.utxos() {
+            *utxos.entry(utxo.unblinded.asset).or_insert(0) += 1;
+        }
+        assert_eq!(stats.utxos, utxos);
+    }
+
+    /// send a tx with the single call creating, signing and broadcasting it
+    pub fn send(&mut self, address: &elements::Address, satoshi: u64) {
+        let init_sat = self.balance_btc();
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &self.policy_asset().to_string(),
+            )
+            .unwrap(),
+        );
+        create_opt.request_id = Some("send".into());
+        let details = self
+            .electrum_wallet
+            .send(&mut create_opt, &self.mnemonic)
+            .unwrap();
+        self.wallet_wait_tx_status_change();
+        assert_eq!(
+            self.balance_btc(),
+            init_sat - satoshi - details.fee.as_sat()
+        );
+        assert_eq!(self.get_tx_from_list(&details.txid).txid, details.txid);
+
+        // retrying with the same request id does not spend again
+        let retried = self
+            .electrum_wallet
+            .send(&mut create_opt, &self.mnemonic)
+            .unwrap();
+        assert_eq!(retried.txid, details.txid);
+        assert_eq!(
+            self.balance_btc(),
+            init_sat - satoshi - details.fee.as_sat()
+        );
+    }
+
+    /// send a tx through the broadcast queue
+    pub fn send_tx_queued(&mut self, address: &elements::Address, satoshi: u64) {
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &self.policy_asset().to_string(),
+            )
+            .unwrap(),
+        );
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let mut tx = tx_details.transaction.clone();
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+
+        self.electrum_wallet.queue_tx(&tx).unwrap();
+        assert_eq!(self.electrum_wallet.queued_txs().unwrap().len(), 1);
+        let events = self.electrum_wallet.process_broadcast_queue().unwrap();
+        assert_eq!(events, vec![crate::BroadcastEvent::Broadcasted(tx.txid())]);
+        assert!(self.electrum_wallet.queued_txs().unwrap().is_empty());
+        self.wallet_wait_tx_status_change();
+
+        // once seen by the wallet a queued tx is not broadcast again
+        self.electrum_wallet.queue_tx(&tx).unwrap();
+        let events = self.electrum_wallet.process_broadcast_queue().unwrap();
+        assert_eq!(events, vec![crate::BroadcastEvent::Broadcasted(tx.txid())]);
+        assert!(self.electrum_wallet.queued_txs().unwrap().is_empty());
+
+        // the worker stops while waiting for the next round once cancelled
+        let wallet = ElectrumWallet::with_persister(
+            self.electrum_wallet.config.clone(),
+            Box::new(crate::persister::MemoryPersister::new()),
+            &self.mnemonic,
+        )
+        .unwrap();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let cancel = crate::CancelToken::new();
+        let worker = crate::spawn_broadcast_worker(
+            Arc::new(wallet),
+            Duration::from_secs(3_600),
+            sender,
+            cancel.clone(),
+        );
+        thread::sleep(Duration::from_millis(200));
+        let start = std::time::Instant::now();
+        cancel.cancel();
+        worker.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    /// send a tx from the wallet to the specified address
+    pub fn send_tx(
+        &mut self,
+        address: &elements::Address,
+        satoshi: u64,
+        asset: Option<elements::issuance::AssetId>,
+        utxos: Option<Vec<UnblindedTXO>>,
+    ) -> String {
+        let asset = asset.unwrap_or(self.policy_asset());
+        let init_sat = self.balance(&asset);
+        //let init_node_balance = self.node_balance(asset.clone());
+        let mut create_opt = CreateTransactionOpt::default();
+        let fee_rate = 100;
+        create_opt.fee_rate = Some(crate::FeeRate::from_sat_per_kvb(fee_rate));
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &asset.to_string(),
+            )
+            .unwrap(),
+        );
+        create_opt.utxos = utxos;
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let mut tx = tx_details.transaction.clone();
+        let len_before = elements::encode::serialize(&tx).len();
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        let len_after = elements::encode::serialize(&tx).len();
+        assert!(len_before < len_after, "sign tx did not increased tx size");
+        //self.check_fee_rate(fee_rate, &signed_tx, MAX_FEE_PERCENT_DIFF);
+        let txid = tx.txid().to_string();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+
+        self.tx_checks(&tx);
+
+        let fee = if asset == self.policy_asset() {
+            tx_details.fee.as_sat()
+        } else {
+            0
+        };
+        //assert_eq!(
+        //    self.node_balance(asset.clone()),
+        //    init_node_balance + satoshi,
+        //    "node balance does not match"
+        //);
+
+        let expected = init_sat - satoshi - fee;
+        for _ in 0..5 {
+            if expected != self.balance(&asset) {
+                // FIXME I should not wait again, but apparently after reconnect it's needed
+                self.wallet_wait_tx_status_change();
+            }
+        }
+        assert_eq!(self.balance(&asset), expected, "gdk balance does not match");
+
+        //self.list_tx_contains(&txid, &vec![address.to_string()], true);
+        let wallet_tx = self.get_tx_from_list(&txid);
+        assert_eq!(txid, wallet_tx.txid);
+        // the values of blinded outputs are recorded by create_tx
+        if let Some(counterparty) = wallet_tx
+            .counterparties
+            .iter()
+            .find(|c| c.script_pubkey == address.script_pubkey())
+        {
+            assert_eq!(counterparty.address, Some(address.to_string()));
+            assert_eq!(counterparty.satoshi, Some(crate::Amount::from_sat(satoshi)));
+            assert_eq!(counterparty.asset, Some(asset));
+        }
+
+        txid
+    }
+
+    /// receive on the unconfidential form of a wallet address and spend the explicit output
+    pub fn send_tx_to_unconf(&mut self, server: &mut TestElectrumServer) {
+        let init_sat = self.balance_btc();
+        let address = self.electrum_wallet.address().unwrap();
+        let txid = server.send_tx_to_unconf(&address);
+        self.wallet_wait_tx_status_change();
+        assert_eq!(init_sat + 10_000, self.balance_btc());
+
+        let utxo = self
+            .utxos()
+            .into_iter()
+            .find(|u| u.txo.outpoint.txid.to_string() == txid)
+            .unwrap();
+        assert!(utxo.explicit);
+        assert_eq!(utxo.unblinded.value, 10_000);
+        let node_address = server.node_getnewaddress(None);
+        self.send_tx(&node_address, 5_000, None, Some(vec![utxo]));
+    }
+
+    pub fn is_verified(&mut self, txid: &str, verified: SPVVerifyResult) {
+        let tx = self.get_tx_from_list(txid);
+        assert_eq!(tx.spv_verified.to_string(), verified.to_string());
+    }
+
+    pub fn peek_address(&self) {
+        let peeked = self.electrum_wallet.peek_address(0).unwrap();
+        assert_eq!(peeked, self.electrum_wallet.peek_address(0).unwrap());
+        assert_ne!(peeked, self.electrum_wallet.peek_address(1).unwrap());
+        assert_eq!(peeked, self.electrum_wallet.address().unwrap());
+    }
+
+    pub fn verify_tx_inclusion(&mut self, txid: &str) {
+        let txid = elements::Txid::from_str(txid).unwrap();
+        let result = self.electrum_wallet.verify_tx_inclusion(&txid).unwrap();
+        assert_eq!(result.to_string(), SPVVerifyResult::Verified.to_string());
+        let tx = self.get_tx_from_list(&txid.to_string());
+        let height = tx.height.unwrap();
+        let header = self.electrum_wallet.get_header(height).unwrap();
+        assert_eq!(header.height, height);
+    }
+
+    /// send a tx with multiple recipients with same amount from the wallet to addresses generated
+    /// by the node. If `assets` contains values, they are used as asset cyclically
+    pub fn send_multi(
+        &mut self,
+        recipients: u8,
+        amount: u64,
+        assets: &Vec<elements::issuance::AssetId>,
+        server: &mut TestElectrumServer,
+    ) {
+        let init_sat = self.balance_btc();
+        let init_balances = self.electrum_wallet.balance().unwrap();
+        let mut create_opt = CreateTransactionOpt::default();
+        let fee_rate = 1000;
+        create_opt.fee_rate = Some(crate::FeeRate::from_sat_per_kvb(fee_rate));
+        let mut addressees = vec![];
+        let mut assets_cycle = assets.iter().cycle();
+        let mut tags = vec![];
+        for _ in 0..recipients {
+            let address = server.node_getnewaddress(None);
+            let asset = if assets.is_empty() {
+                self.policy_asset()
+            } else {
+                let current = elements::issuance::AssetId::from_hex(
+                    &assets_cycle.next().unwrap().to_string(),
+                )
+                .unwrap();
+                tags.push(current);
+                current
+            };
+            create_opt.addressees.push(
+                Destination::new(
+                    &address.to_string(),
+                    crate::Amount::from_sat(amount),
+                    &asset.to_hex(),
+                )
+                .unwrap(),
+            );
+            addressees.push(address);
+        }
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let mut tx = tx_details.transaction.clone();
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        //self.check_fee_rate(fee_rate, &signed_tx, MAX_FEE_PERCENT_DIFF);
+        let _txid = tx.txid().to_string();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+        self.tx_checks(&tx);
+
+        let fee = tx_details.fee.as_sat();
+        let policy_asset = self.policy_asset();
+        // one output per recipient, one change per asset with remainder and the fee output
+        assert_eq!(
+            tx.output.len(),
+            recipients as usize + tx_details.changes().len() + 1
+        );
+        for (asset, change) in tx_details.changes().iter() {
+            assert!(*change > crate::Amount::ZERO);
+            assert!(init_balances.contains_key(asset));
+        }
+        assert_eq!(tx_details.change_outputs.len(), tx_details.changes().len());
+        for change in tx_details.change_outputs.iter() {
+            let output = &tx.output[change.vout as usize];
+            assert_eq!(output.script_pubkey, change.address.script_pubkey());
+            assert_eq!(
+                tx_details.changes().get(&change.asset),
+                Some(&change.satoshi)
+            );
+            let outpoint = elements::OutPoint::new(tx.txid(), change.vout);
+            let utxo = self
+                .electrum_wallet
+                .utxos()
+                .unwrap()
+                .into_iter()
+                .find(|u| u.txo.outpoint == outpoint)
+                .unwrap();
+            assert_eq!(utxo.unblinded.value, change.satoshi.as_sat());
+            let (chain, index) = self
+                .electrum_wallet
+                .index_of(&output.script_pubkey)
+                .unwrap()
+                .unwrap();
+            assert_eq!(chain, 1);
+            assert_eq!(change.derivation_path.to_string(), format!("m/1/{}", index));
+        }
+        if assets.is_empty() {
+            assert_eq!(
+                init_sat - fee - recipients as u64 * amount,
+                self.balance_btc()
+            );
+        } else {
+            let policy_outputs = tags.iter().filter(|t| **t == policy_asset).count() as u64;
+            assert_eq!(init_sat - fee - policy_outputs * amount, self.balance_btc());
+            for asset in assets.iter().filter(|a| **a != policy_asset) {
+                let outputs_for_this_asset = tags.iter().filter(|t| t == &asset).count() as u64;
+                assert_eq!(
+                    *init_balances.get(&asset).unwrap() as u64 - outputs_for_this_asset * amount,
+                    self.balance(asset)
+                );
+            }
+        }
+        //TODO check node balance
+        //self.list_tx_contains(&txid, &addressees, true);
+    }
+
+    /// fund an external transaction paying `address`, then sign and broadcast it
+    pub fn fund_tx(&mut self, address: &elements::Address, satoshi: u64) -> String {
+        let init_sat = self.balance_btc();
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![elements::TxOut {
+                asset: elements::confidential::Asset::Explicit(self.policy_asset()),
+                value: elements::confidential::Value::Explicit(satoshi),
+                nonce: elements::confidential::Nonce::Confidential(
+                    address.blinding_pubkey.unwrap(),
+                ),
+                script_pubkey: address.script_pubkey(),
+                witness: Default::default(),
+            }],
+        };
+        let tx_details = self.electrum_wallet.fund_tx(tx, Some(100)).unwrap();
+        tx = tx_details.transaction.clone();
+        assert!(tx.input.len() > 0);
+        assert!(tx
+            .output
+            .iter()
+            .all(|o| o.is_fee() || o.value.is_confidential()));
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        let txid = tx.txid().to_string();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wait_for_tx(&txid);
+        assert_eq!(
+            self.balance_btc(),
+            init_sat - satoshi - tx_details.fee.as_sat()
+        );
+        txid
+    }
+
+    /// move funds to a labelled address and spend them from its virtual account
+    pub fn virtual_account(&mut self) {
+        let account = "treasury";
+        let policy_asset = self.policy_asset();
+        let address = self.electrum_wallet.address().unwrap();
+        self.electrum_wallet.add_label(&address, account).unwrap();
+        assert_eq!(
+            self.electrum_wallet.account_balance(account).unwrap()[&policy_asset],
+            0
+        );
+
+        let satoshi = 100_000;
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &policy_asset.to_string(),
+            )
+            .unwrap(),
+        );
+        let mut tx = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wait_for_tx(&tx.txid().to_string());
+        assert_eq!(
+            self.electrum_wallet.account_balance(account).unwrap()[&policy_asset],
+            satoshi
+        );
+
+        let spent = 10_000;
+        let to = self.electrum_wallet.address().unwrap();
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &to.to_string(),
+                crate::Amount::from_sat(spent),
+                &policy_asset.to_string(),
+            )
+            .unwrap(),
+        );
+        let tx_details = self
+            .electrum_wallet
+            .account_create_tx(account, &mut create_opt)
+            .unwrap();
+        let mut tx = tx_details.transaction.clone();
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wait_for_tx(&tx.txid().to_string());
+        assert_eq!(
+            self.electrum_wallet.account_balance(account).unwrap()[&policy_asset],
+            satoshi - spent - tx_details.fee.as_sat(),
+            "the change should stay in the account"
+        );
+
+        // accounts do not share utxos, their balances sum to the wallet balance
+        let accounts = self.electrum_wallet.accounts_balance().unwrap();
+        assert_eq!(
+            accounts[account][&policy_asset],
+            satoshi - spent - tx_details.fee.as_sat()
+        );
+        let mut sum: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
+        for balance in accounts.values() {
+            for (asset, satoshi) in balance.iter() {
+                *sum.entry(*asset).or_default() += satoshi;
+            }
+        }
+        let mut balance = self.electrum_wallet.balance().unwrap();
+        balance.retain(|_, satoshi| *satoshi > 0);
+        assert_eq!(sum, balance);
+    }
+
+    /// bump the fee of the unconfirmed `parent_txid` and check the package fee rate
+    pub fn cpfp(&mut self, parent_txid: &str) {
+        let parent_txid = elements::Txid::from_str(parent_txid).unwrap();
+        let target_fee_rate = 10_000;
+        let parent = self.get_tx_from_list(&parent_txid.to_string());
+        let tx_details = self
+            .electrum_wallet
+            .create_cpfp(
+                &parent_txid,
+                crate::FeeRate::from_sat_per_kvb(target_fee_rate),
+            )
+            .unwrap();
+        let mut tx = tx_details.transaction.clone();
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wait_for_tx(&tx.txid().to_string());
+
+        let package_vbytes = (parent.transaction.get_weight() + tx.get_weight()) as f64 / 4.0;
+        let package_fee = (parent.fee.as_sat() + tx_details.fee.as_sat()) as f64;
+        assert!(package_fee / package_vbytes >= target_fee_rate as f64 / 1000.0);
+    }
+
+    /// sweep all the funds to the `to` wallet and check they are received, minus fees
+    pub fn sweep_to(&mut self, to: &mut TestElectrumWallet) {
+        let init_balances = self.electrum_wallet.balance().unwrap();
+        let to_init_balances = to.electrum_wallet.balance().unwrap();
+        let txs = self
+            .electrum_wallet
+            .sweep_to(
+                || to.electrum_wallet.address(),
+                Some(crate::FeeRate::from_sat_per_kvb(1000)),
+            )
+            .unwrap();
+        assert!(!txs.is_empty());
+
+        let mut fees = 0;
+        for tx_details in txs {
+            let mut tx = tx_details.transaction.clone();
+            self.electrum_wallet
+                .sign_tx(&mut tx, &self.mnemonic)
+                .unwrap();
+            self.electrum_wallet.broadcast_tx(&tx).unwrap();
+            let txid = tx.txid().to_string();
+            self.wait_for_tx(&txid);
+            to.wait_for_tx(&txid);
+            fees += tx_details.fee.as_sat();
+        }
+
+        let policy_asset = self.policy_asset();
+        for (asset, value) in init_balances.iter().filter(|(_, v)| **v > 0) {
+            assert_eq!(self.balance(asset), 0);
+            let fee = if *asset == policy_asset { fees } else { 0 };
+            let to_init = to_init_balances.get(asset).cloned().unwrap_or(0);
+            assert_eq!(to.balance(asset), to_init + value - fee);
+        }
+    }
+
+    /// fund a gift key with the node and sweep it into the wallet
+    pub fn sweep_external_key(&mut self, server: &mut TestElectrumServer) {
+        let secp = elements::bitcoin::secp256k1::Secp256k1::new();
+        let key = elements::bitcoin::secp256k1::SecretKey::from_slice(&[3u8; 32]).unwrap();
+        let blinding_key = elements::bitcoin::secp256k1::SecretKey::from_slice(&[4u8; 32]).unwrap();
+        let private_key = elements::bitcoin::PrivateKey {
+            compressed: true,
+            network: elements::bitcoin::Network::Regtest,
+            key,
+        };
+        let public_key = elements::bitcoin::PublicKey::from_private_key(&secp, &private_key);
+        let blinding_pubkey =
+            elements::bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &blinding_key);
+        let address = elements::Address::p2shwpkh(
+            &public_key,
+            Some(blinding_pubkey),
+            &elements::AddressParams::ELEMENTS,
+        );
+
+        let init_sat = self.balance_btc();
+        let satoshi = 100_000;
+        server.fund_btc(&address, satoshi);
+        let wif = private_key.to_wif();
+        let mut tx_details = None;
+        for _ in 0..120 {
+            match self
+                .electrum_wallet
+                .sweep_external_key(&wif, &blinding_key, None)
+            {
+                Ok(details) => {
+                    tx_details = Some(details);
+                    break;
+                }
+                Err(_) => thread::sleep(Duration::from_millis(500)),
+            }
+        }
+        let tx_details = tx_details.expect("gift output not found");
+
+        // a sweep not covering the fee does not consume addresses
+        let peeked = self.electrum_wallet.peek_address(0).unwrap();
+        assert!(matches!(
+            self.electrum_wallet.sweep_external_key(
+                &wif,
+                &blinding_key,
+                crate::FeeRate::from_sat_per_vbyte(1_000.0)
+            ),
+            Err(Error::InsufficientFunds)
+        ));
+        assert_eq!(peeked, self.electrum_wallet.peek_address(0).unwrap());
+
+        let tx = tx_details.transaction;
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wait_for_tx(&tx.txid().to_string());
+        assert_eq!(
+            self.balance_btc(),
+            init_sat + satoshi - tx_details.fee.as_sat()
+        );
+        assert!(self
+            .electrum_wallet
+            .sweep_external_key(&wif, &blinding_key, None)
+            .is_err());
+    }
+
+    /// restore the wallet mnemonic as Green subaccounts: subaccount 0 has the funds of the
+    /// wallet, subaccount 1 has native segwit addresses. The addresses are checked against the
+    /// Green ones in the unit tests.
+    pub fn green_subaccounts(&self) {
+        let db_root_dir = TempDir::new("green_subaccounts").unwrap();
+        let db_root = format!("{}", db_root_dir.path().display());
+        let config = self.electrum_wallet.config.clone();
+
+        let green = ElectrumWallet::new_green(config.clone(), &db_root, &self.mnemonic, 0).unwrap();
+        assert_eq!(
+            green.balance().unwrap(),
+            self.electrum_wallet.balance().unwrap()
+        );
+
+        let native =
+            ElectrumWallet::new_green(config.clone(), &db_root, &self.mnemonic, 1).unwrap();
+        let address = native.peek_address(0).unwrap();
+        assert!(address.script_pubkey().is_v0_p2wpkh());
+        assert!(address.blinding_pubkey.is_some());
+        assert!(ElectrumWallet::new_green(config, &db_root, &self.mnemonic, 2).is_err());
+    }
+
+    /// find the funds of the wallet from the mnemonic only, without its store
+    pub fn recovery(&self) {
+        let config = self.electrum_wallet.config.clone();
+        let client = config.build_client().unwrap();
+        let report =
+            crate::recovery::scan_from_seed(&self.mnemonic, config.clone(), &*client).unwrap();
+        assert_eq!(
+            report.accounts[0].derivation,
+            DerivationTemplate::bip49(config.network(), 0)
+        );
+        assert!(report.accounts.iter().all(|a| a.transactions > 0));
+        assert_eq!(
+            report.accounts[0].balance,
+            self.electrum_wallet.balance().unwrap()
+        );
+        assert_eq!(
+            report.tip_height,
+            self.electrum_wallet.block_status().unwrap().0
+        );
+
+        // the store synced by the scan restores the account without syncing again
+        let account = &report.accounts[0];
+        let mut config = config;
+        config.derivation = Some(account.derivation.clone());
+        let restored =
+            ElectrumWallet::with_persister(config, Box::new(account.store.clone()), &self.mnemonic)
+                .unwrap();
+        assert_eq!(restored.wallet.balance().unwrap(), account.balance);
+    }
+
+    /// pay an address book contact, checking the assets it accepts
+    pub fn send_to_contact(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let address = server.node_getnewaddress(None);
+        self.electrum_wallet
+            .add_contact("Node Exchange", &address.to_string(), vec![policy_asset])
+            .unwrap();
+        let found = self.electrum_wallet.find_contacts("node").unwrap();
+        assert_eq!(found.first().unwrap().address, address.to_string());
+
+        let satoshi = crate::Amount::from_sat(1_000);
+        let mut create_opt = CreateTransactionOpt::default();
+        let other_asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        create_opt.addressees =
+            vec![
+                Destination::new_contact("Node Exchange", satoshi, &other_asset.to_hex()).unwrap(),
+            ];
+        assert!(self.electrum_wallet.create_tx(&mut create_opt).is_err());
+        create_opt.addressees =
+            vec![Destination::new_contact("Unknown", satoshi, &policy_asset.to_hex()).unwrap()];
+        assert!(self.electrum_wallet.create_tx(&mut create_opt).is_err());
+
+        create_opt.addressees =
+            vec![
+                Destination::new_contact("Node Exchange", satoshi, &policy_asset.to_hex()).unwrap(),
+            ];
+        let tx_details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        assert!(tx_details
+            .transaction
+            .output
+            .iter()
+            .any(|o| o.script_pubkey == address.script_pubkey()));
+        assert!(self
+            .electrum_wallet
+            .remove_contact("Node Exchange")
+            .unwrap()
+            .is_some());
+        assert!(self.electrum_wallet.list_contacts().unwrap().is_empty());
+    }
+
+    /// check an unconfirmed payment from the node is pending, all the other txs are confirmed
+    pub fn pending_incoming(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let address = self.electrum_wallet.address().unwrap();
+        let txid = server.fund_btc(&address, 12_345);
+        self.wait_for_tx(&txid);
+
+        let pending = self.electrum_wallet.pending_incoming().unwrap();
+        assert_eq!(pending.txs.len(), 1);
+        let tx = &pending.txs[0];
+        assert_eq!(tx.txid, txid);
+        assert_eq!(tx.received.get(&policy_asset), Some(&12_345));
+        assert_eq!(pending.totals.get(&policy_asset), Some(&12_345));
+        assert!(tx.fee.as_sat() > 0);
+        assert!(tx.fee_rate.as_sat_per_kvb() > 0);
+        // the fee is the one paid by the node, not computed from the inputs not of the wallet
+        let fee_output: u64 = self
+            .get_tx_from_list(&txid)
+            .transaction
+            .output
+            .iter()
+            .filter(|o| o.is_fee())
+            .map(|o| o.minimum_value())
+            .sum();
+        assert_eq!(tx.fee.as_sat(), fee_output);
+    }
+
+    /// wait for payments from the node, timing out for an amount higher than the ones paid
+    pub fn wait_for_payment(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let address = self.electrum_wallet.address().unwrap();
+        let timeout = Duration::from_secs(2);
+        let cancel = crate::CancelToken::new();
+        let none = self
+            .electrum_wallet
+            .wait_for_payment(
+                &address,
+                crate::Amount::from_sat(1),
+                &policy_asset,
+                timeout,
+                &cancel,
+            )
+            .unwrap();
+        assert!(none.is_none());
+
+        let txid = server.fund_btc(&address, 23_456);
+        let payments = self
+            .electrum_wallet
+            .wait_for_payment(
+                &address,
+                crate::Amount::from_sat(23_456),
+                &policy_asset,
+                Duration::from_secs(60),
+                &cancel,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].txid, txid);
+        assert_eq!(payments[0].satoshi, crate::Amount::from_sat(23_456));
+        assert_eq!(payments[0].height, None);
+        self.wallet_wait_tx_status_change();
+
+        let too_much = self
+            .electrum_wallet
+            .wait_for_payment(
+                &address,
+                crate::Amount::from_sat(23_457),
+                &policy_asset,
+                timeout,
+                &cancel,
+            )
+            .unwrap();
+        assert!(too_much.is_none());
+        assert_eq!(
+            self.electrum_wallet
+                .payments_to(&address, &policy_asset)
+                .unwrap(),
+            payments
+        );
+
+        // the amount is reached by the sum of the payments
+        let second = server.fund_btc(&address, 1_000);
+        let payments = self
+            .electrum_wallet
+            .wait_for_payment(
+                &address,
+                crate::Amount::from_sat(24_000),
+                &policy_asset,
+                Duration::from_secs(60),
+                &cancel,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(payments.len(), 2);
+        assert!(payments.iter().any(|p| p.txid == second));
+        self.wallet_wait_tx_status_change();
+
+        cancel.cancel();
+        assert!(matches!(
+            self.electrum_wallet.wait_for_payment(
+                &address,
+                crate::Amount::from_sat(u64::MAX),
+                &policy_asset,
+                Duration::from_secs(60),
+                &cancel,
+            ),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    /// unblind a payment to the node from a wallet restored from the seed
+    pub fn unblind_sent_output(&mut self, server: &mut TestElectrumServer) {
+        let address = server.node_getnewaddress(None);
+        let satoshi = 2_345;
+        let txid = self.send_tx(&address, satoshi, None, None);
+        let txid = elements::Txid::from_hex(&txid).unwrap();
+
+        let db_root_dir = TempDir::new("unblind_sent_output").unwrap();
+        let db_root = format!("{}", db_root_dir.path().display());
+        let restored = ElectrumWallet::new(
+            self.electrum_wallet.config.clone(),
+            &db_root,
+            &self.mnemonic,
+        )
+        .unwrap();
+        let mut opt = GetTransactionsOpt::default();
+        opt.count = 100;
+        let tx = restored
+            .transactions(&opt)
+            .unwrap()
+            .into_iter()
+            .find(|d| d.txid == txid.to_string())
+            .unwrap()
+            .transaction;
+        let vout = tx
+            .output
+            .iter()
+            .position(|o| o.script_pubkey == address.script_pubkey())
+            .unwrap() as u32;
+        let secrets = restored.unblind_sent_output(&txid, vout, &address).unwrap();
+        assert_eq!(secrets.value, satoshi);
+        assert_eq!(secrets.asset, self.policy_asset());
+
+        // the shared secret depends on the receiver blinding key
+        let other = server.node_getnewaddress(None);
+        let mut wrong_key = address.clone();
+        wrong_key.blinding_pubkey = other.blinding_pubkey;
+        assert!(restored
+            .unblind_sent_output(&txid, vout, &wrong_key)
+            .is_err());
+    }
+
+    /// create a tx with a coin selector spending the smallest utxos first, it is not broadcast
+    pub fn coin_selector(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let address = server.node_getnewaddress(None);
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees = vec![Destination::new(
+            &address.to_string(),
+            crate::Amount::from_sat(1_000),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+
+        self.electrum_wallet
+            .set_coin_selector(Some(Arc::new(SmallestFirst)));
+        let tx = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+        let smallest = self
+            .utxos()
+            .into_iter()
+            .filter(|u| u.unblinded.asset == policy_asset)
+            .min_by_key(|u| u.unblinded.value)
+            .unwrap();
+        assert!(tx
+            .input
+            .iter()
+            .any(|i| i.previous_output == smallest.txo.outpoint));
+
+        // a selection not covering the fee is rejected
+        self.electrum_wallet
+            .set_coin_selector(Some(Arc::new(NoFeeSelector)));
+        assert!(self.electrum_wallet.create_tx(&mut create_opt).is_err());
+        self.electrum_wallet.set_coin_selector(None);
+    }
+
+    /// sign a tx only once approved, it is not broadcast
+    pub fn sign_approver(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let address = server.node_getnewaddress(None);
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees = vec![Destination::new(
+            &address.to_string(),
+            crate::Amount::from_sat(1_000),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        let tx = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+
+        let reject = |_: &SignRequest| SignApproval::Reject("no 2FA".into());
+        self.electrum_wallet
+            .set_sign_approver(Some(Arc::new(reject)));
+        let mut rejected = tx.clone();
+        match self.electrum_wallet.sign_tx(&mut rejected, &self.mnemonic) {
+            Err(Error::SigningRejected(reason)) => assert_eq!(reason, "no 2FA"),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert!(rejected.input.iter().all(|i| i.witness.is_empty()));
+
+        let delay = |_: &SignRequest| SignApproval::Delay;
+        self.electrum_wallet
+            .set_sign_approver(Some(Arc::new(delay)));
+        assert!(matches!(
+            self.electrum_wallet
+                .sign_tx(&mut tx.clone(), &self.mnemonic),
+            Err(Error::SigningDelayed)
+        ));
+
+        let inputs = tx.input.len();
+        let approve = move |request: &SignRequest| {
+            assert_eq!(request.kind, SignKind::Transaction);
+            assert_eq!(request.inputs.len(), inputs);
+            assert!(request.fee > 0);
+            assert!(request.spent[&policy_asset] > 1_000 + request.fee);
+            SignApproval::Approve
+        };
+        self.electrum_wallet
+            .set_sign_approver(Some(Arc::new(approve)));
+        let mut signed = tx;
+        self.electrum_wallet
+            .sign_tx(&mut signed, &self.mnemonic)
+            .unwrap();
+        assert!(signed.input.iter().all(|i| !i.witness.is_empty()));
+        self.electrum_wallet.set_sign_approver(None);
+    }
+
+    /// pay the fee of an asset transfer with a designated policy asset utxo, not broadcast
+    pub fn fee_utxos(
+        &mut self,
+        server: &mut TestElectrumServer,
+        asset: &elements::issuance::AssetId,
+    ) {
+        let policy_asset = self.policy_asset();
+        let address = server.node_getnewaddress(None);
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees = vec![Destination::new(
+            &address.to_string(),
+            crate::Amount::from_sat(1_000),
+            &asset.to_hex(),
+        )
+        .unwrap()];
+        let fee_utxo = self
+            .utxos()
+            .into_iter()
+            .filter(|u| u.unblinded.asset == policy_asset && u.unblinded.value > 10_000)
+            .min_by_key(|u| u.unblinded.value)
+            .unwrap();
+        create_opt.fee_utxos = Some(vec![fee_utxo.txo.outpoint]);
+        let details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let utxos = self.utxos();
+        let policy_inputs: Vec<_> = details
+            .transaction
+            .input
+            .iter()
+            .filter(|i| {
+                utxos.iter().any(|u| {
+                    u.txo.outpoint == i.previous_output && u.unblinded.asset == policy_asset
+                })
+            })
+            .map(|i| i.previous_output)
+            .collect();
+        assert_eq!(policy_inputs, vec![fee_utxo.txo.outpoint]);
+
+        // only unspent policy asset outputs of the wallet pay the fee
+        let asset_utxo = utxos.iter().find(|u| u.unblinded.asset == *asset).unwrap();
+        create_opt.fee_utxos = Some(vec![asset_utxo.txo.outpoint]);
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::UtxoNotFound(_))
+        ));
+
+        // the fee utxos must also be allowed by the other options
+        create_opt.fee_utxos = Some(vec![fee_utxo.txo.outpoint]);
+        create_opt.from_labels = vec!["unused label".to_string()];
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::UtxoNotFound(_))
+        ));
+        create_opt.from_labels.clear();
+
+        // the policy asset change is labelled with the fee account only once signed
+        let account = "fees".to_string();
+        let store = self.electrum_wallet.wallet.store.clone();
+        store
+            .write()
+            .unwrap()
+            .label_insert(fee_utxo.txo.script_pubkey.clone(), &account)
+            .unwrap();
+        create_opt.fee_utxos = None;
+        create_opt.fee_account = Some(account.clone());
+        let details = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let change = details
+            .change_outputs
+            .iter()
+            .find(|c| c.asset == policy_asset)
+            .unwrap()
+            .address
+            .script_pubkey();
+        assert!(!store.read().unwrap().has_label(&change, &[account.clone()]));
+        let mut tx = details.transaction;
+        self.electrum_wallet
+            .sign_tx(&mut tx, &self.mnemonic)
+            .unwrap();
+        assert!(store.read().unwrap().has_label(&change, &[account]));
+    }
+
+    /// check the inputs and outputs order of created txs, they are not broadcast
+    pub fn tx_ordering(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let addresses: Vec<_> = (0..3).map(|_| server.node_getnewaddress(None)).collect();
+        let mut create_opt = CreateTransactionOpt::default();
+        for (i, address) in addresses.iter().enumerate() {
+            create_opt.addressees.push(
+                Destination::new(
+                    &address.to_string(),
+                    crate::Amount::from_sat(3_000 - i as u64 * 1_000),
+                    &policy_asset.to_hex(),
+                )
+                .unwrap(),
+            );
+        }
+
+        create_opt.ordering = crate::TxOrdering::Untouched;
+        let tx = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+        for (output, address) in tx.output.iter().zip(addresses.iter()) {
+            assert_eq!(output.script_pubkey, address.script_pubkey());
+        }
+        assert!(tx.output.last().unwrap().is_fee());
+
+        create_opt.ordering = crate::TxOrdering::Bip69;
+        create_opt.utxos = Some(self.asset_utxos(&policy_asset));
+        let tx = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+        let inputs: Vec<_> = tx
+            .input
+            .iter()
+            .map(|i| (i.previous_output.txid.to_string(), i.previous_output.vout))
+            .collect();
+        let mut sorted = inputs.clone();
+        sorted.sort();
+        assert_eq!(inputs, sorted);
+        assert!(tx.output.last().unwrap().is_fee());
+    }
+
+    /// check create_tx failure reasons
+    pub fn create_fails(&mut self, server: &mut TestElectrumServer) {
+        let policy_asset = self.policy_asset();
+        let init_sat = self.balance_btc();
+        let mut create_opt = CreateTransactionOpt::default();
+        let fee_rate = 1000;
+        let address = server.node_getnewaddress(None).to_string();
+        create_opt.fee_rate = Some(crate::FeeRate::from_sat_per_kvb(fee_rate));
+        create_opt.addressees =
+            vec![
+                Destination::new(&address, crate::Amount::from_sat(0), &policy_asset.to_hex())
+                    .unwrap(),
+            ];
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::InvalidAmount)
+        ));
+
+        create_opt.addressees = vec![Destination::new(
+            &address,
+            crate::Amount::from_sat(200),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::InvalidAmount)
+        ));
+
+        create_opt.addressees = vec![Destination::new(
+            &address,
+            crate::Amount::from_sat(init_sat), // not enough to pay fee
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::InsufficientAssetFunds { asset, missing, estimated_fee })
+                if asset == policy_asset && missing > 0 && estimated_fee > 0
+        ));
+
+        assert!(matches!(
+            Destination::new("x", crate::Amount::from_sat(200), &policy_asset.to_hex(),),
+            Err(Error::InvalidAddress)
+        ));
+
+        assert!(
+            matches!(
+                Destination::new(
+                    "38CMdevthTKYAtxaSkYYtcv5QgkHXdKKk5",
+                    crate::Amount::from_sat(200),
+                    &policy_asset.to_hex(),
+                ),
+                Err(Error::InvalidAddress)
+            ),
+            "address with different network should fail"
+        );
+
+        create_opt.addressees = vec![Destination::new(
+            "VJLCbLBTCdxhWyjVLdjcSmGAksVMtabYg15maSi93zknQD2ihC38R7CUd8KbDFnV8A4hiykxnRB3Uv6d",
+            crate::Amount::from_sat(200),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::InvalidAddress)
+            ),
+            "address with different network should fail"
+        );
+
+        // from bip173 test vectors
+        assert!(
+            matches!(
+                Destination::new(
+                    "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7k7grplx",
+                    crate::Amount::from_sat(200),
+                    &policy_asset.to_hex(),
+                ),
+                Err(Error::InvalidAddress)
+            ),
+            "segwit v1 should fail"
+        );
+
+        let mut addr = elements::Address::from_str(
+            "Azpt6vXqrbPuUtsumAioGjKnvukPApDssC1HwoFdSWZaBYJrUVSe5K8x9nk2HVYiYANy9mVQbW3iQ6xU",
+        )
+        .unwrap();
+        addr.blinding_pubkey = None;
+        create_opt.addressees = vec![Destination::new(
+            &addr.to_string(),
+            crate::Amount::from_sat(1000),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::InvalidAddress)
+            ),
+            "unblinded address should fail"
+        );
+
+        create_opt.addressees = vec![Destination::new(
+            &address,
+            crate::Amount::from_sat(1000),
+            &policy_asset.to_hex(),
+        )
+        .unwrap()];
+        let fee_asset = elements::issuance::AssetId::from_slice(&[1u8; 32]).unwrap();
+        create_opt.fee_asset = Some(fee_asset);
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::FeeAssetUnsupported(a)) if a == fee_asset
+            ),
+            "fee in non policy asset should fail"
+        );
+        create_opt.fee_asset = None;
+
+        let future_lock_time = self.electrum_wallet.block_status().unwrap().0 + 10;
+        create_opt.lock_time = Some(future_lock_time);
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::InvalidLockTime(l)) if l == future_lock_time
+            ),
+            "locktime above the tip should fail"
+        );
+        create_opt.lock_time = None;
+
+        create_opt.fee_rate = Some(crate::FeeRate::from_sat_per_kvb(50));
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::FeeRateTooLow(r)) if r < 100
+            ),
+            "fee rate below the minimum relay fee should fail"
+        );
+        create_opt.fee_rate = None;
+
+        create_opt.from_labels = vec!["unused label".to_string()];
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::InsufficientAssetFunds { .. })
+            ),
+            "spending from a label without utxos should fail"
+        );
+        create_opt.from_labels = vec![];
+
+        let key = elements::bitcoin::PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let mainchain_address =
+            elements::bitcoin::Address::p2pkh(&key, elements::bitcoin::Network::Regtest);
+        let pegout = Destination::new(
+            &mainchain_address.to_string(),
+            crate::Amount::from_sat(10_000),
+            &self.policy_asset().to_string(),
+        )
+        .unwrap();
+        let addressees = create_opt.addressees.clone();
+        create_opt.addressees = vec![pegout];
+        assert!(
+            matches!(
+                self.electrum_wallet.create_tx(&mut create_opt),
+                Err(Error::InvalidAddress)
+            ),
+            "peg-out without opting in should fail"
+        );
+        create_opt.allow_pegouts = true;
+        let pegout_tx = self.electrum_wallet.create_tx(&mut create_opt).unwrap();
+        let pegout_output = pegout_tx
+            .transaction
+            .output
+            .iter()
+            .find(|o| o.script_pubkey.is_op_return())
+            .unwrap();
+        assert_eq!(
+            pegout_output.value,
+            elements::confidential::Value::Explicit(10_000)
+        );
+        create_opt.allow_pegouts = false;
+        create_opt.addressees = addressees;
+
+        create_opt.addressees = vec![];
+        assert!(matches!(
+            self.electrum_wallet.create_tx(&mut create_opt),
+            Err(Error::EmptyAddressees)
+        ));
+    }
+
+    pub fn utxos(&self) -> Vec<UnblindedTXO> {
+        self.electrum_wallet.utxos().unwrap()
+    }
+
+    pub fn asset_utxos(&self, asset: &elements::issuance::AssetId) -> Vec<UnblindedTXO> {
+        self.electrum_wallet
+            .utxos()
+            .unwrap()
+            .iter()
+            .cloned()
+            .filter(|u| u.unblinded.asset == *asset)
+            .collect()
+    }
+
+    /// performs checks on transactions, like checking for address reuse in outputs and on liquid confidential commitments inequality
+    pub fn tx_checks(&self, transaction: &elements::Transaction) {
+        let breakdown = crate::weight_breakdown(transaction);
+        assert_eq!(breakdown.total, transaction.get_weight());
+        for (output, weight) in transaction.output.iter().zip(breakdown.outputs.iter()) {
+            assert_eq!(
+                output.witness.rangeproof.is_some(),
+                weight.rangeproof > 0,
+                "rangeproof weight"
+            );
+        }
+        assert!(
+            breakdown.inputs.iter().all(|i| i.witness > 0),
+            "unsigned input"
+        );
+        let output_nofee: Vec<&elements::TxOut> =
+            transaction.output.iter().filter(|o| !o.is_fee()).collect();
+        for current in output_nofee.iter() {
+            assert_eq!(
+                1,
+                output_nofee
+                    .iter()
+                    .filter(|o| o.script_pubkey == current.script_pubkey)
+                    .count(),
+                "address reuse"
+            ); // for example using the same change address for lbtc and asset change
+            assert_eq!(
+                1,
+                output_nofee
+                    .iter()
+                    .filter(|o| o.asset == current.asset)
+                    .count(),
+                "asset commitment equal"
+            );
+            assert_eq!(
+                1,
+                output_nofee
+                    .iter()
+                    .filter(|o| o.value == current.value)
+                    .count(),
+                "value commitment equal"
+            );
+            assert_eq!(
+                1,
+                output_nofee
+                    .iter()
+                    .filter(|o| o.nonce == current.nonce)
+                    .count(),
+                "nonce commitment equal"
+            );
+        }
+        assert!(
+            transaction.output.last().unwrap().is_fee(),
+            "last output is not a fee"
+        );
+    }
+
+    pub fn liquidex_assets_db_roundtrip(&self) {
+        let asset = elements::issuance::AssetId::from_slice(&[0; 32]).unwrap();
+        assert_eq!(self.electrum_wallet.liquidex_assets().unwrap().len(), 0);
+        assert!(self
+            .electrum_wallet
+            .liquidex_assets_insert(asset.clone())
+            .unwrap());
+        assert_eq!(self.electrum_wallet.liquidex_assets().unwrap().len(), 1);
+        assert!(!self
+            .electrum_wallet
+            .liquidex_assets_insert(asset.clone())
+            .unwrap());
+        assert_eq!(self.electrum_wallet.liquidex_assets().unwrap().len(), 1);
+        assert!(self.electrum_wallet.liquidex_assets_remove(&asset).unwrap());
+        assert_eq!(self.electrum_wallet.liquidex_assets().unwrap().len(), 0);
+        assert!(!self.electrum_wallet.liquidex_assets_remove(&asset).unwrap());
+        assert_eq!(self.electrum_wallet.liquidex_assets().unwrap().len(), 0);
+    }
+
+    pub fn liquidex_add_asset(&mut self, asset: &elements::issuance::AssetId) {
+        assert!(self
+            .electrum_wallet
+            .liquidex_assets_insert(asset.clone())
+            .unwrap());
+    }
+
+    pub fn liquidex_make(
+        &self,
+        utxo: &elements::OutPoint,
+        asset: &elements::issuance::AssetId,
+        rate: f64,
+    ) -> LiquidexProposal {
+        let opt = LiquidexMakeOpt {
+            utxo: utxo.clone(),
+            asset_id: asset.clone(),
+            rate,
+        };
+        self.electrum_wallet
+            .liquidex_make(&opt, &self.mnemonic)
+            .unwrap()
+    }
+
+    /// reprice a LiquiDEX proposal, checking it spends the same utxo and receives on the same
+    /// address
+    pub fn liquidex_reprice(&self, proposal: &LiquidexProposal, rate: f64) -> LiquidexProposal {
+        let peeked = self.electrum_wallet.peek_address(0).unwrap();
+        let repriced = self
+            .electrum_wallet
+            .liquidex_reprice(proposal, rate, &self.mnemonic)
+            .unwrap();
+        assert_eq!(peeked, self.electrum_wallet.peek_address(0).unwrap());
+        let old = proposal.transaction().unwrap();
+        let new = repriced.transaction().unwrap();
+        assert_eq!(old.input[0].previous_output, new.input[0].previous_output);
+        assert_eq!(old.output[0].script_pubkey, new.output[0].script_pubkey);
+
+        // a proposal without inputs is rejected instead of panicking
+        let mut empty = proposal.clone();
+        let mut tx = proposal.transaction().unwrap();
+        tx.input.clear();
+        empty.set_transaction(&tx);
+        assert!(self
+            .electrum_wallet
+            .liquidex_reprice(&empty, rate, &self.mnemonic)
+            .is_err());
+        repriced
+    }
+
+    /// check that invalid LiquiDEX proposals are rejected without consuming an address
+    pub fn liquidex_make_invalid(
+        &self,
+        utxo: &elements::OutPoint,
+        asset: &elements::issuance::AssetId,
+    ) {
+        let peeked = self.electrum_wallet.peek_address(0).unwrap();
+        let make = |utxo: elements::OutPoint, rate: f64| {
+            let opt = LiquidexMakeOpt {
+                utxo,
+                asset_id: asset.clone(),
+                rate,
+            };
+            self.electrum_wallet.liquidex_make(&opt, &self.mnemonic)
+        };
+        let unknown = elements::OutPoint::new(utxo.txid, u32::MAX);
+        assert!(matches!(make(unknown, 1.0), Err(Error::UtxoNotFound(_))));
+        assert!(matches!(make(*utxo, 0.0), Err(Error::RateOutOfRange(_))));
+        assert!(matches!(
+            make(*utxo, f64::NAN),
+            Err(Error::RateOutOfRange(_))
+        ));
+        assert!(matches!(make(*utxo, 1e20), Err(Error::RateOutOfRange(_))));
+        assert_eq!(peeked, self.electrum_wallet.peek_address(0).unwrap());
+    }
+
+    pub fn liquidex_make_partial(
+        &self,
+        utxo: &elements::OutPoint,
+        asset: &elements::issuance::AssetId,
+        rate: f64,
+        max_amount: u64,
+    ) -> LiquidexProposal {
+        let opt = LiquidexMakeOpt {
+            utxo: utxo.clone(),
+            asset_id: asset.clone(),
+            rate,
+        };
+        let proposal = self
+            .electrum_wallet
+            .liquidex_make_partial(&opt, crate::Amount::from_sat(max_amount))
+            .unwrap();
+        assert_eq!(proposal.partial().unwrap().max_amount.as_sat(), max_amount);
+        proposal
+    }
+
+    /// take `amount` from a partial proposal of `maker`, checking it can be filled only once
+    pub fn liquidex_take_partial(
+        &mut self,
+        proposal: &LiquidexProposal,
+        amount: u64,
+        maker: &TestElectrumWallet,
+    ) -> String {
+        let amount = crate::Amount::from_sat(amount);
+        let fill =
+            |tx: &elements::Transaction| maker.electrum_wallet.liquidex_fill(tx, &maker.mnemonic);
+        let tx = self
+            .electrum_wallet
+            .liquidex_take_partial(proposal, amount, &self.mnemonic, &fill)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+        assert!(self
+            .electrum_wallet
+            .liquidex_take_partial(proposal, amount, &self.mnemonic, &fill)
+            .is_err());
+        tx.txid().to_string()
+    }
+
+    /// pay `satoshi` of `asset` to `receiver` with a payjoin, the receiver adding one of its
+    /// utxos of `asset`
+    pub fn payjoin(
+        &mut self,
+        receiver: &mut TestElectrumWallet,
+        asset: &elements::issuance::AssetId,
+        satoshi: u64,
+    ) {
+        let address = receiver.electrum_wallet.address().unwrap();
+        let mut create_opt = CreateTransactionOpt::default();
+        create_opt.addressees.push(
+            Destination::new(
+                &address.to_string(),
+                crate::Amount::from_sat(satoshi),
+                &asset.to_string(),
+            )
+            .unwrap(),
+        );
+        let original = self
+            .electrum_wallet
+            .create_tx(&mut create_opt)
+            .unwrap()
+            .transaction;
+        let proposal = self
+            .electrum_wallet
+            .wallet
+            .payjoin_proposal(&original)
+            .unwrap();
+        let body = serde_json::to_string(&proposal).unwrap();
+
+        // the sender cannot receive its own proposal
+        assert!(self
+            .electrum_wallet
+            .payjoin_respond(&body, &self.mnemonic)
+            .is_err());
+        let response = receiver
+            .electrum_wallet
+            .payjoin_respond(&body, &receiver.mnemonic)
+            .unwrap();
+        let tx: elements::Transaction =
+            elements::encode::deserialize(&Vec::<u8>::from_hex(&response).unwrap()).unwrap();
+        assert_eq!(tx.input.len(), original.input.len() + 1);
+
+        // a response lowering the fee is not signed
+        let mut lower_fee = tx.clone();
+        for output in lower_fee.output.iter_mut().filter(|o| o.is_fee()) {
+            output.value = elements::confidential::Value::Explicit(output.minimum_value() - 1);
+        }
+        assert!(self
+            .electrum_wallet
+            .wallet
+            .payjoin_sign(&original, &mut lower_fee, &self.mnemonic)
+            .is_err());
+        // a response removing an input of the sender is not signed
+        let mut removed_input = tx.clone();
+        removed_input
+            .input
+            .retain(|i| i.previous_output != original.input[0].previous_output);
+        assert!(self
+            .electrum_wallet
+            .wallet
+            .payjoin_sign(&original, &mut removed_input, &self.mnemonic)
+            .is_err());
+
+        let sender_before = self.balance(asset);
+        let receiver_before = receiver.balance(asset);
+        let mut tx = tx;
+        self.electrum_wallet
+            .wallet
+            .payjoin_sign(&original, &mut tx, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        let txid = tx.txid().to_string();
+        self.wait_for_tx(&txid);
+        receiver.wait_for_tx(&txid);
+
+        let fee = if *asset == self.policy_asset() {
+            self.get_fee(&txid)
+        } else {
+            0
+        };
+        assert_eq!(self.balance(asset), sender_before - satoshi - fee);
+        assert_eq!(receiver.balance(asset), receiver_before + satoshi);
+    }
+
+    /// make a proposal selling `satoshi` of the policy asset received on a 2-of-2 multisig
+    /// output for `asset` at `rate`, collect the cosigner signature and let `taker` take it
+    pub fn liquidex_multisig(
+        &mut self,
+        server: &mut TestElectrumServer,
+        taker: &mut TestElectrumWallet,
+        asset: &elements::issuance::AssetId,
+        satoshi: u64,
+        rate: f64,
+    ) {
+        let secp = elements::bitcoin::secp256k1::Secp256k1::new();
+        let key = |n: u8| elements::bitcoin::PrivateKey {
+            compressed: true,
+            network: elements::bitcoin::Network::Regtest,
+            key: elements::bitcoin::secp256k1::SecretKey::from_slice(&[n; 32]).unwrap(),
+        };
+        let cosigner_key = key(7);
+        let cosigner = elements::bitcoin::PublicKey::from_private_key(&secp, &cosigner_key);
+        let template = Arc::new(crate::MultisigScript { cosigner });
+        let template_index = self.electrum_wallet.register_script(template).unwrap();
+        let address = self
+            .electrum_wallet
+            .template_address(template_index, 0)
+            .unwrap();
+        let txid = server.fund_btc(&address, satoshi);
+        self.wait_for_tx(&txid);
+        let utxo = self
+            .utxos()
+            .into_iter()
+            .find(|u| u.txo.script_pubkey == address.script_pubkey())
+            .expect("multisig utxo")
+            .txo
+            .outpoint;
+
+        let proposal = self.liquidex_make(&utxo, asset, rate);
+        assert!(proposal.pset().unwrap().is_some());
+        // the proposal cannot be taken until the cosigner signed it
+        assert!(taker
+            .electrum_wallet
+            .liquidex_take(&proposal, &taker.mnemonic)
+            .is_err());
+        assert!(self
+            .electrum_wallet
+            .liquidex_cosign(&proposal, &key(8))
+            .is_err());
+        let cosigned = self
+            .electrum_wallet
+            .liquidex_cosign(&proposal, &cosigner_key)
+            .unwrap();
+        assert!(cosigned.pset().unwrap().is_none());
+
+        let asset_before = self.balance(asset);
+        let policy_before = taker.balance(&taker.policy_asset());
+        let txid = taker.liquidex_take(&cosigned);
+        taker.wait_for_tx(&txid);
+        self.wait_for_tx(&txid);
+        let fee = taker.get_fee(&txid);
+        let received = (satoshi as f64 * rate) as u64;
+        assert_eq!(self.balance(asset), asset_before + received);
+        assert_eq!(
+            taker.balance(&taker.policy_asset()),
+            policy_before + satoshi - fee
+        );
+    }
+
+    pub fn liquidex_take(&mut self, proposal: &LiquidexProposal) -> String {
+        // the taker fee rate is within the configured bounds
+        let min_fee_rate = crate::FeeRate::from_sat_per_kvb(1_000);
+        self.electrum_wallet.wallet.config.min_fee_rate = Some(min_fee_rate);
+        let tx = self
+            .electrum_wallet
+            .liquidex_take(proposal, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.wallet.config.min_fee_rate = None;
+        let fee: u64 = tx
+            .output
+            .iter()
+            .filter(|o| o.is_fee())
+            .map(|o| o.minimum_value())
+            .sum();
+        assert!(fee * 1000 / crate::transaction::cached_vbytes(&tx) as u64 >= 900);
+
+        let tx = self
+            .electrum_wallet
+            .liquidex_take(proposal, &self.mnemonic)
+            .unwrap();
+        self.electrum_wallet.broadcast_tx(&tx).unwrap();
+        self.wallet_wait_tx_status_change();
+        tx.txid().to_string()
+    }
+}
@@ -1,22 +1,76 @@
+use crate::amount::Amount;
 use crate::error::Error;
-use elements::bitcoin::hashes::hex::{FromHex, ToHex};
+use crate::model::{Burn, TxOrdering, TxType};
+use crate::redact::redact;
+use crate::scripts::{is_pegout_script, is_standard_script, AddressType};
+use crate::tx_cache::TxLookup;
 use elements::confidential::{Asset, Value};
 use elements::Script;
-use elements::Txid;
 use elements::{confidential, issuance};
 use elements::{TxInWitness, TxOutWitness};
-use log::{info, trace};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use tracing::{info, trace};
 
 pub const DUST_VALUE: u64 = 546;
 
+/// Maximum amount of satoshi of any asset in a single output
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
 // 3-input ASP
 pub const DEFAULT_SURJECTIONPROOF_SIZE: u64 = 135;
 // 52-bit rangeproof
 pub const DEFAULT_RANGEPROOF_SIZE: u64 = 4174;
 
+/// Check the unblinded `secrets` of `output` match its asset and value commitments, a sender
+/// could craft rangeproofs rewinding to values different from the committed ones
+pub fn verify_unblinded(
+    secp: &elements::secp256k1_zkp::Secp256k1<elements::secp256k1_zkp::All>,
+    output: &elements::TxOut,
+    secrets: &elements::TxOutSecrets,
+) -> bool {
+    use elements::secp256k1_zkp::{Generator, PedersenCommitment, Tag};
+    let tag = Tag::from(secrets.asset.into_inner().into_inner());
+    let generator = Generator::new_blinded(secp, tag, secrets.asset_bf.into_inner());
+    let value_commitment = PedersenCommitment::new(
+        secp,
+        secrets.value,
+        secrets.value_bf.into_inner(),
+        generator,
+    );
+    match (output.asset, output.value) {
+        (Asset::Confidential(asset), Value::Confidential(value)) => {
+            asset == generator && value == value_commitment
+        }
+        (Asset::Explicit(asset), Value::Explicit(value)) => {
+            asset == secrets.asset
+                && value == secrets.value
+                && secrets.asset_bf == confidential::AssetBlindingFactor::zero()
+                && secrets.value_bf == confidential::ValueBlindingFactor::zero()
+        }
+        _ => false,
+    }
+}
+
+/// Secrets of an unconfidential output, whose asset and value are explicit, with zero blinding
+/// factors so that it is spent like the confidential ones
+pub fn explicit_secrets(output: &elements::TxOut) -> Option<elements::TxOutSecrets> {
+    match (output.asset, output.value) {
+        (Asset::Explicit(asset), Value::Explicit(value)) if !output.is_fee() => {
+            Some(elements::TxOutSecrets::new(
+                asset,
+                confidential::AssetBlindingFactor::zero(),
+                value,
+                confidential::ValueBlindingFactor::zero(),
+            ))
+        }
+        _ => None,
+    }
+}
+
 pub fn strip_witness(tx: &mut elements::Transaction) {
     for input in tx.input.iter_mut() {
         input.witness = TxInWitness::default();
@@ -30,12 +84,12 @@ fn get_output_satoshi(
     tx: &elements::Transaction,
     vout: u32,
     all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> u64 {
+) -> Option<u64> {
     let outpoint = elements::OutPoint {
         txid: tx.txid(),
         vout,
     };
-    all_unblinded.get(&outpoint).unwrap().value // TODO return Result<u64>?
+    all_unblinded.get(&outpoint).map(|u| u.value)
 }
 
 fn get_output_asset(
@@ -53,22 +107,13 @@ fn get_output_asset(
     }
 }
 
-fn get_output_asset_hex(
-    tx: &elements::Transaction,
-    vout: u32,
-    all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Option<String> {
-    get_output_asset(tx, vout, all_unblinded).and_then(|a| Some(a.to_hex()))
-}
-
 pub fn add_output(
     tx: &mut elements::Transaction,
     address: &elements::Address,
     value: u64,
-    asset_hex: String,
+    asset_id: issuance::AssetId,
 ) -> Result<(), Error> {
     let blinding_pubkey = address.blinding_pubkey.ok_or(Error::InvalidAddress)?;
-    let asset_id = issuance::AssetId::from_hex(&asset_hex)?;
     let new_out = elements::TxOut {
         asset: confidential::Asset::Explicit(asset_id),
         value: confidential::Value::Explicit(value),
@@ -80,12 +125,120 @@ pub fn add_output(
     Ok(())
 }
 
+/// Add an unblinded output pegging out `value` of the policy asset `asset_id`, `script_pubkey`
+/// is built with `scripts::pegout_script`
+pub fn add_pegout_output(
+    tx: &mut elements::Transaction,
+    script_pubkey: Script,
+    value: u64,
+    asset_id: issuance::AssetId,
+) {
+    tx.output.push(elements::TxOut {
+        asset: confidential::Asset::Explicit(asset_id),
+        value: confidential::Value::Explicit(value),
+        nonce: confidential::Nonce::Null,
+        script_pubkey,
+        witness: TxOutWitness::default(),
+    });
+}
+
+/// Outputs of `tx` burning an explicit value of an explicit asset, peg-outs excluded
+pub fn burns(tx: &elements::Transaction) -> Vec<Burn> {
+    tx.output
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| {
+            output.script_pubkey.is_op_return() && !is_pegout_script(&output.script_pubkey)
+        })
+        .filter_map(|(vout, output)| match (output.asset, output.value) {
+            (Asset::Explicit(asset), Value::Explicit(satoshi)) if satoshi > 0 => Some(Burn {
+                vout: vout as u32,
+                asset,
+                satoshi: Amount::from_sat(satoshi),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Kind of the wallet transaction `tx` with `balances` changes, `burns` are the burns of the
+/// wallet assets.
+///
+/// Peg-ins, peg-outs, issuances and burns are recognized by their inputs and outputs, the other
+/// transactions by the sign of the balance changes with the fee paid by the wallet excluded.
+pub fn tx_type(
+    tx: &elements::Transaction,
+    balances: &HashMap<issuance::AssetId, i64>,
+    fee: u64,
+    policy_asset: issuance::AssetId,
+    burns: &[Burn],
+) -> TxType {
+    if tx.input.iter().any(|input| input.is_pegin) {
+        return TxType::PegIn;
+    }
+    if tx
+        .output
+        .iter()
+        .any(|output| is_pegout_script(&output.script_pubkey))
+    {
+        return TxType::PegOut;
+    }
+    if tx.input.iter().any(|input| input.has_issuance()) {
+        return TxType::Issuance;
+    }
+    if !burns.is_empty() {
+        return TxType::Burn;
+    }
+    let changes = balances.iter().map(|(asset, satoshi)| {
+        if *asset == policy_asset && *satoshi < 0 {
+            // the fee is paid by the wallet if it spent the policy asset
+            satoshi + fee as i64
+        } else {
+            *satoshi
+        }
+    });
+    let (mut sent, mut received) = (false, false);
+    for satoshi in changes {
+        sent |= satoshi < 0;
+        received |= satoshi > 0;
+    }
+    match (sent, received) {
+        (true, true) => TxType::Swap,
+        (true, false) => TxType::Outgoing,
+        (false, true) => TxType::Incoming,
+        (false, false) => TxType::Redeposit,
+    }
+}
+
 pub fn scramble(tx: &mut elements::Transaction) {
     let mut rng = thread_rng();
     tx.input.shuffle(&mut rng);
     tx.output.shuffle(&mut rng);
 }
 
+/// Sort the inputs and outputs of `tx` as BIP69, outputs must still be explicit. Outputs with
+/// the same value and script are sorted by asset.
+pub fn bip69(tx: &mut elements::Transaction) {
+    tx.input
+        .sort_by_cached_key(|i| (i.previous_output.txid.to_string(), i.previous_output.vout));
+    tx.output.sort_by_cached_key(|o| {
+        (
+            o.minimum_value(),
+            o.script_pubkey.as_bytes().to_vec(),
+            elements::encode::serialize(&o.asset),
+        )
+    });
+}
+
+/// Order the inputs and outputs of `tx` following `ordering`
+pub fn order_tx(tx: &mut elements::Transaction, ordering: TxOrdering) {
+    match ordering {
+        TxOrdering::Shuffle => scramble(tx),
+        TxOrdering::Bip69 => bip69(tx),
+        TxOrdering::Untouched => (),
+    }
+}
+
 fn mock_generator() -> elements::secp256k1_zkp::Generator {
     let mut a = [2u8; 33];
     a[0] = 10;
@@ -103,24 +256,220 @@ fn mock_pubkey() -> elements::secp256k1_zkp::PublicKey {
     elements::secp256k1_zkp::PublicKey::from_slice(&a).unwrap()
 }
 
-/// estimates the fee of the final transaction given the `fee_rate`
-/// called when the tx is being built and miss things like signatures and changes outputs.
-pub fn estimated_fee(tx: &elements::Transaction, fee_rate: f64, more_changes: u8) -> u64 {
-    let mut tx = tx.clone();
+/// Minimum fee rate relayed by the nodes, in satoshi/kbyte
+pub const MIN_RELAY_FEE_RATE: u64 = 100;
+
+/// Maximum virtual size of a standard transaction
+pub const MAX_STANDARD_VBYTES: usize = 100_000;
+
+/// Add a signature of a wallet key of `address_type` to the unsigned inputs of `tx`, for size
+/// estimations
+fn add_mock_witnesses(tx: &mut elements::Transaction, address_type: AddressType) {
     for input in tx.input.iter_mut() {
         if input.witness.is_empty() && input.script_sig.is_empty() {
             let mut tx_wit = TxInWitness::default();
             tx_wit.script_witness = vec![vec![0u8; 72], vec![0u8; 33]]; // considering signature sizes (72) and compressed public key (33)
             input.witness = tx_wit;
-            input.script_sig = vec![0u8; 23].into(); // p2shwpkh redeem script size
+            input.script_sig = match address_type {
+                AddressType::P2shP2wpkh => vec![0u8; 23].into(), // p2shwpkh redeem script size
+                AddressType::P2wpkh => Script::new(),
+            };
+        }
+    }
+}
+
+/// Size of the script pubkey of the wallet addresses of `address_type`
+fn mock_script_pubkey(address_type: AddressType) -> Script {
+    match address_type {
+        AddressType::P2shP2wpkh => vec![0u8; 23].into(),
+        AddressType::P2wpkh => vec![0u8; 22].into(),
+    }
+}
+
+/// Virtual size of `tx` once signed and blinded, estimating the missing signatures and proofs,
+/// the unsigned inputs spend wallet outputs of `address_type`
+pub fn estimated_vbytes(tx: &elements::Transaction, address_type: AddressType) -> usize {
+    let mut tx = tx.clone();
+    add_mock_witnesses(&mut tx, address_type);
+    let to_blind = tx
+        .output
+        .iter()
+        .filter(|o| {
+            o.value.is_explicit() && matches!(o.nonce, confidential::Nonce::Confidential(_))
+        })
+        .count();
+    let proofs_size = (DEFAULT_SURJECTIONPROOF_SIZE + DEFAULT_RANGEPROOF_SIZE) as usize * to_blind;
+    (tx.get_weight() + proofs_size + 3) / 4
+}
+
+/// Virtual size of `tx` as broadcast, estimated from a copy cached without witnesses: every
+/// input is counted with a signature and a public key, every confidential output with the
+/// default proofs
+pub fn cached_vbytes(tx: &elements::Transaction) -> usize {
+    let mut tx = tx.clone();
+    for input in tx.input.iter_mut() {
+        if input.witness.is_empty() {
+            input.witness.script_witness = vec![vec![0u8; 72], vec![0u8; 33]];
+        }
+    }
+    let confidential = tx
+        .output
+        .iter()
+        .filter(|o| matches!(o.value, confidential::Value::Confidential(_)))
+        .count();
+    let proofs_size =
+        (DEFAULT_SURJECTIONPROOF_SIZE + DEFAULT_RANGEPROOF_SIZE) as usize * confidential;
+    (tx.get_weight() + proofs_size + 3) / 4
+}
+
+/// Weight units of an input, the non-witness data weights 4 units per byte
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputWeight {
+    /// Outpoint, script sig, sequence and issuance
+    pub base: usize,
+    /// Signatures, witness script and issuance proofs
+    pub witness: usize,
+}
+
+/// Weight units of an output, proofs are part of the witness
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputWeight {
+    /// Asset, value, nonce and script pubkey
+    pub base: usize,
+    pub rangeproof: usize,
+    pub surjection_proof: usize,
+}
+
+impl InputWeight {
+    pub fn total(&self) -> usize {
+        self.base + self.witness
+    }
+}
+
+impl OutputWeight {
+    pub fn total(&self) -> usize {
+        self.base + self.rangeproof + self.surjection_proof
+    }
+}
+
+/// Weight of a transaction split by input and output, the fee paid by an element at a fee rate
+/// in satoshi per 1000 vbytes is `total() * fee_rate / 4000`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WeightBreakdown {
+    /// Version, locktime, counts and witness markers
+    pub overhead: usize,
+    pub inputs: Vec<InputWeight>,
+    pub outputs: Vec<OutputWeight>,
+    /// Weight of the whole transaction
+    pub total: usize,
+}
+
+/// Weight of `tx` split by input and output, as currently serialized: unsigned inputs and
+/// unblinded outputs count without signatures and proofs.
+pub fn weight_breakdown(tx: &elements::Transaction) -> WeightBreakdown {
+    let total = tx.get_weight();
+    let inputs = tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let mut stripped = tx.clone();
+            stripped.input[i].witness = TxInWitness::default();
+            InputWeight {
+                base: elements::encode::serialize(input).len() * 4,
+                witness: total - stripped.get_weight(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let outputs = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(i, output)| {
+            let mut no_rangeproof = tx.clone();
+            no_rangeproof.output[i].witness.rangeproof = None;
+            let mut no_surjection_proof = tx.clone();
+            no_surjection_proof.output[i].witness.surjection_proof = None;
+            OutputWeight {
+                base: elements::encode::serialize(output).len() * 4,
+                rangeproof: total - no_rangeproof.get_weight(),
+                surjection_proof: total - no_surjection_proof.get_weight(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let elements_weight: usize = inputs.iter().map(InputWeight::total).sum::<usize>()
+        + outputs.iter().map(OutputWeight::total).sum::<usize>();
+    WeightBreakdown {
+        overhead: total - elements_weight,
+        inputs,
+        outputs,
+        total,
+    }
+}
+
+/// Check `tx` would be relayed by the nodes: fee rate not below `MIN_RELAY_FEE_RATE`, size not
+/// above `MAX_STANDARD_VBYTES`, no policy asset dust outputs and standard output scripts.
+/// Unsigned and unblinded transactions are checked with their estimated final size, their
+/// unsigned inputs spending outputs of `address_type`.
+pub fn check_standardness(
+    tx: &elements::Transaction,
+    policy_asset: issuance::AssetId,
+    address_type: AddressType,
+) -> Result<(), Error> {
+    let vbytes = estimated_vbytes(tx, address_type);
+    if vbytes > MAX_STANDARD_VBYTES {
+        return Err(Error::TransactionTooLarge(vbytes));
+    }
+
+    let fee: u64 = tx
+        .output
+        .iter()
+        .filter(|o| o.is_fee())
+        .map(|o| o.minimum_value())
+        .sum();
+    let fee_rate = fee * 1000 / vbytes as u64;
+    if fee_rate < MIN_RELAY_FEE_RATE {
+        return Err(Error::FeeRateTooLow(fee_rate));
+    }
+
+    for (vout, output) in tx.output.iter().enumerate() {
+        if output.is_fee() {
+            continue;
+        }
+        let script = &output.script_pubkey;
+        if !is_standard_script(script) {
+            return Err(Error::NonStandardScript(vout));
+        }
+        // unspendable outputs are never dust
+        if script.is_op_return() {
+            continue;
+        }
+        if let (Asset::Explicit(asset), Value::Explicit(value)) = (output.asset, output.value) {
+            if asset == policy_asset && value < DUST_VALUE {
+                return Err(Error::DustOutput(vout));
+            }
         }
     }
+    Ok(())
+}
+
+/// estimates the fee of the final transaction given the `fee_rate`
+/// called when the tx is being built and miss things like signatures and changes outputs.
+/// The unsigned inputs spend and the changes are sent to wallet addresses of `address_type`.
+pub fn estimated_fee(
+    tx: &elements::Transaction,
+    fee_rate: f64,
+    more_changes: u8,
+    address_type: AddressType,
+) -> u64 {
+    let mut tx = tx.clone();
+    add_mock_witnesses(&mut tx, address_type);
     for _ in 0..more_changes {
         let new_out = elements::TxOut {
             asset: confidential::Asset::Confidential(mock_generator()),
             value: confidential::Value::Confidential(mock_pedersen_commitment()),
             nonce: confidential::Nonce::Confidential(mock_pubkey()),
-            script_pubkey: vec![0u8; 23].into(),
+            script_pubkey: mock_script_pubkey(address_type),
             ..Default::default()
         };
         tx.output.push(new_out);
@@ -139,39 +488,36 @@ pub fn estimated_fee(tx: &elements::Transaction, fee_rate: f64, more_changes: u8
         tx.output.len(),
         more_changes,
         vbytes,
-        fee_val
+        redact(fee_val)
     );
     fee_val
 }
 
-/// return a map asset-value for the outputs needed for this transaction to be valid
+/// Return the asset amounts `tx` still needs from additional inputs to be valid.
+///
+/// The outputs of `tx` must be explicit (not yet blinded). Inputs are valued by looking up their
+/// previous outputs in the caller supplied `all_txs`, unblinding them with `unblinded` when
+/// confidential. The fee for `fee_rate` (sat/vbyte), paid in `policy_asset`, is included,
+/// estimated for inputs and changes of `address_type`.
+/// Returns an empty vec when the inputs already cover outputs and fee.
 pub fn needs(
     tx: &elements::Transaction,
     fee_rate: f64,
     policy_asset: elements::issuance::AssetId,
-    all_txs: &HashMap<Txid, elements::Transaction>,
+    all_txs: &dyn TxLookup,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Vec<(elements::issuance::AssetId, u64)> {
-    let mut outputs: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
-    for output in tx.output.iter() {
-        match (output.asset, output.value) {
-            (Asset::Explicit(asset), Value::Explicit(value)) => {
-                *outputs.entry(asset).or_insert(0) += value;
-            }
-            _ => panic!("asset and value should be explicit here"),
-        }
-    }
+    address_type: AddressType,
+) -> Result<Vec<(elements::issuance::AssetId, u64)>, Error> {
+    let mut outputs = explicit_outputs(tx)?;
+    let mut inputs = previous_outputs(tx, all_txs, unblinded)?;
 
-    let mut inputs: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
-
-    for input in tx.input.iter() {
-        let asset = get_previous_output_asset(&all_txs, input.previous_output, unblinded).unwrap();
-        let value = get_previous_output_value(&all_txs, &input.previous_output, unblinded).unwrap();
-        *inputs.entry(asset).or_insert(0) += value;
-    }
-
-    let estimated_fee = estimated_fee(&tx, fee_rate, estimated_changes(&tx, all_txs, unblinded));
-    *outputs.entry(policy_asset).or_insert(0) += estimated_fee;
+    let estimated_fee = estimated_fee(
+        &tx,
+        fee_rate,
+        estimated_changes(&tx, all_txs, unblinded)?,
+        address_type,
+    );
+    add_amount(&mut outputs, policy_asset, estimated_fee)?;
 
     let mut result = vec![];
     for (asset, value) in outputs.iter() {
@@ -182,59 +528,53 @@ pub fn needs(
         }
     }
 
-    result
+    Ok(result)
 }
 
 pub fn estimated_changes(
     tx: &elements::Transaction,
-    all_txs: &HashMap<Txid, elements::Transaction>,
+    all_txs: &dyn TxLookup,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> u8 {
+) -> Result<u8, Error> {
     let mut different_assets = HashSet::new();
     for input in tx.input.iter() {
-        let asset_hex =
-            get_previous_output_asset_hex(&all_txs, input.previous_output, unblinded).unwrap();
-        different_assets.insert(asset_hex.clone());
+        if let Some(asset) = get_previous_output_asset(all_txs, input.previous_output, unblinded)? {
+            different_assets.insert(asset);
+        }
     }
     if different_assets.is_empty() {
-        0
+        Ok(0)
     } else {
-        different_assets.len() as u8
+        Ok(different_assets.len() as u8)
     }
 }
 
-/// return a map asset-value for the changes of this transaction
-/// requires inputs are greater than outputs for earch asset
+/// Return the asset amounts of the change outputs `tx` needs.
+///
+/// Like [`needs`], outputs must be explicit and inputs are valued with the caller supplied
+/// `all_txs` and `unblinded` maps. `estimated_fee` is subtracted from the `policy_asset` change,
+/// which is omitted if it would be dust. Call it once [`needs`] returns an empty vec, since inputs
+/// must cover the outputs of every asset.
 pub fn changes(
     tx: &elements::Transaction,
     estimated_fee: u64,
     policy_asset: elements::issuance::AssetId,
-    all_txs: &HashMap<Txid, elements::Transaction>,
+    all_txs: &dyn TxLookup,
     unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> HashMap<elements::issuance::AssetId, u64> {
-    let mut outputs_asset_amounts: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
-    for output in tx.output.iter() {
-        match (output.asset, output.value) {
-            (Asset::Explicit(asset), Value::Explicit(value)) => {
-                *outputs_asset_amounts.entry(asset).or_insert(0) += value;
-            }
-            _ => panic!("asset and value should be explicit here"),
-        }
-    }
-
-    let mut inputs_asset_amounts: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
-    for input in tx.input.iter() {
-        let asset = get_previous_output_asset(&all_txs, input.previous_output, unblinded).unwrap();
-        let value = get_previous_output_value(&all_txs, &input.previous_output, unblinded).unwrap();
-        *inputs_asset_amounts.entry(asset).or_insert(0) += value;
-    }
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+    let mut outputs_asset_amounts = explicit_outputs(tx)?;
+    let inputs_asset_amounts = previous_outputs(tx, all_txs, unblinded)?;
     let mut result: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
     for (asset, value) in inputs_asset_amounts.iter() {
-        let mut sum: u64 = value - outputs_asset_amounts.remove(asset).unwrap_or(0);
+        let mut sum: u64 = value
+            .checked_sub(outputs_asset_amounts.remove(asset).unwrap_or(0))
+            .ok_or(Error::Unbalanced(*asset))?;
         if *asset == policy_asset {
             // from a purely privacy perspective could make sense to always create the change output in liquid, so min change = 0
             // however elements core use the dust anyway for 2 reasons: rebasing from core and economical considerations
-            sum -= estimated_fee;
+            sum = sum
+                .checked_sub(estimated_fee)
+                .ok_or(Error::Unbalanced(*asset))?;
             if sum > DUST_VALUE {
                 // we apply dust rules for liquid bitcoin as elements do
                 result.insert(*asset, sum);
@@ -243,8 +583,78 @@ pub fn changes(
             result.insert(*asset, sum);
         }
     }
-    assert!(outputs_asset_amounts.is_empty());
-    result
+    if let Some(asset) = outputs_asset_amounts.keys().next() {
+        return Err(Error::Unbalanced(*asset));
+    }
+    Ok(result)
+}
+
+/// Add `value` of `asset` to `amounts`, failing instead of overflowing
+pub(crate) fn add_amount(
+    amounts: &mut HashMap<elements::issuance::AssetId, u64>,
+    asset: elements::issuance::AssetId,
+    value: u64,
+) -> Result<(), Error> {
+    let amount = amounts.entry(asset).or_insert(0);
+    *amount = amount.checked_add(value).ok_or(Error::AmountOverflow)?;
+    Ok(())
+}
+
+/// Add `value` of `asset` to the signed `balances`, subtracting it if `spent`, failing instead
+/// of overflowing
+pub(crate) fn add_balance(
+    balances: &mut HashMap<elements::issuance::AssetId, i64>,
+    asset: elements::issuance::AssetId,
+    value: u64,
+    spent: bool,
+) -> Result<(), Error> {
+    let value = i64::try_from(value).map_err(|_| Error::AmountOverflow)?;
+    let balance = balances.entry(asset).or_insert(0);
+    let sum = if spent {
+        balance.checked_sub(value)
+    } else {
+        balance.checked_add(value)
+    };
+    *balance = sum.ok_or(Error::AmountOverflow)?;
+    Ok(())
+}
+
+/// Sum the explicit outputs of `tx` by asset, failing if any output is confidential
+pub fn explicit_outputs(
+    tx: &elements::Transaction,
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+    let mut result: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
+    for output in tx.output.iter() {
+        match (output.asset, output.value) {
+            (Asset::Explicit(asset), Value::Explicit(value)) => {
+                add_amount(&mut result, asset, value)?;
+            }
+            _ => {
+                return Err(Error::Generic(
+                    "asset and value should be explicit here".into(),
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Sum the previous outputs spent by `tx` by asset, failing if any is unknown
+fn previous_outputs(
+    tx: &elements::Transaction,
+    all_txs: &dyn TxLookup,
+    unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
+) -> Result<HashMap<elements::issuance::AssetId, u64>, Error> {
+    let mut result: HashMap<elements::issuance::AssetId, u64> = HashMap::new();
+    for input in tx.input.iter() {
+        let outpoint = input.previous_output;
+        let missing = || Error::MissingPreviousOutput(outpoint);
+        let asset = get_previous_output_asset(all_txs, outpoint, unblinded)?.ok_or_else(missing)?;
+        let value =
+            get_previous_output_value(all_txs, &outpoint, unblinded)?.ok_or_else(missing)?;
+        add_amount(&mut result, asset, value)?;
+    }
+    Ok(result)
 }
 
 pub fn add_fee_output(
@@ -262,6 +672,16 @@ pub fn add_fee_output(
     Ok(())
 }
 
+/// True if some output has an explicit value and the receiver blinding key in the nonce, as
+/// the confidential outputs of transactions not blinded yet
+pub fn needs_blinding(tx: &elements::Transaction) -> bool {
+    tx.output.iter().any(|o| {
+        !o.is_fee()
+            && o.value.is_explicit()
+            && matches!(o.nonce, confidential::Nonce::Confidential(_))
+    })
+}
+
 pub fn add_input(tx: &mut elements::Transaction, outpoint: elements::OutPoint) {
     let new_in = elements::TxIn {
         previous_output: outpoint,
@@ -275,15 +695,15 @@ pub fn add_input(tx: &mut elements::Transaction, outpoint: elements::OutPoint) {
     tx.input.push(new_in);
 }
 
-/// calculate transaction fee,
-/// for bitcoin it requires all previous output to get input values.
-/// for elements,
-///     for complete transactions looks at the explicit fee output,
-///     for incomplete tx (without explicit fee output) take the sum previous outputs value, previously unblinded
-///                       and use the outputs value that must be still unblinded
+/// Calculate the fee of `tx`.
+///
+/// For complete transactions this is the explicit fee output in `policy_asset`. For incomplete
+/// transactions (without a fee output) it is the sum of the previous outputs values, looked up in
+/// the caller supplied `all_txs` and unblinded with `all_unblinded`, minus the outputs values,
+/// which must still be explicit.
 pub fn fee(
     tx: &elements::Transaction,
-    all_txs: &HashMap<Txid, elements::Transaction>,
+    all_txs: &dyn TxLookup,
     all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
     policy_asset: &Option<Asset>,
 ) -> Result<u64, Error> {
@@ -293,28 +713,44 @@ pub fn fee(
         if has_fee {
             let policy_asset =
                 policy_asset.ok_or_else(|| Error::Generic("Missing policy asset".into()))?;
-            tx.output
-                .iter()
-                .filter(|o| o.is_fee())
-                .filter(|o| policy_asset == o.asset)
-                .map(|o| o.minimum_value()) // minimum_value used for extracting the explicit value (value is always explicit for fee)
-                .sum::<u64>()
+            let mut fee = 0u64;
+            // minimum_value used for extracting the explicit value (value is always explicit for fee)
+            for output in tx.output.iter().filter(|o| o.is_fee()) {
+                if policy_asset == output.asset {
+                    fee = fee
+                        .checked_add(output.minimum_value())
+                        .ok_or(Error::AmountOverflow)?;
+                }
+            }
+            fee
         } else {
             // while we are not filtering assets, the following holds for valid tx because
             // sum of input assets = sum of output assets
-            let sum_outputs: u64 = tx.output.iter().map(|o| o.minimum_value()).sum();
-            let sum_inputs: u64 = tx
-                .input
-                .iter()
-                .map(|i| i.previous_output)
-                .filter_map(|o| get_previous_output_value(&all_txs, &o, all_unblinded))
-                .sum();
-
-            sum_inputs - sum_outputs
+            let mut sum_outputs: u64 = 0;
+            for output in tx.output.iter() {
+                sum_outputs = sum_outputs
+                    .checked_add(output.minimum_value())
+                    .ok_or(Error::AmountOverflow)?;
+            }
+            let mut sum_inputs: u64 = 0;
+            for input in tx.input.iter() {
+                let outpoint = input.previous_output;
+                if let Some(value) = get_previous_output_value(all_txs, &outpoint, all_unblinded)? {
+                    sum_inputs = sum_inputs.checked_add(value).ok_or(Error::AmountOverflow)?;
+                }
+            }
+
+            sum_inputs
+                .checked_sub(sum_outputs)
+                .ok_or(Error::AmountOverflow)?
         }
     })
 }
 
+/// Return the net change per asset that `tx` causes to the owner of `all_unblinded`.
+///
+/// Every input and output found in the caller supplied `all_unblinded` map is counted as the
+/// owner's; assets whose balance does not change are omitted.
 pub fn my_balance_changes(
     tx: &elements::Transaction,
     all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
@@ -322,7 +758,7 @@ pub fn my_balance_changes(
     trace!(
         "tx_id: {} my_balances elements all_unblinded.len(): {:?}",
         tx.txid(),
-        all_unblinded
+        redact(all_unblinded)
     );
     let mut result = HashMap::new();
     for input in tx.input.iter() {
@@ -332,7 +768,7 @@ pub fn my_balance_changes(
                 "tx_id: {} unblinded previous output {} {}",
                 tx.txid(),
                 outpoint,
-                unblinded.value
+                redact(unblinded.value)
             );
             *result.entry(unblinded.asset).or_default() -= unblinded.value as i64;
             // TODO check overflow
@@ -348,7 +784,7 @@ pub fn my_balance_changes(
                 "tx_id: {} unblinded output {} {}",
                 tx.txid(),
                 outpoint,
-                unblinded.value
+                redact(unblinded.value)
             );
             *result.entry(unblinded.asset).or_default() += unblinded.value as i64;
             // TODO check overflow
@@ -360,29 +796,239 @@ pub fn my_balance_changes(
 }
 
 pub fn get_previous_output_value(
-    txs: &HashMap<Txid, elements::Transaction>,
+    txs: &dyn TxLookup,
     outpoint: &elements::OutPoint,
     all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Option<u64> {
-    txs.get(&outpoint.txid)
-        .map(|tx| get_output_satoshi(&tx, outpoint.vout, &all_unblinded))
+) -> Result<Option<u64>, Error> {
+    Ok(txs
+        .get_tx(&outpoint.txid)?
+        .and_then(|tx| get_output_satoshi(&tx, outpoint.vout, &all_unblinded)))
 }
 
 pub fn get_previous_output_asset(
-    txs: &HashMap<Txid, elements::Transaction>,
+    txs: &dyn TxLookup,
     outpoint: elements::OutPoint,
     all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Option<elements::issuance::AssetId> {
-    txs.get(&outpoint.txid)
-        .map(|tx| get_output_asset(&tx, outpoint.vout, &all_unblinded).unwrap())
+) -> Result<Option<elements::issuance::AssetId>, Error> {
+    Ok(txs
+        .get_tx(&outpoint.txid)?
+        .and_then(|tx| get_output_asset(&tx, outpoint.vout, &all_unblinded)))
 }
 
-pub fn get_previous_output_asset_hex(
-    txs: &HashMap<Txid, elements::Transaction>,
-    outpoint: elements::OutPoint,
-    all_unblinded: &HashMap<elements::OutPoint, elements::TxOutSecrets>,
-) -> Option<String> {
-    get_previous_output_asset(txs, outpoint, all_unblinded).and_then(|a| Some(a.to_hex()));
-    txs.get(&outpoint.txid)
-        .map(|tx| get_output_asset_hex(&tx, outpoint.vout, &all_unblinded).unwrap())
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use elements::confidential::{AssetBlindingFactor, ValueBlindingFactor};
+    use elements::issuance::AssetId;
+    use elements::{OutPoint, TxOutSecrets, Txid};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Amount likely to overflow or underflow when summed or subtracted, for the fuzz tests
+    pub(crate) fn amount(rng: &mut StdRng) -> u64 {
+        match rng.gen_range(0, 4) {
+            0 => rng.gen_range(0, 1_000),
+            1 => rng.gen_range(0, MAX_MONEY),
+            2 => u64::MAX - rng.gen_range(0, 1_000),
+            _ => rng.gen(),
+        }
+    }
+
+    fn explicit_output(asset: AssetId, value: u64) -> elements::TxOut {
+        elements::TxOut {
+            asset: Asset::Explicit(asset),
+            value: Value::Explicit(value),
+            script_pubkey: vec![0u8; 22].into(),
+            ..Default::default()
+        }
+    }
+
+    fn empty_tx() -> elements::Transaction {
+        elements::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn test_add_balance() {
+        let asset = AssetId::from_slice(&[1; 32]).unwrap();
+        let mut balances = HashMap::new();
+        add_balance(&mut balances, asset, 10, false).unwrap();
+        add_balance(&mut balances, asset, 15, true).unwrap();
+        assert_eq!(balances.get(&asset), Some(&-5));
+        assert!(matches!(
+            add_balance(&mut balances, asset, u64::MAX, false),
+            Err(Error::AmountOverflow)
+        ));
+        add_balance(&mut balances, asset, i64::MAX as u64 - 10, true).unwrap();
+        assert!(matches!(
+            add_balance(&mut balances, asset, 10, true),
+            Err(Error::AmountOverflow)
+        ));
+        assert_eq!(balances.get(&asset), Some(&(5 - i64::MAX)));
+    }
+
+    #[test]
+    fn test_fee_overflow() {
+        let policy_asset = AssetId::from_slice(&[1; 32]).unwrap();
+        let all_txs: HashMap<Txid, elements::Transaction> = HashMap::new();
+        let unblinded = HashMap::new();
+        let mut tx = empty_tx();
+        for _ in 0..2 {
+            let mut output = explicit_output(policy_asset, u64::MAX);
+            output.script_pubkey = elements::Script::new();
+            tx.output.push(output);
+        }
+        let policy_asset = Some(Asset::Explicit(policy_asset));
+        assert!(matches!(
+            fee(&tx, &all_txs, &unblinded, &policy_asset),
+            Err(Error::AmountOverflow)
+        ));
+
+        // without fee outputs the fee is the difference of the inputs and the outputs
+        tx.output[0].script_pubkey = vec![0u8; 22].into();
+        tx.output.truncate(1);
+        assert!(matches!(
+            fee(&tx, &all_txs, &unblinded, &policy_asset),
+            Err(Error::AmountOverflow)
+        ));
+    }
+
+    #[test]
+    fn test_estimated_fee_address_type() {
+        let mut tx = empty_tx();
+        add_input(&mut tx, OutPoint::default());
+        add_input(&mut tx, OutPoint::default());
+        let p2shwpkh = estimated_vbytes(&tx, AddressType::P2shP2wpkh);
+        let p2wpkh = estimated_vbytes(&tx, AddressType::P2wpkh);
+        // the p2sh inputs also have the 23 bytes script sig, not discounted
+        assert_eq!(p2shwpkh - p2wpkh, 2 * 23);
+        assert!(
+            estimated_fee(&tx, 1.0, 1, AddressType::P2wpkh)
+                < estimated_fee(&tx, 1.0, 1, AddressType::P2shP2wpkh)
+        );
+    }
+
+    #[test]
+    fn test_balancing_fuzz() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let assets: Vec<AssetId> = (1..4u8)
+            .map(|i| AssetId::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let policy_asset = assets[0];
+
+        for _ in 0..1_000 {
+            // cached previous transaction, with some outputs missing the unblinded secrets
+            let mut prev_tx = empty_tx();
+            prev_tx.lock_time = rng.gen();
+            let mut secrets = vec![];
+            for _ in 0..rng.gen_range(0, 4) {
+                let asset = assets[rng.gen_range(0, assets.len())];
+                let value = amount(&mut rng);
+                prev_tx.output.push(explicit_output(asset, value));
+                secrets.push(TxOutSecrets::new(
+                    asset,
+                    AssetBlindingFactor::zero(),
+                    value,
+                    ValueBlindingFactor::zero(),
+                ));
+            }
+            let txid = prev_tx.txid();
+            let mut unblinded = HashMap::new();
+            for (vout, secrets) in secrets.into_iter().enumerate() {
+                if rng.gen_bool(0.9) {
+                    let vout = vout as u32;
+                    unblinded.insert(OutPoint { txid, vout }, secrets);
+                }
+            }
+            let mut all_txs: HashMap<Txid, elements::Transaction> = HashMap::new();
+            if rng.gen_bool(0.9) {
+                all_txs.insert(txid, prev_tx);
+            }
+
+            // spending transaction, possibly of unknown outputs
+            let mut tx = empty_tx();
+            for vout in 0..rng.gen_range(0, 5) {
+                add_input(&mut tx, OutPoint { txid, vout });
+            }
+            for _ in 0..rng.gen_range(0, 4) {
+                let asset = assets[rng.gen_range(0, assets.len())];
+                tx.output.push(explicit_output(asset, amount(&mut rng)));
+            }
+
+            let fee_rate = rng.gen_range(0.1, 10.0);
+            let address_type = if rng.gen_bool(0.5) {
+                AddressType::P2shP2wpkh
+            } else {
+                AddressType::P2wpkh
+            };
+            let _ = needs(
+                &tx,
+                fee_rate,
+                policy_asset,
+                &all_txs,
+                &unblinded,
+                address_type,
+            );
+            let _ = fee(
+                &tx,
+                &all_txs,
+                &unblinded,
+                &Some(Asset::Explicit(policy_asset)),
+            );
+
+            let estimated_fee = amount(&mut rng);
+            let changes = match changes(&tx, estimated_fee, policy_asset, &all_txs, &unblinded) {
+                Ok(changes) => changes,
+                Err(_) => continue,
+            };
+            // with the changes every asset balances, but the policy asset pays the fee and the
+            // dust not worth a change
+            let inputs = previous_outputs(&tx, &all_txs, &unblinded).unwrap();
+            let mut outputs = explicit_outputs(&tx).unwrap();
+            for (asset, satoshi) in changes {
+                add_amount(&mut outputs, asset, satoshi).unwrap();
+            }
+            for (asset, value) in inputs {
+                let output = outputs.remove(&asset).unwrap_or(0);
+                if asset == policy_asset {
+                    assert!(value - output >= estimated_fee);
+                    assert!(value - output - estimated_fee <= DUST_VALUE);
+                } else {
+                    assert_eq!(value, output);
+                }
+            }
+            assert!(outputs.is_empty());
+        }
+
+        let mut tx = empty_tx();
+        tx.output.push(explicit_output(policy_asset, u64::MAX));
+        tx.output.push(explicit_output(policy_asset, 1));
+        assert!(matches!(explicit_outputs(&tx), Err(Error::AmountOverflow)));
+        let all_txs: HashMap<Txid, elements::Transaction> = HashMap::new();
+        assert!(matches!(
+            needs(
+                &tx,
+                0.1,
+                policy_asset,
+                &all_txs,
+                &HashMap::new(),
+                AddressType::default()
+            ),
+            Err(Error::AmountOverflow)
+        ));
+        tx.output.pop();
+        assert!(matches!(
+            changes(&tx, 0, policy_asset, &all_txs, &HashMap::new()),
+            Err(Error::Unbalanced(asset)) if asset == policy_asset
+        ));
+        add_input(&mut tx, OutPoint::default());
+        assert!(matches!(
+            changes(&tx, 0, policy_asset, &all_txs, &HashMap::new()),
+            Err(Error::MissingPreviousOutput(_))
+        ));
+    }
 }
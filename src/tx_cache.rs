@@ -0,0 +1,419 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use aes_gcm_siv::Aes256GcmSiv;
+use elements::bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use elements::Txid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::info;
+
+use crate::error::Error;
+use crate::persister::Persister;
+use crate::store::{encrypt_save, load_decrypt_opt};
+
+/// Transactions kept in memory by a `TxCache` with a persister, the least recently used are
+/// evicted first
+pub const TX_CACHE_CAPACITY: usize = 1_000;
+
+/// Lookup of transactions by txid, implemented by the wallet `TxCache` and by caller supplied
+/// maps
+pub trait TxLookup {
+    /// Transaction with `txid`, None if unknown, fails if it is known but cannot be loaded
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error>;
+}
+
+impl TxLookup for HashMap<Txid, elements::Transaction> {
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error> {
+        Ok(self.get(txid).cloned().map(Arc::new))
+    }
+}
+
+impl TxLookup for HashMap<Txid, Arc<elements::Transaction>> {
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error> {
+        Ok(self.get(txid).cloned())
+    }
+}
+
+/// Wallet transactions and their previous transactions.
+///
+/// Only the txids are serialized with the cache, the transactions are saved one by one,
+/// encrypted, by the persister and loaded when needed, keeping in memory at most
+/// `TX_CACHE_CAPACITY` of them. The names of the saved transactions are keyed hashes of their
+/// txids, so that the files do not list the wallet history. Transactions are kept in memory
+/// until saved, a cache without persister, as one deserialized outside of a `StoreMeta`, keeps
+/// all of them.
+pub struct TxCache {
+    txids: HashSet<Txid>,
+    inner: Mutex<Inner>,
+    backend: Option<Backend>,
+}
+
+#[derive(Clone)]
+struct Backend {
+    persister: Arc<dyn Persister>,
+    cipher: Aes256GcmSiv,
+    /// Key of the hashes naming the saved transactions
+    names_key: [u8; 32],
+}
+
+impl Backend {
+    fn load(&self, name: &str) -> Result<Option<elements::Transaction>, Error> {
+        match load_decrypt_opt(name, &*self.persister, &self.cipher)? {
+            Some(bytes) => Ok(Some(elements::encode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Name of the saved transaction `txid`
+    fn tx_name(&self, txid: &Txid) -> String {
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&self.names_key);
+        engine.input(&txid[..]);
+        format!("tx_{}", hmac::Hmac::<sha256::Hash>::from_engine(engine))
+    }
+}
+
+/// Name of the transactions saved before the names were hashed
+fn legacy_tx_name(txid: &Txid) -> String {
+    format!("tx_{}", txid)
+}
+
+#[derive(Clone, Default)]
+struct Inner {
+    /// transactions not saved by the persister yet
+    unsaved: HashMap<Txid, Arc<elements::Transaction>>,
+    /// transactions loaded from the persister, with the tick of their last use
+    loaded: HashMap<Txid, (Arc<elements::Transaction>, u64)>,
+    tick: u64,
+    /// unsaved transactions loaded with their legacy name, removed once saved again
+    legacy: HashSet<Txid>,
+}
+
+impl Inner {
+    fn touch(&mut self, txid: Txid, tx: Arc<elements::Transaction>) {
+        self.tick += 1;
+        self.loaded.insert(txid, (tx, self.tick));
+    }
+
+    /// Evict the least recently used transactions above the capacity
+    fn evict(&mut self) {
+        if self.loaded.len() <= TX_CACHE_CAPACITY {
+            return;
+        }
+        let mut ticks: Vec<u64> = self.loaded.values().map(|(_, tick)| *tick).collect();
+        ticks.sort_unstable();
+        let threshold = ticks[self.loaded.len() - TX_CACHE_CAPACITY];
+        self.loaded.retain(|_, (_, tick)| *tick >= threshold);
+    }
+}
+
+impl TxCache {
+    /// Save the transactions with `persister` from now on, loading them when needed, the names
+    /// of the saved transactions are hashes keyed with `names_key`
+    pub(crate) fn attach(
+        &mut self,
+        persister: Arc<dyn Persister>,
+        cipher: Aes256GcmSiv,
+        names_key: [u8; 32],
+    ) {
+        self.backend = Some(Backend {
+            persister,
+            cipher,
+            names_key,
+        });
+    }
+
+    /// Transaction with `txid`, None if not in the cache, fails if it cannot be loaded
+    pub fn get(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error> {
+        if !self.txids.contains(txid) {
+            return Ok(None);
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tx) = inner.unsaved.get(txid) {
+            return Ok(Some(tx.clone()));
+        }
+        if let Some((tx, _)) = inner.loaded.get(txid) {
+            let tx = tx.clone();
+            inner.touch(*txid, tx.clone());
+            return Ok(Some(tx));
+        }
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(None),
+        };
+        if let Some(tx) = backend.load(&backend.tx_name(txid))? {
+            let tx = Arc::new(tx);
+            inner.touch(*txid, tx.clone());
+            inner.evict();
+            return Ok(Some(tx));
+        }
+        match backend.load(&legacy_tx_name(txid))? {
+            Some(tx) => {
+                // saved again with the hashed name by the next save
+                info!("loaded tx with legacy name");
+                let tx = Arc::new(tx);
+                inner.unsaved.insert(*txid, tx.clone());
+                inner.legacy.insert(*txid);
+                Ok(Some(tx))
+            }
+            None => Err(Error::Generic(format!(
+                "transaction {} missing from the store",
+                txid
+            ))),
+        }
+    }
+
+    pub fn contains_key(&self, txid: &Txid) -> bool {
+        self.txids.contains(txid)
+    }
+
+    pub fn insert(&mut self, txid: Txid, tx: elements::Transaction) {
+        self.txids.insert(txid);
+        let inner = self.inner.get_mut().unwrap_or_else(|e| e.into_inner());
+        inner.loaded.remove(&txid);
+        inner.unsaved.insert(txid, Arc::new(tx));
+    }
+
+    pub fn len(&self) -> usize {
+        self.txids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txids.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Txid> {
+        self.txids.iter()
+    }
+
+    /// Every transaction, loaded one at a time
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = Result<(Txid, Arc<elements::Transaction>), Error>> + '_ {
+        self.txids.iter().filter_map(move |txid| {
+            self.get(txid)
+                .transpose()
+                .map(|tx| tx.map(|tx| (*txid, tx)))
+        })
+    }
+
+    /// Like `iter` without the txids
+    pub fn values(&self) -> impl Iterator<Item = Result<Arc<elements::Transaction>, Error>> + '_ {
+        self.iter().map(|tx| tx.map(|(_, tx)| tx))
+    }
+
+    /// Save the transactions inserted after the last save, if there is a persister
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(()),
+        };
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let unsaved: Vec<_> = inner.unsaved.drain().collect();
+        for (i, (txid, tx)) in unsaved.iter().enumerate() {
+            let bytes = elements::encode::serialize(&**tx);
+            let saved = encrypt_save(
+                &backend.tx_name(txid),
+                bytes,
+                &*backend.persister,
+                &backend.cipher,
+            )
+            .and_then(|_| match inner.legacy.remove(txid) {
+                true => backend.persister.remove(&legacy_tx_name(txid)),
+                false => Ok(()),
+            });
+            if let Err(e) = saved {
+                // keep the transactions not saved for the next save
+                inner.unsaved.extend(unsaved[i..].iter().cloned());
+                return Err(e);
+            }
+            inner.touch(*txid, tx.clone());
+        }
+        inner.evict();
+        Ok(())
+    }
+
+    /// Remove the saved transactions, for caches replaced by an empty one, which must be saved
+    /// before so that no saved cache lists them
+    pub(crate) fn remove_saved(&self) -> Result<(), Error> {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(()),
+        };
+        for txid in self.txids.iter() {
+            backend.persister.remove(&backend.tx_name(txid))?;
+            backend.persister.remove(&legacy_tx_name(txid))?;
+        }
+        Ok(())
+    }
+}
+
+impl TxLookup for TxCache {
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Arc<elements::Transaction>>, Error> {
+        self.get(txid)
+    }
+}
+
+impl Default for TxCache {
+    fn default() -> Self {
+        TxCache {
+            txids: HashSet::new(),
+            inner: Mutex::new(Inner::default()),
+            backend: None,
+        }
+    }
+}
+
+impl Clone for TxCache {
+    fn clone(&self) -> Self {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        TxCache {
+            txids: self.txids.clone(),
+            inner: Mutex::new(inner),
+            backend: self.backend.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TxCacheRef<'a> {
+    txids: Vec<&'a Txid>,
+    txs: HashMap<Txid, &'a elements::Transaction>,
+}
+
+#[derive(Deserialize)]
+struct TxCacheOwned {
+    txids: Vec<Txid>,
+    #[serde(default)]
+    txs: HashMap<Txid, elements::Transaction>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TxCacheFormat {
+    Lazy(TxCacheOwned),
+    /// caches created before the transactions were saved one by one
+    Legacy(HashMap<Txid, elements::Transaction>),
+}
+
+impl Serialize for TxCache {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        TxCacheRef {
+            txids: self.txids.iter().collect(),
+            txs: inner
+                .unsaved
+                .iter()
+                .map(|(txid, tx)| (*txid, &**tx))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TxCache {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (txids, txs) = match TxCacheFormat::deserialize(deserializer)? {
+            TxCacheFormat::Lazy(cache) => (cache.txids.into_iter().collect(), cache.txs),
+            TxCacheFormat::Legacy(txs) => (txs.keys().cloned().collect(), txs),
+        };
+        let mut cache = TxCache {
+            txids,
+            ..Default::default()
+        };
+        // not saved transactions are saved with the next flush
+        for (txid, tx) in txs {
+            cache.insert(txid, tx);
+        }
+        Ok(cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persister::MemoryPersister;
+    use crate::transaction::add_input;
+    use aes_gcm_siv::aead::{generic_array::GenericArray, NewAead};
+
+    fn tx(lock_time: u32) -> elements::Transaction {
+        let mut tx = elements::Transaction {
+            version: 2,
+            lock_time,
+            input: vec![],
+            output: vec![],
+        };
+        add_input(&mut tx, elements::OutPoint::new(Txid::default(), 0));
+        tx
+    }
+
+    #[test]
+    fn test_tx_cache() {
+        let persister = MemoryPersister::new();
+        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&[1u8; 32]));
+        let mut cache = TxCache::default();
+        cache.attach(Arc::new(persister.clone()), cipher.clone(), [2u8; 32]);
+        let txs: Vec<_> = (0..TX_CACHE_CAPACITY as u32 + 10).map(tx).collect();
+        for tx in txs.iter() {
+            cache.insert(tx.txid(), tx.clone());
+        }
+        let bytes = serde_cbor::to_vec(&cache).unwrap();
+        cache.save().unwrap();
+        let name = cache.backend.as_ref().unwrap().tx_name(&txs[0].txid());
+        assert!(persister.load(&name).unwrap().is_some());
+        assert!(!name.contains(&txs[0].txid().to_string()));
+        assert!(persister
+            .load(&legacy_tx_name(&txs[0].txid()))
+            .unwrap()
+            .is_none());
+        assert!(cache.inner.lock().unwrap().loaded.len() <= TX_CACHE_CAPACITY);
+
+        // only the txids are serialized once saved
+        let saved_bytes = serde_cbor::to_vec(&cache).unwrap();
+        assert!(saved_bytes.len() < bytes.len());
+        let mut loaded: TxCache = serde_cbor::from_slice(&saved_bytes).unwrap();
+        assert_eq!(loaded.len(), txs.len());
+        assert!(loaded.get(&txs[0].txid()).unwrap().is_none());
+        loaded.attach(Arc::new(persister.clone()), cipher.clone(), [2u8; 32]);
+        for tx in txs.iter() {
+            assert_eq!(*loaded.get(&tx.txid()).unwrap().unwrap(), *tx);
+        }
+        assert_eq!(loaded.values().count(), txs.len());
+
+        // a different names key does not find the transactions
+        let mut other: TxCache = serde_cbor::from_slice(&saved_bytes).unwrap();
+        other.attach(Arc::new(persister.clone()), cipher.clone(), [3u8; 32]);
+        assert!(other.get(&txs[0].txid()).is_err());
+
+        // caches with the transactions in the cache file are still readable
+        let legacy: HashMap<Txid, elements::Transaction> =
+            txs.iter().map(|tx| (tx.txid(), tx.clone())).collect();
+        let legacy: TxCache =
+            serde_cbor::from_slice(&serde_cbor::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(*legacy.get(&txs[1].txid()).unwrap().unwrap(), txs[1]);
+    }
+
+    #[test]
+    fn test_tx_cache_legacy_names() {
+        let persister = MemoryPersister::new();
+        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&[1u8; 32]));
+        let tx = tx(0);
+        let txid = tx.txid();
+        let bytes = elements::encode::serialize(&tx);
+        encrypt_save(&legacy_tx_name(&txid), bytes, &persister, &cipher).unwrap();
+
+        let txids: HashSet<Txid> = vec![txid].into_iter().collect();
+        let mut cache = TxCache {
+            txids,
+            ..Default::default()
+        };
+        cache.attach(Arc::new(persister.clone()), cipher, [2u8; 32]);
+        assert_eq!(*cache.get(&txid).unwrap().unwrap(), tx);
+        cache.save().unwrap();
+        let name = cache.backend.as_ref().unwrap().tx_name(&txid);
+        assert!(persister.load(&name).unwrap().is_some());
+        assert!(persister.load(&legacy_tx_name(&txid)).unwrap().is_none());
+
+        cache.remove_saved().unwrap();
+        assert!(persister.load(&name).unwrap().is_none());
+    }
+}
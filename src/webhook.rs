@@ -0,0 +1,97 @@
+use crate::error::Error;
+
+use elements::bitcoin::hashes::hex::ToHex;
+use elements::bitcoin::hashes::{sha256, Hash, HashEngine, Hmac, HmacEngine};
+use serde::Serialize;
+
+/// Http header containing the hex HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Bewallet-Signature";
+
+/// Url receiving a POST request with a `PaymentNotification` for every transaction received on
+/// a wallet address, the body is signed with the shared secret.
+/// Notifications are sent once after the sync detecting the transaction and are not retried,
+/// only for the transactions unconfirmed or confirmed after the tip of the previous sync.
+pub struct Webhook {
+    url: String,
+    secret: Vec<u8>,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentNotification {
+    pub txid: String,
+    /// None if unconfirmed
+    pub height: Option<u32>,
+    pub outputs: Vec<ReceivedOutput>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceivedOutput {
+    pub vout: u32,
+    pub address: String,
+    pub asset: String,
+    pub value: u64,
+}
+
+impl Webhook {
+    pub fn new(url: &str, secret: &[u8]) -> Self {
+        Webhook {
+            url: url.to_string(),
+            secret: secret.to_vec(),
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    /// Hex HMAC-SHA256 of `body` with the webhook secret
+    pub fn sign(&self, body: &str) -> String {
+        let mut engine: HmacEngine<sha256::Hash> = HmacEngine::new(&self.secret);
+        engine.input(body.as_bytes());
+        Hmac::from_engine(engine).to_hex()
+    }
+
+    pub fn notify(&self, notification: &PaymentNotification) -> Result<(), Error> {
+        let body = serde_json::to_string(notification)?;
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .set(SIGNATURE_HEADER, &self.sign(&body))
+            .send_string(&body)?;
+        Ok(())
+    }
+}
+
+/// Whether a transaction found by a sync at `height`, None if unconfirmed, is newer than
+/// `previous_tip`, the tip known before the sync, None if the wallet was never synced
+pub(crate) fn is_new_payment(height: Option<u32>, previous_tip: Option<u32>) -> bool {
+    match (height, previous_tip) {
+        (None, _) | (Some(0), _) => true,
+        (Some(height), Some(previous_tip)) => height > previous_tip,
+        (Some(_), None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_new_payment() {
+        assert!(is_new_payment(None, None));
+        assert!(is_new_payment(None, Some(100)));
+        // the history found by the first sync is not notified
+        assert!(!is_new_payment(Some(90), None));
+        assert!(!is_new_payment(Some(90), Some(100)));
+        assert!(!is_new_payment(Some(100), Some(100)));
+        assert!(is_new_payment(Some(101), Some(100)));
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        let webhook = Webhook::new("http://localhost", b"Jefe");
+        assert_eq!(
+            webhook.sign("what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
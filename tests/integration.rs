@@ -1,8 +1,7 @@
+use bewallet::test_util as test_session;
 use bewallet::SPVVerifyResult;
 use std::env;
 
-mod test_session;
-
 #[test]
 fn liquid() {
     let electrs_exec = env::var("ELECTRS_LIQUID_EXEC")
@@ -36,11 +35,44 @@ fn liquid() {
     let assets = vec![asset1, asset2, asset3];
     wallet.send_multi(3, 1_000, &vec![], &mut server);
     wallet.send_multi(10, 1_000, &assets, &mut server);
+    let mixed = vec![asset1, wallet.policy_asset(), asset2];
+    wallet.send_multi(6, 1_000, &mixed, &mut server);
     wallet.wait_for_block(server.mine_block());
+    wallet.pending_incoming(&mut server);
+    wallet.wait_for_payment(&mut server);
     wallet.create_fails(&mut server);
+    wallet.tx_ordering(&mut server);
+    wallet.coin_selector(&mut server);
+    wallet.sign_approver(&mut server);
+    wallet.fee_utxos(&mut server, &asset1);
+    wallet.unblind_sent_output(&mut server);
+    wallet.send_to_contact(&mut server);
+    wallet.sweep_external_key(&mut server);
     wallet.is_verified(&txid, SPVVerifyResult::Verified);
+    wallet.verify_tx_inclusion(&txid);
+    wallet.peek_address();
     let utxos = wallet.utxos();
     wallet.send_tx(&node_address, 1_000, None, Some(utxos));
+    let parent_txid = wallet.send_tx(&node_address, 1_000, None, None);
+    wallet.cpfp(&parent_txid);
+    wallet.fund_tx(&node_address, 1_000);
+    wallet.send_tx_external_signer(&node_address, 1_000);
+    wallet.send_tx_with_signer(&node_address, 1_000);
+    wallet.virtual_account();
+    wallet.send_tx_queued(&node_address, 1_000);
+    wallet.send(&node_address, 1_000);
+    wallet.wait_for_block(server.mine_block());
+    wallet.balance_at();
+    wallet.utxos_age();
+    wallet.sync_progress();
+    wallet.stats();
+    wallet.green_subaccounts();
+    wallet.recovery();
+
+    let new_mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon actual".to_string();
+    let mut new_wallet =
+        test_session::TestElectrumWallet::new(&server.electrs.electrum_url, new_mnemonic);
+    wallet.sweep_to(&mut new_wallet);
 
     server.stop();
 }
@@ -77,7 +109,9 @@ fn dex() {
     // asset2 10_000 <-> asset1 10_000 (no change)
     maker.liquidex_add_asset(&asset1);
     let utxo = maker.asset_utxos(&asset2)[0].txo.outpoint;
-    let proposal = maker.liquidex_make(&utxo, &asset1, 1.0);
+    maker.liquidex_make_invalid(&utxo, &asset1);
+    let proposal = maker.liquidex_make(&utxo, &asset1, 2.0);
+    let proposal = maker.liquidex_reprice(&proposal, 1.0);
 
     let txid = taker.liquidex_take(&proposal);
     taker.wait_for_tx(&txid);
@@ -250,5 +284,26 @@ fn dex() {
     let balance_btc_6 = taker.balance(&policy_asset);
     assert_eq!(balance_btc_6, balance_btc_5 - fee);
 
+    // partial asset2 1_000 of 5_000 <-> asset1 2_000 (maker change)
+    let utxo = maker.asset_utxos(&asset2)[0].txo.outpoint;
+    let proposal = maker.liquidex_make_partial(&utxo, &asset1, 2.0, 4_000);
+
+    let txid = taker.liquidex_take_partial(&proposal, 1_000, &maker);
+    taker.wait_for_tx(&txid);
+    maker.wait_for_tx(&txid);
+
+    let fee = taker.get_fee(&txid);
+    assert_eq!(taker.balance(&asset1), 8_000);
+    assert_eq!(taker.balance(&asset2), 6_000);
+    assert_eq!(taker.balance(&policy_asset), balance_btc_6 - fee);
+    assert_eq!(maker.balance(&asset1), 2_000);
+    assert_eq!(maker.balance(&asset2), 4_000);
+
+    // policy asset 100_000 from a 2-of-2 multisig <-> asset1 1_000
+    maker.liquidex_multisig(&mut server, &mut taker, &asset1, 100_000, 0.01);
+
+    // payjoin of asset1 1_000, the maker adds one of its asset1 utxos
+    taker.payjoin(&mut maker, &asset1, 1_000);
+
     server.stop();
 }